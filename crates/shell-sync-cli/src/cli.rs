@@ -8,6 +8,10 @@ use clap_complete::Shell;
     version
 )]
 pub struct Cli {
+    /// Disable colored output (also honors the NO_COLOR environment variable)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -16,6 +20,9 @@ pub struct Cli {
 pub enum Commands {
     /// Start the sync server
     Serve {
+        /// Address to listen on (e.g. 127.0.0.1 to accept local connections only)
+        #[arg(long, default_value = "0.0.0.0")]
+        bind: String,
         /// Port to listen on
         #[arg(long, default_value_t = 8888)]
         port: u16,
@@ -25,11 +32,65 @@ pub enum Commands {
         /// Disable embedded web UI
         #[arg(long)]
         no_web_ui: bool,
-        /// Run in foreground (don't daemonize)
+        /// Run in foreground (don't detach; implied under systemd)
         #[arg(long)]
         foreground: bool,
+        /// How to settle concurrent alias edits: manual, last-write-wins, first-write-wins
+        #[arg(long, default_value = "manual")]
+        conflict_strategy: shell_sync_core::config::ConflictStrategy,
+        /// Token that unlocks admin endpoints such as GET /api/audit
+        #[arg(long, env = "SHELL_SYNC_ADMIN_TOKEN", hide_env_values = true)]
+        admin_token: Option<String>,
+        /// Browser origin allowed to call the API (repeatable; default: localhost only)
+        #[arg(long = "cors-origin")]
+        cors_origins: Vec<String>,
+        /// Allow cross-origin requests from any origin (development only)
+        #[arg(long)]
+        cors_any: bool,
+        /// Milliseconds a database call waits on a lock before failing
+        #[arg(long, default_value_t = shell_sync_core::db::DEFAULT_BUSY_TIMEOUT_MS)]
+        db_busy_timeout: u64,
+        /// Database connections kept open for concurrent requests
+        #[arg(long, default_value_t = shell_sync_core::db::DEFAULT_POOL_SIZE)]
+        db_pool_size: u32,
+        /// Seconds between WAL checkpoints (0 disables)
+        #[arg(long, default_value_t = 300)]
+        wal_checkpoint_interval: u64,
+        /// Largest request body in bytes for most API routes
+        #[arg(long, default_value_t = shell_sync_core::config::DEFAULT_MAX_BODY_BYTES)]
+        max_body_bytes: usize,
+        /// Largest request body in bytes for /api/register
+        #[arg(long, default_value_t = shell_sync_core::config::DEFAULT_MAX_REGISTER_BODY_BYTES)]
+        max_register_body_bytes: usize,
+        /// Largest import body or WebSocket message (e.g. history batch) in bytes
+        #[arg(long, default_value_t = shell_sync_core::config::DEFAULT_MAX_BULK_BODY_BYTES)]
+        max_bulk_body_bytes: usize,
+        /// Seconds between pings sent to each WebSocket client (0 disables)
+        #[arg(long, default_value_t = shell_sync_core::config::DEFAULT_PING_INTERVAL_SECS)]
+        ws_ping_interval: u64,
+        /// Days to remember deleted aliases so offline machines cannot re-add them (0 = forever)
+        #[arg(long, default_value_t = shell_sync_core::config::DEFAULT_ALIAS_TOMBSTONE_DAYS)]
+        alias_tombstone_days: u64,
+        /// Days to keep authentication attempts in the audit log (0 = forever)
+        #[arg(long, default_value_t = shell_sync_core::config::DEFAULT_AUTH_LOG_DAYS)]
+        auth_log_days: u64,
+        /// Seconds an API request may take before it fails with 503 (0 disables)
+        #[arg(long, default_value_t = shell_sync_core::config::DEFAULT_REQUEST_TIMEOUT_SECS)]
+        request_timeout: u64,
+        /// Request deadline in seconds for the history and audit listings (0 disables)
+        #[arg(long, default_value_t = shell_sync_core::config::DEFAULT_SLOW_REQUEST_TIMEOUT_SECS)]
+        slow_request_timeout: u64,
+        /// Regex for alias commands to reject in every group (repeatable)
+        #[arg(long = "block-command", value_name = "REGEX")]
+        blocked_commands: Vec<String>,
+        /// Regex for alias commands to reject in one group, as GROUP=REGEX (repeatable)
+        #[arg(long = "block-group-command", value_name = "GROUP=REGEX")]
+        blocked_group_commands: Vec<String>,
     },
 
+    /// Stop a server started in the background by `serve`
+    ServerStop,
+
     /// Register this machine with a sync server
     Register {
         /// Server URL (falls back to SHELL_SYNC_SERVER env, then mDNS)
@@ -52,24 +113,69 @@ pub enum Commands {
 
     /// Add a new alias
     Add {
-        /// Alias name
+        /// Name for the new alias
         name: String,
         /// Alias command
         command: String,
         /// Target group
         #[arg(long, default_value = "default")]
         group: String,
+        /// Double-quote the alias so `$VARS` and backticks expand when it is defined
+        #[arg(long)]
+        expand: bool,
+        /// Write a shell function that fills `{{name}}` placeholders from its
+        /// arguments, prompting for missing ones (e.g. "deploy {{env}}")
+        #[arg(long)]
+        template: bool,
+        /// Only define the alias in these shells (zsh, bash, fish); default all
+        #[arg(long = "shell", value_name = "SHELL", value_delimiter = ',')]
+        shells: Vec<String>,
     },
 
     /// Remove an alias
     Rm {
         /// Alias name
+        #[arg(value_name = "ALIAS")]
+        name: String,
+        /// Target group
+        #[arg(long, default_value = "default")]
+        group: String,
+    },
+
+    /// Pin an alias so `ls` lists it first
+    Pin {
+        /// Alias name
+        #[arg(value_name = "ALIAS")]
+        name: String,
+        /// Target group
+        #[arg(long, default_value = "default")]
+        group: String,
+    },
+
+    /// Unpin an alias
+    Unpin {
+        /// Alias name
+        #[arg(value_name = "ALIAS")]
         name: String,
         /// Target group
         #[arg(long, default_value = "default")]
         group: String,
     },
 
+    /// Show one alias with its metadata
+    #[command(visible_alias = "cat")]
+    Show {
+        /// Alias name
+        #[arg(value_name = "ALIAS")]
+        name: String,
+        /// Target group
+        #[arg(long, default_value = "default")]
+        group: String,
+        /// Print the alias as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
     /// List aliases
     Ls {
         /// Filter by group
@@ -78,17 +184,47 @@ pub enum Commands {
         /// Output format
         #[arg(long, default_value = "table")]
         format: OutputFormat,
+        /// Print only the total and per-group alias counts
+        #[arg(long)]
+        count: bool,
+        /// Order by name, most recently updated, or most used in local history
+        #[arg(long, default_value = "name")]
+        sort: AliasSort,
     },
 
     /// Update an existing alias
     Update {
         /// Alias name
+        #[arg(value_name = "ALIAS")]
         name: String,
         /// New command
         command: String,
         /// Target group
         #[arg(long, default_value = "default")]
         group: String,
+        /// Switch the alias to double-quoted (expanding) output
+        #[arg(long, conflicts_with = "no_expand")]
+        expand: bool,
+        /// Switch the alias back to single-quoted (literal) output
+        #[arg(long)]
+        no_expand: bool,
+        /// Make the alias a template that fills `{{name}}` placeholders
+        #[arg(long, conflicts_with = "no_template")]
+        template: bool,
+        /// Make the alias a plain alias again
+        #[arg(long)]
+        no_template: bool,
+        /// Only define the alias in these shells (zsh, bash, fish)
+        #[arg(
+            long = "shell",
+            value_name = "SHELL",
+            value_delimiter = ',',
+            conflicts_with = "all_shells"
+        )]
+        shells: Vec<String>,
+        /// Define the alias in every shell again
+        #[arg(long)]
+        all_shells: bool,
     },
 
     /// Import aliases from file or stdin
@@ -96,43 +232,100 @@ pub enum Commands {
         /// Path to file with aliases
         #[arg(long)]
         file: Option<String>,
-        /// Target group
+        /// File format: auto, shell, json, yaml (auto uses the extension, then the content)
+        #[arg(long, default_value = "auto")]
+        format: shell_sync_client::commands::ImportFormat,
+        /// Target group (entries in JSON/YAML files may set their own)
         #[arg(long, default_value = "default")]
         group: String,
-        /// Show what would be imported without doing it
+        /// Show what would be imported, and which names the server would
+        /// reject, without doing it
         #[arg(long)]
         dry_run: bool,
     },
 
     /// Export all aliases
-    Export,
+    Export {
+        /// Print only alias definitions for the current shell, for
+        /// `eval "$(shell-sync export --eval)"`
+        #[arg(long)]
+        eval: bool,
+        /// Only aliases created by this machine id (`self` for this machine)
+        #[arg(long, value_name = "MACHINE_ID")]
+        created_by: Option<String>,
+    },
+
+    /// Bulk alias operations
+    #[command(visible_alias = "aliases")]
+    Alias {
+        #[command(subcommand)]
+        command: AliasCommands,
+    },
 
     /// Force a full sync
     Sync,
 
     /// Show daemon and connection status
-    Status,
+    Status {
+        /// Print stable `key=value` lines for scripts
+        #[arg(long, conflicts_with = "json")]
+        porcelain: bool,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check that the server is reachable, how fast it answers, and that it
+    /// accepts this machine's token
+    Ping {
+        /// Health checks to time
+        #[arg(long, default_value_t = 3)]
+        count: u32,
+    },
+
+    /// Print where shell-sync keeps its files and the loaded config
+    Paths,
 
     /// Stop the daemon
     Stop,
 
     /// List and resolve conflicts
-    Conflicts,
+    Conflicts {
+        #[command(subcommand)]
+        command: Option<ConflictCommands>,
+        /// Also list resolved conflicts
+        #[arg(long)]
+        all: bool,
+    },
 
     /// Show sync history
+    #[command(args_conflicts_with_subcommands = true)]
     History {
         /// Maximum entries to show
         #[arg(long, default_value_t = 100)]
         limit: i64,
+        #[command(subcommand)]
+        command: Option<HistoryCommands>,
     },
 
     /// List registered machines (server admin)
     Machines,
 
+    /// Manage groups
+    #[command(visible_alias = "group")]
+    Groups {
+        #[command(subcommand)]
+        command: GroupCommands,
+    },
+
     /// Force a git backup commit
-    GitBackup,
+    GitBackup {
+        /// Show which alias files would change without writing or committing
+        #[arg(long)]
+        dry_run: bool,
+    },
 
-    /// Generate shell completions
+    /// Generate shell completions (alias names and groups complete from this machine)
     Completions {
         /// Shell to generate completions for
         shell: Shell,
@@ -142,6 +335,9 @@ pub enum Commands {
     Migrate {
         /// Path to the old Node.js sync.db database
         old_db_path: String,
+        /// Server database to migrate into
+        #[arg(long, default_value = "./data/sync.db")]
+        target: String,
     },
 
     /// Interactive history search (Ctrl+R replacement)
@@ -152,11 +348,74 @@ pub enum Commands {
         /// Output selected command to stdout (for shell integration)
         #[arg(long)]
         inline: bool,
+        /// Only show commands captured under this group
+        #[arg(long)]
+        group: Option<String>,
+        /// What to print for the accepted entry
+        #[arg(long, value_enum, default_value = "command", alias = "output")]
+        print: SearchPrint,
+        /// Show each fuzzy match's score and matched characters (Alt+E toggles)
+        #[arg(long)]
+        explain: bool,
+    },
+
+    /// Show captured shell commands, optionally following new ones as they sync
+    ShellHistory {
+        /// Recent commands to show before following
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+        /// Only commands captured under this group
+        #[arg(long)]
+        group: Option<String>,
+        /// Only commands at or after this time (YYYY-MM-DD or ISO-8601)
+        #[arg(long)]
+        after: Option<String>,
+        /// Only commands before this time (YYYY-MM-DD or ISO-8601)
+        #[arg(long, conflicts_with = "follow")]
+        before: Option<String>,
+        /// Print the newest command first
+        #[arg(long)]
+        reverse: bool,
+        /// Keep running and print commands from other machines as they arrive
+        #[arg(short, long)]
+        follow: bool,
+    },
+
+    /// Re-queue this machine's history for the daemon to push to the server again
+    ResyncHistory {
+        /// Only entries from this period (e.g., "7d", "30d", "1y", "all")
+        #[arg(long, default_value = "all")]
+        since: String,
     },
 
     /// Encrypt existing plaintext data and re-upload
     EncryptMigrate,
 
+    /// Move group encryption keys between machines by hand
+    Key {
+        #[command(subcommand)]
+        command: KeyCommands,
+    },
+
+    /// Set up this machine in one go: register, install hooks, start the daemon
+    Init {
+        /// Server URL (default: SHELL_SYNC_SERVER or mDNS discovery)
+        #[arg(long)]
+        server: Option<String>,
+        /// Comma-separated groups to join (default: "default")
+        #[arg(long)]
+        groups: Option<String>,
+        /// Start the daemon without asking
+        #[arg(long, conflicts_with = "no_start")]
+        start: bool,
+        /// Do not start the daemon
+        #[arg(long)]
+        no_start: bool,
+        /// Register again if --server names a different server than the saved one
+        #[arg(long)]
+        force: bool,
+    },
+
     /// Generate and install shell hooks for history capture
     InitHooks {
         /// Overwrite existing hook files
@@ -166,9 +425,16 @@ pub enum Commands {
 
     /// Show shell usage statistics and analytics
     Stats {
-        /// Time period (e.g., "7d", "30d", "1y", "all")
-        #[arg(long, default_value = "30d")]
-        last: String,
+        /// Time period (e.g., "7d", "30d", "1y", "all"); defaults to 30d unless
+        /// --after or --before is given
+        #[arg(long)]
+        last: Option<String>,
+        /// Only commands at or after this time (YYYY-MM-DD or ISO-8601)
+        #[arg(long)]
+        after: Option<String>,
+        /// Only commands before this time (YYYY-MM-DD or ISO-8601)
+        #[arg(long)]
+        before: Option<String>,
         /// Filter by machine
         #[arg(long)]
         machine: Option<String>,
@@ -181,7 +447,192 @@ pub enum Commands {
         /// Output as JSON
         #[arg(long)]
         json: bool,
+        /// Write a weekday × hour activity heatmap as SVG to this path
+        #[arg(long, value_name = "PATH", alias = "export-svg")]
+        svg: Option<std::path::PathBuf>,
+        /// Show only the busiest sessions by command count and total duration
+        #[arg(long)]
+        sessions: bool,
+        /// Count top commands by template, replacing ids, numbers, hashes and
+        /// paths with placeholders (e.g. `kubectl logs pod-<id>`)
+        #[arg(long)]
+        normalize: bool,
+        /// How bars are sized against the largest count; `log` keeps one
+        /// busy command from flattening the rest
+        #[arg(long, value_enum, default_value = "linear")]
+        scale: BarScale,
+        /// Drill down into one exact command line (e.g. "git status")
+        #[arg(long, conflicts_with_all = ["svg", "sessions"])]
+        command: Option<String>,
+        /// Write the report to this file instead of stdout, creating parent
+        /// directories; `-` means stdout
+        #[arg(long, short, value_name = "PATH", conflicts_with = "svg")]
+        output: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommands {
+    /// Move shell history older than a period into a gzipped JSONL file
+    Archive {
+        /// Archive entries older than this (e.g., "90d", "1y")
+        #[arg(long)]
+        older_than: String,
+        /// New archive file to write (e.g., archive.jsonl.gz)
+        #[arg(long, short, value_name = "PATH")]
+        output: std::path::PathBuf,
+    },
+    /// Re-import the entries from an archive written by `history archive`
+    RestoreArchive {
+        /// Archive file to read
+        file: std::path::PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConflictCommands {
+    /// Delete this machine's resolved conflicts; open ones are kept
+    Purge {
+        /// Only purge conflicts resolved longer ago than this (e.g., "7d", "30d", "all")
+        #[arg(long, default_value = "30d")]
+        older_than: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum KeyCommands {
+    /// Print this machine's public key
+    Public,
+    /// Print a group key wrapped for another machine
+    Export {
+        /// Group whose key to export
+        #[arg(long, value_name = "GROUP")]
+        group: String,
+        /// The other machine's public key (from `shell-sync key public` there)
+        #[arg(long)]
+        to: String,
+    },
+    /// Install a group key exported on another machine
+    Import {
+        /// Group the key belongs to
+        #[arg(long, value_name = "GROUP")]
+        group: String,
+        /// Output of `shell-sync key export`
+        key: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum GroupCommands {
+    /// List groups with alias and machine counts
+    Ls,
+    /// Create an empty group
+    Create {
+        /// Group name
+        name: String,
+    },
+    /// Delete a group
+    Rm {
+        /// Group name
+        name: String,
+        /// Delete the group's aliases and remove it from every machine
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AliasCommands {
+    /// Search and replace text across alias commands
+    Sed {
+        /// Text (or regex with --regex) to search for
+        find: String,
+        /// Replacement text; supports $1-style capture references with --regex
+        replace: String,
+        /// Only edit aliases in this group
+        #[arg(long)]
+        group: Option<String>,
+        /// Treat <find> as a regular expression
+        #[arg(long)]
+        regex: bool,
+        /// Show the changes without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Compare the local alias file with the aliases on the server
+    Diff,
+
+    /// Show the command an alias runs and the binary it resolves to, without running it
+    Which {
+        /// Alias name
+        #[arg(value_name = "ALIAS")]
+        name: String,
+        /// Only look in this group
+        #[arg(long)]
+        group: Option<String>,
+    },
+
+    /// Report aliases whose command is not found on PATH
+    Verify {
+        /// Only check aliases in this group
+        #[arg(long)]
+        group: Option<String>,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Warn about aliases that shadow commands, expand into themselves, or
+    /// have names a target shell rejects
+    Lint {
+        /// Only check aliases in this group
+        #[arg(long, value_name = "GROUP")]
+        group: Option<String>,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List aliases in groups no machine belongs to, which never sync
+    Orphans {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
     },
+
+    /// Move every alias from one group to another
+    Reassign {
+        /// Group to move aliases out of
+        #[arg(long, value_name = "GROUP")]
+        from: String,
+        /// Group to move them into (this machine must belong to it)
+        #[arg(long, value_name = "GROUP")]
+        to: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum SearchPrint {
+    /// The command alone
+    Command,
+    /// `cd <cwd> && <command>`
+    CdAndCommand,
+    /// The full history entry as JSON
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum BarScale {
+    Linear,
+    Log,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum AliasSort {
+    Name,
+    Recent,
+    Usage,
 }
 
 #[derive(Clone, Debug, clap::ValueEnum)]
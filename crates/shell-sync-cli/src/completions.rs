@@ -0,0 +1,183 @@
+//! Completion scripts: clap's static completions, plus alias names and groups
+//! filled in at Tab time by `shell-sync __complete groups|aliases` (handled
+//! in `main` before argument parsing).
+//!
+//! Alias-name arguments are marked with `value_name = "ALIAS"` in `cli.rs`;
+//! options named `GROUP` (such as `--group`) complete group names.
+
+use clap::{Arg, Command, CommandFactory};
+use clap_complete::Shell;
+
+const BIN: &str = "shell-sync";
+
+/// An argument whose values come from `shell-sync __complete`.
+struct Site {
+    /// Subcommands leading to the one that owns the argument.
+    parents: Vec<String>,
+    /// The owning subcommand's name and visible aliases.
+    names: Vec<String>,
+    /// The option's long name; `None` for a positional.
+    long: Option<String>,
+    /// How zsh's `_arguments` spec writes a positional: `':id -- help:'`.
+    zsh_spec: String,
+}
+
+/// Completion script for `shell`. Bash, zsh and fish also complete alias
+/// names and groups from this machine; other shells get clap's script alone.
+pub fn script(shell: Shell) -> String {
+    let mut cmd = crate::cli::Cli::command();
+    let mut out = Vec::new();
+    clap_complete::generate(shell, &mut cmd, BIN, &mut out);
+    let script = String::from_utf8_lossy(&out).into_owned();
+
+    let mut aliases = Vec::new();
+    let mut groups = Vec::new();
+    find_sites(&cmd, &[], &mut aliases, &mut groups);
+    match shell {
+        Shell::Bash => script + &bash(&aliases, &groups),
+        Shell::Zsh => zsh(script, &aliases),
+        Shell::Fish => script + &fish(&aliases, &groups),
+        _ => script,
+    }
+}
+
+fn value_name(arg: &Arg) -> String {
+    match arg.get_value_names() {
+        Some([name]) => name.to_string(),
+        _ => arg.get_id().as_str().to_uppercase(),
+    }
+}
+
+fn find_sites(cmd: &Command, parents: &[String], aliases: &mut Vec<Site>, groups: &mut Vec<Site>) {
+    for sub in cmd.get_subcommands() {
+        let names: Vec<String> = std::iter::once(sub.get_name())
+            .chain(sub.get_visible_aliases())
+            .map(str::to_string)
+            .collect();
+        for arg in sub
+            .get_arguments()
+            .filter(|a| a.get_action().takes_values())
+        {
+            let site = || Site {
+                parents: parents.to_vec(),
+                names: names.clone(),
+                long: arg.get_long().map(str::to_string),
+                zsh_spec: format!(
+                    "':{} -- {}:",
+                    arg.get_id(),
+                    arg.get_help().map(|h| h.to_string()).unwrap_or_default()
+                ),
+            };
+            match value_name(arg).as_str() {
+                "ALIAS" if arg.is_positional() => aliases.push(site()),
+                "GROUP" if arg.get_long().is_some() => groups.push(site()),
+                _ => {}
+            }
+        }
+        let mut path = parents.to_vec();
+        path.push(sub.get_name().to_string());
+        find_sites(sub, &path, aliases, groups);
+    }
+}
+
+/// Wrap clap's `_shell-sync`: complete groups after a group option, and alias
+/// names right after a command that takes one.
+fn bash(aliases: &[Site], groups: &[Site]) -> String {
+    let mut options: Vec<String> = groups
+        .iter()
+        .filter_map(|s| s.long.as_ref().map(|l| format!("--{l}")))
+        .collect();
+    options.sort();
+    options.dedup();
+    let commands: Vec<String> = aliases
+        .iter()
+        .flat_map(|s| {
+            s.names.iter().map(|n| {
+                let mut path = s.parents.clone();
+                path.push(n.clone());
+                format!("\"{}\"", path.join(" "))
+            })
+        })
+        .collect();
+    format!(
+        r#"
+_shell_sync_dynamic() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}" kind=""
+    case "$prev" in
+        {options}) kind=groups ;;
+    esac
+    if [[ -z "$kind" && "$cur" != -* ]]; then
+        case "${{COMP_WORDS[*]:1:COMP_CWORD-1}}" in
+            {commands}) kind=aliases ;;
+        esac
+    fi
+    if [[ -n "$kind" ]]; then
+        COMPREPLY=($(compgen -W "$({BIN} __complete "$kind" 2>/dev/null)" -- "$cur"))
+        return 0
+    fi
+    _shell-sync "$@"
+}}
+complete -F _shell_sync_dynamic -o bashdefault -o default {BIN}
+"#,
+        options = options.join("|"),
+        commands = commands.join("|"),
+    )
+}
+
+/// Point clap's `_default` actions for group options and alias-name
+/// positionals at helpers that call `shell-sync __complete`.
+fn zsh(script: String, aliases: &[Site]) -> String {
+    let helpers = format!(
+        r#"
+_shell_sync_groups() {{
+    compadd -- ${{(f)"$({BIN} __complete groups 2>/dev/null)"}}
+}}
+_shell_sync_aliases() {{
+    compadd -- ${{(f)"$({BIN} __complete aliases 2>/dev/null)"}}
+}}
+"#
+    );
+    let mut script = script.replace(":GROUP:_default'", ":GROUP:_shell_sync_groups'");
+    for site in aliases {
+        let spec = &site.zsh_spec;
+        script = script.replace(
+            &format!("{spec}_default'"),
+            &format!("{spec}_shell_sync_aliases'"),
+        );
+    }
+    // After the `#compdef` line, so the helpers exist when autoloaded too
+    match script.split_once('\n') {
+        Some((first, rest)) => format!("{first}\n{helpers}{rest}"),
+        None => script,
+    }
+}
+
+/// Extra `complete` rules; fish merges them with clap's.
+fn fish(aliases: &[Site], groups: &[Site]) -> String {
+    let condition = |site: &Site| match site.parents.split_first() {
+        None => format!(
+            "__fish_shell_sync_using_subcommand {}",
+            site.names.join(" ")
+        ),
+        Some((top, _)) => format!(
+            "__fish_shell_sync_using_subcommand {top}; and __fish_seen_subcommand_from {}",
+            site.names.join(" ")
+        ),
+    };
+    let mut out = String::new();
+    for site in aliases {
+        out.push_str(&format!(
+            "complete -c {BIN} -n \"{}\" -f -a \"({BIN} __complete aliases)\"\n",
+            condition(site)
+        ));
+    }
+    for site in groups {
+        if let Some(long) = &site.long {
+            out.push_str(&format!(
+                "complete -c {BIN} -n \"{}\" -l {long} -x -a \"({BIN} __complete groups)\"\n",
+                condition(site)
+            ));
+        }
+    }
+    out
+}
@@ -2,31 +2,133 @@ use clap::Parser;
 use tracing_subscriber::EnvFilter;
 
 mod cli;
+mod completions;
+
+use shell_sync_core::config::server_pid_file_path;
+#[cfg(unix)]
+use {shell_sync_core::config::server_log_path, shell_sync_server::pidfile};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // `shell-sync __complete groups|aliases` runs on every Tab press from the
+    // completion scripts. It is kept out of clap, whose generated scripts
+    // would otherwise offer it as a subcommand.
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|a| a == "__complete") {
+        use shell_sync_client::commands::CompletionKind;
+        match args.get(2).map(String::as_str) {
+            Some("groups") => shell_sync_client::commands::complete(CompletionKind::Groups),
+            Some("aliases") => shell_sync_client::commands::complete(CompletionKind::Aliases),
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    let cli = cli::Cli::parse();
+    if cli.no_color {
+        shell_sync_core::color::disable();
+    }
+
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        // No color codes when output goes to a file (e.g. the background server log)
+        .with_ansi(shell_sync_core::color::stdout_enabled())
         .init();
 
-    let cli = cli::Cli::parse();
-
     match cli.command {
         cli::Commands::Serve {
+            bind,
             port,
             no_mdns,
             no_web_ui,
-            foreground: _,
+            foreground,
+            conflict_strategy,
+            admin_token,
+            cors_origins,
+            cors_any,
+            db_busy_timeout,
+            db_pool_size,
+            wal_checkpoint_interval,
+            max_body_bytes,
+            max_register_body_bytes,
+            max_bulk_body_bytes,
+            ws_ping_interval,
+            alias_tombstone_days,
+            auth_log_days,
+            request_timeout,
+            slow_request_timeout,
+            blocked_commands,
+            blocked_group_commands,
         } => {
+            let mut group_blocked_command_patterns = std::collections::BTreeMap::new();
+            for entry in blocked_group_commands {
+                let Some((group, pattern)) = entry.split_once('=') else {
+                    anyhow::bail!("Invalid --block-group-command '{entry}': expected GROUP=REGEX");
+                };
+                group_blocked_command_patterns
+                    .entry(group.to_string())
+                    .or_insert_with(Vec::new)
+                    .push(pattern.to_string());
+            }
             let config = shell_sync_core::config::ServerConfig {
+                bind_address: bind,
                 port,
                 mdns_enabled: !no_mdns,
                 web_ui_enabled: !no_web_ui,
+                conflict_strategy,
+                admin_token,
+                cors_allowed_origins: cors_origins,
+                cors_any,
+                db_busy_timeout_ms: db_busy_timeout,
+                db_pool_size,
+                wal_checkpoint_interval_secs: wal_checkpoint_interval,
+                pid_file: Some(server_pid_file_path()),
+                max_register_body_bytes,
+                max_body_bytes,
+                max_bulk_body_bytes,
+                ws_ping_interval_secs: ws_ping_interval,
+                alias_tombstone_days,
+                auth_log_days,
+                request_timeout_secs: request_timeout,
+                slow_request_timeout_secs: slow_request_timeout,
+                blocked_command_patterns: blocked_commands,
+                group_blocked_command_patterns,
                 ..Default::default()
             };
+
+            #[cfg(unix)]
+            if !foreground && !pidfile::running_under_systemd() {
+                let pid_path = server_pid_file_path();
+                if let Some(pid) = pidfile::read_pid(&pid_path).filter(|p| pidfile::is_running(*p)) {
+                    anyhow::bail!("Server already running (PID {pid})");
+                }
+                let mut args: Vec<std::ffi::OsString> = std::env::args_os().skip(1).collect();
+                args.push("--foreground".into());
+                let log_path = server_log_path();
+                let mut child =
+                    pidfile::spawn_detached(&std::env::current_exe()?, &args, Some(&log_path))?;
+                pidfile::wait_for_start(&mut child, &pid_path, std::time::Duration::from_secs(10))
+                    .map_err(|e| anyhow::anyhow!("{e}; see {}", log_path.display()))?;
+                println!("Shell Sync server started in background (PID {})", child.id());
+                println!("Logs: {}", log_path.display());
+                println!("Stop it with: shell-sync server-stop");
+                return Ok(());
+            }
+
             shell_sync_server::server::run(config).await?;
         }
 
+        cli::Commands::ServerStop => {
+            #[cfg(unix)]
+            if pidfile::stop(&server_pid_file_path(), std::time::Duration::from_secs(10))? {
+                println!("Server stopped");
+            } else {
+                println!("Server is not running");
+            }
+            #[cfg(not(unix))]
+            anyhow::bail!("server-stop is only supported on Unix");
+        }
+
         cli::Commands::Register { server, groups } => {
             let groups: Vec<String> = groups.split(',').map(|s| s.trim().to_string()).collect();
             shell_sync_client::registration::register(server, groups).await?;
@@ -36,87 +138,330 @@ async fn main() -> anyhow::Result<()> {
             shell_sync_client::daemon::run(server, foreground).await?;
         }
 
-        cli::Commands::Add { name, command, group } => {
-            shell_sync_client::commands::add_alias(&name, &command, &group).await?;
+        cli::Commands::Add {
+            name,
+            command,
+            group,
+            expand,
+            template,
+            shells,
+        } => {
+            shell_sync_client::commands::add_alias(
+                &name, &command, &group, expand, template, &shells,
+            )
+            .await?;
         }
 
         cli::Commands::Rm { name, group } => {
             shell_sync_client::commands::remove_alias(&name, &group).await?;
         }
 
-        cli::Commands::Ls { group, format } => {
-            shell_sync_client::commands::list_aliases(group.as_deref(), matches!(format, cli::OutputFormat::Json)).await?;
+        cli::Commands::Pin { name, group } => {
+            shell_sync_client::commands::set_pinned(&name, &group, true).await?;
         }
 
-        cli::Commands::Update { name, command, group } => {
-            shell_sync_client::commands::update_alias(&name, &command, &group).await?;
+        cli::Commands::Unpin { name, group } => {
+            shell_sync_client::commands::set_pinned(&name, &group, false).await?;
         }
 
-        cli::Commands::Import { file, group, dry_run } => {
-            shell_sync_client::commands::import_aliases(file.as_deref(), &group, dry_run).await?;
+        cli::Commands::Show { name, group, json } => {
+            shell_sync_client::commands::show_alias(&name, &group, json).await?;
         }
 
-        cli::Commands::Export => {
-            shell_sync_client::commands::export_aliases().await?;
+        cli::Commands::Ls {
+            group,
+            format,
+            count,
+            sort,
+        } => {
+            let sort = match sort {
+                cli::AliasSort::Name => shell_sync_client::commands::AliasSort::Name,
+                cli::AliasSort::Recent => shell_sync_client::commands::AliasSort::Recent,
+                cli::AliasSort::Usage => shell_sync_client::commands::AliasSort::Usage,
+            };
+            let json = matches!(format, cli::OutputFormat::Json);
+            shell_sync_client::commands::list_aliases(group.as_deref(), json, count, sort).await?;
+        }
+
+        cli::Commands::Update {
+            name,
+            command,
+            group,
+            expand,
+            no_expand,
+            template,
+            no_template,
+            shells,
+            all_shells,
+        } => {
+            let expand = if expand {
+                Some(true)
+            } else if no_expand {
+                Some(false)
+            } else {
+                None
+            };
+            let template = if template {
+                Some(true)
+            } else if no_template {
+                Some(false)
+            } else {
+                None
+            };
+            let shells = if all_shells {
+                Some(Vec::new())
+            } else if !shells.is_empty() {
+                Some(shells)
+            } else {
+                None
+            };
+            shell_sync_client::commands::update_alias(
+                &name, &command, &group, expand, template, shells,
+            )
+            .await?;
         }
 
+        cli::Commands::Import {
+            file,
+            format,
+            group,
+            dry_run,
+        } => {
+            shell_sync_client::commands::import_aliases(file.as_deref(), format, &group, dry_run)
+                .await?;
+        }
+
+        cli::Commands::Export { eval, created_by } => {
+            shell_sync_client::commands::export_aliases(eval, created_by.as_deref()).await?;
+        }
+
+        cli::Commands::Alias { command } => match command {
+            cli::AliasCommands::Sed {
+                find,
+                replace,
+                group,
+                regex,
+                dry_run,
+            } => {
+                shell_sync_client::commands::sed_aliases(&find, &replace, group.as_deref(), regex, dry_run).await?;
+            }
+            cli::AliasCommands::Diff => {
+                shell_sync_client::commands::diff_alias_file().await?;
+            }
+            cli::AliasCommands::Which { name, group } => {
+                shell_sync_client::commands::alias_which(&name, group.as_deref()).await?;
+            }
+            cli::AliasCommands::Verify { group, json } => {
+                shell_sync_client::commands::verify_aliases(group.as_deref(), json).await?;
+            }
+            cli::AliasCommands::Lint { group, json } => {
+                shell_sync_client::commands::lint_aliases(group.as_deref(), json).await?;
+            }
+            cli::AliasCommands::Orphans { json } => {
+                shell_sync_client::commands::list_orphaned_aliases(json).await?;
+            }
+            cli::AliasCommands::Reassign { from, to } => {
+                shell_sync_client::commands::reassign_aliases(&from, &to).await?;
+            }
+        },
+
         cli::Commands::Sync => {
             shell_sync_client::commands::force_sync().await?;
         }
 
-        cli::Commands::Status => {
-            shell_sync_client::commands::status()?;
+        cli::Commands::Status { porcelain, json } => {
+            shell_sync_client::commands::status(porcelain, json)?;
         }
 
-        cli::Commands::Stop => {
-            shell_sync_client::commands::stop_daemon()?;
+        cli::Commands::Ping { count } => {
+            shell_sync_client::commands::ping(count).await?;
         }
 
-        cli::Commands::Conflicts => {
-            shell_sync_client::commands::list_conflicts().await?;
+        cli::Commands::Paths => {
+            shell_sync_client::commands::show_paths()?;
         }
 
-        cli::Commands::History { limit } => {
-            shell_sync_client::commands::show_history(limit).await?;
+        cli::Commands::Stop => {
+            shell_sync_client::commands::stop_daemon()?;
         }
 
+        cli::Commands::Conflicts { command, all } => match command {
+            None => {
+                shell_sync_client::commands::list_conflicts(all).await?;
+            }
+            Some(cli::ConflictCommands::Purge { older_than }) => {
+                shell_sync_client::commands::purge_conflicts(&older_than).await?;
+            }
+        },
+
+        cli::Commands::History { limit, command } => match command {
+            None => shell_sync_client::commands::show_history(limit).await?,
+            Some(cli::HistoryCommands::Archive { older_than, output }) => {
+                shell_sync_client::shell_history::archive_history(&older_than, &output)?;
+            }
+            Some(cli::HistoryCommands::RestoreArchive { file }) => {
+                shell_sync_client::shell_history::restore_history_archive(&file)?;
+            }
+        },
+
         cli::Commands::Machines => {
             shell_sync_client::commands::list_machines().await?;
         }
 
-        cli::Commands::GitBackup => {
-            shell_sync_client::commands::git_backup().await?;
+        cli::Commands::Groups { command } => match command {
+            cli::GroupCommands::Ls => {
+                shell_sync_client::commands::list_groups().await?;
+            }
+            cli::GroupCommands::Create { name } => {
+                shell_sync_client::commands::create_group(&name).await?;
+            }
+            cli::GroupCommands::Rm { name, force } => {
+                shell_sync_client::commands::delete_group(&name, force).await?;
+            }
+        },
+
+        cli::Commands::GitBackup { dry_run } => {
+            shell_sync_client::commands::git_backup(dry_run).await?;
         }
 
         cli::Commands::Completions { shell } => {
-            use clap::CommandFactory;
-            clap_complete::generate(shell, &mut cli::Cli::command(), "shell-sync", &mut std::io::stdout());
+            print!("{}", completions::script(shell));
         }
 
-        cli::Commands::Migrate { old_db_path } => {
-            shell_sync_client::commands::migrate(&old_db_path)?;
+        cli::Commands::Migrate {
+            old_db_path,
+            target,
+        } => {
+            shell_sync_client::commands::migrate(&old_db_path, &target)?;
         }
 
-        cli::Commands::Search { query, inline } => {
-            shell_sync_tui::run_search(&query, inline)?;
+        cli::Commands::Search {
+            query,
+            inline,
+            group,
+            print,
+            explain,
+        } => {
+            let print = match print {
+                cli::SearchPrint::Command => shell_sync_tui::app::PrintMode::Command,
+                cli::SearchPrint::CdAndCommand => shell_sync_tui::app::PrintMode::CdAndCommand,
+                cli::SearchPrint::Json => shell_sync_tui::app::PrintMode::Json,
+            };
+            shell_sync_tui::run_search(&query, inline, group.as_deref(), print, explain)?;
+        }
+
+        cli::Commands::ShellHistory {
+            limit,
+            group,
+            after,
+            before,
+            reverse,
+            follow,
+        } => {
+            shell_sync_client::shell_history::shell_history(
+                limit,
+                group.as_deref(),
+                after.as_deref(),
+                before.as_deref(),
+                reverse,
+                follow,
+            )
+            .await?;
+        }
+
+        cli::Commands::ResyncHistory { since } => {
+            shell_sync_client::commands::resync_history(&since)?;
         }
 
         cli::Commands::EncryptMigrate => {
             shell_sync_client::commands::encrypt_migrate().await?;
         }
 
+        cli::Commands::Key { command } => match command {
+            cli::KeyCommands::Public => {
+                shell_sync_client::commands::show_public_key()?;
+            }
+            cli::KeyCommands::Export { group, to } => {
+                shell_sync_client::commands::export_group_key(&group, &to)?;
+            }
+            cli::KeyCommands::Import { group, key } => {
+                shell_sync_client::commands::import_group_key(&group, &key)?;
+            }
+        },
+
+        cli::Commands::Init {
+            server,
+            groups,
+            start,
+            no_start,
+            force,
+        } => {
+            let groups = groups.map(|g| g.split(',').map(|s| s.trim().to_string()).collect());
+            let start_daemon = if start {
+                Some(true)
+            } else if no_start {
+                Some(false)
+            } else {
+                None
+            };
+            shell_sync_client::init::init(server, groups, start_daemon, force).await?;
+        }
+
         cli::Commands::InitHooks { force } => {
             shell_sync_client::commands::init_hooks(force)?;
         }
 
         cli::Commands::Stats {
             last,
+            after,
+            before,
             machine,
             group,
             directory,
             json,
+            svg,
+            sessions,
+            normalize,
+            scale,
+            command,
+            output,
         } => {
-            shell_sync_client::commands::show_stats(&last, machine, group, directory, json)?;
+            let last = last.unwrap_or_else(|| {
+                let absolute = after.is_some() || before.is_some();
+                if absolute { "all" } else { "30d" }.to_string()
+            });
+            let (after, before) = (after.as_deref(), before.as_deref());
+            if let Some(command) = command {
+                return shell_sync_client::commands::show_command_stats(
+                    &command,
+                    &last,
+                    after,
+                    before,
+                    machine,
+                    group,
+                    directory,
+                    json,
+                    output.as_deref(),
+                );
+            }
+            let scale = match scale {
+                cli::BarScale::Linear => shell_sync_client::commands::BarScale::Linear,
+                cli::BarScale::Log => shell_sync_client::commands::BarScale::Log,
+            };
+            shell_sync_client::commands::show_stats(
+                &last,
+                after,
+                before,
+                machine,
+                group,
+                directory,
+                json,
+                svg.as_deref(),
+                sessions,
+                normalize,
+                scale,
+                output.as_deref(),
+            )?;
         }
     }
 
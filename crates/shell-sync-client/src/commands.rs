@@ -1,5 +1,8 @@
 use shell_sync_core::config::{load_client_config, pid_file_path, ClientConfig};
 use shell_sync_core::models::Alias;
+use shell_sync_core::protocol::{features, ServerVersion};
+use shell_sync_core::tz::DisplayTz;
+use std::collections::BTreeMap;
 
 fn client_and_config() -> anyhow::Result<(reqwest::Client, ClientConfig)> {
     let config = load_client_config()?;
@@ -10,14 +13,110 @@ fn auth_header(config: &ClientConfig) -> String {
     format!("Bearer {}", config.auth_token)
 }
 
-/// `shell-sync add <name> <command> --group <group>`
-pub async fn add_alias(name: &str, command: &str, group: &str) -> anyhow::Result<()> {
+/// `<server>/api/<segments...>`, with each segment encoded on its own so
+/// user-supplied names cannot change the path.
+fn api_url<'a>(
+    server_url: &str,
+    segments: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<reqwest::Url> {
+    let mut url = reqwest::Url::parse(server_url)
+        .map_err(|e| anyhow::anyhow!("Invalid server URL '{server_url}': {e}"))?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("Invalid server URL '{server_url}'"))?
+        .pop_if_empty()
+        .push("api")
+        .extend(segments);
+    Ok(url)
+}
+
+/// `<server>/api/aliases/name/<name>[/<action>]?group=<group>`, with the
+/// alias name encoded as a single path segment.
+pub(crate) fn alias_name_url(
+    server_url: &str,
+    name: &str,
+    action: Option<&str>,
+    group: &str,
+) -> anyhow::Result<reqwest::Url> {
+    let mut url = api_url(
+        server_url,
+        ["aliases", "name", name].into_iter().chain(action),
+    )?;
+    url.query_pairs_mut().append_pair("group", group);
+    Ok(url)
+}
+
+/// `GET /api/version`, or `None` from a server that predates it.
+pub async fn fetch_server_version(
+    client: &reqwest::Client,
+    config: &ClientConfig,
+) -> anyhow::Result<Option<ServerVersion>> {
+    let resp = client
+        .get(format!("{}/api/version", config.server_url))
+        .send()
+        .await?;
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        anyhow::bail!("Failed to fetch server version (HTTP {})", resp.status());
+    }
+    Ok(Some(resp.json().await?))
+}
+
+/// Fail before `what` if the server answers but does not list `feature`.
+/// An unreachable server passes; the request itself reports that. Any other
+/// failure to read the feature list fails.
+async fn require_feature(
+    client: &reqwest::Client,
+    config: &ClientConfig,
+    feature: &str,
+    what: &str,
+) -> anyhow::Result<()> {
+    match fetch_server_version(client, config).await {
+        Ok(Some(version)) if version.supports(feature) => Ok(()),
+        Ok(Some(version)) => anyhow::bail!(
+            "The server (version {}) does not support {}",
+            version.version,
+            what
+        ),
+        Ok(None) => anyhow::bail!("The server is too old to support {}", what),
+        Err(e) if is_unreachable(&e) => Ok(()),
+        Err(e) => anyhow::bail!("Failed to check server support for {what}: {e}"),
+    }
+}
+
+/// Whether `e` is a failure to reach the server at all, as opposed to an
+/// answer that could not be used.
+fn is_unreachable(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+/// `shell-sync add <name> <command> --group <group> [--expand] [--template] [--shell S,...]`
+pub async fn add_alias(
+    name: &str,
+    command: &str,
+    group: &str,
+    expand: bool,
+    template: bool,
+    shells: &[String],
+) -> anyhow::Result<()> {
     let (client, config) = client_and_config()?;
+    if !shells.is_empty() {
+        require_feature(&client, &config, features::ALIAS_SHELLS, "--shell").await?;
+    }
 
     let resp = client
         .post(format!("{}/api/aliases", config.server_url))
         .header("Authorization", auth_header(&config))
-        .json(&serde_json::json!({ "name": name, "command": command, "group": group }))
+        .json(&serde_json::json!({
+            "name": name,
+            "command": command,
+            "group": group,
+            "expand": expand,
+            "template": template,
+            "shells": shells,
+        }))
         .send()
         .await;
 
@@ -34,7 +133,15 @@ pub async fn add_alias(name: &str, command: &str, group: &str) -> anyhow::Result
             // Offline — queue it
             crate::offline::queue_operation(
                 "add",
-                &serde_json::json!({ "name": name, "command": command, "group": group }),
+                &serde_json::json!({
+                    "name": name,
+                    "command": command,
+                    "group": group,
+                    "expand": expand,
+                    "template": template,
+                    "shells": shells,
+                    "created_at": chrono::Utc::now().timestamp_millis(),
+                }),
             )?;
             println!("Server unreachable — queued for offline sync");
         }
@@ -48,10 +155,7 @@ pub async fn remove_alias(name: &str, group: &str) -> anyhow::Result<()> {
     let (client, config) = client_and_config()?;
 
     let resp = client
-        .delete(format!(
-            "{}/api/aliases/name/{}?group={}",
-            config.server_url, name, group
-        ))
+        .delete(alias_name_url(&config.server_url, name, None, group)?)
         .header("Authorization", auth_header(&config))
         .send()
         .await;
@@ -77,76 +181,263 @@ pub async fn remove_alias(name: &str, group: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// `shell-sync ls [--group X] [--format table|json]`
-pub async fn list_aliases(group: Option<&str>, json_format: bool) -> anyhow::Result<()> {
+/// Alias totals shown under `ls` output.
+#[derive(Debug, Default, serde::Serialize, PartialEq)]
+struct AliasSummary {
+    total: usize,
+    groups: std::collections::BTreeMap<String, usize>,
+}
+
+fn summarize_aliases(aliases: &[&Alias]) -> AliasSummary {
+    let mut summary = AliasSummary::default();
+    for a in aliases {
+        summary.total += 1;
+        *summary.groups.entry(a.group_name.clone()).or_default() += 1;
+    }
+    summary
+}
+
+fn print_alias_summary(summary: &AliasSummary) {
+    println!("Total: {} alias(es)", summary.total);
+    for (group, count) in &summary.groups {
+        println!("  {group}: {count}");
+    }
+}
+
+/// Aliases in `group` (or all), pinned ones first; otherwise in server order.
+fn select_for_listing<'a>(aliases: &'a [Alias], group: Option<&str>) -> Vec<&'a Alias> {
+    let mut selected: Vec<&Alias> = aliases
+        .iter()
+        .filter(|a| group.is_none_or(|g| a.group_name == g))
+        .collect();
+    selected.sort_by_key(|a| !a.pinned);
+    selected
+}
+
+/// Order of `shell-sync ls`; pinned aliases come first either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AliasSort {
+    #[default]
+    Name,
+    /// Most recently updated first, sorted by the server.
+    Recent,
+    /// Most used first, by how often the alias starts a command in local history.
+    Usage,
+}
+
+/// Order `aliases` by `uses` (keyed by alias name), most used first; ties keep
+/// their order.
+fn sort_by_usage(aliases: &mut [Alias], uses: &std::collections::HashMap<String, i64>) {
+    aliases.sort_by_key(|a| std::cmp::Reverse(uses.get(&a.name).copied().unwrap_or(0)));
+}
+
+/// `shell-sync ls [--group X] [--format table|json] [--sort name|recent|usage]`
+pub async fn list_aliases(
+    group: Option<&str>,
+    json_format: bool,
+    count_only: bool,
+    sort: AliasSort,
+) -> anyhow::Result<()> {
     let (client, config) = client_and_config()?;
 
-    let resp = client
+    let mut request = client
         .get(format!("{}/api/aliases", config.server_url))
-        .header("Authorization", auth_header(&config))
-        .send()
-        .await?;
+        .header("Authorization", auth_header(&config));
+    if sort == AliasSort::Recent {
+        request = request.query(&[("sort", "recent")]);
+    }
+    let resp = request.send().await?;
 
     if !resp.status().is_success() {
         anyhow::bail!("Failed to fetch aliases (HTTP {})", resp.status());
     }
 
     let data: serde_json::Value = resp.json().await?;
-    let aliases: Vec<Alias> = serde_json::from_value(data["aliases"].clone()).unwrap_or_default();
+    let mut aliases: Vec<Alias> =
+        serde_json::from_value(data["aliases"].clone()).unwrap_or_default();
+
+    if sort == AliasSort::Usage {
+        let db_path = shell_sync_core::config::history_db_path();
+        if !db_path.exists() {
+            anyhow::bail!(
+                "No history database found at {}. Run the daemon first.",
+                db_path.display()
+            );
+        }
+        let db = shell_sync_core::db::SyncDatabase::open(db_path.to_str().unwrap_or("history.db"))?;
+        sort_by_usage(&mut aliases, &db.command_head_counts()?);
+    }
 
-    let filtered: Vec<&Alias> = if let Some(g) = group {
-        aliases.iter().filter(|a| a.group_name == g).collect()
-    } else {
-        aliases.iter().collect()
-    };
+    let filtered = select_for_listing(&aliases, group);
 
-    if json_format {
+    let summary = summarize_aliases(&filtered);
+
+    // JSON output stays a bare array of aliases; counts only with --count
+    if json_format && count_only {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else if json_format {
         println!("{}", serde_json::to_string_pretty(&filtered)?);
+    } else if count_only {
+        print_alias_summary(&summary);
     } else {
         if filtered.is_empty() {
             println!("No aliases found");
             return Ok(());
         }
 
-        let mut table = comfy_table::Table::new();
-        table.set_header(vec!["Name", "Command", "Group", "Version"]);
+        let mut table = new_table();
+        table.set_header(vec!["Name", "Command", "Group", "Version", "Pinned"]);
         for a in &filtered {
             table.add_row(vec![
-                &a.name,
+                a.name.as_str(),
                 &a.command,
                 &a.group_name,
                 &a.version.to_string(),
+                if a.pinned { "yes" } else { "" },
             ]);
         }
         println!("{table}");
+        print_alias_summary(&summary);
     }
 
     Ok(())
 }
 
-/// `shell-sync update <name> <command> --group <group>`
-pub async fn update_alias(name: &str, command: &str, group: &str) -> anyhow::Result<()> {
+/// `shell-sync pin|unpin <name> --group <group>`
+pub async fn set_pinned(name: &str, group: &str, pinned: bool) -> anyhow::Result<()> {
     let (client, config) = client_and_config()?;
 
-    // First find the alias by name to get its ID
-    let resp = client
-        .get(format!("{}/api/aliases", config.server_url))
+    let url = alias_name_url(&config.server_url, name, Some("pin"), group)?;
+    let request = if pinned {
+        client.post(url)
+    } else {
+        client.delete(url)
+    };
+    let resp = request
         .header("Authorization", auth_header(&config))
         .send()
         .await?;
 
-    let data: serde_json::Value = resp.json().await?;
-    let aliases: Vec<Alias> = serde_json::from_value(data["aliases"].clone()).unwrap_or_default();
+    if !resp.status().is_success() {
+        let body: serde_json::Value = resp.json().await.unwrap_or_default();
+        let msg = body["error"].as_str().unwrap_or("Unknown error");
+        anyhow::bail!("Failed: {}", msg);
+    }
 
-    let alias = aliases
-        .iter()
-        .find(|a| a.name == name && a.group_name == group)
+    println!(
+        "Alias '{}' {}",
+        name,
+        if pinned { "pinned" } else { "unpinned" }
+    );
+    Ok(())
+}
+
+/// `shell-sync show <name> --group <group> [--json]`
+pub async fn show_alias(name: &str, group: &str, json: bool) -> anyhow::Result<()> {
+    let (client, config) = client_and_config()?;
+    let out = show_alias_with(&client, &config, name, group, json, DisplayTz::from_env()).await?;
+    print!("{out}");
+    Ok(())
+}
+
+async fn show_alias_with(
+    client: &reqwest::Client,
+    config: &ClientConfig,
+    name: &str,
+    group: &str,
+    json: bool,
+    tz: DisplayTz,
+) -> anyhow::Result<String> {
+    let alias = fetch_alias_by_name(client, config, name, group)
+        .await?
         .ok_or_else(|| anyhow::anyhow!("Alias '{}' not found in group '{}'", name, group))?;
+    if json {
+        return Ok(format!("{}\n", serde_json::to_string_pretty(&alias)?));
+    }
+    Ok(render_alias_details(&alias, tz))
+}
+
+/// Detailed view of a single alias for `show`.
+fn render_alias_details(alias: &Alias, tz: DisplayTz) -> String {
+    let fmt = "%Y-%m-%d %H:%M:%S";
+    format!(
+        "Name:       {}\n\
+         Command:    {}\n\
+         Group:      {}\n\
+         Shells:     {}\n\
+         Version:    {}\n\
+         Pinned:     {}\n\
+         Created by: {}\n\
+         Created:    {}\n\
+         Updated:    {}\n",
+        alias.name,
+        alias.command,
+        alias.group_name,
+        match alias.shells.is_empty() {
+            true => "all".to_string(),
+            false => alias.shells.join(", "),
+        },
+        alias.version,
+        if alias.pinned { "yes" } else { "no" },
+        alias.created_by_machine,
+        tz.format(alias.created_at, fmt),
+        tz.format(alias.updated_at, fmt),
+    )
+}
+
+/// `shell-sync update <name> <command> --group <group> [--[no-]expand] [--[no-]template]
+/// [--shell S,... | --all-shells]`
+///
+/// `expand`, `template` or `shells` of `None` leaves that setting as it was.
+pub async fn update_alias(
+    name: &str,
+    command: &str,
+    group: &str,
+    expand: Option<bool>,
+    template: Option<bool>,
+    shells: Option<Vec<String>>,
+) -> anyhow::Result<()> {
+    let (client, config) = client_and_config()?;
+    update_alias_with(&client, &config, name, command, group, expand, template, shells).await
+}
 
+#[allow(clippy::too_many_arguments)]
+async fn update_alias_with(
+    client: &reqwest::Client,
+    config: &ClientConfig,
+    name: &str,
+    command: &str,
+    group: &str,
+    expand: Option<bool>,
+    template: Option<bool>,
+    shells: Option<Vec<String>>,
+) -> anyhow::Result<()> {
+    if shells.is_some() {
+        require_feature(client, config, features::ALIAS_SHELLS, "--shell").await?;
+    }
+    let alias = fetch_alias_by_name(client, config, name, group)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Alias '{}' not found in group '{}'", name, group))?;
+
+    // The edit time lets a last-write-wins server order it against others
+    let mut body = serde_json::json!({
+        "command": command,
+        "expected_version": alias.version,
+        "updated_at": chrono::Utc::now().timestamp_millis(),
+    });
+    if let Some(expand) = expand {
+        body["expand"] = expand.into();
+    }
+    if let Some(template) = template {
+        body["template"] = template.into();
+    }
+    if let Some(shells) = shells {
+        body["shells"] = shells.into();
+    }
     let resp = client
         .put(format!("{}/api/aliases/{}", config.server_url, alias.id))
-        .header("Authorization", auth_header(&config))
-        .json(&serde_json::json!({ "command": command }))
+        .header("Authorization", auth_header(config))
+        .json(&body)
         .send()
         .await?;
 
@@ -160,8 +451,324 @@ pub async fn update_alias(name: &str, command: &str, group: &str) -> anyhow::Res
     Ok(())
 }
 
-/// `shell-sync import [--file path] --group <group> [--dry-run]`
-pub async fn import_aliases(file: Option<&str>, group: &str, dry_run: bool) -> anyhow::Result<()> {
+/// Look up a single alias by name within a group, or `None` if it doesn't exist.
+async fn fetch_alias_by_name(
+    client: &reqwest::Client,
+    config: &ClientConfig,
+    name: &str,
+    group: &str,
+) -> anyhow::Result<Option<Alias>> {
+    let resp = client
+        .get(alias_name_url(&config.server_url, name, None, group)?)
+        .header("Authorization", auth_header(config))
+        .send()
+        .await?;
+
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        let body: serde_json::Value = resp.json().await.unwrap_or_default();
+        anyhow::bail!("Failed: {}", body["error"].as_str().unwrap_or("Unknown error"));
+    }
+
+    let data: serde_json::Value = resp.json().await?;
+    Ok(Some(serde_json::from_value(data["alias"].clone())?))
+}
+
+/// A pending change to a single alias's command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AliasEdit {
+    pub id: i64,
+    pub name: String,
+    pub group_name: String,
+    pub old_command: String,
+    pub new_command: String,
+    /// The alias version the edit was planned against.
+    pub version: i64,
+}
+
+/// Compute the edits produced by replacing `find` with `replace` in every alias command.
+///
+/// With `regex` set, `find` is a regular expression and `replace` may reference
+/// capture groups (`$1`, `${name}`). Aliases whose command is unchanged are skipped.
+pub fn plan_alias_replacements(
+    aliases: &[Alias],
+    find: &str,
+    replace: &str,
+    group: Option<&str>,
+    regex: bool,
+) -> anyhow::Result<Vec<AliasEdit>> {
+    let pattern = if regex {
+        Some(
+            regex::Regex::new(find)
+                .map_err(|e| anyhow::anyhow!("Invalid regex '{}': {}", find, e))?,
+        )
+    } else {
+        None
+    };
+
+    let edits = aliases
+        .iter()
+        .filter(|a| group.is_none_or(|g| a.group_name == g))
+        .filter_map(|a| {
+            let new_command = match &pattern {
+                Some(re) => re.replace_all(&a.command, replace).into_owned(),
+                None => a.command.replace(find, replace),
+            };
+            (new_command != a.command).then(|| AliasEdit {
+                id: a.id,
+                name: a.name.clone(),
+                group_name: a.group_name.clone(),
+                old_command: a.command.clone(),
+                new_command,
+                version: a.version,
+            })
+        })
+        .collect();
+
+    Ok(edits)
+}
+
+/// `shell-sync alias sed <find> <replace> [--group g] [--regex] [--dry-run]`
+pub async fn sed_aliases(
+    find: &str,
+    replace: &str,
+    group: Option<&str>,
+    regex: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let (client, config) = client_and_config()?;
+    sed_aliases_with(&client, &config, find, replace, group, regex, dry_run).await
+}
+
+async fn sed_aliases_with(
+    client: &reqwest::Client,
+    config: &ClientConfig,
+    find: &str,
+    replace: &str,
+    group: Option<&str>,
+    regex: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    if find.is_empty() {
+        anyhow::bail!("Search pattern must not be empty");
+    }
+
+    let aliases = fetch_aliases(client, config).await?;
+    let edits = plan_alias_replacements(&aliases, find, replace, group, regex)?;
+
+    if edits.is_empty() {
+        println!("No aliases matched '{}'", find);
+        return Ok(());
+    }
+
+    for edit in &edits {
+        println!("{} ({})", edit.name, edit.group_name);
+        println!("  - {}", edit.old_command);
+        println!("  + {}", edit.new_command);
+    }
+    println!();
+
+    if dry_run {
+        println!("Would update {} aliases (dry run)", edits.len());
+        return Ok(());
+    }
+
+    let mut updated = 0;
+    let mut failed = 0;
+    for edit in &edits {
+        let resp = client
+            .put(format!("{}/api/aliases/{}", config.server_url, edit.id))
+            .header("Authorization", auth_header(config))
+            .json(&serde_json::json!({
+                "command": edit.new_command,
+                "expected_version": edit.version,
+                "updated_at": chrono::Utc::now().timestamp_millis(),
+            }))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            updated += 1;
+        } else {
+            let body: serde_json::Value = resp.json().await.unwrap_or_default();
+            println!(
+                "  Failed to update '{}': {}",
+                edit.name,
+                body["error"].as_str().unwrap_or("Unknown error")
+            );
+            failed += 1;
+        }
+    }
+
+    println!("Updated {} aliases", updated);
+    if failed > 0 {
+        println!("Failed:  {}", failed);
+    }
+
+    Ok(())
+}
+
+/// Fetch all aliases visible to this machine.
+async fn fetch_aliases(client: &reqwest::Client, config: &ClientConfig) -> anyhow::Result<Vec<Alias>> {
+    let resp = client
+        .get(format!("{}/api/aliases", config.server_url))
+        .header("Authorization", auth_header(config))
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        anyhow::bail!("Failed to fetch aliases (HTTP {})", resp.status());
+    }
+
+    let data: serde_json::Value = resp.json().await?;
+    Ok(serde_json::from_value(data["aliases"].clone()).unwrap_or_default())
+}
+
+/// Input format for `shell-sync import`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImportFormat {
+    /// Pick from the file extension, else sniff the content (JSON or shell).
+    #[default]
+    Auto,
+    /// `alias name='command'` / `name=command` lines.
+    Shell,
+    /// A JSON array of `{name, command, group}` or a map of name to command.
+    Json,
+    /// The same shapes as JSON, written in YAML.
+    Yaml,
+}
+
+impl std::str::FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "shell" | "sh" => Ok(Self::Shell),
+            "json" => Ok(Self::Json),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            _ => Err(format!("unknown format '{}': expected auto, shell, json or yaml", s)),
+        }
+    }
+}
+
+impl ImportFormat {
+    /// Resolve `Auto` using the file name, then the content.
+    fn detect(self, path: Option<&str>, content: &str) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+        let ext = path
+            .and_then(|p| std::path::Path::new(p).extension())
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match ext.as_deref() {
+            Some("json") => Self::Json,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ if content.trim_start().starts_with(['[', '{']) => Self::Json,
+            _ => Self::Shell,
+        }
+    }
+}
+
+/// One alias to import, with its target group resolved.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ImportEntry {
+    pub name: String,
+    pub command: String,
+    pub group: String,
+}
+
+/// Structured import files: a list of entries, or a map keyed by alias name
+/// whose values are a command or `{command, group}`.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum StructuredAliases {
+    List(Vec<StructuredAlias>),
+    Map(BTreeMap<String, StructuredValue>),
+}
+
+#[derive(serde::Deserialize)]
+struct StructuredAlias {
+    name: String,
+    command: String,
+    #[serde(default)]
+    group: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum StructuredValue {
+    Command(String),
+    Full {
+        command: String,
+        #[serde(default)]
+        group: Option<String>,
+    },
+}
+
+impl StructuredAliases {
+    fn into_entries(self, default_group: &str) -> Vec<ImportEntry> {
+        let entry = |name: String, command: String, group: Option<String>| ImportEntry {
+            name,
+            command,
+            group: group.unwrap_or_else(|| default_group.to_string()),
+        };
+        match self {
+            Self::List(list) => list
+                .into_iter()
+                .map(|a| entry(a.name, a.command, a.group))
+                .collect(),
+            Self::Map(map) => map
+                .into_iter()
+                .map(|(name, value)| match value {
+                    StructuredValue::Command(command) => entry(name, command, None),
+                    StructuredValue::Full { command, group } => entry(name, command, group),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Parse an import file. Entries without their own group go to `default_group`.
+pub fn parse_import(
+    content: &str,
+    format: ImportFormat,
+    default_group: &str,
+) -> anyhow::Result<Vec<ImportEntry>> {
+    let parsed: StructuredAliases = match format {
+        ImportFormat::Auto | ImportFormat::Shell => {
+            return Ok(parse_shell_aliases(content, default_group));
+        }
+        ImportFormat::Json => serde_json::from_str(content)
+            .map_err(|e| anyhow::anyhow!("Invalid JSON alias file: {}", e))?,
+        ImportFormat::Yaml => serde_yaml::from_str(content)
+            .map_err(|e| anyhow::anyhow!("Invalid YAML alias file: {}", e))?,
+    };
+    Ok(parsed.into_entries(default_group))
+}
+
+/// Parse alias lines (`alias name='command'`, `name=command`, fish `alias name 'command'`).
+fn parse_shell_aliases(content: &str, group: &str) -> Vec<ImportEntry> {
+    shell_sync_core::shell::parse_alias_file(content)
+        .into_iter()
+        .map(|(name, command)| ImportEntry {
+            name,
+            command,
+            group: group.to_string(),
+        })
+        .collect()
+}
+
+/// `shell-sync import [--file path] [--format auto|shell|json|yaml] --group <group> [--dry-run]`
+pub async fn import_aliases(
+    file: Option<&str>,
+    format: ImportFormat,
+    group: &str,
+    dry_run: bool,
+) -> anyhow::Result<()> {
     let content = match file {
         Some(path) => std::fs::read_to_string(path)?,
         None => {
@@ -173,52 +780,435 @@ pub async fn import_aliases(file: Option<&str>, group: &str, dry_run: bool) -> a
         }
     };
 
-    // Parse alias lines: `alias name='command'` or `name=command` or `name command`
-    let mut aliases = Vec::new();
-    for line in content.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
+    let entries = parse_import(&content, format.detect(file, &content), group)?;
+
+    // The server imports into one group per request.
+    let mut by_group: BTreeMap<&str, Vec<serde_json::Value>> = BTreeMap::new();
+    for e in &entries {
+        by_group
+            .entry(e.group.as_str())
+            .or_default()
+            .push(serde_json::json!({ "name": e.name, "command": e.command }));
+    }
+
+    if dry_run {
+        println!("Would import {} aliases:", entries.len());
+        for e in &entries {
+            println!("  [{}] {} = {}", e.group, e.name, e.command);
+        }
+        // Collisions and rejections depend on what the server already has.
+        let Ok((client, config)) = client_and_config() else {
+            println!("Not registered with a server; existing names were not checked.");
+            return Ok(());
+        };
+        let version = fetch_server_version(&client, &config).await?;
+        if !version.is_some_and(|v| v.supports(features::IMPORT_DRY_RUN)) {
+            println!("The server cannot preview imports; existing names were not checked.");
+            return Ok(());
+        }
+        let (mut would_add, mut would_fail) = (0, 0);
+        for (group, aliases) in &by_group {
+            let data: serde_json::Value = client
+                .post(format!("{}/api/import?dry_run=true", config.server_url))
+                .header("Authorization", auth_header(&config))
+                .json(&serde_json::json!({ "aliases": aliases, "group": group }))
+                .send()
+                .await?
+                .json()
+                .await?;
+            if let Some(error) = data["error"].as_str() {
+                eprintln!("Group '{}': {}", group, error);
+                would_fail += aliases.len() as i64;
+                continue;
+            }
+            would_add += data["added"].as_i64().unwrap_or(0);
+            would_fail += data["failed"].as_i64().unwrap_or(0);
+            for failure in data["results"]["failed"].as_array().into_iter().flatten() {
+                println!(
+                    "  [{}] {}: {}",
+                    group,
+                    failure["name"].as_str().unwrap_or("?"),
+                    failure["error"].as_str().unwrap_or("rejected")
+                );
+            }
+        }
+        println!("Server check: {} would be added, {} would fail", would_add, would_fail);
+        return Ok(());
+    }
+
+    let (client, config) = client_and_config()?;
+
+    let (mut added, mut failed) = (0, 0);
+    for (group, aliases) in &by_group {
+        let resp = client
+            .post(format!("{}/api/import", config.server_url))
+            .header("Authorization", auth_header(&config))
+            .json(&serde_json::json!({ "aliases": aliases, "group": group }))
+            .send()
+            .await?;
+
+        let data: serde_json::Value = resp.json().await?;
+        if let Some(error) = data["error"].as_str() {
+            eprintln!("Group '{}': {}", group, error);
+            failed += aliases.len() as i64;
             continue;
         }
+        added += data["added"].as_i64().unwrap_or(0);
+        failed += data["failed"].as_i64().unwrap_or(0);
+    }
+    println!("Import completed: {} added, {} failed", added, failed);
+
+    Ok(())
+}
 
-        let line = line.strip_prefix("alias ").unwrap_or(line);
+/// How the local alias file differs from the aliases on the server.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AliasDiff {
+    /// In the local file only, as `(name, command)`.
+    pub added: Vec<(String, String)>,
+    /// On the server only, as `(name, command)`.
+    pub removed: Vec<(String, String)>,
+    /// In both with different commands, as `(name, server command, local command)`.
+    pub changed: Vec<(String, String, String)>,
+}
 
-        if let Some((name, cmd)) = line.split_once('=') {
-            let name = name.trim();
-            let cmd = cmd.trim().trim_matches('\'').trim_matches('"');
-            aliases.push(serde_json::json!({ "name": name, "command": cmd }));
+impl AliasDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare parsed alias-file lines with the server's aliases. When a name
+/// appears more than once, the last definition wins, as it would in the shell.
+pub fn diff_aliases(local: &[(String, String)], server: &[Alias]) -> AliasDiff {
+    let local: BTreeMap<&str, &str> = local
+        .iter()
+        .map(|(n, c)| (n.as_str(), c.as_str()))
+        .collect();
+    let server: BTreeMap<&str, &str> = server
+        .iter()
+        .map(|a| (a.name.as_str(), a.command.as_str()))
+        .collect();
+
+    let mut diff = AliasDiff::default();
+    for (name, local_cmd) in &local {
+        match server.get(name) {
+            None => diff.added.push((name.to_string(), local_cmd.to_string())),
+            Some(server_cmd) if server_cmd != local_cmd => diff.changed.push((
+                name.to_string(),
+                server_cmd.to_string(),
+                local_cmd.to_string(),
+            )),
+            Some(_) => {}
         }
     }
+    for (name, server_cmd) in &server {
+        if !local.contains_key(name) {
+            diff.removed.push((name.to_string(), server_cmd.to_string()));
+        }
+    }
+    diff
+}
 
-    if dry_run {
-        println!("Would import {} aliases to group '{}':", aliases.len(), group);
-        for a in &aliases {
-            println!("  {} = {}", a["name"].as_str().unwrap_or(""), a["command"].as_str().unwrap_or(""));
+/// What `shell-sync __complete` lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// This machine's groups.
+    Groups,
+    /// Aliases in the local alias file(s).
+    Aliases,
+}
+
+/// Candidates for shell completion, one per line, sorted. Read from local
+/// files only so pressing Tab never waits on the server.
+pub fn completion_candidates(
+    kind: CompletionKind,
+    shell: shell_sync_core::shell::ShellType,
+    config: &ClientConfig,
+) -> Vec<String> {
+    let mut candidates: Vec<String> = match kind {
+        CompletionKind::Groups => config.groups.clone(),
+        CompletionKind::Aliases => crate::shell_writer::alias_files(shell, config)
+            .1
+            .iter()
+            .filter_map(|file| std::fs::read_to_string(file).ok())
+            .flat_map(|content| crate::shell_writer::alias_names_in_file(&content))
+            .collect(),
+    };
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// `shell-sync __complete groups|aliases`, called by the completion scripts.
+/// Prints nothing rather than failing when the machine is not set up.
+pub fn complete(kind: CompletionKind) {
+    let Ok(config) = load_client_config() else {
+        return;
+    };
+    let shell = shell_sync_core::shell::detect_shell();
+    for candidate in completion_candidates(kind, shell, &config) {
+        println!("{candidate}");
+    }
+}
+
+/// `shell-sync alias diff`
+pub async fn diff_alias_file() -> anyhow::Result<()> {
+    use shell_sync_core::shell::{detect_shell, parse_alias_file};
+
+    let (client, config) = client_and_config()?;
+    let (path, files) = crate::shell_writer::alias_files(detect_shell(), &config);
+    let mut content = String::new();
+    for file in &files {
+        match std::fs::read_to_string(file) {
+            Ok(c) => content.push_str(&c),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
         }
+    }
+    let local = parse_alias_file(&content);
+
+    let server = fetch_aliases(&client, &config).await?;
+
+    // The file holds one definition per name, so compare against the same winners.
+    let server: Vec<Alias> = crate::shell_writer::resolve_precedence(&server, &config.groups)
+        .active
+        .into_iter()
+        .cloned()
+        .collect();
+    let diff = diff_aliases(&local, &server);
+    if diff.is_empty() {
+        println!("{} matches the server ({} aliases)", path.display(), local.len());
         return Ok(());
     }
 
+    println!("Differences between {} and the server:", path.display());
+    for (name, command) in &diff.added {
+        println!("  + {} = {}  (local only)", name, command);
+    }
+    for (name, command) in &diff.removed {
+        println!("  - {} = {}  (server only)", name, command);
+    }
+    for (name, server_cmd, local_cmd) in &diff.changed {
+        println!("  ~ {}: server '{}' -> local '{}'", name, server_cmd, local_cmd);
+    }
+    println!("Run `shell-sync sync` to overwrite the local file with the server's aliases.");
+
+    Ok(())
+}
+
+/// `shell-sync alias which <name>`: show what an alias would run, without running it.
+pub async fn alias_which(name: &str, group: Option<&str>) -> anyhow::Result<()> {
+    use shell_sync_core::shell::{resolve_command, CommandTarget};
+
+    let (client, config) = client_and_config()?;
+    let aliases = fetch_aliases(&client, &config).await?;
+    let matches: Vec<&Alias> = aliases
+        .iter()
+        .filter(|a| a.name == name && group.is_none_or(|g| a.group_name == g))
+        .collect();
+    if matches.is_empty() {
+        anyhow::bail!("Alias '{}' not found", name);
+    }
+
+    let path = std::env::var_os("PATH");
+    for alias in matches {
+        // Resolve against the other aliases in the same group, as a shell would see them.
+        let others: std::collections::HashMap<String, String> = aliases
+            .iter()
+            .filter(|a| a.group_name == alias.group_name && a.name != alias.name)
+            .map(|a| (a.name.clone(), a.command.clone()))
+            .collect();
+        println!("{} = {}  [{}]", alias.name, alias.command, alias.group_name);
+
+        let resolution = resolve_command(&alias.command, &others, path.as_deref());
+        for (inner, command) in &resolution.chain {
+            println!("  {} is an alias for {}", inner, command);
+        }
+        match resolution.target {
+            CommandTarget::Executable(bin) => println!("  runs {}", bin.display()),
+            CommandTarget::Builtin(word) => println!("  {} is a shell builtin", word),
+            CommandTarget::NotFound(word) if word.is_empty() => println!("  (empty command)"),
+            CommandTarget::NotFound(word) => println!("  {}: not found on PATH", word),
+        }
+    }
+    Ok(())
+}
+
+/// Whether an alias's command resolves to something runnable.
+#[derive(Debug, serde::Serialize)]
+pub struct AliasCheck {
+    pub name: String,
+    pub group: String,
+    pub command: String,
+    /// The binary path or builtin the command resolves to, or the missing word.
+    pub target: String,
+    pub ok: bool,
+}
+
+/// Resolve every alias (optionally only in `group`) against `path`, following
+/// other aliases in the same group as a shell would.
+fn verify_alias_targets(
+    aliases: &[Alias],
+    group: Option<&str>,
+    path: Option<&std::ffi::OsStr>,
+) -> Vec<AliasCheck> {
+    use shell_sync_core::shell::{resolve_command, CommandTarget};
+
+    aliases
+        .iter()
+        .filter(|a| group.is_none_or(|g| a.group_name == g))
+        .map(|alias| {
+            let others: std::collections::HashMap<String, String> = aliases
+                .iter()
+                .filter(|a| a.group_name == alias.group_name && a.name != alias.name)
+                .map(|a| (a.name.clone(), a.command.clone()))
+                .collect();
+            let (target, ok) = match resolve_command(&alias.command, &others, path).target {
+                CommandTarget::Executable(bin) => (bin.display().to_string(), true),
+                CommandTarget::Builtin(word) => (format!("{word} (builtin)"), true),
+                CommandTarget::NotFound(word) => (word, false),
+            };
+            AliasCheck {
+                name: alias.name.clone(),
+                group: alias.group_name.clone(),
+                command: alias.command.clone(),
+                target,
+                ok,
+            }
+        })
+        .collect()
+}
+
+/// `shell-sync alias verify [--group <group>] [--json]`
+pub async fn verify_aliases(group: Option<&str>, json: bool) -> anyhow::Result<()> {
+    let (client, config) = client_and_config()?;
+    let aliases = fetch_aliases(&client, &config).await?;
+    let checks = verify_alias_targets(&aliases, group, std::env::var_os("PATH").as_deref());
+    let missing = checks.iter().filter(|c| !c.ok).count();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&checks)?);
+        return Ok(());
+    }
+    if checks.is_empty() {
+        println!("No aliases found");
+        return Ok(());
+    }
+
+    let mut table = new_table();
+    table.set_header(vec!["Name", "Group", "Target", "Status"]);
+    for c in &checks {
+        table.add_row(vec![
+            c.name.as_str(),
+            &c.group,
+            &c.target,
+            if c.ok { "OK" } else { "MISSING" },
+        ]);
+    }
+    println!("{table}");
+    println!("{} aliases checked, {} missing", checks.len(), missing);
+    Ok(())
+}
+
+/// `shell-sync alias lint [--group <group>] [--json]`
+pub async fn lint_aliases(group: Option<&str>, json: bool) -> anyhow::Result<()> {
+    let (client, config) = client_and_config()?;
+    let aliases = fetch_aliases(&client, &config).await?;
+    let findings = shell_sync_core::lint::lint_aliases(
+        &aliases,
+        group,
+        std::env::var_os("PATH").as_deref(),
+    );
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&findings)?);
+        return Ok(());
+    }
+    if findings.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+
+    let mut table = new_table();
+    table.set_header(vec!["Severity", "Name", "Group", "Problem"]);
+    for f in &findings {
+        table.add_row(vec![f.severity.label(), &f.name, &f.group, &f.message]);
+    }
+    println!("{table}");
+    println!("{} problems found", findings.len());
+    Ok(())
+}
+
+/// `shell-sync alias orphans [--json]`
+pub async fn list_orphaned_aliases(json: bool) -> anyhow::Result<()> {
     let (client, config) = client_and_config()?;
 
     let resp = client
-        .post(format!("{}/api/import", config.server_url))
+        .get(format!("{}/api/aliases/orphans", config.server_url))
         .header("Authorization", auth_header(&config))
-        .json(&serde_json::json!({ "aliases": aliases, "group": group }))
         .send()
         .await?;
-
+    if !resp.status().is_success() {
+        anyhow::bail!("Failed to fetch orphaned aliases (HTTP {})", resp.status());
+    }
     let data: serde_json::Value = resp.json().await?;
+    let groups: BTreeMap<String, usize> =
+        serde_json::from_value(data["groups"].clone()).unwrap_or_default();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&groups)?);
+        return Ok(());
+    }
+    if groups.is_empty() {
+        println!("No orphaned aliases");
+        return Ok(());
+    }
+
+    let mut table = new_table();
+    table.set_header(vec!["Group", "Aliases"]);
+    for (group, count) in &groups {
+        table.add_row(vec![group.clone(), count.to_string()]);
+    }
+    println!("{table}");
     println!(
-        "Import completed: {} added, {} failed",
-        data["added"].as_i64().unwrap_or(0),
-        data["failed"].as_i64().unwrap_or(0)
+        "{} aliases are in groups no machine belongs to. Move them with: \
+         shell-sync alias reassign --from <group> --to <group>",
+        groups.values().sum::<usize>()
     );
+    Ok(())
+}
+
+/// `shell-sync alias reassign --from <group> --to <group>`
+pub async fn reassign_aliases(from: &str, to: &str) -> anyhow::Result<()> {
+    let (client, config) = client_and_config()?;
+
+    let resp = client
+        .post(format!("{}/api/aliases/reassign", config.server_url))
+        .header("Authorization", auth_header(&config))
+        .json(&serde_json::json!({ "from": from, "to": to }))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        let body: serde_json::Value = resp.json().await.unwrap_or_default();
+        let msg = body["error"].as_str().unwrap_or("Unknown error");
+        anyhow::bail!("Failed: {}", msg);
+    }
 
+    let outcome: shell_sync_core::models::AliasReassignment = resp.json().await?;
+    println!("Moved {} aliases from '{}' to '{}'", outcome.moved.len(), from, to);
+    if !outcome.skipped.is_empty() {
+        println!(
+            "Skipped (already in '{}'): {}",
+            to,
+            outcome.skipped.join(", ")
+        );
+    }
     Ok(())
 }
 
-/// `shell-sync export`
-pub async fn export_aliases() -> anyhow::Result<()> {
+/// `shell-sync export [--eval] [--created-by MACHINE_ID]`
+pub async fn export_aliases(eval: bool, created_by: Option<&str>) -> anyhow::Result<()> {
     let (client, config) = client_and_config()?;
 
     let resp = client
@@ -228,16 +1218,36 @@ pub async fn export_aliases() -> anyhow::Result<()> {
         .await?;
 
     let data: serde_json::Value = resp.json().await?;
-    let aliases: Vec<Alias> = serde_json::from_value(data["aliases"].clone()).unwrap_or_default();
+    let mut aliases: Vec<Alias> =
+        serde_json::from_value(data["aliases"].clone()).unwrap_or_default();
+    if let Some(machine) = created_by {
+        retain_created_by(&mut aliases, machine, &config);
+    }
+    let aliases = crate::shell_writer::honor_expand(&aliases, &config);
+
+    if eval {
+        let shell = shell_sync_core::shell::detect_shell();
+        print!("{}", crate::shell_writer::eval_script(shell, &aliases, &config.groups));
+        return Ok(());
+    }
 
     for a in &aliases {
-        let escaped = a.command.replace('\'', "'\\''");
-        println!("alias {}='{}'", a.name, escaped);
+        println!("{}", shell_sync_core::shell::ShellType::Bash.format_synced_alias(a));
     }
 
     Ok(())
 }
 
+/// Keep only aliases whose `created_by_machine` is `machine`; `self` means
+/// this machine's id.
+pub fn retain_created_by(aliases: &mut Vec<Alias>, machine: &str, config: &ClientConfig) {
+    let machine = match machine {
+        "self" => config.machine_id.as_str(),
+        other => other,
+    };
+    aliases.retain(|a| a.created_by_machine == machine);
+}
+
 /// `shell-sync sync`
 pub async fn force_sync() -> anyhow::Result<()> {
     let (client, config) = client_and_config()?;
@@ -257,16 +1267,133 @@ pub async fn force_sync() -> anyhow::Result<()> {
     let data: serde_json::Value = resp.json().await?;
     let aliases: Vec<Alias> = serde_json::from_value(data["aliases"].clone()).unwrap_or_default();
 
-    crate::shell_writer::apply_aliases(&aliases)?;
+    crate::shell_writer::apply_aliases(&aliases, &config)?;
     println!("Synced {} aliases", aliases.len());
+    println!(
+        "To load them into this shell now, run: {}",
+        shell_sync_core::shell::detect_shell().eval_hint()
+    );
 
     Ok(())
 }
 
-/// `shell-sync status`
-pub fn status() -> anyhow::Result<()> {
+/// How long `shell-sync ping` waits on each request.
+const PING_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// What `shell-sync ping` found out about the server.
+#[derive(Debug)]
+pub struct PingReport {
+    /// Round trip of each successful `GET /api/health`, in order.
+    pub latencies: Vec<std::time::Duration>,
+    /// Why the server could not be reached or failed its health check.
+    pub error: Option<String>,
+    /// Status of an authenticated request, once the server was reached.
+    pub auth_status: Option<reqwest::StatusCode>,
+}
+
+impl PingReport {
+    /// Whether the server accepted this machine's token.
+    pub fn authorized(&self) -> bool {
+        self.auth_status.is_some_and(|s| s.is_success())
+    }
+}
+
+/// Time `count` health checks against the server, then make one
+/// authenticated request to check this machine's token.
+pub async fn ping_server(
+    client: &reqwest::Client,
+    config: &ClientConfig,
+    count: u32,
+) -> PingReport {
+    let mut report = PingReport {
+        latencies: Vec::new(),
+        error: None,
+        auth_status: None,
+    };
+    for _ in 0..count.max(1) {
+        let started = std::time::Instant::now();
+        match client
+            .get(format!("{}/api/health", config.server_url))
+            .send()
+            .await
+        {
+            Ok(resp) if resp.status().is_success() => report.latencies.push(started.elapsed()),
+            Ok(resp) => {
+                report.error = Some(format!("health check returned HTTP {}", resp.status()));
+                return report;
+            }
+            Err(e) => {
+                report.error = Some(e.to_string());
+                return report;
+            }
+        }
+    }
+    match client
+        .get(format!("{}/api/groups", config.server_url))
+        .header("Authorization", auth_header(config))
+        .send()
+        .await
+    {
+        Ok(resp) => report.auth_status = Some(resp.status()),
+        Err(e) => report.error = Some(e.to_string()),
+    }
+    report
+}
+
+fn format_latency(d: std::time::Duration) -> String {
+    format!("{:.1}ms", d.as_secs_f64() * 1000.0)
+}
+
+/// `shell-sync ping [--count N]`
+/// Check that the server answers, how fast, and that it accepts this
+/// machine's token.
+pub async fn ping(count: u32) -> anyhow::Result<()> {
+    let config = load_client_config()?;
+    let client = reqwest::Client::builder()
+        .timeout(PING_TIMEOUT)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to create HTTP client: {e}"))?;
+
+    println!("Pinging {}", config.server_url);
+    let report = ping_server(&client, &config, count).await;
+    if let Some(e) = &report.error {
+        anyhow::bail!("Server unreachable: {e}");
+    }
+    let latencies = &report.latencies;
+    let total: std::time::Duration = latencies.iter().sum();
+    println!(
+        "  Reachable: {} health checks, round trip min {} / avg {} / max {}",
+        latencies.len(),
+        format_latency(*latencies.iter().min().unwrap_or(&total)),
+        format_latency(total / latencies.len().max(1) as u32),
+        format_latency(*latencies.iter().max().unwrap_or(&total)),
+    );
+    match report.auth_status {
+        Some(status) if status.is_success() => {
+            println!(
+                "  Auth:      token accepted for machine {}",
+                config.machine_id
+            )
+        }
+        Some(status @ (reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN)) => {
+            anyhow::bail!(
+                "Server rejected this machine's token (HTTP {status}); \
+                 run `shell-sync register` again"
+            )
+        }
+        Some(status) => println!("  Auth:      could not check (HTTP {status})"),
+        None => {}
+    }
+    Ok(())
+}
+
+/// `shell-sync status [--porcelain | --json]`
+pub fn status(porcelain: bool, json: bool) -> anyhow::Result<()> {
     let config = match load_client_config() {
         Ok(c) => c,
+        Err(_) if porcelain || json => {
+            anyhow::bail!("Not configured. Run: shell-sync register")
+        }
         Err(_) => {
             println!("Status: Not configured");
             println!("Run: shell-sync register");
@@ -275,16 +1402,290 @@ pub fn status() -> anyhow::Result<()> {
     };
 
     let running = is_daemon_running();
+    // A stale file from a daemon that died says nothing about the connection
+    let conn = running
+        .then(|| {
+            let path = shell_sync_core::config::daemon_status_path();
+            crate::connection_status::ConnectionStatus::load(&path)
+        })
+        .flatten();
+    // Keys the daemon keeps asking other members for
+    let waiting_for_keys = match &conn {
+        Some(conn) => {
+            shell_sync_core::encryption::KeyManager::new(shell_sync_core::config::keys_dir_path())
+                .map(|km| crate::daemon::missing_group_keys(&config, &km, &conn.encrypted_groups))
+                .unwrap_or_default()
+        }
+        None => Vec::new(),
+    };
+
+    let offline_queue = crate::offline::pending_count().unwrap_or(0);
+    let db_path = shell_sync_core::config::history_db_path();
+    let usage = if db_path.exists() {
+        let db = shell_sync_core::db::SyncDatabase::open(db_path.to_str().unwrap_or("history.db"))?;
+        storage_usage(&db, &db_path, offline_queue)?
+    } else {
+        StorageUsage {
+            offline_queue,
+            ..Default::default()
+        }
+    };
+
+    if porcelain || json {
+        let report = StatusReport::new(&config, running, conn.as_ref(), &usage, waiting_for_keys);
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print!("{}", report.porcelain());
+        }
+        return Ok(());
+    }
+
     println!("Status: {}", if running { "Running" } else { "Not running" });
+    if let Some(conn) = &conn {
+        print!("{}", conn.render(DisplayTz::from_env()));
+    }
     println!("Server: {}", config.server_url);
     println!("Groups: {}", config.groups.join(", "));
     println!("Machine: {}", config.machine_id);
+    if !waiting_for_keys.is_empty() {
+        println!(
+            "Waiting for group keys: {} (asked again until a member that has them is online)",
+            waiting_for_keys.join(", ")
+        );
+    }
+    print!("{}", render_storage(&usage, &StorageThresholds::default()));
+
+    Ok(())
+}
+
+/// `status` for scripts. The field names are the `--porcelain` keys and are
+/// kept stable.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct StatusReport {
+    pub running: bool,
+    pub server: String,
+    pub groups: Vec<String>,
+    pub machine: String,
+    /// History entries waiting to be pushed.
+    pub pending: i64,
+    /// Alias changes queued while the server was unreachable.
+    pub offline_queue: usize,
+    pub connected: bool,
+    /// Last message from the server (epoch ms).
+    pub last_sync: Option<i64>,
+    /// Groups whose key the daemon has requested and not yet received.
+    pub waiting_for_keys: Vec<String>,
+    /// Last successful alias sync (epoch ms).
+    pub last_alias_sync: Option<i64>,
+    /// Server aliases the last sync could not apply.
+    pub unapplied_aliases: usize,
+    /// How far the newest applied alias change trails the server's newest,
+    /// in ms; `None` when unknown.
+    pub version_gap_ms: Option<i64>,
+}
+
+impl StatusReport {
+    fn new(
+        config: &ClientConfig,
+        running: bool,
+        conn: Option<&crate::connection_status::ConnectionStatus>,
+        usage: &StorageUsage,
+        waiting_for_keys: Vec<String>,
+    ) -> Self {
+        use crate::connection_status::ConnectionState;
+        Self {
+            running,
+            server: config.server_url.clone(),
+            groups: config.groups.clone(),
+            machine: config.machine_id.clone(),
+            pending: usage.pending_history,
+            offline_queue: usage.offline_queue,
+            connected: conn.is_some_and(|c| c.state == ConnectionState::Connected),
+            last_sync: conn.and_then(|c| c.last_sync),
+            waiting_for_keys,
+            last_alias_sync: conn.and_then(|c| c.last_alias_sync),
+            unapplied_aliases: conn.map_or(0, |c| c.unapplied_aliases),
+            version_gap_ms: conn
+                .and_then(|c| c.sync_lag(chrono::Utc::now().timestamp_millis()))
+                .and_then(|lag| lag.version_gap_ms),
+        }
+    }
+
+    /// One `key=value` line per field. Lists are comma-separated and unknown
+    /// times and gaps are empty.
+    pub fn porcelain(&self) -> String {
+        let time = |t: Option<i64>| t.map(|t| t.to_string()).unwrap_or_default();
+        format!(
+            "running={}\nserver={}\ngroups={}\nmachine={}\npending={}\n\
+             offline_queue={}\nconnected={}\nlast_sync={}\nwaiting_for_keys={}\n\
+             last_alias_sync={}\nunapplied_aliases={}\nversion_gap_ms={}\n",
+            self.running,
+            self.server,
+            self.groups.join(","),
+            self.machine,
+            self.pending,
+            self.offline_queue,
+            self.connected,
+            time(self.last_sync),
+            self.waiting_for_keys.join(","),
+            time(self.last_alias_sync),
+            self.unapplied_aliases,
+            time(self.version_gap_ms),
+        )
+    }
+}
+
+/// How much the local history database and queues are holding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StorageUsage {
+    pub history_rows: i64,
+    /// Size of the database file plus its WAL.
+    pub db_bytes: u64,
+    pub pending_history: i64,
+    pub offline_queue: usize,
+}
+
+/// Levels above which `status` prints a warning hint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageThresholds {
+    pub history_rows: i64,
+    pub db_bytes: u64,
+    pub pending_history: i64,
+    pub offline_queue: usize,
+}
+
+impl Default for StorageThresholds {
+    fn default() -> Self {
+        Self {
+            history_rows: 1_000_000,
+            db_bytes: 1024 * 1024 * 1024,
+            pending_history: 5_000,
+            offline_queue: 100,
+        }
+    }
+}
+
+fn storage_usage(
+    db: &shell_sync_core::db::SyncDatabase,
+    db_path: &std::path::Path,
+    offline_queue: usize,
+) -> anyhow::Result<StorageUsage> {
+    let file_len = |path: &std::path::Path| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let mut wal = db_path.as_os_str().to_owned();
+    wal.push("-wal");
+    Ok(StorageUsage {
+        history_rows: db.get_history_count()?,
+        db_bytes: file_len(db_path) + file_len(std::path::Path::new(&wal)),
+        pending_history: db.get_pending_count()?,
+        offline_queue,
+    })
+}
+
+/// Storage lines for `status`, followed by a hint for each threshold exceeded.
+fn render_storage(usage: &StorageUsage, limits: &StorageThresholds) -> String {
+    let mut out = format!(
+        "History: {} entries ({})\n\
+         History waiting to push: {} entries\n\
+         Offline queue: {} pending operations\n",
+        usage.history_rows,
+        format_size(usage.db_bytes),
+        usage.pending_history,
+        usage.offline_queue,
+    );
+    if usage.history_rows > limits.history_rows || usage.db_bytes > limits.db_bytes {
+        out.push_str(&format!(
+            "Warning: the history database is large ({} entries, {}); \
+             searches and stats will slow down\n",
+            usage.history_rows,
+            format_size(usage.db_bytes),
+        ));
+    }
+    if usage.pending_history > limits.pending_history {
+        out.push_str(&format!(
+            "Warning: {} history entries have not been pushed; \
+             check that the daemon can reach the server\n",
+            usage.pending_history,
+        ));
+    }
+    if usage.offline_queue > limits.offline_queue {
+        out.push_str(&format!(
+            "Warning: {} alias changes are queued offline; \
+             run `shell-sync sync` once the server is reachable\n",
+            usage.offline_queue,
+        ));
+    }
+    out
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Every client-side file and directory, labelled.
+fn client_paths() -> Vec<(&'static str, std::path::PathBuf)> {
+    use shell_sync_core::config::*;
+    let shell = shell_sync_core::shell::detect_shell();
+    vec![
+        ("config dir", client_config_dir()),
+        ("config file", client_config_path()),
+        ("alias file", client_alias_path(shell.alias_extension())),
+        ("history db", history_db_path()),
+        ("socket", socket_path()),
+        ("keys dir", keys_dir_path()),
+        ("hooks dir", hooks_dir_path()),
+        ("offline queue", offline_queue_db_path()),
+        ("daemon pid", pid_file_path()),
+        ("daemon status", daemon_status_path()),
+        ("server pid", server_pid_file_path()),
+        ("server log", server_log_path()),
+    ]
+}
 
-    let pending = crate::offline::pending_count().unwrap_or(0);
-    if pending > 0 {
-        println!("Offline queue: {} pending operations", pending);
+/// Resolved paths with whether each exists, then the config with its token redacted.
+fn render_paths(
+    paths: &[(&str, std::path::PathBuf)],
+    config: Option<&ClientConfig>,
+) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut out = String::from("Paths:\n");
+    for (label, path) in paths {
+        let state = if path.exists() { "" } else { "  (missing)" };
+        writeln!(out, "  {:<14} {}{}", label, path.display(), state)?;
     }
 
+    match config {
+        Some(config) => {
+            let redacted = ClientConfig {
+                auth_token: "<redacted>".to_string(),
+                ..config.clone()
+            };
+            writeln!(out, "\nConfig:")?;
+            for line in toml::to_string(&redacted)?.lines() {
+                writeln!(out, "  {}", line)?;
+            }
+        }
+        None => writeln!(out, "\nConfig: not loaded (run `shell-sync register`)")?,
+    }
+    Ok(out)
+}
+
+/// `shell-sync paths`
+pub fn show_paths() -> anyhow::Result<()> {
+    let config = load_client_config().ok();
+    print!("{}", render_paths(&client_paths(), config.as_ref())?);
     Ok(())
 }
 
@@ -305,17 +1706,19 @@ pub fn stop_daemon() -> anyhow::Result<()> {
     }
 
     let _ = std::fs::remove_file(&pid_path);
+    let _ = std::fs::remove_file(shell_sync_core::config::daemon_status_path());
     println!("Daemon stopped");
 
     Ok(())
 }
 
-/// `shell-sync conflicts`
-pub async fn list_conflicts() -> anyhow::Result<()> {
+/// `shell-sync conflicts [--all]`
+pub async fn list_conflicts(include_resolved: bool) -> anyhow::Result<()> {
     let (client, config) = client_and_config()?;
 
     let resp = client
         .get(format!("{}/api/conflicts", config.server_url))
+        .query(&[("include_resolved", include_resolved)])
         .header("Authorization", auth_header(&config))
         .send()
         .await?;
@@ -327,7 +1730,13 @@ pub async fn list_conflicts() -> anyhow::Result<()> {
         Some(c) if !c.is_empty() => {
             println!("{} conflicts found:\n", c.len());
             for (i, conflict) in c.iter().enumerate() {
-                println!("{}. {}", i + 1, conflict["alias_name"].as_str().unwrap_or(""));
+                let name = conflict["alias_name"].as_str().unwrap_or("");
+                match conflict["resolution"].as_str() {
+                    Some(resolution) if conflict["resolved"].as_bool() == Some(true) => {
+                        println!("{}. {} (resolved: {})", i + 1, name, resolution)
+                    }
+                    _ => println!("{}. {}", i + 1, name),
+                }
                 println!("   Local:  {}", conflict["local_command"].as_str().unwrap_or(""));
                 println!("   Remote: {}", conflict["remote_command"].as_str().unwrap_or(""));
                 println!();
@@ -335,6 +1744,47 @@ pub async fn list_conflicts() -> anyhow::Result<()> {
         }
         _ => println!("No conflicts"),
     }
+    let shown = conflicts.map_or(0, |c| c.len() as i64);
+    if let Some(total) = data["total"].as_i64().filter(|&t| t > shown) {
+        println!("Showing the newest {} of {} conflicts", shown, total);
+    }
+    if let (Some(open), Some(resolved)) = (
+        data["counts"]["open"].as_i64(),
+        data["counts"]["resolved"].as_i64(),
+    ) {
+        println!("This machine: {} open, {} resolved", open, resolved);
+    }
+
+    Ok(())
+}
+
+/// `shell-sync conflicts purge [--older-than 30d]`
+pub async fn purge_conflicts(older_than: &str) -> anyhow::Result<()> {
+    use shell_sync_core::stats::parse_last_filter;
+
+    let older_than_ms = match parse_last_filter(older_than) {
+        Some(cutoff) => chrono::Utc::now().timestamp_millis() - cutoff,
+        None if older_than.trim().eq_ignore_ascii_case("all") => 0,
+        None => anyhow::bail!(
+            "Invalid --older-than '{}'. Use e.g. 7d, 4w, 6m, 1y or all.",
+            older_than
+        ),
+    };
+    let (client, config) = client_and_config()?;
+
+    let resp = client
+        .post(format!("{}/api/conflicts/purge", config.server_url))
+        .header("Authorization", auth_header(&config))
+        .json(&serde_json::json!({ "older_than_ms": older_than_ms }))
+        .send()
+        .await?;
+
+    let status = resp.status();
+    let body: serde_json::Value = resp.json().await.unwrap_or_default();
+    if !status.is_success() {
+        anyhow::bail!("Failed: {}", body["error"].as_str().unwrap_or("Unknown error"));
+    }
+    println!("Purged {} resolved conflicts", body["deleted"].as_i64().unwrap_or(0));
 
     Ok(())
 }
@@ -354,13 +1804,12 @@ pub async fn show_history(limit: i64) -> anyhow::Result<()> {
 
     match history {
         Some(h) if !h.is_empty() => {
-            let mut table = comfy_table::Table::new();
+            let tz = DisplayTz::from_env();
+            let mut table = new_table();
             table.set_header(vec!["Time", "Action", "Alias", "Group"]);
             for entry in h {
                 let ts = entry["timestamp"].as_i64().unwrap_or(0);
-                let time = chrono::DateTime::from_timestamp_millis(ts)
-                    .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
-                    .unwrap_or_else(|| "unknown".to_string());
+                let time = tz.format(ts, "%Y-%m-%d %H:%M");
                 table.add_row(vec![
                     &time,
                     entry["action"].as_str().unwrap_or(""),
@@ -391,13 +1840,24 @@ pub async fn list_machines() -> anyhow::Result<()> {
 
     match machines {
         Some(m) if !m.is_empty() => {
-            let mut table = comfy_table::Table::new();
-            table.set_header(vec!["Hostname", "OS", "Groups", "Last Seen"]);
+            let tz = DisplayTz::from_env();
+            let mut table = new_table();
+            table.set_header(vec![
+                "Hostname",
+                "OS",
+                "Groups",
+                "Last Seen",
+                "Commands (24h)",
+                "Last Command",
+            ]);
             for machine in m {
                 let last_seen = machine["last_seen"].as_i64().unwrap_or(0);
-                let time = chrono::DateTime::from_timestamp_millis(last_seen)
-                    .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
-                    .unwrap_or_else(|| "unknown".to_string());
+                let time = tz.format(last_seen, "%Y-%m-%d %H:%M");
+                let recent = machine["recent_commands"].as_i64().unwrap_or(0).to_string();
+                let last_command = machine["last_command_at"]
+                    .as_i64()
+                    .map(|t| tz.format(t, "%Y-%m-%d %H:%M"))
+                    .unwrap_or_else(|| "-".into());
                 let groups = machine["groups"]
                     .as_array()
                     .map(|g| {
@@ -412,6 +1872,8 @@ pub async fn list_machines() -> anyhow::Result<()> {
                     machine["os_type"].as_str().unwrap_or(""),
                     &groups,
                     &time,
+                    &recent,
+                    &last_command,
                 ]);
             }
             println!("{table}");
@@ -422,17 +1884,110 @@ pub async fn list_machines() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// `shell-sync groups ls`
+pub async fn list_groups() -> anyhow::Result<()> {
+    let (client, config) = client_and_config()?;
+
+    let resp = client
+        .get(format!("{}/api/groups", config.server_url))
+        .header("Authorization", auth_header(&config))
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        anyhow::bail!("Failed to fetch groups (HTTP {})", resp.status());
+    }
+
+    let data: serde_json::Value = resp.json().await?;
+    let groups: Vec<shell_sync_core::models::GroupInfo> =
+        serde_json::from_value(data["groups"].clone()).unwrap_or_default();
+
+    if groups.is_empty() {
+        println!("No groups");
+        return Ok(());
+    }
+
+    let mut table = new_table();
+    table.set_header(vec!["Group", "Aliases", "Machines", "Member"]);
+    for g in &groups {
+        let member = if config.groups.contains(&g.name) { "yes" } else { "" };
+        table.add_row(vec![
+            g.name.as_str(),
+            &g.alias_count.to_string(),
+            &g.machine_count.to_string(),
+            member,
+        ]);
+    }
+    println!("{table}");
+
+    Ok(())
+}
+
+/// `shell-sync groups create <name>`
+pub async fn create_group(name: &str) -> anyhow::Result<()> {
+    let (client, config) = client_and_config()?;
+
+    let resp = client
+        .post(format!("{}/api/groups", config.server_url))
+        .header("Authorization", auth_header(&config))
+        .json(&serde_json::json!({ "name": name }))
+        .send()
+        .await?;
+
+    if resp.status().is_success() {
+        println!("Group '{}' created", name);
+        Ok(())
+    } else {
+        let body: serde_json::Value = resp.json().await.unwrap_or_default();
+        let msg = body["error"].as_str().unwrap_or("Unknown error");
+        anyhow::bail!("Failed: {}", msg)
+    }
+}
+
+/// `shell-sync groups rm <name> [--force]`
+pub async fn delete_group(name: &str, force: bool) -> anyhow::Result<()> {
+    let (client, config) = client_and_config()?;
+
+    let mut url = api_url(&config.server_url, ["groups", name])?;
+    url.query_pairs_mut()
+        .append_pair("force", &force.to_string());
+    let resp = client
+        .delete(url)
+        .header("Authorization", auth_header(&config))
+        .send()
+        .await?;
+
+    if resp.status().is_success() {
+        let body: serde_json::Value = resp.json().await.unwrap_or_default();
+        let removed = body["aliases_removed"].as_i64().unwrap_or(0);
+        if removed > 0 {
+            println!("Group '{}' deleted along with {} alias(es)", name, removed);
+        } else {
+            println!("Group '{}' deleted", name);
+        }
+        Ok(())
+    } else {
+        let body: serde_json::Value = resp.json().await.unwrap_or_default();
+        let msg = body["error"].as_str().unwrap_or("Unknown error");
+        anyhow::bail!("Failed: {}", msg)
+    }
+}
+
 /// `shell-sync git-backup`
-pub async fn git_backup() -> anyhow::Result<()> {
+pub async fn git_backup(dry_run: bool) -> anyhow::Result<()> {
     let (client, config) = client_and_config()?;
 
     let resp = client
         .post(format!("{}/api/git/sync", config.server_url))
+        .query(&[("dry_run", dry_run)])
         .header("Authorization", auth_header(&config))
         .send()
         .await?;
 
-    if resp.status().is_success() {
+    if resp.status().is_success() && dry_run {
+        let body: serde_json::Value = resp.json().await?;
+        print!("{}", render_git_plan(&body["plan"]));
+    } else if resp.status().is_success() {
         println!("Git backup completed");
     } else {
         let body: serde_json::Value = resp.json().await.unwrap_or_default();
@@ -442,77 +1997,123 @@ pub async fn git_backup() -> anyhow::Result<()> {
     Ok(())
 }
 
-/// `shell-sync migrate <old-db-path>`
-pub fn migrate(old_db_path: &str) -> anyhow::Result<()> {
-    use rusqlite::Connection;
+/// Text for `git-backup --dry-run`.
+fn render_git_plan(plan: &serde_json::Value) -> String {
+    let files = |key: &str| -> Vec<String> {
+        plan[key]
+            .as_array()
+            .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default()
+    };
+    let mut out = String::new();
+    for path in files("written") {
+        out.push_str(&format!("  write   {path}\n"));
+    }
+    for path in files("removed") {
+        out.push_str(&format!("  remove  {path}\n"));
+    }
+    if plan["would_commit"].as_bool().unwrap_or(false) {
+        out.push_str("A commit would be made (dry run, nothing written)\n");
+    } else {
+        out.push_str("Nothing to commit\n");
+    }
+    out
+}
 
-    println!("Migrating from Node.js database: {}", old_db_path);
+/// `shell-sync resync-history [--since 7d]`
+pub fn resync_history(since: &str) -> anyhow::Result<()> {
+    use shell_sync_core::config::history_db_path;
+    use shell_sync_core::db::SyncDatabase;
+    use shell_sync_core::stats::parse_last_filter;
 
-    let old_conn = Connection::open(old_db_path)?;
+    let config = load_client_config()?;
+    let after_timestamp = parse_last_filter(since);
+    if after_timestamp.is_none() && !since.trim().eq_ignore_ascii_case("all") {
+        anyhow::bail!("Invalid --since '{}'. Use e.g. 7d, 4w, 6m, 1y or all.", since);
+    }
 
-    // Read machines
-    let mut stmt = old_conn.prepare("SELECT machine_id, hostname, groups, os_type, auth_token, last_seen, created_at FROM machines")?;
-    let machines: Vec<(String, String, String, Option<String>, String, i64, i64)> = stmt
-        .query_map([], |row| {
-            Ok((
-                row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?,
-                row.get(4)?, row.get(5)?, row.get(6)?,
-            ))
-        })?
-        .collect::<Result<_, _>>()?;
+    let db_path = history_db_path();
+    if !db_path.exists() {
+        anyhow::bail!("No history database found at {}. Run the daemon first.", db_path.display());
+    }
+    let db = SyncDatabase::open(db_path.to_str().unwrap_or("history.db"))?;
 
-    // Read aliases
-    let mut stmt = old_conn.prepare("SELECT name, command, group_name, created_by_machine, created_at, updated_at, version FROM aliases")?;
-    let aliases: Vec<(String, String, String, String, i64, i64, i64)> = stmt
-        .query_map([], |row| {
-            Ok((
-                row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?,
-                row.get(4)?, row.get(5)?, row.get(6)?,
-            ))
-        })?
-        .collect::<Result<_, _>>()?;
+    let queued = db.requeue_history(&config.machine_id, after_timestamp, |e| {
+        config.pushed_entry(e)
+    })?;
+    println!("Queued {} history entries for re-upload", queued);
+    if !is_daemon_running() {
+        println!("The daemon is not running; start it with: shell-sync connect");
+    }
 
-    println!("Found {} machines and {} aliases", machines.len(), aliases.len());
+    Ok(())
+}
 
-    // Create new database
-    let new_db = shell_sync_core::db::SyncDatabase::open("./data/sync.db")?;
+/// `shell-sync migrate <old-db-path> [--target PATH]`
+///
+/// Safe to run again after a failure: rows already in `target` are skipped.
+pub fn migrate(old_db_path: &str, target: &str) -> anyhow::Result<()> {
+    println!("Migrating from Node.js database: {}", old_db_path);
 
-    // Migrate machines (preserving UUIDs and tokens)
-    for (mid, host, groups, os, token, _, _) in &machines {
-        let groups: Vec<String> = serde_json::from_str(groups).unwrap_or_default();
-        new_db.register_machine(mid, host, &groups, os.as_deref().unwrap_or("unknown"), token, None)?;
+    if let Some(parent) = std::path::Path::new(target).parent() {
+        std::fs::create_dir_all(parent)?;
     }
+    let new_db = shell_sync_core::db::SyncDatabase::open(target)?;
+    let report = new_db.import_legacy(std::path::Path::new(old_db_path))?;
 
-    // Migrate aliases
-    let mut added = 0;
-    let mut skipped = 0;
-    for (name, command, group, machine, _, _, _) in &aliases {
-        match new_db.add_alias(name, command, group, machine) {
-            Ok(_) => added += 1,
-            Err(_) => skipped += 1,
-        }
+    println!(
+        "Migration complete into {}: {} machines, {} aliases, {} sync history entries, \
+         {} conflicts ({} rows already present)",
+        target,
+        report.machines,
+        report.aliases,
+        report.sync_history,
+        report.conflicts,
+        report.skipped
+    );
+
+    Ok(())
+}
+
+/// `shell-sync init-hooks [--force]`
+pub fn init_hooks(force: bool) -> anyhow::Result<()> {
+    use shell_sync_core::config::{
+        client_config_dir, hooks_dir_path, socket_path, CaptureEndpoint,
+    };
+    use shell_sync_core::shell::detect_shell;
+
+    let shell = detect_shell();
+    // Before registration there is no config; hooks then use the Unix socket
+    let endpoint = match load_client_config() {
+        Ok(config) => config.capture_endpoint(&client_config_dir())?,
+        Err(_) => CaptureEndpoint::Unix(socket_path()),
+    };
+    let (hook_file, written) = write_hook_file(shell, &hooks_dir_path(), &endpoint, force)?;
+    if !written {
+        println!("Hook file already exists: {}", hook_file.display());
+        println!("Use --force to overwrite");
+        return Ok(());
     }
+    println!("Hook file written: {}", hook_file.display());
 
-    println!("Migration complete: {} aliases migrated, {} skipped (duplicates)", added, skipped);
+    let rc_file = shell.rc_file();
+    println!();
+    println!("Add this line to {}:", rc_file.display());
+    println!("  {}", hook_source_line(shell, &hook_file));
 
     Ok(())
 }
 
-/// `shell-sync init-hooks [--force]`
-pub fn init_hooks(force: bool) -> anyhow::Result<()> {
-    use shell_sync_core::config::{hooks_dir_path, socket_path};
+/// Write the capture hooks for `shell` into `hooks_dir`, talking to `endpoint`.
+/// An existing file is kept unless `force` is set. Returns the file's path
+/// and whether it was written.
+pub fn write_hook_file(
+    shell: shell_sync_core::shell::ShellType,
+    hooks_dir: &std::path::Path,
+    endpoint: &shell_sync_core::config::CaptureEndpoint,
+    force: bool,
+) -> anyhow::Result<(std::path::PathBuf, bool)> {
     use shell_sync_core::hooks::generate_hooks;
-    use shell_sync_core::shell::detect_shell;
-
-    let shell = detect_shell();
-    let session_id = uuid::Uuid::new_v4().to_string();
-    let sock = socket_path();
-    let sock_str = sock.to_str().unwrap_or("/tmp/shell-sync.sock");
-
-    let hooks_content = generate_hooks(shell, sock_str, &session_id);
-
-    let hooks_dir = hooks_dir_path();
-    std::fs::create_dir_all(&hooks_dir)?;
 
     let extension = match shell {
         shell_sync_core::shell::ShellType::Zsh => "zsh",
@@ -520,17 +2121,28 @@ pub fn init_hooks(force: bool) -> anyhow::Result<()> {
         shell_sync_core::shell::ShellType::Fish => "fish",
     };
     let hook_file = hooks_dir.join(format!("shell-sync-hooks.{}", extension));
-
     if hook_file.exists() && !force {
-        println!("Hook file already exists: {}", hook_file.display());
-        println!("Use --force to overwrite");
-        return Ok(());
+        return Ok((hook_file, false));
     }
 
-    std::fs::write(&hook_file, &hooks_content)?;
-    println!("Hook file written: {}", hook_file.display());
+    let session_id = uuid::Uuid::new_v4().to_string();
+    std::fs::create_dir_all(hooks_dir)?;
+    std::fs::write(&hook_file, generate_hooks(shell, endpoint, &session_id))?;
+    // May hold the capture secret
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&hook_file, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok((hook_file, true))
+}
 
-    let source_line = match shell {
+/// The line that loads `hook_file` from the shell's rc file.
+pub fn hook_source_line(
+    shell: shell_sync_core::shell::ShellType,
+    hook_file: &std::path::Path,
+) -> String {
+    match shell {
         shell_sync_core::shell::ShellType::Fish => {
             format!("source \"{}\"", hook_file.display())
         }
@@ -541,29 +2153,44 @@ pub fn init_hooks(force: bool) -> anyhow::Result<()> {
                 hook_file.display()
             )
         }
-    };
-
-    let rc_file = shell.rc_file();
-    println!();
-    println!("Add this line to {}:", rc_file.display());
-    println!("  {}", source_line);
-
-    Ok(())
+    }
 }
 
 /// `shell-sync encrypt-migrate`
-/// Encrypt existing plaintext aliases and re-upload them.
+/// Create group keys for this machine's groups, then encrypt existing
+/// plaintext aliases and re-upload them if the server can store them.
 pub async fn encrypt_migrate() -> anyhow::Result<()> {
     use shell_sync_core::config::keys_dir_path;
     use shell_sync_core::encryption::{self, KeyManager};
 
     let (client, config) = client_and_config()?;
+    // A server without encrypted alias storage would keep the ciphertext as
+    // each alias's command, so anything short of a clear yes skips the upload.
+    let store_encrypted = match fetch_server_version(&client, &config).await {
+        Ok(version) => version.is_some_and(|v| v.supports(features::ENCRYPTED_ALIASES)),
+        Err(e) => anyhow::bail!("Failed to check server features: {e}"),
+    };
 
     // Init key manager
     let keys_dir = keys_dir_path();
     let mut key_mgr = KeyManager::new(keys_dir)
         .map_err(|e| anyhow::anyhow!("Failed to init encryption: {e}"))?;
 
+    for group in config.groups.iter().filter(|g| !config.is_local_only(g)) {
+        if !key_mgr.has_group_key(group) {
+            key_mgr
+                .create_group_key(group)
+                .map_err(|e| anyhow::anyhow!("Failed to create group key for '{group}': {e}"))?;
+            println!("  Created encryption key for group '{}'", group);
+        }
+    }
+
+    if !store_encrypted {
+        println!("The server does not store encrypted aliases; they are left as they are.");
+        println!("Group keys are stored in ~/.shell-sync/keys/groups/");
+        return Ok(());
+    }
+
     println!("Fetching aliases from server...");
 
     let resp = client
@@ -649,17 +2276,60 @@ pub async fn encrypt_migrate() -> anyhow::Result<()> {
     Ok(())
 }
 
-/// `shell-sync stats [--last 30d] [--machine X] [--group X] [--directory X] [--json]`
-pub fn show_stats(
+fn open_key_manager() -> anyhow::Result<shell_sync_core::encryption::KeyManager> {
+    shell_sync_core::encryption::KeyManager::new(shell_sync_core::config::keys_dir_path())
+        .map_err(|e| anyhow::anyhow!("Failed to init encryption: {e}"))
+}
+
+/// `shell-sync key public`
+/// Print this machine's public key, for another member to export a group key to.
+pub fn show_public_key() -> anyhow::Result<()> {
+    println!("{}", open_key_manager()?.public_key_b64());
+    Ok(())
+}
+
+/// `shell-sync key export --group <g> --to <public key>`
+/// Print `group`'s key wrapped for the machine with public key `recipient`,
+/// for when the two are never online together for the automatic exchange.
+pub fn export_group_key(group: &str, recipient: &str) -> anyhow::Result<()> {
+    let exported = open_key_manager()?
+        .export_group_key(group, recipient.trim())
+        .map_err(|e| anyhow::anyhow!("Failed to export key for group '{group}': {e}"))?;
+    println!("{exported}");
+    eprintln!("On the other machine, run: shell-sync key import --group {group} <key>");
+    Ok(())
+}
+
+/// `shell-sync key import --group <g> <key>`
+/// Install a group key printed by `key export` on another machine.
+pub fn import_group_key(group: &str, exported: &str) -> anyhow::Result<()> {
+    let installed = open_key_manager()?
+        .import_group_key(group, exported)
+        .map_err(|e| anyhow::anyhow!("Failed to import key for group '{group}': {e}"))?;
+    if installed {
+        println!("Installed the encryption key for group '{group}'");
+    } else {
+        println!("This machine already has that key for group '{group}'");
+    }
+    Ok(())
+}
+
+/// Open the local history database and build the stats filter shared by
+/// `stats` and `stats --command`.
+fn open_stats(
     last: &str,
+    after: Option<&str>,
+    before: Option<&str>,
     machine: Option<String>,
     group: Option<String>,
     directory: Option<String>,
-    json_output: bool,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<(shell_sync_core::db::SyncDatabase, shell_sync_core::stats::StatsFilter)> {
     use shell_sync_core::config::history_db_path;
     use shell_sync_core::db::SyncDatabase;
-    use shell_sync_core::stats::{compute_stats, parse_last_filter, StatsFilter};
+    use shell_sync_core::stats::{parse_time_range, StatsFilter};
+
+    let tz = DisplayTz::from_env();
+    let (after_timestamp, before_timestamp) = parse_time_range(last, after, before, tz)?;
 
     let db_path = history_db_path();
     if !db_path.exists() {
@@ -668,136 +2338,365 @@ pub fn show_stats(
 
     let db = SyncDatabase::open(db_path.to_str().unwrap_or("history.db"))?;
 
-    let after_timestamp = parse_last_filter(last);
     let filter = StatsFilter {
         after_timestamp,
+        before_timestamp,
         machine_id: machine,
         group_name: group,
         directory,
+        tz,
+        normalize: false,
+    };
+    Ok((db, filter))
+}
+
+/// Where `stats` writes its report: stdout for `None` or `-`, otherwise a
+/// file, creating its parent directories.
+fn write_stats_output(output: Option<&std::path::Path>, text: &str) -> anyhow::Result<()> {
+    match output {
+        Some(path) if path != std::path::Path::new("-") => {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent).map_err(|e| {
+                    anyhow::anyhow!("Failed to create {}: {e}", parent.display())
+                })?;
+            }
+            std::fs::write(path, text)
+                .map_err(|e| anyhow::anyhow!("Failed to write {}: {e}", path.display()))?;
+        }
+        _ => print!("{text}"),
+    }
+    Ok(())
+}
+
+/// Colour only when the report goes to a terminal.
+fn stats_color(output: Option<&std::path::Path>) -> bool {
+    output.is_none_or(|p| p == std::path::Path::new("-"))
+        && shell_sync_core::color::stdout_enabled()
+}
+
+/// `shell-sync stats --command "git status" [--last 30d] [filters] [--json] [--output PATH]`
+#[allow(clippy::too_many_arguments)]
+pub fn show_command_stats(
+    command: &str,
+    last: &str,
+    after: Option<&str>,
+    before: Option<&str>,
+    machine: Option<String>,
+    group: Option<String>,
+    directory: Option<String>,
+    json_output: bool,
+    output: Option<&std::path::Path>,
+) -> anyhow::Result<()> {
+    use shell_sync_core::stats::command_detail;
+
+    let (db, filter) = open_stats(last, after, before, machine, group, directory)?;
+    let detail = command_detail(&db, command, &filter, chrono::Utc::now().timestamp_millis())?;
+
+    let text = if json_output {
+        serde_json::to_string_pretty(&detail)? + "\n"
+    } else {
+        render_command_stats(command, &detail, last, filter.tz, stats_color(output))
     };
+    write_stats_output(output, &text)
+}
+
+fn render_command_stats(
+    command: &str,
+    detail: &shell_sync_core::stats::CommandDetail,
+    last: &str,
+    tz: DisplayTz,
+    color: bool,
+) -> String {
+    use shell_sync_core::stats::{sparkline, RECENT_DAYS};
+    use std::fmt::Write;
+
+    let heading = |text: &str| shell_sync_core::color::paint(color, "1;36", text);
+    let mut out = String::new();
+
+    writeln!(out).unwrap();
+    writeln!(out, "  {}", heading(&format!("`{}` (last {})", command, last))).unwrap();
+    writeln!(out, "  {}", "=".repeat(40)).unwrap();
+    if detail.count == 0 {
+        writeln!(out, "  Never run with exactly this command line.").unwrap();
+    } else {
+        let when =
+            |ts: Option<i64>| ts.map(|t| tz.format(t, "%Y-%m-%d %H:%M")).unwrap_or_default();
+        writeln!(out, "  Runs:          {}", detail.count).unwrap();
+        writeln!(out, "  Success rate:  {:.1}%", detail.success_rate).unwrap();
+        writeln!(out, "  Median:        {} ms", detail.median_duration_ms).unwrap();
+        writeln!(out, "  P95:           {} ms", detail.p95_duration_ms).unwrap();
+        writeln!(out, "  First run:     {}", when(detail.first_run)).unwrap();
+        writeln!(out, "  Last run:      {}", when(detail.last_run)).unwrap();
+        let recent = sparkline(&detail.recent_daily);
+        writeln!(out, "  Last {} days:  {}", RECENT_DAYS, recent).unwrap();
+    }
+    if detail.with_arguments_count > 0 {
+        writeln!(
+            out,
+            "  With arguments: {} more run(s) of `{} ...` not counted above",
+            detail.with_arguments_count, command
+        )
+        .unwrap();
+    }
+    writeln!(out).unwrap();
+
+    if !detail.directories.is_empty() {
+        writeln!(out, "  {}", heading("Directories")).unwrap();
+        writeln!(out, "  {}", "-".repeat(30)).unwrap();
+        for (dir, count) in &detail.directories {
+            writeln!(out, "  {:>6}  {}", count, dir).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+
+    out
+}
+
+/// `shell-sync stats [--last 30d] [--machine X] [--group X] [--directory X] [--normalize]
+/// [--scale linear|log] [--json] [--output PATH]`
+#[allow(clippy::too_many_arguments)]
+pub fn show_stats(
+    last: &str,
+    after: Option<&str>,
+    before: Option<&str>,
+    machine: Option<String>,
+    group: Option<String>,
+    directory: Option<String>,
+    json_output: bool,
+    svg_path: Option<&std::path::Path>,
+    sessions_only: bool,
+    normalize: bool,
+    scale: BarScale,
+    output: Option<&std::path::Path>,
+) -> anyhow::Result<()> {
+    use shell_sync_core::stats::{compute_stats, render_heatmap_svg};
 
+    let (db, mut filter) = open_stats(last, after, before, machine, group, directory)?;
+    filter.normalize = normalize;
     let stats = compute_stats(&db, &filter)?;
 
-    if json_output {
-        println!("{}", serde_json::to_string_pretty(&stats)?);
+    if let Some(path) = svg_path {
+        std::fs::write(path, render_heatmap_svg(&stats))?;
+        println!("Wrote activity heatmap to {}", path.display());
         return Ok(());
     }
 
-    // Pretty print
-    println!();
-    println!("  Shell Usage Statistics (last {})", last);
-    println!("  {}", "=".repeat(40));
-    println!();
+    let text = match (sessions_only, json_output) {
+        (true, true) => serde_json::to_string_pretty(&stats.per_session)? + "\n",
+        (true, false) => render_session_stats(&stats.per_session, last),
+        (false, true) => serde_json::to_string_pretty(&stats)? + "\n",
+        (false, false) => render_stats(&stats, last, stats_color(output), scale),
+    };
+    write_stats_output(output, &text)
+}
+
+/// How `shell-sync stats` sizes its bars against the largest count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BarScale {
+    /// Length proportional to the count.
+    #[default]
+    Linear,
+    /// Length proportional to `ln(1 + count)`, so one spike doesn't flatten
+    /// every other bar.
+    Log,
+}
+
+impl BarScale {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::Log => "log",
+        }
+    }
+
+    /// Bar length out of [`BAR_WIDTH`] for `count` when `max` fills the bar.
+    fn bar_len(self, count: i64, max: i64) -> usize {
+        if max <= 0 || count <= 0 {
+            return 0;
+        }
+        let fraction = match self {
+            Self::Linear => count as f64 / max as f64,
+            Self::Log => (count as f64).ln_1p() / (max as f64).ln_1p(),
+        };
+        (fraction * BAR_WIDTH as f64) as usize
+    }
+}
+
+const BAR_WIDTH: usize = 20;
+
+/// Human-readable stats report. With `color` off it is plain text with no
+/// ANSI escape sequences.
+fn render_stats(
+    stats: &shell_sync_core::stats::StatsResult,
+    last: &str,
+    color: bool,
+    scale: BarScale,
+) -> String {
+    use shell_sync_core::color::paint;
+    use std::fmt::Write;
+
+    let heading = |text: &str| paint(color, "1;36", text);
+    // Pad before painting so escape codes don't count towards the column width.
+    let bar = |ch: &str, len: usize, width: usize| {
+        paint(color, "32", &format!("{:<width$}", ch.repeat(len)))
+    };
+    let legend =
+        |ch: &str, max: i64| format!("  {ch} = up to {max} cmds ({} scale)", scale.name());
+    let mut out = String::new();
+
+    writeln!(out).unwrap();
+    writeln!(out, "  {}", heading(&format!("Shell Usage Statistics (last {})", last))).unwrap();
+    writeln!(out, "  {}", "=".repeat(40)).unwrap();
+    writeln!(out).unwrap();
 
     // Summary
-    println!("  Total commands:   {}", stats.total_commands);
-    println!("  Unique commands:  {}", stats.unique_commands);
-    println!("  Success rate:     {:.1}%", stats.success_rate);
-    println!("  Streak:           {} day(s)", stats.streak_days);
-    println!();
+    writeln!(out, "  Total commands:   {}", stats.total_commands).unwrap();
+    writeln!(out, "  Unique commands:  {}", stats.unique_commands).unwrap();
+    writeln!(out, "  Success rate:     {:.1}%", stats.success_rate).unwrap();
+    writeln!(out, "  Streak:           {} day(s)", stats.streak_days).unwrap();
+    writeln!(out).unwrap();
 
     // Duration
-    println!("  Duration");
-    println!("  {}", "-".repeat(30));
-    println!("  Average:  {:.0} ms", stats.avg_duration_ms);
-    println!("  Median:   {} ms", stats.median_duration_ms);
-    println!("  P95:      {} ms", stats.p95_duration_ms);
-    println!();
+    writeln!(out, "  {}", heading("Duration")).unwrap();
+    writeln!(out, "  {}", "-".repeat(30)).unwrap();
+    writeln!(out, "  Average:  {:.0} ms", stats.avg_duration_ms).unwrap();
+    writeln!(out, "  Median:   {} ms", stats.median_duration_ms).unwrap();
+    writeln!(out, "  P95:      {} ms", stats.p95_duration_ms).unwrap();
+    writeln!(out).unwrap();
 
     // Top commands
     if !stats.top_commands.is_empty() {
-        println!("  Top Commands");
-        println!("  {}", "-".repeat(30));
+        writeln!(out, "  {}", heading("Top Commands")).unwrap();
+        writeln!(out, "  {}", "-".repeat(30)).unwrap();
         let max_count = stats.top_commands.first().map(|c| c.1).unwrap_or(1);
+        writeln!(out, "{}", legend("\u{2588}", max_count)).unwrap();
         for (cmd, count) in &stats.top_commands {
-            let bar_len = if max_count > 0 {
-                ((*count as f64 / max_count as f64) * 20.0) as usize
-            } else {
-                0
-            };
-            let bar: String = "\u{2588}".repeat(bar_len);
+            let bar_len = scale.bar_len(*count, max_count);
+            let bar = bar("\u{2588}", bar_len, BAR_WIDTH);
             let cmd_display = if cmd.len() > 30 {
                 format!("{}...", &cmd[..27])
             } else {
                 cmd.clone()
             };
-            println!("  {:>6}  {:<20}  {}", count, bar, cmd_display);
+            writeln!(out, "  {:>6}  {}  {}", count, bar, cmd_display).unwrap();
         }
-        println!();
+        writeln!(out).unwrap();
     }
 
     // Top prefixes
     if !stats.top_prefixes.is_empty() {
-        println!("  Top Prefixes");
-        println!("  {}", "-".repeat(30));
+        writeln!(out, "  {}", heading("Top Prefixes")).unwrap();
+        writeln!(out, "  {}", "-".repeat(30)).unwrap();
         let max_count = stats.top_prefixes.first().map(|c| c.1).unwrap_or(1);
+        writeln!(out, "{}", legend("\u{2588}", max_count)).unwrap();
         for (prefix, count) in &stats.top_prefixes {
-            let bar_len = if max_count > 0 {
-                ((*count as f64 / max_count as f64) * 20.0) as usize
-            } else {
-                0
-            };
-            let bar: String = "\u{2588}".repeat(bar_len);
-            println!("  {:>6}  {:<20}  {}", count, bar, prefix);
+            let bar_len = scale.bar_len(*count, max_count);
+            let bar = bar("\u{2588}", bar_len, BAR_WIDTH);
+            writeln!(out, "  {:>6}  {}  {}", count, bar, prefix).unwrap();
         }
-        println!();
+        writeln!(out).unwrap();
+    }
+
+    // Top subcommands
+    if !stats.top_subcommands.is_empty() {
+        writeln!(out, "  {}", heading("Top Subcommands")).unwrap();
+        writeln!(out, "  {}", "-".repeat(30)).unwrap();
+        let max_count = stats.top_subcommands.first().map(|c| c.1).unwrap_or(1);
+        writeln!(out, "{}", legend("\u{2588}", max_count)).unwrap();
+        for (subcommand, count) in &stats.top_subcommands {
+            let bar_len = scale.bar_len(*count, max_count);
+            let bar = bar("\u{2588}", bar_len, BAR_WIDTH);
+            writeln!(out, "  {:>6}  {}  {}", count, bar, subcommand).unwrap();
+        }
+        writeln!(out).unwrap();
     }
 
     // Hourly distribution
-    println!("  Activity by Hour");
-    println!("  {}", "-".repeat(30));
+    writeln!(out, "  {}", heading("Activity by Hour")).unwrap();
+    writeln!(out, "  {}", "-".repeat(30)).unwrap();
     let max_hourly = stats.hourly_distribution.iter().max().copied().unwrap_or(1);
+    writeln!(out, "{}", legend("\u{2592}", max_hourly)).unwrap();
     for (hour, &count) in stats.hourly_distribution.iter().enumerate() {
-        let bar_len = if max_hourly > 0 {
-            ((count as f64 / max_hourly as f64) * 20.0) as usize
-        } else {
-            0
-        };
-        let bar: String = "\u{2592}".repeat(bar_len);
-        println!("  {:02}:00  {:>5}  {}", hour, count, bar);
+        let bar_len = scale.bar_len(count, max_hourly);
+        let bar = bar("\u{2592}", bar_len, 0);
+        writeln!(out, "  {:02}:00  {:>5}  {}", hour, count, bar).unwrap();
     }
-    println!();
+    writeln!(out).unwrap();
 
     // Daily distribution
     let day_names = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
-    println!("  Activity by Day");
-    println!("  {}", "-".repeat(30));
+    writeln!(out, "  {}", heading("Activity by Day")).unwrap();
+    writeln!(out, "  {}", "-".repeat(30)).unwrap();
     let max_daily = stats.daily_distribution.iter().max().copied().unwrap_or(1);
+    writeln!(out, "{}", legend("\u{2592}", max_daily)).unwrap();
     for (i, &count) in stats.daily_distribution.iter().enumerate() {
-        let bar_len = if max_daily > 0 {
-            ((count as f64 / max_daily as f64) * 20.0) as usize
-        } else {
-            0
-        };
-        let bar: String = "\u{2592}".repeat(bar_len);
-        println!("  {}  {:>5}  {}", day_names[i], count, bar);
+        let bar_len = scale.bar_len(count, max_daily);
+        let bar = bar("\u{2592}", bar_len, 0);
+        writeln!(out, "  {}  {:>5}  {}", day_names[i], count, bar).unwrap();
     }
-    println!();
+    writeln!(out).unwrap();
 
     // Per directory
     if !stats.per_directory.is_empty() {
-        println!("  Top Directories");
-        println!("  {}", "-".repeat(30));
+        writeln!(out, "  {}", heading("Top Directories")).unwrap();
+        writeln!(out, "  {}", "-".repeat(30)).unwrap();
         for (dir, count) in &stats.per_directory {
-            println!("  {:>6}  {}", count, dir);
+            writeln!(out, "  {:>6}  {}", count, dir).unwrap();
         }
-        println!();
+        writeln!(out).unwrap();
     }
 
     // Per machine
     if stats.per_machine.len() > 1 {
-        println!("  Per Machine");
-        println!("  {}", "-".repeat(30));
+        writeln!(out, "  {}", heading("Per Machine")).unwrap();
+        writeln!(out, "  {}", "-".repeat(30)).unwrap();
         for (host, count) in &stats.per_machine {
-            println!("  {:>6}  {}", count, host);
+            writeln!(out, "  {:>6}  {}", count, host).unwrap();
         }
-        println!();
+        writeln!(out).unwrap();
     }
 
-    Ok(())
+    out
+}
+
+/// A table for terminal output; cell styling is dropped when color is off.
+fn new_table() -> comfy_table::Table {
+    let mut table = comfy_table::Table::new();
+    if !shell_sync_core::color::stdout_enabled() {
+        table.force_no_tty();
+    }
+    table
+}
+
+fn render_session_stats(sessions: &[(String, i64, i64)], last: &str) -> String {
+    if sessions.is_empty() {
+        return format!("No sessions recorded in the last {}\n", last);
+    }
+
+    let mut table = new_table();
+    table.set_header(vec!["Session", "Commands", "Total Duration"]);
+    for (session, count, duration_ms) in sessions {
+        table.add_row(vec![
+            session.clone(),
+            count.to_string(),
+            format_duration_ms(*duration_ms),
+        ]);
+    }
+    format!("{table}\n")
+}
+
+fn format_duration_ms(ms: i64) -> String {
+    let secs = ms / 1000;
+    if secs < 60 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else if secs < 3600 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+    }
 }
 
-fn is_daemon_running() -> bool {
+pub(crate) fn is_daemon_running() -> bool {
     let pid_path = pid_file_path();
     if !pid_path.exists() {
         return false;
@@ -815,3 +2714,877 @@ fn is_daemon_running() -> bool {
         Err(_) => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn make_alias(id: i64, name: &str, command: &str, group: &str) -> Alias {
+        Alias {
+            id,
+            name: name.into(),
+            command: command.into(),
+            group_name: group.into(),
+            created_by_machine: "m1".into(),
+            created_at: 1000,
+            updated_at: 1000,
+            version: 1,
+            pinned: false,
+            expand: false,
+            template: false,
+            shells: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn alias_name_urls_encode_the_name() {
+        let url = alias_name_url("http://sync.local:8888", "a/b c?", None, "ops&dev").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://sync.local:8888/api/aliases/name/a%2Fb%20c%3F?group=ops%26dev"
+        );
+        let url = alias_name_url("https://host/sync/", "kgp", Some("pin"), "default").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://host/sync/api/aliases/name/kgp/pin?group=default"
+        );
+        assert!(alias_name_url("not a url", "kgp", None, "default").is_err());
+    }
+
+    #[test]
+    fn api_urls_encode_each_segment() {
+        let url = api_url("http://sync.local:8888/", ["groups", "ops/dev?x"]).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://sync.local:8888/api/groups/ops%2Fdev%3Fx"
+        );
+    }
+
+    #[test]
+    fn created_by_keeps_only_that_machines_aliases() {
+        let config = test_config("http://localhost:8888".into());
+        let mut other = make_alias(2, "gp", "git push", "default");
+        other.created_by_machine = "m2".into();
+        let all = vec![make_alias(1, "gs", "git status", "default"), other];
+
+        let mut mine = all.clone();
+        retain_created_by(&mut mine, "self", &config);
+        assert_eq!(mine.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(), ["gs"]);
+
+        let mut theirs = all.clone();
+        retain_created_by(&mut theirs, "m2", &config);
+        assert_eq!(theirs.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(), ["gp"]);
+
+        let mut none = all;
+        retain_created_by(&mut none, "m9", &config);
+        assert!(none.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_reports_present_and_missing_targets() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("kubectl");
+        std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let path = dir.path().as_os_str();
+
+        let aliases = vec![
+            make_alias(1, "k", "kubectl", "default"),
+            make_alias(2, "kgp", "k get pods", "default"),
+            make_alias(3, "old", "frobnicate-xyz --all", "default"),
+            make_alias(4, "up", "cd .. && ls", "work"),
+        ];
+        let checks = verify_alias_targets(&aliases, None, Some(path));
+        let status: Vec<(&str, bool)> = checks.iter().map(|c| (c.name.as_str(), c.ok)).collect();
+        assert_eq!(
+            status,
+            [("k", true), ("kgp", true), ("old", false), ("up", true)]
+        );
+        assert_eq!(checks[1].target, tool.display().to_string());
+        assert_eq!(checks[2].target, "frobnicate-xyz");
+        assert_eq!(checks[3].target, "cd (builtin)");
+
+        let work = verify_alias_targets(&aliases, Some("work"), Some(path));
+        assert_eq!(work.len(), 1);
+    }
+
+    fn sample_stats() -> shell_sync_core::stats::StatsResult {
+        let mut hourly = vec![0; 24];
+        hourly[9] = 12;
+        hourly[14] = 4;
+        shell_sync_core::stats::StatsResult {
+            total_commands: 16,
+            unique_commands: 3,
+            success_rate: 93.8,
+            top_commands: vec![("git status".into(), 10), ("ls".into(), 6)],
+            top_prefixes: vec![("git".into(), 10)],
+            top_subcommands: vec![("git status".into(), 10)],
+            avg_duration_ms: 120.0,
+            median_duration_ms: 80,
+            p95_duration_ms: 400,
+            hourly_distribution: hourly,
+            daily_distribution: vec![16, 0, 0, 0, 0, 0, 0],
+            per_directory: vec![("/src".into(), 16)],
+            per_machine: vec![("a".into(), 10), ("b".into(), 6)],
+            per_session: Vec::new(),
+            streak_days: 1,
+        }
+    }
+
+    #[test]
+    fn stats_without_color_has_no_ansi_escapes() {
+        let plain = render_stats(&sample_stats(), "30d", false, BarScale::Linear);
+        assert!(!plain.contains('\x1b'), "{plain:?}");
+        assert!(plain.contains("Top Commands"));
+        assert!(plain.contains("Top Subcommands"));
+        assert!(plain.contains("\u{2588}"));
+
+        let colored = render_stats(&sample_stats(), "30d", true, BarScale::Linear);
+        assert!(colored.contains("\x1b["));
+    }
+
+    #[test]
+    fn colored_bars_keep_column_alignment() {
+        let strip = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+        let colored = render_stats(&sample_stats(), "30d", true, BarScale::Linear);
+        assert_eq!(
+            strip.replace_all(&colored, ""),
+            render_stats(&sample_stats(), "30d", false, BarScale::Linear)
+        );
+    }
+
+    #[test]
+    fn log_scale_compresses_an_outlier() {
+        // One command run 1000 times next to ones run 10 and 2 times
+        assert_eq!(BarScale::Linear.bar_len(1000, 1000), BAR_WIDTH);
+        assert_eq!(BarScale::Log.bar_len(1000, 1000), BAR_WIDTH);
+        assert_eq!(BarScale::Linear.bar_len(10, 1000), 0);
+        assert_eq!(BarScale::Log.bar_len(10, 1000), 6);
+        assert!(BarScale::Log.bar_len(2, 1000) > BarScale::Linear.bar_len(2, 1000));
+        assert_eq!(BarScale::Log.bar_len(0, 1000), 0);
+        assert_eq!(BarScale::Log.bar_len(5, 0), 0);
+
+        let report = render_stats(&sample_stats(), "30d", false, BarScale::Log);
+        assert!(report.contains("\u{2588} = up to 10 cmds (log scale)"), "{report}");
+        assert!(report.contains("\u{2592} = up to 12 cmds (log scale)"), "{report}");
+    }
+
+    #[test]
+    fn json_stats_written_to_a_file_parse_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshots/nightly/stats.json");
+        let json = serde_json::to_string_pretty(&sample_stats()).unwrap() + "\n";
+
+        write_stats_output(Some(&path), &json).unwrap();
+        let back: shell_sync_core::stats::StatsResult =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(back.total_commands, 16);
+        assert_eq!(back.top_commands, sample_stats().top_commands);
+        assert_eq!(back.hourly_distribution[9], 12);
+        // A report written to a file never carries colour codes
+        assert!(!stats_color(Some(&path)));
+    }
+
+    const JSON_FIXTURE: &str = r#"[
+        {"name": "gs", "command": "git status"},
+        {"name": "k", "command": "kubectl --context 'prod'", "group": "work"}
+    ]"#;
+
+    const YAML_FIXTURE: &str = r#"
+gs: git status
+k:
+  command: kubectl --context 'prod'
+  group: work
+"#;
+
+    #[test]
+    fn json_and_yaml_fixtures_import_the_same_aliases() {
+        let mut from_json = parse_import(JSON_FIXTURE, ImportFormat::Json, "default").unwrap();
+        let mut from_yaml = parse_import(YAML_FIXTURE, ImportFormat::Yaml, "default").unwrap();
+        from_json.sort();
+        from_yaml.sort();
+        assert_eq!(from_json, from_yaml);
+
+        let entry = |name: &str, command: &str, group: &str| ImportEntry {
+            name: name.into(),
+            command: command.into(),
+            group: group.into(),
+        };
+        // Per-entry group overrides the default; the rest use it.
+        assert_eq!(
+            from_json,
+            [
+                entry("gs", "git status", "default"),
+                entry("k", "kubectl --context 'prod'", "work"),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_format_detection() {
+        assert_eq!(ImportFormat::Auto.detect(Some("a.yml"), "gs: x"), ImportFormat::Yaml);
+        assert_eq!(ImportFormat::Auto.detect(Some("a.JSON"), ""), ImportFormat::Json);
+        assert_eq!(ImportFormat::Auto.detect(None, "  [{}]"), ImportFormat::Json);
+        assert_eq!(ImportFormat::Auto.detect(Some(".zshrc"), "alias gs='git status'"), ImportFormat::Shell);
+        assert_eq!(ImportFormat::Shell.detect(Some("a.json"), "[]"), ImportFormat::Shell);
+        assert_eq!("yaml".parse(), Ok(ImportFormat::Yaml));
+        assert!("toml".parse::<ImportFormat>().is_err());
+    }
+
+    #[test]
+    fn shell_lines_use_default_group_and_bad_json_errors() {
+        let entries = parse_import("alias gs='git status'\n# comment\nll=ls -la\n", ImportFormat::Shell, "home").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|e| e.group == "home"));
+
+        assert!(parse_import("[{\"name\": \"x\"}]", ImportFormat::Json, "default").is_err());
+    }
+
+    #[test]
+    fn diff_reports_local_edits_additions_and_removals() {
+        let server = [
+            make_alias(1, "gs", "git status", "default"),
+            make_alias(2, "gl", "git log --oneline", "default"),
+            make_alias(3, "k", "kubectl", "work"),
+        ];
+        let file = r#"#!/bin/bash
+# Shell Sync - auto-generated aliases
+
+alias gs='git status'
+alias gl='git log --oneline --graph'
+alias mine='echo '\''local'\'''
+"#;
+        let local = shell_sync_core::shell::parse_alias_file(file);
+
+        let diff = diff_aliases(&local, &server);
+        assert_eq!(diff.added, [("mine".to_string(), "echo 'local'".to_string())]);
+        assert_eq!(diff.removed, [("k".to_string(), "kubectl".to_string())]);
+        assert_eq!(
+            diff.changed,
+            [(
+                "gl".to_string(),
+                "git log --oneline".to_string(),
+                "git log --oneline --graph".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn diff_of_generated_file_is_empty() {
+        use shell_sync_core::shell::{parse_alias_file, ShellType};
+
+        let server = [
+            make_alias(1, "gs", "git status", "default"),
+            make_alias(2, "say", r"echo 'hi' \ there", "default"),
+        ];
+        for shell in [ShellType::Bash, ShellType::Fish] {
+            let file: String = server
+                .iter()
+                .map(|a| format!("{}\n", shell.format_alias(&a.name, &a.command)))
+                .collect();
+            let local = parse_alias_file(&file);
+            assert!(diff_aliases(&local, &server).is_empty(), "{:?}", shell);
+        }
+    }
+
+    #[test]
+    fn summarize_counts_per_group() {
+        let aliases = [
+            make_alias(1, "gs", "git status", "default"),
+            make_alias(2, "gp", "git push", "default"),
+            make_alias(3, "k", "kubectl", "work"),
+        ];
+        let refs: Vec<&Alias> = aliases.iter().collect();
+        let summary = summarize_aliases(&refs);
+        assert_eq!(summary.total, 3);
+        assert_eq!(summary.groups.get("default"), Some(&2));
+        assert_eq!(summary.groups.get("work"), Some(&1));
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["groups"]["default"], 2);
+    }
+
+    #[test]
+    fn git_plan_lists_changes() {
+        let plan = serde_json::json!({
+            "written": ["aliases/work.sh"],
+            "removed": ["aliases/old.sh"],
+            "would_commit": true,
+        });
+        assert_eq!(
+            render_git_plan(&plan),
+            "  write   aliases/work.sh\n  remove  aliases/old.sh\n\
+             A commit would be made (dry run, nothing written)\n"
+        );
+        let idle = serde_json::json!({ "written": [], "removed": [], "would_commit": false });
+        assert_eq!(render_git_plan(&idle), "Nothing to commit\n");
+    }
+
+    #[test]
+    fn sort_by_usage_puts_most_used_first() {
+        let mut aliases: Vec<Alias> = ["a", "b", "c", "d"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| make_alias(i as i64, name, "ls", "default"))
+            .collect();
+        let uses = [("c".to_string(), 5), ("b".to_string(), 2), ("x".to_string(), 9)];
+        sort_by_usage(&mut aliases, &uses.into_iter().collect());
+        let names: Vec<&str> = aliases.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["c", "b", "a", "d"]);
+    }
+
+    #[test]
+    fn listing_puts_pinned_first() {
+        let mut aliases = vec![
+            make_alias(1, "a", "one", "default"),
+            make_alias(2, "b", "two", "work"),
+            make_alias(3, "c", "three", "default"),
+        ];
+        let names = |list: Vec<&Alias>| list.iter().map(|a| a.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(select_for_listing(&aliases, None)), ["a", "b", "c"]);
+
+        aliases[2].pinned = true;
+        aliases[1].pinned = true;
+        assert_eq!(names(select_for_listing(&aliases, None)), ["b", "c", "a"]);
+        assert_eq!(
+            names(select_for_listing(&aliases, Some("default"))),
+            ["c", "a"]
+        );
+    }
+
+    #[test]
+    fn summarize_filtered_subset() {
+        let aliases = [
+            make_alias(1, "gs", "git status", "default"),
+            make_alias(2, "k", "kubectl", "work"),
+        ];
+        let work: Vec<&Alias> = aliases.iter().filter(|a| a.group_name == "work").collect();
+        let summary = summarize_aliases(&work);
+        assert_eq!(summary.total, 1);
+        assert_eq!(summary.groups.len(), 1);
+
+        assert_eq!(summarize_aliases(&[]), AliasSummary::default());
+    }
+
+    fn test_config(server_url: String) -> ClientConfig {
+        toml::from_str(&format!(
+            r#"
+            server_url = "{server_url}"
+            machine_id = "m1"
+            auth_token = "tok"
+            groups = ["default"]
+            hostname = "host"
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn complete_aliases_lists_the_current_alias_names() {
+        use shell_sync_core::config::AliasOutput;
+        use shell_sync_core::shell::ShellType;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = ClientConfig {
+            groups: vec!["default".into(), "work".into()],
+            alias_dir: Some(dir.path().to_path_buf()),
+            alias_output: AliasOutput::PerGroup,
+            ..test_config("http://localhost:8888".into())
+        };
+        let alias = |name: &str, command: &str, group: &str, template: bool| Alias {
+            template,
+            ..make_alias(1, name, command, group)
+        };
+        let aliases = vec![
+            alias("gs", "git status", "default", false),
+            alias("deploy", "kubectl apply -f {{file}}", "work", true),
+            alias("gs", "git status -sb", "work", false),
+        ];
+
+        for shell in [ShellType::Bash, ShellType::Fish] {
+            crate::shell_writer::write_alias_files(
+                shell,
+                &aliases,
+                &config.groups,
+                config.alias_output,
+                config.alias_file_mode,
+                dir.path(),
+            )
+            .unwrap();
+            assert_eq!(
+                completion_candidates(CompletionKind::Aliases, shell, &config),
+                ["deploy", "gs"],
+                "{shell:?}"
+            );
+        }
+        assert_eq!(
+            completion_candidates(CompletionKind::Groups, ShellType::Bash, &config),
+            ["default", "work"]
+        );
+
+        // Nothing written yet: no candidates, no error
+        let empty = ClientConfig {
+            alias_dir: Some(dir.path().join("missing")),
+            ..config
+        };
+        assert!(completion_candidates(CompletionKind::Aliases, ShellType::Zsh, &empty).is_empty());
+    }
+
+    #[test]
+    fn paths_lists_every_location_and_redacts_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ClientConfig {
+            auth_token: "s3cret-token".into(),
+            ..test_config("http://sync.example:8888".into())
+        };
+        let paths = client_paths();
+        assert!(paths.len() >= 9);
+
+        let out = render_paths(&paths, Some(&config)).unwrap();
+        for (label, path) in &paths {
+            assert!(out.contains(label), "missing {label}");
+            assert!(out.contains(&path.display().to_string()), "missing {label} path");
+        }
+        assert!(!out.contains("s3cret-token"));
+        assert!(out.contains(r#"auth_token = "<redacted>""#));
+        assert!(out.contains(r#"server_url = "http://sync.example:8888""#));
+
+        let present = render_paths(&[("here", dir.path().to_path_buf())], None).unwrap();
+        assert!(!present.contains("(missing)"));
+        assert!(present.contains("Config: not loaded"));
+        let gone = render_paths(&[("gone", dir.path().join("nope"))], None).unwrap();
+        assert!(gone.contains("(missing)"));
+    }
+
+    #[test]
+    fn replace_literal() {
+        let aliases = vec![
+            make_alias(1, "kgp", "kubectl get pods", "default"),
+            make_alias(2, "gs", "git status", "default"),
+        ];
+        let edits = plan_alias_replacements(&aliases, "kubectl", "k", None, false).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].name, "kgp");
+        assert_eq!(edits[0].new_command, "k get pods");
+    }
+
+    #[test]
+    fn replace_literal_does_not_treat_find_as_regex() {
+        let aliases = vec![make_alias(1, "dots", "ls a.b axb", "default")];
+        let edits = plan_alias_replacements(&aliases, "a.b", "c", None, false).unwrap();
+        assert_eq!(edits[0].new_command, "ls c axb");
+    }
+
+    #[test]
+    fn replace_regex_with_backrefs() {
+        let aliases = vec![make_alias(1, "kl", "kubectl logs -n prod", "default")];
+        let edits =
+            plan_alias_replacements(&aliases, r"kubectl (\w+) -n (\w+)", "k -n $2 $1", None, true)
+                .unwrap();
+        assert_eq!(edits[0].new_command, "k -n prod logs");
+    }
+
+    #[test]
+    fn replace_respects_group_filter() {
+        let aliases = vec![
+            make_alias(1, "kgp", "kubectl get pods", "default"),
+            make_alias(2, "kgs", "kubectl get svc", "work"),
+        ];
+        let edits = plan_alias_replacements(&aliases, "kubectl", "k", Some("work"), false).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].name, "kgs");
+    }
+
+    #[test]
+    fn status_reports_storage_from_seeded_db() {
+        use shell_sync_core::db::SyncDatabase;
+        use shell_sync_core::models::HistoryEntry;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let db = SyncDatabase::open(path.to_str().unwrap()).unwrap();
+        for i in 0..3 {
+            let entry = HistoryEntry {
+                timestamp: 1_000 + i,
+                ..crate::test_entry(&format!("h{i}"), "ls")
+            };
+            db.insert_history_entry(&entry).unwrap();
+            if i == 0 {
+                db.add_history_pending(&entry).unwrap();
+            }
+        }
+
+        let usage = storage_usage(&db, &path, 2).unwrap();
+        assert_eq!(usage.history_rows, 3);
+        assert_eq!(usage.pending_history, 1);
+        assert_eq!(usage.offline_queue, 2);
+        assert!(usage.db_bytes > 0);
+
+        let out = render_storage(&usage, &StorageThresholds::default());
+        assert!(out.contains("History: 3 entries ("));
+        assert!(out.contains("History waiting to push: 1 entries"));
+        assert!(out.contains("Offline queue: 2 pending operations"));
+        assert!(!out.contains("Warning"));
+    }
+
+    #[test]
+    fn status_porcelain_reports_config_and_daemon_state() {
+        use crate::connection_status::{ConnectionState, ConnectionStatus};
+        let mut config = test_config("http://sync.local:8888".into());
+        config.groups.push("work".into());
+        let usage = StorageUsage {
+            pending_history: 3,
+            offline_queue: 1,
+            ..Default::default()
+        };
+        let conn = ConnectionStatus {
+            state: ConnectionState::Connected,
+            since: 1000,
+            last_error: None,
+            last_sync: Some(1_705_321_800_000),
+            last_alias_sync: Some(1_705_321_790_000),
+            unapplied_aliases: 0,
+            server_updated_at: Some(1_705_321_700_000),
+            applied_updated_at: Some(1_705_321_700_000),
+            encrypted_groups: ["work".to_string()].into(),
+        };
+
+        let report = StatusReport::new(&config, true, Some(&conn), &usage, vec!["work".into()]);
+        assert_eq!(
+            report.porcelain(),
+            "running=true\n\
+             server=http://sync.local:8888\n\
+             groups=default,work\n\
+             machine=m1\n\
+             pending=3\n\
+             offline_queue=1\n\
+             connected=true\n\
+             last_sync=1705321800000\n\
+             waiting_for_keys=work\n\
+             last_alias_sync=1705321790000\n\
+             unapplied_aliases=0\n\
+             version_gap_ms=0\n"
+        );
+
+        let stopped = StatusReport::new(&config, false, None, &usage, Vec::new());
+        let out = stopped.porcelain();
+        let lines: Vec<&str> = out.lines().collect();
+        let keys: Vec<&str> = lines
+            .iter()
+            .filter_map(|l| l.split_once('=').map(|(k, _)| k))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "running",
+                "server",
+                "groups",
+                "machine",
+                "pending",
+                "offline_queue",
+                "connected",
+                "last_sync",
+                "waiting_for_keys",
+                "last_alias_sync",
+                "unapplied_aliases",
+                "version_gap_ms"
+            ]
+        );
+        assert!(lines.contains(&"running=false"));
+        assert!(lines.contains(&"connected=false"));
+        assert!(lines.contains(&"last_sync="));
+
+        let json = serde_json::to_value(&stopped).unwrap();
+        assert_eq!(json["groups"], serde_json::json!(["default", "work"]));
+        assert_eq!(json["pending"], 3);
+        assert!(json["last_sync"].is_null());
+    }
+
+    #[test]
+    fn status_warns_when_thresholds_exceeded() {
+        let usage = StorageUsage {
+            history_rows: 20,
+            db_bytes: 3 * 1024 * 1024,
+            pending_history: 7,
+            offline_queue: 4,
+        };
+        let limits = StorageThresholds {
+            history_rows: 10,
+            db_bytes: u64::MAX,
+            pending_history: 5,
+            offline_queue: 5,
+        };
+        let out = render_storage(&usage, &limits);
+        assert!(out.contains("History: 20 entries (3.0 MB)"));
+        assert!(out.contains("Warning: the history database is large (20 entries, 3.0 MB)"));
+        assert!(out.contains("Warning: 7 history entries have not been pushed"));
+        assert!(!out.contains("alias changes are queued offline"));
+        assert_eq!(format_size(512), "512 B");
+    }
+
+    #[test]
+    fn replace_invalid_regex_errors() {
+        let aliases = vec![make_alias(1, "gs", "git status", "default")];
+        assert!(plan_alias_replacements(&aliases, "(", "x", None, true).is_err());
+    }
+
+    /// Spawn a fake server that serves one alias and counts PUT requests.
+    async fn fake_server(writes: Arc<AtomicUsize>) -> String {
+        use axum::routing::{get, put};
+
+        let app = axum::Router::new()
+            .route(
+                "/api/aliases",
+                get(|| async {
+                    axum::Json(serde_json::json!({
+                        "aliases": [make_alias(7, "kgp", "kubectl get pods", "default")]
+                    }))
+                }),
+            )
+            .route(
+                "/api/aliases/:id",
+                put(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                    assert_eq!(body["expected_version"], 1);
+                    assert!(body["updated_at"].as_i64().is_some_and(|t| t > 0), "{body}");
+                    let writes = Arc::clone(&writes);
+                    async move {
+                        writes.fetch_add(1, Ordering::SeqCst);
+                        axum::Json(serde_json::json!({ "message": "ok" }))
+                    }
+                }),
+            );
+
+        crate::spawn_fake(app).await
+    }
+
+    #[tokio::test]
+    async fn sed_dry_run_makes_no_writes() {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let config = test_config(fake_server(Arc::clone(&writes)).await);
+        let client = reqwest::Client::new();
+
+        sed_aliases_with(&client, &config, "kubectl", "k", None, false, true)
+            .await
+            .unwrap();
+        assert_eq!(writes.load(Ordering::SeqCst), 0);
+
+        sed_aliases_with(&client, &config, "kubectl", "k", None, false, false)
+            .await
+            .unwrap();
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn ping_reports_latency_and_token_validity() {
+        use axum::http::{HeaderMap, StatusCode};
+        use axum::routing::get;
+
+        let app = axum::Router::new()
+            .route("/api/health", get(|| async { "ok" }))
+            .route(
+                "/api/groups",
+                get(|headers: HeaderMap| async move {
+                    match headers.get("authorization").and_then(|v| v.to_str().ok()) {
+                        Some("Bearer tok") => Ok(axum::Json(serde_json::json!({ "groups": [] }))),
+                        _ => Err(StatusCode::UNAUTHORIZED),
+                    }
+                }),
+            );
+        let client = reqwest::Client::new();
+
+        let mut config = test_config(crate::spawn_fake(app).await);
+        let report = ping_server(&client, &config, 3).await;
+        assert!(report.error.is_none());
+        assert_eq!(report.latencies.len(), 3);
+        assert!(report.latencies.iter().all(|d| !d.is_zero()));
+        assert!(report.authorized());
+
+        config.auth_token = "stale".into();
+        let report = ping_server(&client, &config, 1).await;
+        assert!(report.error.is_none());
+        assert_eq!(report.auth_status, Some(reqwest::StatusCode::UNAUTHORIZED));
+        assert!(!report.authorized());
+
+        // Nothing listening
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = test_config(format!("http://{}", closed.local_addr().unwrap()));
+        drop(closed);
+        let report = ping_server(&client, &config, 3).await;
+        assert!(report.error.is_some());
+        assert!(report.latencies.is_empty());
+        assert_eq!(report.auth_status, None);
+    }
+
+    /// Spawn a fake server that counts list, lookup-by-name and PUT requests.
+    async fn fake_lookup_server(
+        lists: Arc<AtomicUsize>,
+        lookups: Arc<AtomicUsize>,
+        writes: Arc<AtomicUsize>,
+    ) -> String {
+        use axum::extract::Path;
+        use axum::http::StatusCode;
+        use axum::routing::{get, put};
+        use axum::Json;
+
+        let app = axum::Router::new()
+            .route(
+                "/api/aliases",
+                get(move || {
+                    lists.fetch_add(1, Ordering::SeqCst);
+                    async { axum::Json(serde_json::json!({ "aliases": [] })) }
+                }),
+            )
+            .route(
+                "/api/aliases/name/:name",
+                get(move |Path(name): Path<String>| {
+                    lookups.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if name == "kgp" {
+                            let alias = make_alias(7, "kgp", "kubectl get pods", "default");
+                            Ok(axum::Json(serde_json::json!({ "alias": alias })))
+                        } else {
+                            Err(StatusCode::NOT_FOUND)
+                        }
+                    }
+                }),
+            )
+            .route(
+                "/api/aliases/:id",
+                put(
+                    move |Path(id): Path<i64>, Json(body): Json<serde_json::Value>| {
+                        assert_eq!(id, 7);
+                        assert_eq!(body["expected_version"], 1);
+                        assert!(body["updated_at"].as_i64().is_some_and(|t| t > 0), "{body}");
+                        writes.fetch_add(1, Ordering::SeqCst);
+                        async { axum::Json(serde_json::json!({ "message": "ok" })) }
+                    },
+                ),
+            );
+
+        crate::spawn_fake(app).await
+    }
+
+    #[tokio::test]
+    async fn update_looks_up_single_alias() {
+        let lists = Arc::new(AtomicUsize::new(0));
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let writes = Arc::new(AtomicUsize::new(0));
+        let url = fake_lookup_server(
+            Arc::clone(&lists),
+            Arc::clone(&lookups),
+            Arc::clone(&writes),
+        )
+        .await;
+        let config = test_config(url);
+        let client = reqwest::Client::new();
+
+        update_alias_with(&client, &config, "kgp", "k get pods", "default", None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(lists.load(Ordering::SeqCst), 0);
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+
+        let missing =
+            update_alias_with(&client, &config, "nope", "x", "default", None, None, None).await;
+        assert!(missing.unwrap_err().to_string().contains("not found"));
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn require_feature_checks_the_server_version() {
+        use axum::routing::get;
+
+        let app = axum::Router::new().route(
+            "/api/version",
+            get(|| async {
+                axum::Json(serde_json::json!({
+                    "version": "9.9.9",
+                    "features": [features::ALIAS_SHELLS],
+                }))
+            }),
+        );
+        let config = test_config(crate::spawn_fake(app).await);
+        let client = reqwest::Client::new();
+
+        let version = fetch_server_version(&client, &config).await.unwrap().unwrap();
+        assert_eq!(version.version, "9.9.9");
+        require_feature(&client, &config, features::ALIAS_SHELLS, "--shell")
+            .await
+            .unwrap();
+        let err = require_feature(&client, &config, features::ENCRYPTED_ALIASES, "encryption")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("version 9.9.9"));
+
+        // An answer that is not a feature list fails; no answer at all passes
+        let app = axum::Router::new().route("/api/version", get(|| async { "<html>" }));
+        let garbled = test_config(crate::spawn_fake(app).await);
+        assert!(
+            require_feature(&client, &garbled, features::ALIAS_SHELLS, "--shell")
+                .await
+                .is_err()
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let down = test_config(format!("http://{}", listener.local_addr().unwrap()));
+        drop(listener);
+        require_feature(&client, &down, features::ALIAS_SHELLS, "--shell")
+            .await
+            .unwrap();
+
+        // A server without /api/version lists no features
+        let counter = || Arc::new(AtomicUsize::new(0));
+        let old = test_config(fake_lookup_server(counter(), counter(), counter()).await);
+        assert!(fetch_server_version(&client, &old).await.unwrap().is_none());
+        let writes = Arc::new(AtomicUsize::new(0));
+        let old = test_config(fake_lookup_server(counter(), counter(), Arc::clone(&writes)).await);
+        let shells = Some(vec!["zsh".to_string()]);
+        let err = update_alias_with(&client, &old, "kgp", "k", "default", None, None, shells)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("too old"));
+        assert_eq!(writes.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn show_prints_alias_details() {
+        let counter = || Arc::new(AtomicUsize::new(0));
+        let url = fake_lookup_server(counter(), counter(), counter()).await;
+        let config = test_config(url);
+        let client = reqwest::Client::new();
+        let tz = DisplayTz::parse("utc").unwrap();
+
+        let out = show_alias_with(&client, &config, "kgp", "default", false, tz)
+            .await
+            .unwrap();
+        assert_eq!(
+            out,
+            "Name:       kgp\n\
+             Command:    kubectl get pods\n\
+             Group:      default\n\
+             Shells:     all\n\
+             Version:    1\n\
+             Pinned:     no\n\
+             Created by: m1\n\
+             Created:    1970-01-01 00:00:01\n\
+             Updated:    1970-01-01 00:00:01\n"
+        );
+
+        let json = show_alias_with(&client, &config, "kgp", "default", true, tz)
+            .await
+            .unwrap();
+        let alias: Alias = serde_json::from_str(&json).unwrap();
+        assert_eq!((alias.id, alias.command.as_str()), (7, "kubectl get pods"));
+
+        let missing = show_alias_with(&client, &config, "nope", "work", false, tz).await;
+        assert_eq!(
+            missing.unwrap_err().to_string(),
+            "Alias 'nope' not found in group 'work'"
+        );
+    }
+}
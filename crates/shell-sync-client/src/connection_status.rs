@@ -0,0 +1,351 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use shell_sync_core::tz::DisplayTz;
+use tracing::warn;
+
+/// Whether the daemon currently holds a WebSocket connection to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    Connected,
+    #[default]
+    Disconnected,
+}
+
+/// Connection state the daemon publishes for `shell-sync status`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ConnectionStatus {
+    pub state: ConnectionState,
+    /// When `state` last changed (epoch ms).
+    pub since: i64,
+    /// Error that ended the most recent connection, if it failed.
+    pub last_error: Option<String>,
+    /// Last time a message was received from the server (epoch ms).
+    pub last_sync: Option<i64>,
+    /// Last time aliases were fetched and written out (epoch ms).
+    #[serde(default)]
+    pub last_alias_sync: Option<i64>,
+    /// Aliases the server returned at that sync that were not applied, e.g.
+    /// because they could not be decrypted.
+    #[serde(default)]
+    pub unapplied_aliases: usize,
+    /// Newest `updated_at` among the aliases the server returned at that sync.
+    #[serde(default)]
+    pub server_updated_at: Option<i64>,
+    /// Newest `updated_at` among the aliases that sync applied.
+    #[serde(default)]
+    pub applied_updated_at: Option<i64>,
+    /// Groups the server has sent encrypted aliases or history for. Only
+    /// these are worth asking other members for a missing key.
+    #[serde(default)]
+    pub encrypted_groups: BTreeSet<String>,
+}
+
+/// How stale this machine's aliases are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncLag {
+    /// Time since the last successful alias sync.
+    pub since_sync_ms: i64,
+    /// How far the newest applied change trails the server's newest change;
+    /// `None` when the server has aliases but none were applied.
+    pub version_gap_ms: Option<i64>,
+    /// Server aliases missing from the last sync.
+    pub unapplied: usize,
+}
+
+impl ConnectionStatus {
+    /// Read a status file written by the daemon.
+    pub fn load(path: &Path) -> Option<Self> {
+        serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+    }
+
+    /// Lines for `shell-sync status`.
+    pub fn render(&self, tz: DisplayTz) -> String {
+        let fmt = "%Y-%m-%d %H:%M:%S";
+        let state = match self.state {
+            ConnectionState::Connected => "connected",
+            ConnectionState::Disconnected => "disconnected",
+        };
+        let mut out = format!("Connection: {state} since {}\n", tz.format(self.since, fmt));
+        if let Some(error) = &self.last_error {
+            out.push_str(&format!("Last error: {error}\n"));
+        }
+        match self.last_sync {
+            Some(ts) => out.push_str(&format!("Last sync: {}\n", tz.format(ts, fmt))),
+            None => out.push_str("Last sync: never\n"),
+        }
+        if let (Some(ts), Some(lag)) = (
+            self.last_alias_sync,
+            self.sync_lag(chrono::Utc::now().timestamp_millis()),
+        ) {
+            let mut gap = match lag.unapplied {
+                0 => "up to date with the server".to_string(),
+                1 => "1 server alias not applied".to_string(),
+                n => format!("{n} server aliases not applied"),
+            };
+            if let Some(ms @ 1..) = lag.version_gap_ms {
+                gap.push_str(&format!(", {} behind", format_lag(ms)));
+            }
+            out.push_str(&format!(
+                "Alias sync: {} ({} ago, {gap})\n",
+                tz.format(ts, fmt),
+                format_lag(lag.since_sync_ms)
+            ));
+        }
+        out
+    }
+
+    /// Alias sync lag at `now` (epoch ms); `None` before the first sync.
+    pub fn sync_lag(&self, now: i64) -> Option<SyncLag> {
+        let at = self.last_alias_sync?;
+        let version_gap_ms = match (self.server_updated_at, self.applied_updated_at) {
+            (None, _) => Some(0),
+            (Some(server), Some(applied)) => Some((server - applied).max(0)),
+            (Some(_), None) => None,
+        };
+        Some(SyncLag {
+            since_sync_ms: (now - at).max(0),
+            version_gap_ms,
+            unapplied: self.unapplied_aliases,
+        })
+    }
+}
+
+/// Coarse duration for status lines: `45s`, `12m`, `3h`, `2d`.
+fn format_lag(ms: i64) -> String {
+    let secs = ms / 1000;
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86_399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
+/// How often incoming frames may rewrite the status file.
+const SYNC_SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The daemon's status file, rewritten on every connection state change.
+pub struct StatusFile {
+    path: PathBuf,
+    status: Mutex<ConnectionStatus>,
+    /// When [`Self::record_sync`] last wrote the file.
+    sync_saved: Mutex<Option<std::time::Instant>>,
+}
+
+impl StatusFile {
+    /// Start out disconnected and write the file.
+    pub fn create(path: PathBuf) -> Self {
+        let file = Self {
+            path,
+            status: Mutex::new(ConnectionStatus {
+                since: chrono::Utc::now().timestamp_millis(),
+                ..Default::default()
+            }),
+            sync_saved: Mutex::new(None),
+        };
+        file.save();
+        file
+    }
+
+    pub fn set_connected(&self) {
+        self.update(|s| {
+            if s.state != ConnectionState::Connected {
+                s.state = ConnectionState::Connected;
+                s.since = chrono::Utc::now().timestamp_millis();
+            }
+            s.last_error = None;
+        });
+    }
+
+    /// Record a lost connection; `error` is `None` for a clean close.
+    pub fn set_disconnected(&self, error: Option<String>) {
+        self.update(|s| {
+            if s.state != ConnectionState::Disconnected {
+                s.state = ConnectionState::Disconnected;
+                s.since = chrono::Utc::now().timestamp_millis();
+            }
+            s.last_error = error;
+        });
+    }
+
+    /// Note that the server just sent us something. This runs for every
+    /// frame, so the file is rewritten at most once per second; any other
+    /// update writes the latest time too.
+    pub async fn record_sync(&self) {
+        self.status.lock().unwrap().last_sync = Some(chrono::Utc::now().timestamp_millis());
+        {
+            let mut saved = self.sync_saved.lock().unwrap();
+            if saved.is_some_and(|at| at.elapsed() < SYNC_SAVE_INTERVAL) {
+                return;
+            }
+            *saved = Some(std::time::Instant::now());
+        }
+        let json = match serde_json::to_string(&self.status()) {
+            Ok(json) => json,
+            Err(e) => return warn!("Failed to encode daemon status: {e}"),
+        };
+        let tmp = self.path.with_extension("sync.tmp");
+        let written = match tokio::fs::write(&tmp, json).await {
+            Ok(()) => tokio::fs::rename(&tmp, &self.path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            warn!(path = %self.path.display(), "Failed to write daemon status: {e}");
+        }
+    }
+
+    /// Note a successful alias sync: how many of the server's aliases did
+    /// not make it into the alias file, and the newest `updated_at` the
+    /// server sent and that was applied.
+    pub fn record_alias_sync(
+        &self,
+        unapplied: usize,
+        server_updated_at: Option<i64>,
+        applied_updated_at: Option<i64>,
+    ) {
+        self.update(|s| {
+            s.last_alias_sync = Some(chrono::Utc::now().timestamp_millis());
+            s.unapplied_aliases = unapplied;
+            s.server_updated_at = server_updated_at;
+            s.applied_updated_at = applied_updated_at;
+        });
+    }
+
+    /// Note groups seen with encrypted data; the file is rewritten only when
+    /// one is new.
+    pub fn record_encrypted_groups<'a>(&self, groups: impl IntoIterator<Item = &'a str>) {
+        let known = self.status().encrypted_groups;
+        let new: Vec<String> = groups
+            .into_iter()
+            .filter(|g| !known.contains(*g))
+            .map(String::from)
+            .collect();
+        if !new.is_empty() {
+            self.update(|s| s.encrypted_groups.extend(new));
+        }
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Remove the file when the daemon stops.
+    pub fn remove(&self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ConnectionStatus)) {
+        f(&mut self.status.lock().unwrap());
+        self.save();
+    }
+
+    /// Write via a temp file so `status` never reads a partial file.
+    fn save(&self) {
+        let json = match serde_json::to_string(&self.status()) {
+            Ok(json) => json,
+            Err(e) => return warn!("Failed to encode daemon status: {e}"),
+        };
+        let tmp = self.path.with_extension("tmp");
+        if let Err(e) = std::fs::write(&tmp, json).and_then(|_| std::fs::rename(&tmp, &self.path)) {
+            warn!(path = %self.path.display(), "Failed to write daemon status: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn status_file_tracks_connection_transitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon-status.json");
+
+        let file = StatusFile::create(path.clone());
+        let status = ConnectionStatus::load(&path).unwrap();
+        assert_eq!(status.state, ConnectionState::Disconnected);
+        assert_eq!(status.last_sync, None);
+
+        file.set_connected();
+        file.record_sync().await;
+        let connected = ConnectionStatus::load(&path).unwrap();
+        assert_eq!(connected.state, ConnectionState::Connected);
+        assert!(connected.last_sync.is_some());
+
+        // Frames right after that only update the in-memory time
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        file.record_sync().await;
+        assert_eq!(ConnectionStatus::load(&path).unwrap(), connected);
+        assert!(file.status().last_sync > connected.last_sync);
+
+        file.set_disconnected(Some("connection reset".into()));
+        let status = ConnectionStatus::load(&path).unwrap();
+        assert_eq!(status.state, ConnectionState::Disconnected);
+        assert_eq!(status.last_error.as_deref(), Some("connection reset"));
+        // The latest sync is written out and kept across the disconnect
+        assert_eq!(status.last_sync, file.status().last_sync);
+        assert!(status.since >= connected.since);
+
+        file.remove();
+        assert!(ConnectionStatus::load(&path).is_none());
+    }
+
+    #[test]
+    fn render_shows_state_error_and_sync() {
+        let tz = DisplayTz::parse("utc").unwrap();
+        let status = ConnectionStatus {
+            state: ConnectionState::Disconnected,
+            since: 1_705_321_800_000,
+            last_error: Some("connection refused".into()),
+            last_sync: None,
+            ..Default::default()
+        };
+        assert_eq!(
+            status.render(tz),
+            "Connection: disconnected since 2024-01-15 12:30:00\n\
+             Last error: connection refused\n\
+             Last sync: never\n"
+        );
+    }
+
+    #[test]
+    fn alias_sync_lag_is_near_zero_after_a_sync_and_grows_without_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon-status.json");
+        let file = StatusFile::create(path.clone());
+        assert_eq!(file.status().sync_lag(0), None);
+
+        file.record_alias_sync(0, Some(5_000), Some(5_000));
+        let status = ConnectionStatus::load(&path).unwrap();
+        let synced_at = status.last_alias_sync.unwrap();
+        let now = chrono::Utc::now().timestamp_millis();
+        let lag = status.sync_lag(now).unwrap();
+        assert!(lag.since_sync_ms < 5_000, "{lag:?}");
+        assert_eq!(lag.version_gap_ms, Some(0));
+        assert_eq!(lag.unapplied, 0);
+
+        // An hour later with no sync in between
+        let later = status.sync_lag(synced_at + 3_600_000).unwrap();
+        assert_eq!(later.since_sync_ms, 3_600_000);
+
+        // Aliases the daemon could not apply leave a gap
+        file.record_alias_sync(2, Some(7_205_000), Some(5_000));
+        let status = ConnectionStatus::load(&path).unwrap();
+        let lag = status.sync_lag(now).unwrap();
+        assert_eq!(lag.unapplied, 2);
+        assert_eq!(lag.version_gap_ms, Some(7_200_000));
+        assert!(status
+            .render(DisplayTz::parse("utc").unwrap())
+            .contains("2 server aliases not applied, 2h behind"));
+
+        // Nothing applied leaves the gap unknown; an empty server has none
+        file.record_alias_sync(1, Some(5_000), None);
+        assert_eq!(file.status().sync_lag(now).unwrap().version_gap_ms, None);
+        file.record_alias_sync(0, None, None);
+        assert_eq!(file.status().sync_lag(now).unwrap().version_gap_ms, Some(0));
+    }
+}
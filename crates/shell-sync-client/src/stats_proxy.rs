@@ -8,6 +8,7 @@ use axum::Router;
 use serde::Deserialize;
 use shell_sync_core::db::SyncDatabase;
 use shell_sync_core::stats::{compute_stats, parse_last_filter, StatsFilter, StatsResult};
+use shell_sync_core::tz::DisplayTz;
 use tokio::net::TcpListener;
 use tower_http::cors::{Any, CorsLayer};
 use tracing::{error, info};
@@ -26,9 +27,22 @@ struct SearchQuery {
     limit: Option<i64>,
 }
 
-/// Start the local stats HTTP proxy on 127.0.0.1:18888.
-/// This is spawned as a background task in the daemon.
-pub async fn start_stats_proxy(db: Arc<SyncDatabase>) -> anyhow::Result<()> {
+/// Run the proxy as a background task unless `port` is 0. A failure such as
+/// the port being taken is logged; the rest of the daemon keeps running.
+pub fn spawn_stats_proxy(db: Arc<SyncDatabase>, port: u16) -> Option<tokio::task::JoinHandle<()>> {
+    if port == 0 {
+        info!("Stats proxy disabled (stats_proxy_port = 0)");
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        if let Err(e) = start_stats_proxy(db, port).await {
+            error!("Stats proxy not started: {e:#}");
+        }
+    }))
+}
+
+/// Start the local stats HTTP proxy on 127.0.0.1:`port`.
+pub async fn start_stats_proxy(db: Arc<SyncDatabase>, port: u16) -> anyhow::Result<()> {
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
@@ -40,8 +54,16 @@ pub async fn start_stats_proxy(db: Arc<SyncDatabase>) -> anyhow::Result<()> {
         .layer(cors)
         .with_state(db);
 
-    let listener = TcpListener::bind("127.0.0.1:18888").await?;
-    info!("Stats proxy listening on http://127.0.0.1:18888");
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.map_err(|e| {
+        if e.kind() == std::io::ErrorKind::AddrInUse {
+            anyhow::anyhow!(
+                "port {port} is already in use; set stats_proxy_port in config.toml to a free port, or 0 to disable the proxy"
+            )
+        } else {
+            anyhow::anyhow!("failed to bind 127.0.0.1:{port}: {e}")
+        }
+    })?;
+    info!("Stats proxy listening on http://127.0.0.1:{port}");
 
     axum::serve(listener, app).await?;
 
@@ -57,9 +79,12 @@ async fn handle_stats(
 
     let filter = StatsFilter {
         after_timestamp,
+        before_timestamp: None,
         machine_id: params.machine,
         group_name: params.group,
         directory: params.directory,
+        tz: DisplayTz::from_env(),
+        normalize: false,
     };
 
     match compute_stats(&db, &filter) {
@@ -78,7 +103,7 @@ async fn handle_search(
     let query = params.q.as_deref().unwrap_or("");
     let limit = params.limit.unwrap_or(50).min(500);
 
-    match db.search_history(query, None, None, None, limit, 0) {
+    match db.search_history(query, None, None, None, None, limit, 0) {
         Ok(entries) => Ok(Json(entries)),
         Err(e) => {
             error!("Search failed: {e}");
@@ -86,3 +111,61 @@ async fn handle_search(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db(dir: &tempfile::TempDir) -> Arc<SyncDatabase> {
+        Arc::new(SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn port_zero_disables_the_proxy() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(spawn_stats_proxy(test_db(&dir), 0).is_none());
+    }
+
+    #[tokio::test]
+    async fn taken_port_is_a_handled_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = test_db(&dir);
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let err = start_stats_proxy(db.clone(), port).await.unwrap_err();
+        assert!(err.to_string().contains("already in use"), "{err}");
+
+        // The spawned task logs and ends instead of panicking, and other
+        // daemon tasks on the runtime carry on.
+        let heartbeat = tokio::spawn(async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            "alive"
+        });
+        spawn_stats_proxy(db, port).unwrap().await.unwrap();
+        assert_eq!(heartbeat.await.unwrap(), "alive");
+        drop(taken);
+    }
+
+    #[tokio::test]
+    async fn serves_on_the_configured_port() {
+        let dir = tempfile::tempdir().unwrap();
+        let port = {
+            let probe = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            probe.local_addr().unwrap().port()
+        };
+        let handle = spawn_stats_proxy(test_db(&dir), port).unwrap();
+
+        let url = format!("http://127.0.0.1:{port}/api/local/stats?last=all");
+        let mut status = None;
+        for _ in 0..50 {
+            if let Ok(resp) = reqwest::get(&url).await {
+                status = Some(resp.status());
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(status, Some(reqwest::StatusCode::OK));
+        handle.abort();
+    }
+}
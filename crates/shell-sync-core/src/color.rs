@@ -0,0 +1,41 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turn color off for the rest of the process (the `--no-color` flag).
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether styled output is allowed at all: not turned off with `--no-color`
+/// or a non-empty `NO_COLOR` (see <https://no-color.org>).
+pub fn enabled() -> bool {
+    !DISABLED.load(Ordering::Relaxed)
+        && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+/// Whether to style what is printed to stdout: allowed, and stdout is a terminal.
+pub fn stdout_enabled() -> bool {
+    enabled() && std::io::stdout().is_terminal()
+}
+
+/// Wrap `text` in the ANSI SGR sequence `sgr` (e.g. `"1;36"`) when `on`.
+pub fn paint(on: bool, sgr: &str, text: &str) -> String {
+    if on {
+        format!("\x1b[{}m{}\x1b[0m", sgr, text)
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint_only_when_on() {
+        assert_eq!(paint(false, "1", "hi"), "hi");
+        assert_eq!(paint(true, "1;36", "hi"), "\x1b[1;36mhi\x1b[0m");
+    }
+}
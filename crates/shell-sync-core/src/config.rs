@@ -1,9 +1,14 @@
+use crate::models::HistoryEntry;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 /// Server configuration stored in config.toml.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
+    /// IP address the HTTP/WebSocket listener binds to.
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
     #[serde(default = "default_port")]
     pub port: u16,
     #[serde(default = "default_db_path")]
@@ -16,6 +21,120 @@ pub struct ServerConfig {
     pub web_ui_enabled: bool,
     #[serde(default = "default_git_sync_interval")]
     pub git_sync_interval_secs: u64,
+    /// Outbound messages queued per WebSocket client before the overflow policy applies.
+    #[serde(default = "default_ws_channel_capacity")]
+    pub ws_channel_capacity: usize,
+    #[serde(default)]
+    pub ws_overflow_policy: WsOverflowPolicy,
+    /// Seconds between pings the server sends each WebSocket client; 0 disables them.
+    #[serde(default = "default_ping_interval_secs")]
+    pub ws_ping_interval_secs: u64,
+    #[serde(default)]
+    pub conflict_strategy: ConflictStrategy,
+    /// Bearer token for admin-only endpoints such as the audit log; unset disables them.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Browser origins allowed to call the API cross-origin. Empty means only
+    /// `http://localhost:<port>` and `http://127.0.0.1:<port>`.
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+    /// Allow any origin (development only).
+    #[serde(default)]
+    pub cors_any: bool,
+    /// How long database calls wait on a lock before failing.
+    #[serde(default = "default_db_busy_timeout_ms")]
+    pub db_busy_timeout_ms: u64,
+    /// Database connections kept open for concurrent requests.
+    #[serde(default = "default_db_pool_size")]
+    pub db_pool_size: u32,
+    /// Seconds between WAL checkpoints; 0 disables them.
+    #[serde(default = "default_wal_checkpoint_interval")]
+    pub wal_checkpoint_interval_secs: u64,
+    /// Where the running server records its PID; unset writes none.
+    #[serde(default)]
+    pub pid_file: Option<PathBuf>,
+    /// Largest request body, in bytes, accepted by `/api/register`.
+    #[serde(default = "default_max_register_body_bytes")]
+    pub max_register_body_bytes: usize,
+    /// Largest request body, in bytes, accepted by routes without a specific limit.
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Largest bulk upload, in bytes: `/api/import` bodies and WebSocket
+    /// messages such as history batches.
+    #[serde(default = "default_max_bulk_body_bytes")]
+    pub max_bulk_body_bytes: usize,
+    /// Days a deleted alias is remembered so stale adds cannot resurrect it;
+    /// 0 keeps tombstones forever.
+    #[serde(default = "default_alias_tombstone_days")]
+    pub alias_tombstone_days: u64,
+    /// Days authentication attempts are kept in the audit log; 0 keeps them
+    /// forever.
+    #[serde(default = "default_auth_log_days")]
+    pub auth_log_days: u64,
+    /// Seconds an API request may take before it fails with 503; 0 disables.
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// Like `request_timeout_secs`, for the history and audit listings that
+    /// can scan many rows.
+    #[serde(default = "default_slow_request_timeout_secs")]
+    pub slow_request_timeout_secs: u64,
+    /// Regexes an alias command must not match in any group.
+    #[serde(default)]
+    pub blocked_command_patterns: Vec<String>,
+    /// Additional blocked command regexes, keyed by group.
+    #[serde(default)]
+    pub group_blocked_command_patterns: BTreeMap<String, Vec<String>>,
+}
+
+/// How the server settles an alias update made against a stale version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Record an unresolved conflict and reject the update for the user to resolve.
+    #[default]
+    Manual,
+    /// Keep whichever edit has the highest `updated_at`.
+    LastWriteWins,
+    /// Keep the edit that reached the server first.
+    FirstWriteWins,
+}
+
+impl ConflictStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Manual => "manual",
+            Self::LastWriteWins => "last_write_wins",
+            Self::FirstWriteWins => "first_write_wins",
+        }
+    }
+}
+
+impl std::str::FromStr for ConflictStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().replace('-', "_").as_str() {
+            "manual" => Ok(Self::Manual),
+            "last_write_wins" | "lww" => Ok(Self::LastWriteWins),
+            "first_write_wins" | "fww" => Ok(Self::FirstWriteWins),
+            other => Err(format!(
+                "unknown conflict strategy '{}' (expected manual, last-write-wins or first-write-wins)",
+                other
+            )),
+        }
+    }
+}
+
+/// What the server does when a WebSocket client's outbound queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WsOverflowPolicy {
+    /// Disconnect the client; it resyncs when it reconnects.
+    #[default]
+    Disconnect,
+    /// Drop the message. Alias events collapse into a single `sync_required`
+    /// sent once the queue drains.
+    Coalesce,
 }
 
 /// Client configuration stored in ~/.shell-sync/config.toml.
@@ -26,21 +145,375 @@ pub struct ClientConfig {
     pub auth_token: String,
     pub groups: Vec<String>,
     pub hostname: String,
+    #[serde(default)]
+    pub history_id_mode: HistoryIdMode,
+    /// Groups whose shell history is stored locally but never pushed to the server.
+    #[serde(default)]
+    pub local_only_groups: Vec<String>,
+    /// Captured commands longer than this many bytes are truncated; 0 disables the limit.
+    #[serde(default = "default_max_command_len")]
+    pub max_command_len: usize,
+    /// Captured durations above this many seconds (e.g. a laptop that slept
+    /// mid-command) are stored as unknown; 0 disables the limit.
+    #[serde(default = "default_max_duration_secs")]
+    pub max_duration_secs: u64,
+    /// How long the daemon's history database waits on a lock before failing.
+    #[serde(default = "default_db_busy_timeout_ms")]
+    pub db_busy_timeout_ms: u64,
+    /// Port of the local stats proxy on 127.0.0.1; 0 disables it.
+    #[serde(default = "default_stats_proxy_port")]
+    pub stats_proxy_port: u16,
+    /// Most history entries kept waiting to be pushed; the oldest beyond this
+    /// are dropped from the queue (not from history). 0 means no limit.
+    #[serde(default = "default_max_pending_history")]
+    pub max_pending_history: usize,
+    /// Search mode the search UI starts in (`fuzzy`, `prefix`, `fulltext`,
+    /// `regex`); `SHELL_SYNC_SEARCH_MODE` overrides it.
+    #[serde(default)]
+    pub default_search_mode: Option<String>,
+    /// Filter the search UI starts with (`global`, `host`, `session`,
+    /// `directory`, `group`); `SHELL_SYNC_FILTER_MODE` overrides it.
+    #[serde(default)]
+    pub default_filter_mode: Option<String>,
+    /// Record all commands, only successful ones, or only failed ones.
+    #[serde(default)]
+    pub capture_policy: CapturePolicy,
+    /// Write all aliases to one file or one file per group.
+    #[serde(default)]
+    pub alias_output: AliasOutput,
+    /// Rewrite only the managed block of each alias file, or the whole file.
+    #[serde(default)]
+    pub alias_file_mode: AliasFileMode,
+    /// Where alias files are written; defaults to ~/.shell-sync.
+    #[serde(default)]
+    pub alias_dir: Option<PathBuf>,
+    /// Delete local history older than this ("90d", "1y"); unset or "all"
+    /// keeps it forever.
+    #[serde(default)]
+    pub history_retention: Option<String>,
+    /// Per-group overrides of `history_retention`, e.g. `scratch = "7d"`.
+    #[serde(default)]
+    pub group_history_retention: BTreeMap<String, String>,
+    /// Seconds between the daemon's pings; it reconnects when nothing arrives
+    /// from the server for twice this long.
+    #[serde(default = "default_ping_interval_secs")]
+    pub ping_interval_secs: u64,
+    /// Groups whose commands are pushed without their arguments; the full
+    /// command is still kept in local history.
+    #[serde(default)]
+    pub strip_args_groups: Vec<String>,
+    /// Words of a command kept in `strip_args_groups` (the program is the first).
+    #[serde(default = "default_strip_args_keep")]
+    pub strip_args_keep: usize,
+    /// How shell hooks reach the daemon: a Unix socket, or a TCP port on
+    /// 127.0.0.1 where Unix sockets are awkward (WSL, shared volumes).
+    #[serde(default)]
+    pub capture_transport: CaptureTransport,
+    /// Port used when `capture_transport` is `tcp`.
+    #[serde(default = "default_capture_port")]
+    pub capture_port: u16,
+    /// Redact or withhold pushed history that looks like it holds a secret.
+    #[serde(default)]
+    pub history_secrets: HistorySecretPolicy,
+    /// Write aliases flagged `expand` double-quoted, so `$VAR` and `$(...)`
+    /// run at every shell start. Off by default, since any member of a
+    /// shared group can set the flag; such aliases are then single-quoted.
+    #[serde(default)]
+    pub honor_expand: bool,
+}
+
+impl ClientConfig {
+    /// Parse `history_retention` and `group_history_retention`.
+    pub fn history_retention(&self) -> anyhow::Result<HistoryRetention> {
+        let parse = |key: &str, spec: &str| -> anyhow::Result<Option<i64>> {
+            match crate::stats::parse_period_ms(spec) {
+                Some(ms) => Ok(Some(ms)),
+                None if spec.trim().eq_ignore_ascii_case("all") => Ok(None),
+                None => anyhow::bail!(
+                    "Invalid {key} '{spec}': expected a period such as 7d, 12w, 6m, 1y or all"
+                ),
+            }
+        };
+        let default = match &self.history_retention {
+            Some(spec) => parse("history_retention", spec)?,
+            None => None,
+        };
+        let mut groups = BTreeMap::new();
+        for (group, spec) in &self.group_history_retention {
+            let key = format!("group_history_retention.{group}");
+            groups.insert(group.clone(), parse(&key, spec)?);
+        }
+        Ok(HistoryRetention { default, groups })
+    }
+
+    /// Where hooks send captured commands, for the client config directory
+    /// `dir`: its `sock`, or the TCP port with the secret kept in
+    /// `capture-secret`.
+    pub fn capture_endpoint(&self, dir: &Path) -> anyhow::Result<CaptureEndpoint> {
+        Ok(match self.capture_transport {
+            CaptureTransport::Unix => CaptureEndpoint::Unix(dir.join("sock")),
+            CaptureTransport::Tcp => CaptureEndpoint::Tcp {
+                port: self.capture_port,
+                secret: load_capture_secret(&dir.join("capture-secret"))?,
+            },
+        })
+    }
+
+    /// Whether history captured in `group` must stay on this machine.
+    pub fn is_local_only(&self, group: &str) -> bool {
+        self.local_only_groups.iter().any(|g| g == group)
+    }
+
+    /// The copy of a captured entry that is pushed to the server: `None` for
+    /// local-only groups, the command cut to `strip_args_keep` words for
+    /// `strip_args_groups` (`None` if nothing but assignments is left),
+    /// otherwise the entry as is.
+    pub fn pushed_entry(&self, entry: &HistoryEntry) -> Option<HistoryEntry> {
+        if self.is_local_only(&entry.group_name) {
+            return None;
+        }
+        let mut entry = entry.clone();
+        if self.strip_args_groups.contains(&entry.group_name) {
+            entry.command = strip_args(&entry.command, self.strip_args_keep);
+            if entry.command.is_empty() {
+                return None;
+            }
+        }
+        Some(entry)
+    }
+
+    /// Directory the alias files are written to.
+    pub fn alias_dir(&self) -> PathBuf {
+        self.alias_dir.clone().unwrap_or_else(client_config_dir)
+    }
+}
+
+/// The first `keep` words of `command` (at least one). Leading `NAME=value`
+/// assignments are dropped too, as they are often where the secrets are.
+fn strip_args(command: &str, keep: usize) -> String {
+    command
+        .split_whitespace()
+        .skip_while(|word| crate::shell::is_assignment(word))
+        .take(keep.max(1))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// How long local history is kept, per group. Ages are in ms; `None` keeps
+/// entries forever.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryRetention {
+    pub default: Option<i64>,
+    pub groups: BTreeMap<String, Option<i64>>,
+}
+
+impl HistoryRetention {
+    /// Whether any history is ever deleted.
+    pub fn is_enabled(&self) -> bool {
+        self.default.is_some() || self.groups.values().any(Option::is_some)
+    }
+
+    /// Entries in `group` older than this timestamp are expired.
+    pub fn cutoff(&self, group: &str, now_ms: i64) -> Option<i64> {
+        let age = match self.groups.get(group) {
+            Some(age) => *age,
+            None => self.default,
+        };
+        age.map(|age| now_ms - age)
+    }
+}
+
+/// Layout of the alias files the client writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AliasOutput {
+    /// Everything in `aliases.<ext>`.
+    #[default]
+    Single,
+    /// `aliases/<group>.<ext>` per group, each sourced separately.
+    PerGroup,
+}
+
+/// How much of an alias file the client owns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AliasFileMode {
+    /// Only the lines between the shell-sync markers; anything the user adds
+    /// outside them is kept.
+    #[default]
+    Merge,
+    /// The whole file, replaced on every sync.
+    Overwrite,
+}
+
+/// What the daemon does with a history entry that looks like it holds a
+/// secret before pushing it to a shared group. Local history keeps the
+/// command as typed either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistorySecretPolicy {
+    /// Push the command as captured.
+    Off,
+    /// Push the command with the secret values replaced by `<redacted>`.
+    #[default]
+    Redact,
+    /// Do not push the entry at all.
+    Withhold,
+}
+
+/// Which captured commands are recorded, by exit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapturePolicy {
+    /// Every command.
+    #[default]
+    All,
+    /// Only commands that exited 0.
+    SuccessOnly,
+    /// Only commands that exited non-zero.
+    FailureOnly,
+}
+
+impl CapturePolicy {
+    /// Whether a command with this exit code is recorded.
+    pub fn captures(&self, exit_code: i32) -> bool {
+        match self {
+            Self::All => true,
+            Self::SuccessOnly => exit_code == 0,
+            Self::FailureOnly => exit_code != 0,
+        }
+    }
+}
+
+/// How shell hooks send captured commands to the daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureTransport {
+    /// The Unix socket at `~/.shell-sync/sock`.
+    #[default]
+    Unix,
+    /// `capture_port` on 127.0.0.1.
+    Tcp,
+}
+
+/// Resolved address of the capture listener.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureEndpoint {
+    Unix(PathBuf),
+    /// A port on 127.0.0.1. Any local user can connect to it, so hooks send
+    /// `secret` with each payload and the daemon drops payloads without it.
+    Tcp {
+        port: u16,
+        secret: String,
+    },
+}
+
+/// The secret TCP capture payloads carry, read from `path`, or created there
+/// (readable by the owner only) on first use.
+pub fn load_capture_secret(path: &Path) -> anyhow::Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(secret) if !secret.trim().is_empty() => return Ok(secret.trim().to_string()),
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => anyhow::bail!("Failed to read {}: {e}", path.display()),
+    }
+    use rand::RngCore;
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    use std::io::Write;
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(secret.as_bytes()))
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {e}", path.display()))?;
+    Ok(secret)
+}
+
+/// How ids are assigned to newly captured history entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryIdMode {
+    /// A fresh random UUID per entry.
+    #[default]
+    Random,
+    /// `sha256(command|cwd|session|timestamp)`, so re-captured events dedupe on insert.
+    ContentHash,
+}
+
+impl HistoryIdMode {
+    /// Build the id for a history entry with the given content.
+    pub fn entry_id(&self, command: &str, cwd: &str, session_id: &str, timestamp: i64) -> String {
+        match self {
+            Self::Random => uuid::Uuid::new_v4().to_string(),
+            Self::ContentHash => {
+                use sha2::{Digest, Sha256};
+                let mut hasher = Sha256::new();
+                hasher.update(command.as_bytes());
+                hasher.update(b"|");
+                hasher.update(cwd.as_bytes());
+                hasher.update(b"|");
+                hasher.update(session_id.as_bytes());
+                hasher.update(b"|");
+                hasher.update(timestamp.to_string().as_bytes());
+                hasher
+                    .finalize()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect()
+            }
+        }
+    }
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
+            bind_address: default_bind_address(),
             port: default_port(),
             db_path: default_db_path(),
             git_repo_path: default_git_repo_path(),
             mdns_enabled: true,
             web_ui_enabled: true,
             git_sync_interval_secs: default_git_sync_interval(),
+            ws_channel_capacity: default_ws_channel_capacity(),
+            ws_overflow_policy: WsOverflowPolicy::default(),
+            ws_ping_interval_secs: default_ping_interval_secs(),
+            conflict_strategy: ConflictStrategy::default(),
+            admin_token: None,
+            cors_allowed_origins: Vec::new(),
+            cors_any: false,
+            db_busy_timeout_ms: default_db_busy_timeout_ms(),
+            db_pool_size: default_db_pool_size(),
+            wal_checkpoint_interval_secs: default_wal_checkpoint_interval(),
+            pid_file: None,
+            max_register_body_bytes: default_max_register_body_bytes(),
+            max_body_bytes: default_max_body_bytes(),
+            max_bulk_body_bytes: default_max_bulk_body_bytes(),
+            alias_tombstone_days: default_alias_tombstone_days(),
+            auth_log_days: default_auth_log_days(),
+            request_timeout_secs: default_request_timeout_secs(),
+            slow_request_timeout_secs: default_slow_request_timeout_secs(),
+            blocked_command_patterns: Vec::new(),
+            group_blocked_command_patterns: BTreeMap::new(),
         }
     }
 }
 
+fn default_bind_address() -> String {
+    "0.0.0.0".to_string()
+}
+
 fn default_port() -> u16 {
     8888
 }
@@ -61,6 +534,103 @@ fn default_git_sync_interval() -> u64 {
     300
 }
 
+fn default_db_busy_timeout_ms() -> u64 {
+    crate::db::DEFAULT_BUSY_TIMEOUT_MS
+}
+
+fn default_db_pool_size() -> u32 {
+    crate::db::DEFAULT_POOL_SIZE
+}
+
+fn default_wal_checkpoint_interval() -> u64 {
+    300
+}
+
+pub const DEFAULT_MAX_REGISTER_BODY_BYTES: usize = 16 * 1024;
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+pub const DEFAULT_MAX_BULK_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+fn default_max_register_body_bytes() -> usize {
+    DEFAULT_MAX_REGISTER_BODY_BYTES
+}
+
+fn default_max_body_bytes() -> usize {
+    DEFAULT_MAX_BODY_BYTES
+}
+
+fn default_max_bulk_body_bytes() -> usize {
+    DEFAULT_MAX_BULK_BODY_BYTES
+}
+
+pub const DEFAULT_ALIAS_TOMBSTONE_DAYS: u64 = 30;
+
+fn default_alias_tombstone_days() -> u64 {
+    DEFAULT_ALIAS_TOMBSTONE_DAYS
+}
+
+pub const DEFAULT_AUTH_LOG_DAYS: u64 = 90;
+
+fn default_auth_log_days() -> u64 {
+    DEFAULT_AUTH_LOG_DAYS
+}
+
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_SLOW_REQUEST_TIMEOUT_SECS: u64 = 120;
+
+fn default_request_timeout_secs() -> u64 {
+    DEFAULT_REQUEST_TIMEOUT_SECS
+}
+
+fn default_slow_request_timeout_secs() -> u64 {
+    DEFAULT_SLOW_REQUEST_TIMEOUT_SECS
+}
+
+pub const DEFAULT_MAX_COMMAND_LEN: usize = 8192;
+
+fn default_max_command_len() -> usize {
+    DEFAULT_MAX_COMMAND_LEN
+}
+
+pub const DEFAULT_MAX_DURATION_SECS: u64 = 24 * 60 * 60;
+
+fn default_max_duration_secs() -> u64 {
+    DEFAULT_MAX_DURATION_SECS
+}
+
+pub const DEFAULT_MAX_PENDING_HISTORY: usize = 100_000;
+
+fn default_max_pending_history() -> usize {
+    DEFAULT_MAX_PENDING_HISTORY
+}
+
+pub const DEFAULT_STATS_PROXY_PORT: u16 = 18888;
+
+fn default_stats_proxy_port() -> u16 {
+    DEFAULT_STATS_PROXY_PORT
+}
+
+fn default_ws_channel_capacity() -> usize {
+    256
+}
+
+pub const DEFAULT_PING_INTERVAL_SECS: u64 = 30;
+
+fn default_ping_interval_secs() -> u64 {
+    DEFAULT_PING_INTERVAL_SECS
+}
+
+pub const DEFAULT_STRIP_ARGS_KEEP: usize = 1;
+
+fn default_strip_args_keep() -> usize {
+    DEFAULT_STRIP_ARGS_KEEP
+}
+
+pub const DEFAULT_CAPTURE_PORT: u16 = 18889;
+
+fn default_capture_port() -> u16 {
+    DEFAULT_CAPTURE_PORT
+}
+
 /// Returns the path to the client config directory (~/.shell-sync/).
 pub fn client_config_dir() -> PathBuf {
     let home = directories::BaseDirs::new()
@@ -70,6 +640,21 @@ pub fn client_config_dir() -> PathBuf {
     home.join(".shell-sync")
 }
 
+/// Returns the path of the background server's PID file.
+pub fn server_pid_file_path() -> PathBuf {
+    client_config_dir().join("server.pid")
+}
+
+/// Returns the path of the background server's log file.
+pub fn server_log_path() -> PathBuf {
+    client_config_dir().join("server.log")
+}
+
+/// Returns the path of the log file of a daemon started by `shell-sync init`.
+pub fn daemon_log_path() -> PathBuf {
+    client_config_dir().join("daemon.log")
+}
+
 /// Returns the path to the client config file.
 pub fn client_config_path() -> PathBuf {
     client_config_dir().join("config.toml")
@@ -85,6 +670,11 @@ pub fn pid_file_path() -> PathBuf {
     client_config_dir().join("daemon.pid")
 }
 
+/// Returns the path of the daemon's connection status file.
+pub fn daemon_status_path() -> PathBuf {
+    client_config_dir().join("daemon-status.json")
+}
+
 /// Returns the path to the offline queue database.
 pub fn offline_queue_db_path() -> PathBuf {
     client_config_dir().join("offline-queue.db")
@@ -112,8 +702,12 @@ pub fn hooks_dir_path() -> PathBuf {
 
 /// Load client config from disk.
 pub fn load_client_config() -> anyhow::Result<ClientConfig> {
-    let path = client_config_path();
-    let content = std::fs::read_to_string(&path)
+    load_client_config_from(&client_config_path())
+}
+
+/// Load client config from a specific file.
+pub fn load_client_config_from(path: &Path) -> anyhow::Result<ClientConfig> {
+    let content = std::fs::read_to_string(path)
         .map_err(|e| anyhow::anyhow!("Failed to read config at {}: {}", path.display(), e))?;
     let config: ClientConfig = toml::from_str(&content)?;
     Ok(config)
@@ -121,11 +715,16 @@ pub fn load_client_config() -> anyhow::Result<ClientConfig> {
 
 /// Save client config to disk.
 pub fn save_client_config(config: &ClientConfig) -> anyhow::Result<()> {
-    let dir = client_config_dir();
-    std::fs::create_dir_all(&dir)?;
-    let path = client_config_path();
+    save_client_config_to(&client_config_path(), config)
+}
+
+/// Save client config to a specific file, creating its directory.
+pub fn save_client_config_to(path: &Path, config: &ClientConfig) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
     let content = toml::to_string_pretty(config)?;
-    std::fs::write(&path, content)?;
+    std::fs::write(path, content)?;
     Ok(())
 }
 
@@ -136,6 +735,7 @@ mod tests {
     #[test]
     fn server_config_defaults() {
         let cfg = ServerConfig::default();
+        assert_eq!(cfg.bind_address, "0.0.0.0");
         assert_eq!(cfg.port, 8888);
         assert!(cfg.mdns_enabled);
         assert!(cfg.web_ui_enabled);
@@ -145,20 +745,103 @@ mod tests {
     #[test]
     fn server_config_toml_roundtrip() {
         let cfg = ServerConfig {
+            bind_address: "127.0.0.1".into(),
             port: 9999,
             db_path: "/tmp/test.db".into(),
             git_repo_path: "/tmp/git".into(),
             mdns_enabled: false,
             web_ui_enabled: false,
             git_sync_interval_secs: 60,
+            ws_channel_capacity: 8,
+            ws_overflow_policy: WsOverflowPolicy::Coalesce,
+            ws_ping_interval_secs: 0,
+            conflict_strategy: ConflictStrategy::LastWriteWins,
+            admin_token: Some("admin".into()),
+            cors_allowed_origins: vec!["https://ui.example.com".into()],
+            cors_any: false,
+            db_busy_timeout_ms: 1500,
+            db_pool_size: 2,
+            wal_checkpoint_interval_secs: 0,
+            pid_file: Some(PathBuf::from("/run/shell-sync.pid")),
+            max_register_body_bytes: 1024,
+            max_body_bytes: 2048,
+            max_bulk_body_bytes: 4096,
+            alias_tombstone_days: 7,
+            auth_log_days: 14,
+            request_timeout_secs: 10,
+            slow_request_timeout_secs: 0,
+            blocked_command_patterns: vec![r"rm\s+-rf\s+/".into()],
+            group_blocked_command_patterns: [("team".to_string(), vec!["^sudo ".to_string()])]
+                .into(),
         };
         let toml_str = toml::to_string(&cfg).unwrap();
         let parsed: ServerConfig = toml::from_str(&toml_str).unwrap();
+        assert_eq!(parsed.bind_address, "127.0.0.1");
         assert_eq!(parsed.port, 9999);
         assert_eq!(parsed.db_path, "/tmp/test.db");
         assert!(!parsed.mdns_enabled);
         assert!(!parsed.web_ui_enabled);
         assert_eq!(parsed.git_sync_interval_secs, 60);
+        assert_eq!(parsed.ws_channel_capacity, 8);
+        assert_eq!(parsed.ws_overflow_policy, WsOverflowPolicy::Coalesce);
+        assert_eq!(parsed.ws_ping_interval_secs, 0);
+        assert_eq!(parsed.conflict_strategy, ConflictStrategy::LastWriteWins);
+        assert_eq!(parsed.cors_allowed_origins, vec!["https://ui.example.com"]);
+        assert_eq!(parsed.db_busy_timeout_ms, 1500);
+        assert_eq!(parsed.db_pool_size, 2);
+        assert_eq!(parsed.wal_checkpoint_interval_secs, 0);
+        assert_eq!(parsed.max_register_body_bytes, 1024);
+        assert_eq!(parsed.max_body_bytes, 2048);
+        assert_eq!(parsed.max_bulk_body_bytes, 4096);
+        assert_eq!(parsed.alias_tombstone_days, 7);
+        assert_eq!(parsed.auth_log_days, 14);
+        assert_eq!(parsed.request_timeout_secs, 10);
+        assert_eq!(parsed.slow_request_timeout_secs, 0);
+        assert_eq!(parsed.blocked_command_patterns, vec![r"rm\s+-rf\s+/"]);
+        assert_eq!(parsed.group_blocked_command_patterns["team"], vec!["^sudo "]);
+    }
+
+    #[test]
+    fn history_retention_uses_group_overrides() {
+        let config: ClientConfig = toml::from_str(
+            r#"
+            server_url = "http://localhost:8888"
+            machine_id = "m1"
+            auth_token = "t"
+            groups = ["default", "work", "scratch"]
+            hostname = "h"
+            history_retention = "90d"
+
+            [group_history_retention]
+            work = "365d"
+            scratch = "7d"
+            default = "all"
+            "#,
+        )
+        .unwrap();
+        let retention = config.history_retention().unwrap();
+        let day = 86_400_000;
+        let now = 1_000 * day;
+        assert_eq!(retention.cutoff("scratch", now), Some(now - 7 * day));
+        assert_eq!(retention.cutoff("work", now), Some(now - 365 * day));
+        assert_eq!(retention.cutoff("default", now), None);
+        assert_eq!(retention.cutoff("other", now), Some(now - 90 * day));
+        assert!(retention.is_enabled());
+
+        let bad = ClientConfig {
+            group_history_retention: [("work".to_string(), "forever".to_string())].into(),
+            ..config
+        };
+        let e = bad.history_retention().unwrap_err().to_string();
+        assert!(e.contains("group_history_retention.work"), "{e}");
+    }
+
+    #[test]
+    fn conflict_strategy_from_str() {
+        assert_eq!("manual".parse(), Ok(ConflictStrategy::Manual));
+        assert_eq!("last-write-wins".parse(), Ok(ConflictStrategy::LastWriteWins));
+        assert_eq!("first_write_wins".parse(), Ok(ConflictStrategy::FirstWriteWins));
+        assert!("newest".parse::<ConflictStrategy>().is_err());
     }
 
     #[test]
@@ -168,6 +851,116 @@ mod tests {
         assert!(cfg.mdns_enabled);
         assert!(cfg.web_ui_enabled);
         assert_eq!(cfg.git_sync_interval_secs, 300);
+        assert_eq!(cfg.ws_channel_capacity, 256);
+        assert_eq!(cfg.ws_overflow_policy, WsOverflowPolicy::Disconnect);
+        assert_eq!(cfg.ws_ping_interval_secs, 30);
+    }
+
+    #[test]
+    fn content_hash_id_depends_on_every_field() {
+        let mode = HistoryIdMode::ContentHash;
+        let id = mode.entry_id("ls", "/tmp", "s1", 1000);
+        assert_eq!(id.len(), 64);
+        assert_eq!(id, mode.entry_id("ls", "/tmp", "s1", 1000));
+        assert_ne!(id, mode.entry_id("ls", "/var", "s1", 1000));
+        assert_ne!(id, mode.entry_id("ls", "/tmp", "s2", 1000));
+        assert_ne!(id, mode.entry_id("ls", "/tmp", "s1", 1001));
+    }
+
+    #[test]
+    fn client_config_id_mode_defaults_to_random() {
+        let config: ClientConfig = toml::from_str(
+            r#"
+server_url = "http://localhost:8888"
+machine_id = "m1"
+auth_token = "t"
+groups = ["default"]
+hostname = "h"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.history_id_mode, HistoryIdMode::Random);
+        assert!(config.local_only_groups.is_empty());
+        assert_eq!(config.max_command_len, 8192);
+        assert_eq!(config.max_duration_secs, 86_400);
+        assert!(!config.is_local_only("default"));
+        assert!(config.strip_args_groups.is_empty());
+        assert_eq!(config.strip_args_keep, 1);
+        assert_eq!(config.capture_policy, CapturePolicy::All);
+        assert_eq!(config.alias_output, AliasOutput::Single);
+        assert_eq!(config.alias_file_mode, AliasFileMode::Merge);
+        assert_eq!(config.alias_dir(), client_config_dir());
+    }
+
+    #[test]
+    fn strip_args_keeps_the_command_head() {
+        assert_eq!(strip_args("aws s3 cp secret.txt s3://bucket/", 1), "aws");
+        assert_eq!(strip_args("aws s3 cp secret.txt s3://bucket/", 2), "aws s3");
+        assert_eq!(strip_args("  AWS_PROFILE=prod  aws  sts  ", 0), "aws");
+        assert_eq!(strip_args("x=1", 1), "");
+        assert_eq!(strip_args("./run --a=b", 3), "./run --a=b");
+    }
+
+    #[test]
+    fn pushed_entry_applies_local_only_and_strip_args_groups() {
+        let config: ClientConfig = toml::from_str(
+            r#"
+server_url = "http://localhost:8888"
+machine_id = "m1"
+auth_token = "t"
+groups = ["default", "team", "secrets"]
+hostname = "h"
+local_only_groups = ["secrets"]
+strip_args_groups = ["team"]
+strip_args_keep = 2
+"#,
+        )
+        .unwrap();
+        let entry = |group: &str| HistoryEntry {
+            id: "h1".into(),
+            command: "aws s3 cp key.pem s3://bucket".into(),
+            cwd: "/tmp".into(),
+            exit_code: 0,
+            duration_ms: 5,
+            session_id: "s1".into(),
+            machine_id: "m1".into(),
+            hostname: "h".into(),
+            timestamp: 1000,
+            shell: "zsh".into(),
+            group_name: group.into(),
+            truncated: false,
+            unknown_duration: false,
+        };
+
+        assert_eq!(config.pushed_entry(&entry("team")).unwrap().command, "aws s3");
+        assert_eq!(
+            config.pushed_entry(&entry("default")).unwrap().command,
+            "aws s3 cp key.pem s3://bucket"
+        );
+        assert!(config.pushed_entry(&entry("secrets")).is_none());
+        let assignment_only = HistoryEntry {
+            command: "TOKEN=abc123".into(),
+            ..entry("team")
+        };
+        assert!(config.pushed_entry(&assignment_only).is_none());
+    }
+
+    #[test]
+    fn capture_policy_parses_snake_case() {
+        let config: ClientConfig = toml::from_str(
+            r#"
+server_url = "http://localhost:8888"
+machine_id = "m1"
+auth_token = "t"
+groups = ["default"]
+hostname = "h"
+capture_policy = "failure_only"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.capture_policy, CapturePolicy::FailureOnly);
+        assert!(config.capture_policy.captures(127));
+        assert!(!config.capture_policy.captures(0));
     }
 
     #[test]
@@ -180,5 +973,22 @@ mod tests {
 
         let alias_path = client_alias_path("sh");
         assert!(alias_path.to_str().unwrap().ends_with("aliases.sh"));
+
+        assert!(server_pid_file_path().ends_with(".shell-sync/server.pid"));
+    }
+
+    #[test]
+    fn capture_secret_is_created_private_and_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture-secret");
+        let secret = load_capture_secret(&path).unwrap();
+        assert_eq!(secret.len(), 64);
+        assert_eq!(load_capture_secret(&path).unwrap(), secret);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
     }
 }
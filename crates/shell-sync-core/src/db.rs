@@ -1,33 +1,210 @@
 use crate::models::*;
 use rusqlite::{params, Connection, Result as SqlResult};
+use std::cell::Cell;
 use std::path::Path;
 use std::sync::Mutex;
+use std::time::Instant;
+
+/// How long a connection waits on a locked database before failing.
+pub const DEFAULT_BUSY_TIMEOUT_MS: u64 = 5000;
+
+/// Connections kept open per database.
+pub const DEFAULT_POOL_SIZE: u32 = 8;
+
+/// Conflicts returned per page when the caller does not ask for a size.
+pub const DEFAULT_CONFLICT_LIMIT: i64 = 100;
+
+/// Connection settings applied when opening a [`SyncDatabase`].
+#[derive(Debug, Clone, Copy)]
+pub struct DbOptions {
+    /// `PRAGMA busy_timeout`: wait this long for another writer instead of
+    /// failing immediately with `database is locked`.
+    pub busy_timeout_ms: u64,
+    /// Most connections open at once. Reads on separate connections run in
+    /// parallel; writes still take turns on SQLite's write lock.
+    pub pool_size: u32,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            busy_timeout_ms: DEFAULT_BUSY_TIMEOUT_MS,
+            pool_size: DEFAULT_POOL_SIZE,
+        }
+    }
+}
+
+/// SQLite VM steps between checks of the query deadline.
+const DEADLINE_CHECK_STEPS: i32 = 1000;
+
+thread_local! {
+    /// Set by [`with_query_deadline`] for the statements it runs.
+    static QUERY_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Run `f` with SQLite statements on this thread interrupted once `deadline`
+/// passes; they then fail with `interrupted`. Unlike a timeout around the
+/// blocking call, this stops a slow query partway.
+pub fn with_query_deadline<T>(deadline: Instant, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Instant>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            QUERY_DEADLINE.with(|d| d.set(self.0));
+        }
+    }
+    let _restore = Restore(QUERY_DEADLINE.with(|d| d.replace(Some(deadline))));
+    f()
+}
+
+fn query_deadline_passed() -> bool {
+    QUERY_DEADLINE.with(|d| d.get().is_some_and(|deadline| Instant::now() >= deadline))
+}
+
+/// Opens pooled connections with the settings each connection needs.
+pub struct ConnectionManager {
+    path: String,
+    busy_timeout_ms: u64,
+}
+
+impl r2d2::ManageConnection for ConnectionManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> SqlResult<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(std::time::Duration::from_millis(self.busy_timeout_ms))?;
+        // Safe with WAL: a crash can lose the last commits but never corrupts the DB.
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.progress_handler(DEADLINE_CHECK_STEPS, Some(query_deadline_passed));
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> SqlResult<()> {
+        conn.execute_batch("")
+    }
+
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
+
+/// Timestamp order of history search results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HistoryOrder {
+    #[default]
+    NewestFirst,
+    OldestFirst,
+}
+
+impl HistoryOrder {
+    pub fn reversed(self) -> Self {
+        match self {
+            Self::NewestFirst => Self::OldestFirst,
+            Self::OldestFirst => Self::NewestFirst,
+        }
+    }
+
+    fn sql(self) -> &'static str {
+        match self {
+            Self::NewestFirst => "DESC",
+            Self::OldestFirst => "ASC",
+        }
+    }
+}
+
+/// Order of alias listings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AliasOrder {
+    #[default]
+    Name,
+    /// Most recently updated first.
+    Recent,
+}
+
+impl AliasOrder {
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Recent => "updated_at DESC, name",
+        }
+    }
+}
+
+/// Rows copied by [`SyncDatabase::import_legacy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LegacyImport {
+    pub machines: usize,
+    pub aliases: usize,
+    pub sync_history: usize,
+    pub conflicts: usize,
+    /// Rows that were already present.
+    pub skipped: usize,
+}
 
-/// Thread-safe database wrapper for shell-sync.
+/// A connection checked out of a [`SyncDatabase`]'s pool.
+pub type DbConnection = r2d2::PooledConnection<ConnectionManager>;
+
+/// Thread-safe database wrapper for shell-sync, backed by a connection pool.
 pub struct SyncDatabase {
-    conn: Mutex<Connection>,
+    pool: r2d2::Pool<ConnectionManager>,
+    /// Connection outside the pool handed out by [`Self::raw_connection`].
+    raw: Mutex<Connection>,
 }
 
 impl SyncDatabase {
-    /// Open (or create) the database at the given path.
+    /// Open (or create) the database at the given path with default options.
     pub fn open(db_path: &str) -> anyhow::Result<Self> {
+        Self::open_with_options(db_path, DbOptions::default())
+    }
+
+    /// Open (or create) the database at the given path.
+    pub fn open_with_options(db_path: &str, options: DbOptions) -> anyhow::Result<Self> {
         // Ensure parent directory exists
         if let Some(parent) = Path::new(db_path).parent() {
             std::fs::create_dir_all(parent)?;
         }
 
+        // WAL is a property of the database file, so set it once before the
+        // pool opens its connections; it also lets readers run alongside a writer.
         let conn = Connection::open(db_path)?;
+        conn.busy_timeout(std::time::Duration::from_millis(options.busy_timeout_ms))?;
         conn.pragma_update(None, "journal_mode", "WAL")?;
+        drop(conn);
 
-        let db = Self {
-            conn: Mutex::new(conn),
+        let manager = ConnectionManager {
+            path: db_path.to_string(),
+            busy_timeout_ms: options.busy_timeout_ms,
         };
+        let raw = Mutex::new(r2d2::ManageConnection::connect(&manager)?);
+        let pool = r2d2::Pool::builder()
+            .max_size(options.pool_size.max(1))
+            .min_idle(Some(1))
+            .test_on_check_out(false)
+            .build(manager)?;
+
+        let db = Self { pool, raw };
         db.init_schema()?;
         Ok(db)
     }
 
+    fn conn(&self) -> anyhow::Result<DbConnection> {
+        Ok(self.pool.get()?)
+    }
+
+    /// Run `f` in a `BEGIN IMMEDIATE` transaction, committing if it succeeds.
+    ///
+    /// Used for read-then-write sequences so another connection's write cannot
+    /// land in between.
+    fn write<T>(&self, f: impl FnOnce(&Connection) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        let result = f(&tx)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
     fn init_schema(&self) -> anyhow::Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
 
         conn.execute_batch(
             "
@@ -95,7 +272,9 @@ impl SyncDatabase {
                 hostname TEXT NOT NULL,
                 timestamp INTEGER NOT NULL,
                 shell TEXT NOT NULL DEFAULT 'bash',
-                group_name TEXT NOT NULL DEFAULT 'default'
+                group_name TEXT NOT NULL DEFAULT 'default',
+                truncated BOOLEAN NOT NULL DEFAULT 0,
+                unknown_duration BOOLEAN NOT NULL DEFAULT 0
             );
             CREATE INDEX IF NOT EXISTS idx_hist_timestamp ON history(timestamp);
             CREATE INDEX IF NOT EXISTS idx_hist_machine ON history(machine_id);
@@ -108,6 +287,22 @@ impl SyncDatabase {
                 created_at INTEGER NOT NULL
             );
 
+            CREATE TABLE IF NOT EXISTS auth_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER NOT NULL,
+                machine_id TEXT,
+                token_hash TEXT,
+                result TEXT NOT NULL,
+                client_ip TEXT NOT NULL,
+                source TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_auth_log_timestamp ON auth_log(timestamp);
+
+            CREATE TABLE IF NOT EXISTS groups (
+                name TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL
+            );
+
             CREATE TABLE IF NOT EXISTS schema_version (
                 version INTEGER NOT NULL
             );
@@ -115,6 +310,53 @@ impl SyncDatabase {
             ",
         )?;
 
+        // Columns added after the initial schema; CREATE TABLE IF NOT EXISTS
+        // leaves older databases without them.
+        Self::ensure_column(
+            &conn,
+            "history",
+            "truncated",
+            "BOOLEAN NOT NULL DEFAULT 0",
+        )?;
+        Self::ensure_column(
+            &conn,
+            "history",
+            "unknown_duration",
+            "BOOLEAN NOT NULL DEFAULT 0",
+        )?;
+        Self::ensure_column(&conn, "aliases", "deleted_at", "INTEGER")?;
+        Self::ensure_column(&conn, "aliases", "shells", "TEXT NOT NULL DEFAULT '[]'")?;
+        Self::ensure_column(&conn, "aliases", "pinned", "BOOLEAN NOT NULL DEFAULT 0")?;
+        Self::ensure_column(&conn, "aliases", "expand", "BOOLEAN NOT NULL DEFAULT 0")?;
+        Self::ensure_column(&conn, "aliases", "template", "BOOLEAN NOT NULL DEFAULT 0")?;
+        Self::ensure_column(&conn, "conflicts", "resolved_at", "INTEGER")?;
+        Self::ensure_column(
+            &conn,
+            "machines",
+            "read_only_groups",
+            "TEXT NOT NULL DEFAULT '[]'",
+        )?;
+
+        Ok(())
+    }
+
+    fn ensure_column(
+        conn: &Connection,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> anyhow::Result<()> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<SqlResult<Vec<_>>>()?
+            .iter()
+            .any(|name| name == column);
+        if !exists {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+            ))?;
+        }
         Ok(())
     }
 
@@ -129,7 +371,7 @@ impl SyncDatabase {
         auth_token: &str,
         public_key: Option<&str>,
     ) -> anyhow::Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let now = chrono::Utc::now().timestamp_millis();
         let groups_json = serde_json::to_string(groups)?;
 
@@ -149,7 +391,7 @@ impl SyncDatabase {
     }
 
     pub fn get_machine_by_token(&self, auth_token: &str) -> anyhow::Result<Option<Machine>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let mut stmt = conn.prepare("SELECT * FROM machines WHERE auth_token = ?1")?;
         let machine = stmt
             .query_row(params![auth_token], Self::row_to_machine)
@@ -158,7 +400,7 @@ impl SyncDatabase {
     }
 
     pub fn update_machine_last_seen(&self, machine_id: &str) -> anyhow::Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let now = chrono::Utc::now().timestamp_millis();
         conn.execute(
             "UPDATE machines SET last_seen = ?1 WHERE machine_id = ?2",
@@ -167,8 +409,44 @@ impl SyncDatabase {
         Ok(())
     }
 
+    /// Set what `machine_id` may do with `group`'s aliases. Returns the
+    /// updated machine, or `None` if it isn't registered.
+    pub fn set_group_permission(
+        &self,
+        machine_id: &str,
+        group: &str,
+        permission: GroupPermission,
+    ) -> anyhow::Result<Option<Machine>> {
+        self.write(|conn| {
+            let Some(mut machine) = conn
+                .query_row(
+                    "SELECT * FROM machines WHERE machine_id = ?1",
+                    params![machine_id],
+                    Self::row_to_machine,
+                )
+                .optional()?
+            else {
+                return Ok(None);
+            };
+            machine.read_only_groups.retain(|g| g != group);
+            if permission == GroupPermission::Read {
+                machine.read_only_groups.push(group.to_string());
+                machine.read_only_groups.sort();
+            }
+            conn.execute(
+                "UPDATE machines SET read_only_groups = ?1 WHERE machine_id = ?2",
+                params![serde_json::to_string(&machine.read_only_groups)?, machine_id],
+            )?;
+            Ok(Some(machine))
+        })
+    }
+
     pub fn get_all_machines(&self) -> anyhow::Result<Vec<Machine>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
+        Self::get_all_machines_inner(&conn)
+    }
+
+    fn get_all_machines_inner(conn: &Connection) -> anyhow::Result<Vec<Machine>> {
         let mut stmt = conn.prepare("SELECT * FROM machines")?;
         let machines = stmt
             .query_map([], Self::row_to_machine)?
@@ -176,6 +454,42 @@ impl SyncDatabase {
         Ok(machines)
     }
 
+    /// How often each first word appears in shell history, e.g. to see which
+    /// aliases are used.
+    pub fn command_head_counts(&self) -> anyhow::Result<std::collections::HashMap<String, i64>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT CASE WHEN instr(ltrim(command), ' ') > 0
+                    THEN substr(ltrim(command), 1, instr(ltrim(command), ' ') - 1)
+                    ELSE ltrim(command) END AS head, COUNT(*)
+             FROM history GROUP BY head",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<SqlResult<_>>()?)
+    }
+
+    /// Shell history per machine id: commands at or after `since` and the
+    /// latest command time. Machines without history are absent.
+    pub fn machine_activity(
+        &self,
+        since: i64,
+    ) -> anyhow::Result<std::collections::HashMap<String, MachineActivity>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT machine_id, SUM(timestamp >= ?1), MAX(timestamp) FROM history GROUP BY machine_id",
+        )?;
+        let rows = stmt.query_map(params![since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                MachineActivity {
+                    recent_commands: row.get(1)?,
+                    last_command_at: row.get(2)?,
+                },
+            ))
+        })?;
+        Ok(rows.collect::<SqlResult<_>>()?)
+    }
+
     pub fn get_machines_by_group(&self, group_name: &str) -> anyhow::Result<Vec<Machine>> {
         let all = self.get_all_machines()?;
         Ok(all
@@ -198,9 +512,128 @@ impl SyncDatabase {
             last_seen: row.get(6)?,
             created_at: row.get(7)?,
             public_key: row.get(8)?,
+            read_only_groups: {
+                let s: String = row.get(9)?;
+                serde_json::from_str(&s).unwrap_or_default()
+            },
+        })
+    }
+
+    // ===== GROUPS =====
+
+    /// Create an explicit group. Returns `false` if it already exists.
+    pub fn create_group(&self, name: &str) -> anyhow::Result<bool> {
+        let conn = self.conn()?;
+        let now = chrono::Utc::now().timestamp_millis();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO groups (name, created_at) VALUES (?1, ?2)",
+            params![name, now],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// List every known group: explicitly created ones plus any referenced by
+    /// aliases or machines, with alias and machine counts.
+    pub fn list_groups(&self) -> anyhow::Result<Vec<GroupInfo>> {
+        let conn = self.conn()?;
+        Self::list_groups_inner(&conn)
+    }
+
+    fn list_groups_inner(conn: &Connection) -> anyhow::Result<Vec<GroupInfo>> {
+        let mut groups: std::collections::BTreeMap<String, GroupInfo> = Default::default();
+        fn slot<'a>(
+            groups: &'a mut std::collections::BTreeMap<String, GroupInfo>,
+            name: &str,
+        ) -> &'a mut GroupInfo {
+            groups.entry(name.to_string()).or_insert_with(|| GroupInfo {
+                name: name.to_string(),
+                alias_count: 0,
+                machine_count: 0,
+                created_at: None,
+            })
+        }
+
+        let mut stmt = conn.prepare("SELECT name, created_at FROM groups")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<SqlResult<Vec<_>>>()?;
+        for (name, created_at) in rows {
+            slot(&mut groups, &name).created_at = Some(created_at);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT group_name, COUNT(*) FROM aliases WHERE deleted_at IS NULL GROUP BY group_name",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<SqlResult<Vec<_>>>()?;
+        for (name, count) in rows {
+            slot(&mut groups, &name).alias_count = count;
+        }
+
+        for machine in Self::get_all_machines_inner(conn)? {
+            for name in &machine.groups {
+                slot(&mut groups, name).machine_count += 1;
+            }
+        }
+
+        Ok(groups.into_values().collect())
+    }
+
+    /// Delete a group. Unless `force` is set, refuses while any alias or machine
+    /// still belongs to it; with `force`, its aliases are deleted (left as
+    /// tombstones, like [`Self::delete_alias`]) and it is removed from every
+    /// machine's group list.
+    pub fn delete_group(&self, name: &str, force: bool) -> anyhow::Result<GroupDeletion> {
+        self.write(|conn| {
+            let groups = Self::list_groups_inner(conn)?;
+            let Some(info) = groups.into_iter().find(|g| g.name == name) else {
+                return Ok(GroupDeletion::NotFound);
+            };
+            if !force && (info.alias_count > 0 || info.machine_count > 0) {
+                return Ok(GroupDeletion::NotEmpty {
+                    aliases: info.alias_count,
+                    machines: info.machine_count,
+                });
+            }
+
+            let aliases_removed = conn.execute(
+                "UPDATE aliases SET deleted_at = ?1, updated_at = ?1, version = version + 1
+                 WHERE group_name = ?2 AND deleted_at IS NULL",
+                params![chrono::Utc::now().timestamp_millis(), name],
+            )? as i64;
+            for machine in Self::get_all_machines_inner(conn)? {
+                if !machine.groups.iter().any(|g| g == name) {
+                    continue;
+                }
+                let remaining: Vec<&String> =
+                    machine.groups.iter().filter(|g| *g != name).collect();
+                conn.execute(
+                    "UPDATE machines SET groups = ?1 WHERE machine_id = ?2",
+                    params![serde_json::to_string(&remaining)?, machine.machine_id],
+                )?;
+            }
+            conn.execute("DELETE FROM groups WHERE name = ?1", params![name])?;
+
+            Ok(GroupDeletion::Deleted { aliases_removed })
         })
     }
 
+    /// Groups aliases are synced through: created explicitly or joined by at
+    /// least one machine.
+    pub fn known_groups(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .list_groups()?
+            .into_iter()
+            .filter(|g| g.machine_count > 0 || g.created_at.is_some())
+            .map(|g| g.name)
+            .collect())
+    }
+
     // ===== ALIASES =====
 
     pub fn add_alias(
@@ -210,95 +643,246 @@ impl SyncDatabase {
         group_name: &str,
         created_by_machine: &str,
     ) -> anyhow::Result<Alias> {
-        let conn = self.conn.lock().unwrap();
-        let now = chrono::Utc::now().timestamp_millis();
-
-        let result = conn.execute(
-            "INSERT INTO aliases (name, command, group_name, created_by_machine, created_at, updated_at, version)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1)",
-            params![name, command, group_name, created_by_machine, now, now],
-        );
+        self.add_alias_at(name, command, group_name, created_by_machine, None, false, false)
+    }
 
-        match result {
-            Ok(_) => {
-                let id = conn.last_insert_rowid();
+    /// Add an alias, reviving it if it was deleted.
+    ///
+    /// `made_at` is when the add was made on the client, if it was queued
+    /// offline. An add made before the alias's deletion is stale and rejected,
+    /// so a machine that missed the delete cannot resurrect the alias.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_alias_at(
+        &self,
+        name: &str,
+        command: &str,
+        group_name: &str,
+        created_by_machine: &str,
+        made_at: Option<i64>,
+        expand: bool,
+        template: bool,
+    ) -> anyhow::Result<Alias> {
+        self.write(|conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+
+            let deleted_at: Option<i64> = conn
+                .query_row(
+                    "SELECT deleted_at FROM aliases
+                     WHERE name = ?1 AND group_name = ?2 AND deleted_at IS NOT NULL",
+                    params![name, group_name],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(deleted_at) = deleted_at {
+                if made_at.is_some_and(|made| made < deleted_at) {
+                    anyhow::bail!(
+                        "Alias '{}' in group '{}' was deleted after this add was made",
+                        name,
+                        group_name
+                    );
+                }
+                conn.execute(
+                    "UPDATE aliases SET command = ?1, created_by_machine = ?2, created_at = ?3,
+                         updated_at = ?3, version = version + 1, deleted_at = NULL, pinned = 0,
+                         expand = ?6, template = ?7, shells = '[]'
+                     WHERE name = ?4 AND group_name = ?5",
+                    params![command, created_by_machine, now, name, group_name, expand, template],
+                )?;
                 self.log_history_inner(
-                    &conn,
+                    conn,
                     created_by_machine,
                     "add",
                     name,
                     Some(command),
                     Some(group_name),
                 )?;
-                Ok(Alias {
-                    id,
-                    name: name.to_string(),
-                    command: command.to_string(),
-                    group_name: group_name.to_string(),
-                    created_by_machine: created_by_machine.to_string(),
-                    created_at: now,
-                    updated_at: now,
-                    version: 1,
-                })
+                return Self::get_alias_by_name_inner(conn, name, group_name)?
+                    .ok_or_else(|| anyhow::anyhow!("Alias '{}' vanished while reviving it", name));
             }
-            Err(e) if e.to_string().contains("UNIQUE constraint failed") => {
-                anyhow::bail!("Alias '{}' already exists in group '{}'", name, group_name)
+
+            let result = conn.execute(
+                "INSERT INTO aliases (name, command, group_name, created_by_machine, created_at, updated_at, version, expand, template)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1, ?7, ?8)",
+                params![name, command, group_name, created_by_machine, now, now, expand, template],
+            );
+
+            match result {
+                Ok(_) => {
+                    let id = conn.last_insert_rowid();
+                    self.log_history_inner(
+                        conn,
+                        created_by_machine,
+                        "add",
+                        name,
+                        Some(command),
+                        Some(group_name),
+                    )?;
+                    Ok(Alias {
+                        id,
+                        name: name.to_string(),
+                        command: command.to_string(),
+                        group_name: group_name.to_string(),
+                        created_by_machine: created_by_machine.to_string(),
+                        created_at: now,
+                        updated_at: now,
+                        version: 1,
+                        pinned: false,
+                        expand,
+                        template,
+                        shells: Vec::new(),
+                    })
+                }
+                Err(e) if e.to_string().contains("UNIQUE constraint failed") => {
+                    anyhow::bail!("Alias '{}' already exists in group '{}'", name, group_name)
+                }
+                Err(e) => Err(e.into()),
             }
-            Err(e) => Err(e.into()),
-        }
+        })
     }
 
+    /// Change an alias's command, and its `expand` and `template` flags if given.
     pub fn update_alias(
         &self,
         id: i64,
         command: &str,
+        expand: Option<bool>,
+        template: Option<bool>,
         machine_id: &str,
     ) -> anyhow::Result<Option<Alias>> {
-        let conn = self.conn.lock().unwrap();
-        let now = chrono::Utc::now().timestamp_millis();
+        self.write(|conn| {
+            let now = chrono::Utc::now().timestamp_millis();
 
-        let changes = conn.execute(
-            "UPDATE aliases SET command = ?1, updated_at = ?2, version = version + 1 WHERE id = ?3",
-            params![command, now, id],
-        )?;
+            let changes = conn.execute(
+                "UPDATE aliases SET command = ?1, updated_at = ?2, version = version + 1,
+                     expand = COALESCE(?4, expand), template = COALESCE(?5, template)
+                 WHERE id = ?3 AND deleted_at IS NULL",
+                params![command, now, id, expand, template],
+            )?;
 
-        if changes > 0 {
-            let alias = Self::get_alias_by_id_inner(&conn, id)?;
-            if let Some(ref a) = alias {
-                self.log_history_inner(
-                    &conn,
-                    machine_id,
-                    "update",
-                    &a.name,
-                    Some(command),
-                    Some(&a.group_name),
-                )?;
+            if changes > 0 {
+                let alias = Self::get_alias_by_id_inner(conn, id)?;
+                if let Some(ref a) = alias {
+                    self.log_history_inner(
+                        conn,
+                        machine_id,
+                        "update",
+                        &a.name,
+                        Some(command),
+                        Some(&a.group_name),
+                    )?;
+                }
+                Ok(alias)
+            } else {
+                Ok(None)
             }
-            Ok(alias)
-        } else {
-            Ok(None)
-        }
+        })
     }
 
-    pub fn delete_alias(&self, id: i64, machine_id: &str) -> anyhow::Result<bool> {
-        let conn = self.conn.lock().unwrap();
-        let alias = Self::get_alias_by_id_inner(&conn, id)?;
+    /// Set the shells an alias is written for (empty for all), as part of an
+    /// add or update that already bumped its version.
+    pub fn set_alias_shells(&self, id: i64, shells: &[String]) -> anyhow::Result<Option<Alias>> {
+        self.write(|conn| {
+            conn.execute(
+                "UPDATE aliases SET shells = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+                params![serde_json::to_string(shells)?, id],
+            )?;
+            Self::get_alias_by_id_inner(conn, id)
+        })
+    }
 
-        if let Some(alias) = alias {
-            let changes = conn.execute("DELETE FROM aliases WHERE id = ?1", params![id])?;
-            if changes > 0 {
+    /// Live aliases whose group is not in `known_groups` (see
+    /// [`Self::known_groups`]), which no machine receives.
+    pub fn find_orphaned_aliases(&self, known_groups: &[String]) -> anyhow::Result<Vec<Alias>> {
+        Ok(self
+            .get_all_aliases()?
+            .into_iter()
+            .filter(|a| !known_groups.contains(&a.group_name))
+            .collect())
+    }
+
+    /// Move every live alias in `from` to `to`. Aliases whose name is already
+    /// live in `to` are left where they are; a deleted one there is replaced.
+    pub fn reassign_aliases(
+        &self,
+        from: &str,
+        to: &str,
+        machine_id: &str,
+    ) -> anyhow::Result<AliasReassignment> {
+        self.write(|conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let aliases = {
+                let mut stmt = conn.prepare(
+                    "SELECT * FROM aliases WHERE group_name = ?1 AND deleted_at IS NULL ORDER BY name",
+                )?;
+                let rows = stmt.query_map(params![from], Self::row_to_alias)?;
+                rows.collect::<SqlResult<Vec<_>>>()?
+            };
+
+            let mut outcome = AliasReassignment::default();
+            for alias in aliases {
+                let taken: Option<bool> = conn
+                    .query_row(
+                        "SELECT deleted_at IS NULL FROM aliases WHERE name = ?1 AND group_name = ?2",
+                        params![alias.name, to],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                match taken {
+                    Some(true) => {
+                        outcome.skipped.push(alias.name);
+                        continue;
+                    }
+                    Some(false) => {
+                        conn.execute(
+                            "DELETE FROM aliases WHERE name = ?1 AND group_name = ?2",
+                            params![alias.name, to],
+                        )?;
+                    }
+                    None => {}
+                }
+                conn.execute(
+                    "UPDATE aliases SET group_name = ?1, updated_at = ?2, version = version + 1
+                     WHERE id = ?3",
+                    params![to, now, alias.id],
+                )?;
                 self.log_history_inner(
-                    &conn,
+                    conn,
                     machine_id,
-                    "delete",
+                    "reassign",
                     &alias.name,
                     Some(&alias.command),
-                    Some(&alias.group_name),
+                    Some(to),
                 )?;
-                return Ok(true);
+                outcome.moved.push(alias.name);
             }
-        }
-        Ok(false)
+            Ok(outcome)
+        })
+    }
+
+    pub fn delete_alias(&self, id: i64, machine_id: &str) -> anyhow::Result<bool> {
+        self.write(|conn| {
+            let alias = Self::get_alias_by_id_inner(conn, id)?;
+
+            if let Some(alias) = alias {
+                let changes = conn.execute(
+                    "UPDATE aliases SET deleted_at = ?1, updated_at = ?1, version = version + 1
+                     WHERE id = ?2 AND deleted_at IS NULL",
+                    params![chrono::Utc::now().timestamp_millis(), id],
+                )?;
+                if changes > 0 {
+                    self.log_history_inner(
+                        conn,
+                        machine_id,
+                        "delete",
+                        &alias.name,
+                        Some(&alias.command),
+                        Some(&alias.group_name),
+                    )?;
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        })
     }
 
     pub fn delete_alias_by_name(
@@ -307,42 +891,73 @@ impl SyncDatabase {
         group_name: &str,
         machine_id: &str,
     ) -> anyhow::Result<bool> {
-        let conn = self.conn.lock().unwrap();
-        let alias = Self::get_alias_by_name_inner(&conn, name, group_name)?;
+        self.write(|conn| {
+            let alias = Self::get_alias_by_name_inner(conn, name, group_name)?;
+
+            if let Some(alias) = alias {
+                let changes = conn.execute(
+                    "UPDATE aliases SET deleted_at = ?1, updated_at = ?1, version = version + 1
+                     WHERE name = ?2 AND group_name = ?3 AND deleted_at IS NULL",
+                    params![chrono::Utc::now().timestamp_millis(), name, group_name],
+                )?;
+                if changes > 0 {
+                    self.log_history_inner(
+                        conn,
+                        machine_id,
+                        "delete",
+                        name,
+                        Some(&alias.command),
+                        Some(group_name),
+                    )?;
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        })
+    }
 
-        if let Some(alias) = alias {
+    /// Pin or unpin a live alias. Returns `None` if it does not exist.
+    pub fn set_alias_pinned(
+        &self,
+        name: &str,
+        group_name: &str,
+        pinned: bool,
+        machine_id: &str,
+    ) -> anyhow::Result<Option<Alias>> {
+        self.write(|conn| {
             let changes = conn.execute(
-                "DELETE FROM aliases WHERE name = ?1 AND group_name = ?2",
-                params![name, group_name],
+                "UPDATE aliases SET pinned = ?1, updated_at = ?2, version = version + 1
+                 WHERE name = ?3 AND group_name = ?4 AND deleted_at IS NULL",
+                params![pinned, chrono::Utc::now().timestamp_millis(), name, group_name],
             )?;
-            if changes > 0 {
-                self.log_history_inner(
-                    &conn,
-                    machine_id,
-                    "delete",
-                    name,
-                    Some(&alias.command),
-                    Some(group_name),
-                )?;
-                return Ok(true);
+            if changes == 0 {
+                return Ok(None);
             }
-        }
-        Ok(false)
+            self.log_history_inner(
+                conn,
+                machine_id,
+                if pinned { "pin" } else { "unpin" },
+                name,
+                None,
+                Some(group_name),
+            )?;
+            Self::get_alias_by_name_inner(conn, name, group_name)
+        })
     }
 
     pub fn get_alias_by_id(&self, id: i64) -> anyhow::Result<Option<Alias>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         Self::get_alias_by_id_inner(&conn, id)
     }
 
     fn get_alias_by_id_inner(conn: &Connection, id: i64) -> anyhow::Result<Option<Alias>> {
-        let mut stmt = conn.prepare("SELECT * FROM aliases WHERE id = ?1")?;
+        let mut stmt = conn.prepare("SELECT * FROM aliases WHERE id = ?1 AND deleted_at IS NULL")?;
         let alias = stmt.query_row(params![id], Self::row_to_alias).optional()?;
         Ok(alias)
     }
 
     pub fn get_alias_by_name(&self, name: &str, group_name: &str) -> anyhow::Result<Option<Alias>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         Self::get_alias_by_name_inner(&conn, name, group_name)
     }
 
@@ -351,7 +966,9 @@ impl SyncDatabase {
         name: &str,
         group_name: &str,
     ) -> anyhow::Result<Option<Alias>> {
-        let mut stmt = conn.prepare("SELECT * FROM aliases WHERE name = ?1 AND group_name = ?2")?;
+        let mut stmt = conn.prepare(
+            "SELECT * FROM aliases WHERE name = ?1 AND group_name = ?2 AND deleted_at IS NULL",
+        )?;
         let alias = stmt
             .query_row(params![name, group_name], Self::row_to_alias)
             .optional()?;
@@ -359,7 +976,15 @@ impl SyncDatabase {
     }
 
     pub fn get_aliases_by_groups(&self, groups: &[String]) -> anyhow::Result<Vec<Alias>> {
-        let conn = self.conn.lock().unwrap();
+        self.get_aliases_by_groups_ordered(groups, AliasOrder::Name)
+    }
+
+    pub fn get_aliases_by_groups_ordered(
+        &self,
+        groups: &[String],
+        order: AliasOrder,
+    ) -> anyhow::Result<Vec<Alias>> {
+        let conn = self.conn()?;
         if groups.is_empty() {
             return Ok(vec![]);
         }
@@ -377,8 +1002,9 @@ impl SyncDatabase {
             .collect();
 
         let sql = format!(
-            "SELECT * FROM aliases WHERE group_name IN ({}) ORDER BY name",
-            placeholders
+            "SELECT * FROM aliases WHERE group_name IN ({}) AND deleted_at IS NULL ORDER BY {}",
+            placeholders,
+            order.sql()
         );
 
         let mut stmt = conn.prepare(&sql)?;
@@ -394,14 +1020,28 @@ impl SyncDatabase {
     }
 
     pub fn get_all_aliases(&self) -> anyhow::Result<Vec<Alias>> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM aliases ORDER BY group_name, name")?;
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT * FROM aliases WHERE deleted_at IS NULL ORDER BY group_name, name",
+        )?;
         let aliases = stmt
             .query_map([], Self::row_to_alias)?
             .collect::<SqlResult<Vec<_>>>()?;
         Ok(aliases)
     }
 
+    /// Permanently remove alias tombstones deleted before `before_ms`.
+    ///
+    /// Until then a deleted alias keeps its row so that stale adds from
+    /// machines that missed the delete are recognised and rejected.
+    pub fn purge_alias_tombstones(&self, before_ms: i64) -> anyhow::Result<usize> {
+        let conn = self.conn()?;
+        Ok(conn.execute(
+            "DELETE FROM aliases WHERE deleted_at IS NOT NULL AND deleted_at < ?1",
+            params![before_ms],
+        )?)
+    }
+
     fn row_to_alias(row: &rusqlite::Row<'_>) -> SqlResult<Alias> {
         Ok(Alias {
             id: row.get(0)?,
@@ -412,6 +1052,10 @@ impl SyncDatabase {
             created_at: row.get(5)?,
             updated_at: row.get(6)?,
             version: row.get(7)?,
+            pinned: row.get("pinned")?,
+            expand: row.get("expand")?,
+            template: row.get("template")?,
+            shells: serde_json::from_str(&row.get::<_, String>("shells")?).unwrap_or_default(),
         })
     }
 
@@ -425,7 +1069,7 @@ impl SyncDatabase {
         remote_command: &str,
         machine_id: &str,
     ) -> anyhow::Result<i64> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let now = chrono::Utc::now().timestamp_millis();
         conn.execute(
             "INSERT INTO conflicts (alias_name, group_name, local_command, remote_command, machine_id, created_at)
@@ -435,34 +1079,137 @@ impl SyncDatabase {
         Ok(conn.last_insert_rowid())
     }
 
+    /// Record a conflict that was settled automatically, for auditing.
+    pub fn record_resolved_conflict(
+        &self,
+        alias_name: &str,
+        group_name: &str,
+        local_command: &str,
+        remote_command: &str,
+        machine_id: &str,
+        resolution: &str,
+    ) -> anyhow::Result<i64> {
+        let conn = self.conn()?;
+        let now = chrono::Utc::now().timestamp_millis();
+        conn.execute(
+            "INSERT INTO conflicts (alias_name, group_name, local_command, remote_command, machine_id, created_at, resolved, resolution, resolved_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1, ?7, ?6)",
+            params![alias_name, group_name, local_command, remote_command, machine_id, now, resolution],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn get_conflict(&self, conflict_id: i64) -> anyhow::Result<Option<Conflict>> {
+        let conn = self.conn()?;
+        let conflict = conn
+            .query_row(
+                "SELECT * FROM conflicts WHERE id = ?1",
+                params![conflict_id],
+                Self::row_to_conflict,
+            )
+            .optional()?;
+        Ok(conflict)
+    }
+
+    /// A machine's newest [`DEFAULT_CONFLICT_LIMIT`] open conflicts.
     pub fn get_conflicts_by_machine(&self, machine_id: &str) -> anyhow::Result<Vec<Conflict>> {
-        let conn = self.conn.lock().unwrap();
+        self.get_conflicts_filtered(machine_id, false, DEFAULT_CONFLICT_LIMIT, 0)
+    }
+
+    /// One page of a machine's conflicts, newest first, with resolved ones
+    /// only if asked.
+    pub fn get_conflicts_filtered(
+        &self,
+        machine_id: &str,
+        include_resolved: bool,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<Conflict>> {
+        let conn = self.conn()?;
         let mut stmt = conn.prepare(
-            "SELECT * FROM conflicts WHERE machine_id = ?1 AND resolved = 0 ORDER BY created_at DESC",
+            "SELECT * FROM conflicts WHERE machine_id = ?1 AND (?2 OR resolved = 0)
+             ORDER BY created_at DESC, id DESC
+             LIMIT ?3 OFFSET ?4",
         )?;
         let conflicts = stmt
-            .query_map(params![machine_id], |row| {
-                Ok(Conflict {
-                    id: row.get(0)?,
-                    alias_name: row.get(1)?,
-                    group_name: row.get(2)?,
-                    local_command: row.get(3)?,
-                    remote_command: row.get(4)?,
-                    machine_id: row.get(5)?,
-                    created_at: row.get(6)?,
-                    resolved: row.get(7)?,
-                    resolution: row.get(8)?,
-                })
-            })?
+            .query_map(
+                params![machine_id, include_resolved, limit, offset],
+                Self::row_to_conflict,
+            )?
             .collect::<SqlResult<Vec<_>>>()?;
         Ok(conflicts)
     }
 
+    /// How many conflicts [`get_conflicts_filtered`](Self::get_conflicts_filtered)
+    /// pages through.
+    pub fn count_conflicts(
+        &self,
+        machine_id: &str,
+        include_resolved: bool,
+    ) -> anyhow::Result<i64> {
+        let conn = self.conn()?;
+        let count = conn.query_row(
+            "SELECT COUNT(*) FROM conflicts WHERE machine_id = ?1 AND (?2 OR resolved = 0)",
+            params![machine_id, include_resolved],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    pub fn get_conflict_counts(&self, machine_id: &str) -> anyhow::Result<ConflictCounts> {
+        let conn = self.conn()?;
+        let counts = conn.query_row(
+            "SELECT COALESCE(SUM(resolved = 0), 0), COALESCE(SUM(resolved != 0), 0)
+             FROM conflicts WHERE machine_id = ?1",
+            params![machine_id],
+            |row| {
+                Ok(ConflictCounts {
+                    open: row.get(0)?,
+                    resolved: row.get(1)?,
+                })
+            },
+        )?;
+        Ok(counts)
+    }
+
+    /// Delete conflicts resolved more than `older_than_ms` ago; open conflicts
+    /// are never touched. Conflicts resolved before `resolved_at` was recorded
+    /// are aged by when they were created. Returns the number deleted.
+    pub fn delete_resolved_conflicts(
+        &self,
+        machine_id: &str,
+        older_than_ms: i64,
+    ) -> anyhow::Result<usize> {
+        let conn = self.conn()?;
+        let cutoff = chrono::Utc::now().timestamp_millis() - older_than_ms;
+        let deleted = conn.execute(
+            "DELETE FROM conflicts
+             WHERE machine_id = ?1 AND resolved != 0 AND COALESCE(resolved_at, created_at) < ?2",
+            params![machine_id, cutoff],
+        )?;
+        Ok(deleted)
+    }
+
+    fn row_to_conflict(row: &rusqlite::Row<'_>) -> SqlResult<Conflict> {
+        Ok(Conflict {
+            id: row.get(0)?,
+            alias_name: row.get(1)?,
+            group_name: row.get(2)?,
+            local_command: row.get(3)?,
+            remote_command: row.get(4)?,
+            machine_id: row.get(5)?,
+            created_at: row.get(6)?,
+            resolved: row.get(7)?,
+            resolution: row.get(8)?,
+            resolved_at: row.get("resolved_at")?,
+        })
+    }
+
     pub fn resolve_conflict(&self, conflict_id: i64, resolution: &str) -> anyhow::Result<bool> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let changes = conn.execute(
-            "UPDATE conflicts SET resolved = 1, resolution = ?1 WHERE id = ?2",
-            params![resolution, conflict_id],
+            "UPDATE conflicts SET resolved = 1, resolution = ?1, resolved_at = ?2 WHERE id = ?3",
+            params![resolution, chrono::Utc::now().timestamp_millis(), conflict_id],
         )?;
         Ok(changes > 0)
     }
@@ -488,11 +1235,47 @@ impl SyncDatabase {
     }
 
     pub fn get_history(&self, limit: i64) -> anyhow::Result<Vec<SyncHistoryEntry>> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt =
-            conn.prepare("SELECT * FROM sync_history ORDER BY timestamp DESC LIMIT ?1")?;
-        let entries = stmt
-            .query_map(params![limit], |row| {
+        self.get_history_filtered(limit, 0, None, None)
+    }
+
+    /// Sync actions, newest first, optionally narrowed to one action and/or machine.
+    /// Rows with equal timestamps are ordered by id so pages never overlap.
+    pub fn get_history_filtered(
+        &self,
+        limit: i64,
+        offset: i64,
+        action: Option<&str>,
+        machine_id: Option<&str>,
+    ) -> anyhow::Result<Vec<SyncHistoryEntry>> {
+        let conn = self.conn()?;
+        let mut sql = String::from("SELECT * FROM sync_history WHERE 1 = 1");
+        let mut param_values: Vec<Box<dyn rusqlite::types::ToSql>> = Vec::new();
+        let mut idx = 1;
+
+        if let Some(a) = action {
+            sql.push_str(&format!(" AND action = ?{idx}"));
+            param_values.push(Box::new(a.to_string()));
+            idx += 1;
+        }
+        if let Some(mid) = machine_id {
+            sql.push_str(&format!(" AND machine_id = ?{idx}"));
+            param_values.push(Box::new(mid.to_string()));
+            idx += 1;
+        }
+
+        sql.push_str(&format!(
+            " ORDER BY timestamp DESC, id DESC LIMIT ?{idx} OFFSET ?{}",
+            idx + 1
+        ));
+        param_values.push(Box::new(limit));
+        param_values.push(Box::new(offset));
+
+        let params_ref: Vec<&dyn rusqlite::types::ToSql> =
+            param_values.iter().map(|p| p.as_ref()).collect();
+
+        let mut stmt = conn.prepare(&sql)?;
+        let entries = stmt
+            .query_map(params_ref.as_slice(), |row| {
                 Ok(SyncHistoryEntry {
                     id: row.get(0)?,
                     timestamp: row.get(1)?,
@@ -507,6 +1290,64 @@ impl SyncDatabase {
         Ok(entries)
     }
 
+    // ===== AUTH AUDIT LOG =====
+
+    pub fn log_auth(
+        &self,
+        machine_id: Option<&str>,
+        token_hash: Option<&str>,
+        success: bool,
+        client_ip: &str,
+        source: &str,
+    ) -> anyhow::Result<()> {
+        let conn = self.conn()?;
+        let now = chrono::Utc::now().timestamp_millis();
+        let result = if success { "success" } else { "failure" };
+        conn.execute(
+            "INSERT INTO auth_log (timestamp, machine_id, token_hash, result, client_ip, source)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![now, machine_id, token_hash, result, client_ip, source],
+        )?;
+        Ok(())
+    }
+
+    /// Remove auth log entries older than `before_ms`.
+    pub fn purge_auth_log(&self, before_ms: i64) -> anyhow::Result<usize> {
+        let conn = self.conn()?;
+        Ok(conn.execute(
+            "DELETE FROM auth_log WHERE timestamp < ?1",
+            params![before_ms],
+        )?)
+    }
+
+    /// Most recent auth attempts first.
+    pub fn get_auth_log(&self, limit: i64, offset: i64) -> anyhow::Result<Vec<AuthLogEntry>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, machine_id, token_hash, result, client_ip, source
+             FROM auth_log ORDER BY id DESC LIMIT ?1 OFFSET ?2",
+        )?;
+        let entries = stmt
+            .query_map(params![limit, offset], |row| {
+                Ok(AuthLogEntry {
+                    id: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    machine_id: row.get(2)?,
+                    token_hash: row.get(3)?,
+                    result: row.get(4)?,
+                    client_ip: row.get(5)?,
+                    source: row.get(6)?,
+                })
+            })?
+            .collect::<SqlResult<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    pub fn get_auth_log_count(&self) -> anyhow::Result<i64> {
+        let conn = self.conn()?;
+        Ok(conn.query_row("SELECT COUNT(*) FROM auth_log", [], |row| row.get(0))?)
+    }
+
     // ===== SHELL HISTORY =====
 
     fn row_to_history_entry(row: &rusqlite::Row<'_>) -> SqlResult<HistoryEntry> {
@@ -522,14 +1363,16 @@ impl SyncDatabase {
             timestamp: row.get(8)?,
             shell: row.get(9)?,
             group_name: row.get(10)?,
+            truncated: row.get(11)?,
+            unknown_duration: row.get(12)?,
         })
     }
 
     pub fn insert_history_entry(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         conn.execute(
-            "INSERT OR IGNORE INTO history (id, command, cwd, exit_code, duration_ms, session_id, machine_id, hostname, timestamp, shell, group_name)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            "INSERT OR IGNORE INTO history (id, command, cwd, exit_code, duration_ms, session_id, machine_id, hostname, timestamp, shell, group_name, truncated, unknown_duration)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
             params![
                 entry.id,
                 entry.command,
@@ -542,22 +1385,27 @@ impl SyncDatabase {
                 entry.timestamp,
                 entry.shell,
                 entry.group_name,
+                entry.truncated,
+                entry.unknown_duration,
             ],
         )?;
         Ok(())
     }
 
-    pub fn insert_history_batch(&self, entries: &[HistoryEntry]) -> usize {
-        let conn = self.conn.lock().unwrap();
-        let mut count = 0usize;
+    /// Insert entries, skipping ids already stored. Returns the ids that were new.
+    pub fn insert_history_batch(&self, entries: &[HistoryEntry]) -> Vec<String> {
+        let mut inserted = Vec::new();
+        let Ok(conn) = self.conn() else {
+            return inserted;
+        };
         let tx = match conn.unchecked_transaction() {
             Ok(tx) => tx,
-            Err(_) => return 0,
+            Err(_) => return inserted,
         };
         for entry in entries {
             let result = tx.execute(
-                "INSERT OR IGNORE INTO history (id, command, cwd, exit_code, duration_ms, session_id, machine_id, hostname, timestamp, shell, group_name)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                "INSERT OR IGNORE INTO history (id, command, cwd, exit_code, duration_ms, session_id, machine_id, hostname, timestamp, shell, group_name, truncated, unknown_duration)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
                 params![
                     entry.id,
                     entry.command,
@@ -570,26 +1418,64 @@ impl SyncDatabase {
                     entry.timestamp,
                     entry.shell,
                     entry.group_name,
+                    entry.truncated,
+                    entry.unknown_duration,
                 ],
             );
             if let Ok(changes) = result {
-                count += changes;
+                if changes > 0 {
+                    inserted.push(entry.id.clone());
+                }
             }
         }
-        let _ = tx.commit();
-        count
+        if tx.commit().is_err() {
+            inserted.clear();
+        }
+        inserted
     }
 
+    /// Like [`insert_history_batch`](Self::insert_history_batch), returning only how many were new.
+    pub fn insert_history_batch_count(&self, entries: &[HistoryEntry]) -> usize {
+        self.insert_history_batch(entries).len()
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn search_history(
         &self,
         query: &str,
         machine_id: Option<&str>,
         session_id: Option<&str>,
         cwd: Option<&str>,
+        group_name: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<HistoryEntry>> {
+        self.search_history_ordered(
+            query,
+            machine_id,
+            session_id,
+            cwd,
+            group_name,
+            limit,
+            offset,
+            HistoryOrder::NewestFirst,
+        )
+    }
+
+    /// [`search_history`](Self::search_history) with the timestamp order chosen.
+    #[allow(clippy::too_many_arguments)]
+    pub fn search_history_ordered(
+        &self,
+        query: &str,
+        machine_id: Option<&str>,
+        session_id: Option<&str>,
+        cwd: Option<&str>,
+        group_name: Option<&str>,
         limit: i64,
         offset: i64,
+        order: HistoryOrder,
     ) -> anyhow::Result<Vec<HistoryEntry>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let mut sql = String::from("SELECT * FROM history WHERE command LIKE ?1");
         let mut param_values: Vec<Box<dyn rusqlite::types::ToSql>> =
             vec![Box::new(format!("%{}%", query))];
@@ -610,9 +1496,15 @@ impl SyncDatabase {
             param_values.push(Box::new(c.to_string()));
             idx += 1;
         }
+        if let Some(g) = group_name {
+            sql.push_str(&format!(" AND group_name = ?{idx}"));
+            param_values.push(Box::new(g.to_string()));
+            idx += 1;
+        }
 
         sql.push_str(&format!(
-            " ORDER BY timestamp DESC LIMIT ?{idx} OFFSET ?{}",
+            " ORDER BY timestamp {} LIMIT ?{idx} OFFSET ?{}",
+            order.sql(),
             idx + 1
         ));
         param_values.push(Box::new(limit));
@@ -628,13 +1520,41 @@ impl SyncDatabase {
         Ok(entries)
     }
 
+    /// The newest `limit` entries with `after <= timestamp < before`, optionally
+    /// in one group, newest first.
+    pub fn history_in_range(
+        &self,
+        group_name: Option<&str>,
+        after: Option<i64>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<HistoryEntry>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT * FROM history WHERE (?1 IS NULL OR group_name = ?1) AND timestamp BETWEEN ?2 AND ?3 \
+             ORDER BY timestamp DESC LIMIT ?4",
+        )?;
+        let entries = stmt
+            .query_map(
+                params![
+                    group_name,
+                    after.unwrap_or(i64::MIN),
+                    before.map_or(i64::MAX, |b| b.saturating_sub(1)),
+                    limit
+                ],
+                Self::row_to_history_entry,
+            )?
+            .collect::<SqlResult<Vec<_>>>()?;
+        Ok(entries)
+    }
+
     pub fn get_history_after_timestamp(
         &self,
         after: i64,
         group_name: &str,
         limit: i64,
     ) -> anyhow::Result<Vec<HistoryEntry>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let mut stmt = conn.prepare(
             "SELECT * FROM history WHERE timestamp > ?1 AND group_name = ?2 ORDER BY timestamp ASC LIMIT ?3",
         )?;
@@ -647,21 +1567,135 @@ impl SyncDatabase {
         Ok(entries)
     }
 
-    pub fn get_history_count(&self) -> i64 {
-        let conn = self.conn.lock().unwrap();
-        conn.query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))
-            .unwrap_or(0)
+    /// Like [`get_history_after_timestamp`](Self::get_history_after_timestamp), but
+    /// hands rows to `f` one at a time instead of collecting them. Stops early
+    /// when `f` returns `false`.
+    ///
+    /// A slow consumer only ties up the one pooled connection it reads from.
+    pub fn for_each_history_after_timestamp(
+        &self,
+        after: i64,
+        group_name: &str,
+        limit: i64,
+        mut f: impl FnMut(HistoryEntry) -> bool,
+    ) -> anyhow::Result<()> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT * FROM history WHERE timestamp > ?1 AND group_name = ?2 ORDER BY timestamp ASC LIMIT ?3",
+        )?;
+        let mut rows = stmt.query(params![after, group_name, limit])?;
+        while let Some(row) = rows.next()? {
+            if !f(Self::row_to_history_entry(row)?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the WAL back into the main database file and truncate it.
+    pub fn checkpoint(&self) -> anyhow::Result<()> {
+        let conn = self.conn()?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    pub fn get_history_count(&self) -> anyhow::Result<i64> {
+        let conn = self.conn()?;
+        Ok(conn.query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))?)
+    }
+
+    pub fn get_session_history_count(&self, session_id: &str) -> anyhow::Result<i64> {
+        let conn = self.conn()?;
+        Ok(conn.query_row(
+            "SELECT COUNT(*) FROM history WHERE session_id = ?1",
+            params![session_id],
+            |row| row.get(0),
+        )?)
     }
 
     pub fn delete_history_entry(&self, id: &str) -> bool {
-        let conn = self.conn.lock().unwrap();
+        let Ok(conn) = self.conn() else {
+            return false;
+        };
         conn.execute("DELETE FROM history WHERE id = ?1", params![id])
             .map(|changes| changes > 0)
             .unwrap_or(false)
     }
 
+    /// Delete several history entries, along with any copies still waiting
+    /// to be pushed. Returns how many history rows were removed.
+    pub fn delete_history_entries(&self, ids: &[String]) -> anyhow::Result<usize> {
+        let conn = self.conn()?;
+        let tx = conn.unchecked_transaction()?;
+        let mut deleted = 0;
+        for id in ids {
+            deleted += tx.execute("DELETE FROM history WHERE id = ?1", params![id])?;
+            tx.execute("DELETE FROM history_pending WHERE id = ?1", params![id])?;
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Delete history older than its group's cutoff (`None` keeps the group's
+    /// history), along with any of it still queued for push.
+    pub fn prune_history(&self, cutoff: impl Fn(&str) -> Option<i64>) -> anyhow::Result<usize> {
+        self.write(|conn| {
+            let groups = conn
+                .prepare("SELECT DISTINCT group_name FROM history")?
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<SqlResult<Vec<_>>>()?;
+            let mut deleted = 0;
+            for group in groups {
+                if let Some(before) = cutoff(&group) {
+                    deleted += conn.execute(
+                        "DELETE FROM history WHERE group_name = ?1 AND timestamp < ?2",
+                        params![group, before],
+                    )?;
+                }
+            }
+            if deleted > 0 {
+                conn.execute(
+                    "DELETE FROM history_pending WHERE id NOT IN (SELECT id FROM history)",
+                    [],
+                )?;
+            }
+            Ok(deleted)
+        })
+    }
+
+    /// Hand every history entry older than `before` to `archive`, oldest
+    /// first, then delete them and any copies still queued for push. `archive`
+    /// runs outside any transaction so writers are not held up while it does
+    /// I/O; if it fails nothing is deleted. Only the entries it was given are
+    /// deleted. Returns how many entries were archived.
+    pub fn archive_history(
+        &self,
+        before: i64,
+        archive: impl FnOnce(&[HistoryEntry]) -> anyhow::Result<()>,
+    ) -> anyhow::Result<usize> {
+        let entries = self
+            .conn()?
+            .prepare("SELECT * FROM history WHERE timestamp < ?1 ORDER BY timestamp, id")?
+            .query_map(params![before], Self::row_to_history_entry)?
+            .collect::<SqlResult<Vec<_>>>()?;
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        archive(&entries)?;
+        self.write(|tx| {
+            let mut history = tx.prepare("DELETE FROM history WHERE id = ?1")?;
+            let mut pending = tx.prepare("DELETE FROM history_pending WHERE id = ?1")?;
+            for entry in &entries {
+                history.execute(params![entry.id])?;
+                pending.execute(params![entry.id])?;
+            }
+            Ok(())
+        })?;
+        Ok(entries.len())
+    }
+
     pub fn add_history_pending(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let json = serde_json::to_string(entry)?;
         let now = chrono::Utc::now().timestamp_millis();
         conn.execute(
@@ -671,8 +1705,44 @@ impl SyncDatabase {
         Ok(())
     }
 
+    /// Queue this machine's own history for the daemon to push again, e.g. after
+    /// the server lost a range of it. Entries keep their ids so the server
+    /// ignores any it already has. `since` limits the resync to entries at or
+    /// after that timestamp; `pushed` gives the copy of each entry to queue, or
+    /// `None` to leave it out (see `ClientConfig::pushed_entry`).
+    /// Returns the number of entries newly queued.
+    pub fn requeue_history(
+        &self,
+        machine_id: &str,
+        since: Option<i64>,
+        pushed: impl Fn(&HistoryEntry) -> Option<HistoryEntry>,
+    ) -> anyhow::Result<usize> {
+        self.write(|tx| {
+            let entries = {
+                let mut stmt = tx.prepare(
+                    "SELECT * FROM history WHERE machine_id = ?1 AND timestamp >= ?2 ORDER BY timestamp ASC",
+                )?;
+                let rows = stmt.query_map(
+                    params![machine_id, since.unwrap_or(i64::MIN)],
+                    Self::row_to_history_entry,
+                )?;
+                rows.collect::<SqlResult<Vec<_>>>()?
+            };
+
+            let now = chrono::Utc::now().timestamp_millis();
+            let mut queued = 0;
+            for entry in entries.iter().filter_map(pushed) {
+                queued += tx.execute(
+                    "INSERT OR IGNORE INTO history_pending (id, entry_json, created_at) VALUES (?1, ?2, ?3)",
+                    params![entry.id, serde_json::to_string(&entry)?, now],
+                )?;
+            }
+            Ok(queued)
+        })
+    }
+
     pub fn get_pending_history(&self, limit: i64) -> anyhow::Result<Vec<HistoryEntry>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let mut stmt = conn
             .prepare("SELECT entry_json FROM history_pending ORDER BY created_at ASC LIMIT ?1")?;
         let entries = stmt
@@ -687,20 +1757,128 @@ impl SyncDatabase {
     }
 
     pub fn remove_pending_history(&self, ids: &[String]) -> anyhow::Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         for id in ids {
             conn.execute("DELETE FROM history_pending WHERE id = ?1", params![id])?;
         }
         Ok(())
     }
 
-    /// Expose the inner connection mutex for direct SQL queries (e.g. stats).
+    pub fn get_pending_count(&self) -> anyhow::Result<i64> {
+        let conn = self.conn()?;
+        Ok(conn.query_row("SELECT COUNT(*) FROM history_pending", [], |row| row.get(0))?)
+    }
+
+    /// Drop the oldest queued entries beyond `cap`, so the queue stays bounded
+    /// while pushes keep failing. The entries themselves remain in `history`
+    /// and can be queued again with [`Self::requeue_history`]. Returns the
+    /// number dropped.
+    pub fn compact_pending_history(&self, cap: usize) -> anyhow::Result<usize> {
+        let conn = self.conn()?;
+        let dropped = conn.execute(
+            "DELETE FROM history_pending WHERE rowid IN (
+                 SELECT rowid FROM history_pending
+                 ORDER BY created_at DESC, rowid DESC
+                 LIMIT -1 OFFSET ?1
+             )",
+            params![cap as i64],
+        )?;
+        Ok(dropped)
+    }
+
+    /// Copy machines, aliases, sync history and conflicts from a database
+    /// written by the Node.js server, in one transaction.
+    ///
+    /// Rows already here are skipped (machines by id, aliases by name and
+    /// group, history and conflicts by their contents), so a migration that
+    /// failed partway can simply be run again. Tables missing from the old
+    /// database are skipped.
+    pub fn import_legacy(&self, old_path: &Path) -> anyhow::Result<LegacyImport> {
+        let old =
+            Connection::open_with_flags(old_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let has_table = |name: &str| -> anyhow::Result<bool> {
+            Ok(old.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![name],
+                |row| row.get::<_, i64>(0),
+            )? > 0)
+        };
+        // Rows as loose SQL values, so they go back in column for column
+        let rows = |sql: &str, width: usize| -> anyhow::Result<Vec<Vec<rusqlite::types::Value>>> {
+            let mut stmt = old.prepare(sql)?;
+            let rows = stmt.query_map([], |row| (0..width).map(|i| row.get(i)).collect())?;
+            Ok(rows.collect::<SqlResult<_>>()?)
+        };
+
+        let machines = rows(
+            "SELECT machine_id, hostname, groups, os_type, auth_token, last_seen, created_at FROM machines",
+            7,
+        )?;
+        let aliases = rows(
+            "SELECT name, command, group_name, created_by_machine, created_at, updated_at, version FROM aliases",
+            7,
+        )?;
+        let sync_history = match has_table("sync_history")? {
+            true => rows(
+                "SELECT timestamp, machine_id, action, alias_name, alias_command, group_name FROM sync_history ORDER BY id",
+                6,
+            )?,
+            false => Vec::new(),
+        };
+        let conflicts = match has_table("conflicts")? {
+            true => rows(
+                "SELECT alias_name, group_name, local_command, remote_command, machine_id, created_at, resolved, resolution FROM conflicts ORDER BY id",
+                8,
+            )?,
+            false => Vec::new(),
+        };
+
+        self.write(|tx| {
+            let mut report = LegacyImport::default();
+            let mut copy = |sql: &str, rows: &[Vec<rusqlite::types::Value>]| {
+                let mut stmt = tx.prepare(sql)?;
+                let mut added = 0;
+                for row in rows {
+                    added += stmt.execute(rusqlite::params_from_iter(row))?;
+                }
+                report.skipped += rows.len() - added;
+                anyhow::Ok(added)
+            };
+            report.machines = copy(
+                "INSERT OR IGNORE INTO machines (machine_id, hostname, groups, os_type, auth_token, last_seen, created_at)
+                 VALUES (?1, ?2, ?3, COALESCE(?4, 'unknown'), ?5, ?6, ?7)",
+                &machines,
+            )?;
+            report.aliases = copy(
+                "INSERT OR IGNORE INTO aliases (name, command, group_name, created_by_machine, created_at, updated_at, version)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                &aliases,
+            )?;
+            report.sync_history = copy(
+                "INSERT INTO sync_history (timestamp, machine_id, action, alias_name, alias_command, group_name)
+                 SELECT ?1, ?2, ?3, ?4, ?5, ?6 WHERE NOT EXISTS (
+                     SELECT 1 FROM sync_history WHERE timestamp = ?1 AND machine_id = ?2
+                         AND action = ?3 AND alias_name = ?4 AND group_name IS ?6)",
+                &sync_history,
+            )?;
+            report.conflicts = copy(
+                "INSERT INTO conflicts (alias_name, group_name, local_command, remote_command, machine_id, created_at, resolved, resolution)
+                 SELECT ?1, ?2, ?3, ?4, ?5, ?6, COALESCE(?7, 0), ?8 WHERE NOT EXISTS (
+                     SELECT 1 FROM conflicts WHERE alias_name = ?1 AND group_name = ?2
+                         AND machine_id = ?5 AND created_at = ?6)",
+                &conflicts,
+            )?;
+            Ok(report)
+        })
+    }
+
+    /// Expose a connection mutex for direct SQL queries (e.g. stats).
     pub fn raw_connection(&self) -> &Mutex<Connection> {
-        &self.conn
+        &self.raw
     }
 
     pub fn get_machine_by_id(&self, machine_id: &str) -> anyhow::Result<Option<Machine>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let mut stmt = conn.prepare("SELECT * FROM machines WHERE machine_id = ?1")?;
         let machine = stmt
             .query_row(params![machine_id], Self::row_to_machine)
@@ -849,6 +2027,52 @@ mod tests {
         assert_eq!(alias.group_name, "default");
     }
 
+    #[test]
+    fn pinned_state_roundtrips() {
+        let (db, _dir) = setup();
+        seed_machine(&db, "m1");
+        let alias = db.add_alias("gs", "git status", "default", "m1").unwrap();
+        assert!(!alias.pinned);
+
+        let pinned = db
+            .set_alias_pinned("gs", "default", true, "m1")
+            .unwrap()
+            .unwrap();
+        assert!(pinned.pinned);
+        assert_eq!(pinned.version, alias.version + 1);
+        assert!(db.get_alias_by_id(alias.id).unwrap().unwrap().pinned);
+        assert!(db.get_all_aliases().unwrap()[0].pinned);
+        assert_eq!(db.get_history(1).unwrap()[0].action, "pin");
+
+        // Editing the command keeps the pin
+        db.update_alias(alias.id, "git status -sb", None, None, "m1").unwrap();
+        assert!(db.get_alias_by_id(alias.id).unwrap().unwrap().pinned);
+
+        db.set_alias_pinned("gs", "default", false, "m1").unwrap();
+        assert!(!db.get_alias_by_id(alias.id).unwrap().unwrap().pinned);
+        assert!(db
+            .set_alias_pinned("missing", "default", true, "m1")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn expand_flag_roundtrips() {
+        let (db, _dir) = setup();
+        seed_machine(&db, "m1");
+        let alias = db
+            .add_alias_at("home", "cd $HOME", "default", "m1", None, true, false)
+            .unwrap();
+        assert!(alias.expand);
+        assert!(db.get_alias_by_id(alias.id).unwrap().unwrap().expand);
+
+        // Leaving the flag out of an update keeps it; passing it changes it
+        let kept = db.update_alias(alias.id, "cd $HOME/src", None, None, "m1").unwrap();
+        assert!(kept.unwrap().expand);
+        let cleared = db.update_alias(alias.id, "cd $HOME", Some(false), None, "m1").unwrap();
+        assert!(!cleared.unwrap().expand);
+    }
+
     #[test]
     fn add_alias_logs_history() {
         let (db, _dir) = setup();
@@ -922,7 +2146,7 @@ mod tests {
         seed_machine(&db, "m1");
         let alias = db.add_alias("gs", "git status", "default", "m1").unwrap();
         let updated = db
-            .update_alias(alias.id, "git status -sb", "m1")
+            .update_alias(alias.id, "git status -sb", None, None, "m1")
             .unwrap()
             .unwrap();
         assert_eq!(updated.version, 2);
@@ -932,7 +2156,7 @@ mod tests {
     #[test]
     fn update_alias_nonexistent() {
         let (db, _dir) = setup();
-        assert!(db.update_alias(99999, "cmd", "m1").unwrap().is_none());
+        assert!(db.update_alias(99999, "cmd", None, None, "m1").unwrap().is_none());
     }
 
     #[test]
@@ -963,6 +2187,56 @@ mod tests {
         assert!(db.get_alias_by_name("gs", "default").unwrap().is_none());
     }
 
+    #[test]
+    fn stale_add_does_not_resurrect_deleted_alias() {
+        let (db, _dir) = setup();
+        seed_machine(&db, "m1");
+        let queued_at = chrono::Utc::now().timestamp_millis() - 60_000;
+        let alias = db.add_alias("gs", "git status", "default", "m1").unwrap();
+        assert!(db.delete_alias(alias.id, "m1").unwrap());
+        assert!(!db.delete_alias(alias.id, "m1").unwrap());
+        assert!(db.get_aliases_by_groups(&["default".into()]).unwrap().is_empty());
+        assert!(db.get_all_aliases().unwrap().is_empty());
+        assert!(db.update_alias(alias.id, "git st", None, None, "m1").unwrap().is_none());
+
+        // An add queued offline before the delete stays deleted.
+        let err = db
+            .add_alias_at("gs", "git status", "default", "m2", Some(queued_at), false, false)
+            .unwrap_err();
+        assert!(err.to_string().contains("was deleted"), "{err}");
+        assert!(db.get_alias_by_name("gs", "default").unwrap().is_none());
+
+        // A fresh add revives the row with a newer version.
+        let revived = db.add_alias("gs", "git status -sb", "default", "m1").unwrap();
+        assert_eq!(revived.id, alias.id);
+        assert_eq!(revived.command, "git status -sb");
+        assert!(revived.version > alias.version);
+        assert_eq!(db.get_aliases_by_groups(&["default".into()]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn purge_alias_tombstones_drops_only_old_deletes() {
+        let (db, _dir) = setup();
+        seed_machine(&db, "m1");
+        db.add_alias("gs", "git status", "default", "m1").unwrap();
+        db.add_alias("ll", "ls -la", "default", "m1").unwrap();
+        db.delete_alias_by_name("gs", "default", "m1").unwrap();
+
+        let before_delete = chrono::Utc::now().timestamp_millis() - 60_000;
+        assert_eq!(db.purge_alias_tombstones(before_delete).unwrap(), 0);
+        assert!(db
+            .add_alias_at("gs", "x", "default", "m2", Some(before_delete), false, false)
+            .is_err());
+
+        let after_delete = chrono::Utc::now().timestamp_millis() + 1;
+        assert_eq!(db.purge_alias_tombstones(after_delete).unwrap(), 1);
+        assert_eq!(db.get_all_aliases().unwrap().len(), 1);
+        // With the tombstone gone, the old add is accepted like any other.
+        assert!(db
+            .add_alias_at("gs", "x", "default", "m2", Some(before_delete), false, false)
+            .is_ok());
+    }
+
     // ===== Group filtering tests =====
 
     #[test]
@@ -995,6 +2269,140 @@ mod tests {
         assert!(result.is_empty());
     }
 
+    // ===== Group management tests =====
+
+    #[test]
+    fn create_group_is_idempotent() {
+        let (db, _dir) = setup();
+        assert!(db.create_group("ops").unwrap());
+        assert!(!db.create_group("ops").unwrap());
+
+        let groups = db.list_groups().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "ops");
+        assert!(groups[0].created_at.is_some());
+        assert_eq!(groups[0].alias_count, 0);
+    }
+
+    #[test]
+    fn list_groups_includes_implicit_groups_with_counts() {
+        let (db, _dir) = setup();
+        seed_machine(&db, "m1");
+        seed_machine(&db, "m2");
+        db.add_alias("gs", "git status", "default", "m1").unwrap();
+        db.add_alias("dc", "docker-compose", "work", "m1").unwrap();
+        db.add_alias("k", "kubectl", "work", "m1").unwrap();
+        db.create_group("empty").unwrap();
+
+        let groups = db.list_groups().unwrap();
+        let names: Vec<&str> = groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["default", "empty", "work"]);
+
+        assert_eq!(groups[0].alias_count, 1);
+        assert_eq!(groups[0].machine_count, 2);
+        assert!(groups[0].created_at.is_none());
+        assert_eq!(groups[1].alias_count, 0);
+        assert_eq!(groups[1].machine_count, 0);
+        assert_eq!(groups[2].alias_count, 2);
+        assert_eq!(groups[2].machine_count, 0);
+    }
+
+    #[test]
+    fn orphaned_aliases_are_those_outside_known_groups() {
+        let (db, _dir) = setup();
+        seed_machine(&db, "m1");
+        db.create_group("ops").unwrap();
+        db.add_alias("gs", "git status", "default", "m1").unwrap();
+        db.add_alias("dep", "deploy", "ops", "m1").unwrap();
+        db.add_alias("k", "kubectl", "old-team", "m1").unwrap();
+        db.add_alias("kx", "kubectx", "old-team", "m1").unwrap();
+        db.delete_alias_by_name("kx", "old-team", "m1").unwrap();
+
+        let known = db.known_groups().unwrap();
+        assert_eq!(known, ["default", "ops"]);
+        let orphans = db.find_orphaned_aliases(&known).unwrap();
+        let names: Vec<&str> = orphans.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["k"]);
+        assert!(db.find_orphaned_aliases(&["old-team".into()]).unwrap().len() == 2);
+    }
+
+    #[test]
+    fn reassign_moves_aliases_and_skips_taken_names() {
+        let (db, _dir) = setup();
+        db.add_alias("k", "kubectl", "old-team", "m1").unwrap();
+        db.add_alias("gs", "git status -s", "old-team", "m1").unwrap();
+        db.add_alias("tf", "terraform", "old-team", "m1").unwrap();
+        db.add_alias("gs", "git status", "default", "m1").unwrap();
+        db.add_alias("tf", "tofu", "default", "m1").unwrap();
+        db.delete_alias_by_name("tf", "default", "m1").unwrap();
+
+        let outcome = db.reassign_aliases("old-team", "default", "m2").unwrap();
+        assert_eq!(outcome.moved, ["k", "tf"]);
+        assert_eq!(outcome.skipped, ["gs"]);
+
+        let moved = db.get_alias_by_name("k", "default").unwrap().unwrap();
+        assert_eq!(moved.command, "kubectl");
+        assert_eq!(moved.version, 2);
+        let revived = db.get_alias_by_name("tf", "default").unwrap().unwrap();
+        assert_eq!(revived.command, "terraform");
+        assert_eq!(db.get_alias_by_name("gs", "default").unwrap().unwrap().command, "git status");
+        assert!(db.get_alias_by_name("gs", "old-team").unwrap().is_some());
+        assert!(db.get_alias_by_name("k", "old-team").unwrap().is_none());
+        assert_eq!(db.get_history_filtered(10, 0, Some("reassign"), None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn delete_group_refuses_non_empty_without_force() {
+        let (db, _dir) = setup();
+        seed_machine(&db, "m1");
+        db.add_alias("gs", "git status", "default", "m1").unwrap();
+
+        assert_eq!(
+            db.delete_group("default", false).unwrap(),
+            GroupDeletion::NotEmpty {
+                aliases: 1,
+                machines: 1
+            }
+        );
+        assert_eq!(db.get_all_aliases().unwrap().len(), 1);
+        assert_eq!(db.delete_group("missing", false).unwrap(), GroupDeletion::NotFound);
+
+        db.create_group("empty").unwrap();
+        assert_eq!(
+            db.delete_group("empty", false).unwrap(),
+            GroupDeletion::Deleted { aliases_removed: 0 }
+        );
+        assert!(db.list_groups().unwrap().iter().all(|g| g.name != "empty"));
+    }
+
+    #[test]
+    fn force_delete_group_cascades() {
+        let (db, _dir) = setup();
+        db.register_machine(
+            "m1",
+            "host-m1",
+            &["default".into(), "work".into()],
+            "linux",
+            "tok-m1",
+            None,
+        )
+        .unwrap();
+        db.add_alias("dc", "docker-compose", "work", "m1").unwrap();
+        db.add_alias("gs", "git status", "default", "m1").unwrap();
+
+        assert_eq!(
+            db.delete_group("work", true).unwrap(),
+            GroupDeletion::Deleted { aliases_removed: 1 }
+        );
+        let aliases = db.get_all_aliases().unwrap();
+        assert_eq!(aliases.len(), 1);
+        assert_eq!(aliases[0].name, "gs");
+        let machine = db.get_machine_by_token("tok-m1").unwrap().unwrap();
+        assert_eq!(machine.groups, vec!["default".to_string()]);
+        // The removed alias is left as a tombstone so peers see the delete.
+        assert_eq!(db.purge_alias_tombstones(i64::MAX).unwrap(), 1);
+    }
+
     // ===== Conflict tests =====
 
     #[test]
@@ -1030,6 +2438,89 @@ mod tests {
         assert!(conflicts.is_empty());
     }
 
+    #[test]
+    fn record_resolved_conflict_is_not_pending() {
+        let (db, _dir) = setup();
+        let id = db
+            .record_resolved_conflict("gs", "default", "cmd1", "cmd2", "m1", "last_write_wins:applied")
+            .unwrap();
+        assert!(db.get_conflicts_by_machine("m1").unwrap().is_empty());
+        let conflict = db.get_conflict(id).unwrap().unwrap();
+        assert!(conflict.resolved);
+        assert_eq!(conflict.resolution.as_deref(), Some("last_write_wins:applied"));
+    }
+
+    #[test]
+    fn purge_removes_only_old_resolved_conflicts() {
+        let (db, _dir) = setup();
+        let open_old = db.create_conflict("gs", "default", "a", "b", "m1").unwrap();
+        let resolved_old = db.create_conflict("dc", "default", "a", "b", "m1").unwrap();
+        let resolved_new = db.create_conflict("ll", "default", "a", "b", "m1").unwrap();
+        db.resolve_conflict(resolved_old, "keep_local").unwrap();
+        db.resolve_conflict(resolved_new, "keep_remote").unwrap();
+        let auto = db
+            .record_resolved_conflict("gl", "default", "a", "b", "m1", "first_write_wins:rejected")
+            .unwrap();
+        let other = db
+            .record_resolved_conflict("gs", "default", "a", "b", "m2", "keep_local")
+            .unwrap();
+        // Backdate everything but `resolved_new` by 40 days.
+        let old = chrono::Utc::now().timestamp_millis() - 40 * 86_400_000;
+        db.raw_connection()
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE conflicts SET created_at = ?1, resolved_at = CASE WHEN resolved THEN ?1 END WHERE id != ?2",
+                params![old, resolved_new],
+            )
+            .unwrap();
+        assert_eq!(
+            db.get_conflict_counts("m1").unwrap(),
+            ConflictCounts { open: 1, resolved: 3 }
+        );
+
+        assert_eq!(
+            db.delete_resolved_conflicts("m1", 30 * 86_400_000).unwrap(),
+            2
+        );
+        assert!(db.get_conflict(resolved_old).unwrap().is_none());
+        assert!(db.get_conflict(auto).unwrap().is_none());
+        assert!(db.get_conflict(resolved_new).unwrap().is_some());
+        assert!(db.get_conflict(open_old).unwrap().is_some());
+        // Other machines' conflicts are theirs to purge
+        assert!(db.get_conflict(other).unwrap().is_some());
+        assert_eq!(
+            db.get_conflict_counts("m1").unwrap(),
+            ConflictCounts { open: 1, resolved: 1 }
+        );
+        let all = db.get_conflicts_filtered("m1", true, 100, 0).unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(all.iter().any(|c| c.resolved && c.resolved_at.is_some()));
+    }
+
+    #[test]
+    fn conflicts_page_newest_first() {
+        let (db, _dir) = setup();
+        for i in 0..5 {
+            db.create_conflict(&format!("a{i}"), "default", "x", "y", "m1").unwrap();
+        }
+        db.create_conflict("other", "default", "x", "y", "m2").unwrap();
+
+        let names = |limit, offset| -> Vec<String> {
+            db.get_conflicts_filtered("m1", false, limit, offset)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.alias_name)
+                .collect()
+        };
+        assert_eq!(names(2, 0), ["a4", "a3"]);
+        assert_eq!(names(2, 2), ["a2", "a1"]);
+        assert_eq!(names(2, 4), ["a0"]);
+        assert!(names(2, 6).is_empty());
+        assert_eq!(db.count_conflicts("m1", false).unwrap(), 5);
+        assert_eq!(db.count_conflicts("m2", true).unwrap(), 1);
+    }
+
     #[test]
     fn resolve_conflict() {
         let (db, _dir) = setup();
@@ -1066,4 +2557,564 @@ mod tests {
         assert_eq!(history[0].alias_name, "second");
         assert_eq!(history[1].alias_name, "first");
     }
+
+    #[test]
+    fn history_filtered_by_action_and_machine() {
+        let (db, _dir) = setup();
+        seed_machine(&db, "m1");
+        seed_machine(&db, "m2");
+        let a = db.add_alias("a", "cmd", "default", "m1").unwrap();
+        db.add_alias("b", "cmd", "default", "m2").unwrap();
+        db.delete_alias(a.id, "m2").unwrap();
+
+        let adds = db.get_history_filtered(10, 0, Some("add"), None).unwrap();
+        assert_eq!(adds.len(), 2);
+        assert!(adds.iter().all(|h| h.action == "add"));
+
+        let m2 = db.get_history_filtered(10, 0, None, Some("m2")).unwrap();
+        let names: Vec<_> = m2
+            .iter()
+            .map(|h| (h.action.as_str(), h.alias_name.as_str()))
+            .collect();
+        assert_eq!(names, vec![("delete", "a"), ("add", "b")]);
+
+        let both = db
+            .get_history_filtered(10, 0, Some("add"), Some("m2"))
+            .unwrap();
+        assert_eq!(both.len(), 1);
+        assert_eq!(both[0].alias_name, "b");
+    }
+
+    #[test]
+    fn history_pages_do_not_overlap() {
+        let (db, _dir) = setup();
+        seed_machine(&db, "m1");
+        // Many rows share a millisecond; paging must still be stable
+        for i in 0..7 {
+            db.add_alias(&format!("a{i}"), "cmd", "default", "m1")
+                .unwrap();
+        }
+        let mut seen = Vec::new();
+        for page in 0..3 {
+            let rows = db.get_history_filtered(3, page * 3, None, None).unwrap();
+            seen.extend(rows.into_iter().map(|h| h.alias_name));
+        }
+        let expected: Vec<String> = (0..7).rev().map(|i| format!("a{i}")).collect();
+        assert_eq!(seen, expected);
+    }
+
+    // ===== Auth log tests =====
+
+    #[test]
+    fn auth_log_newest_first_with_paging() {
+        let (db, _dir) = setup();
+        db.log_auth(Some("m1"), None, true, "10.0.0.1", "http").unwrap();
+        db.log_auth(None, Some("abc123"), false, "10.0.0.2", "ws").unwrap();
+        db.log_auth(Some("m2"), None, true, "10.0.0.3", "http").unwrap();
+
+        assert_eq!(db.get_auth_log_count().unwrap(), 3);
+        let page = db.get_auth_log(2, 0).unwrap();
+        assert_eq!(page[0].machine_id.as_deref(), Some("m2"));
+        assert_eq!(page[1].result, "failure");
+        assert_eq!(page[1].token_hash.as_deref(), Some("abc123"));
+        assert_eq!(page[1].source, "ws");
+
+        let rest = db.get_auth_log(2, 2).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].client_ip, "10.0.0.1");
+
+        let before = chrono::Utc::now().timestamp_millis() - 60_000;
+        assert_eq!(db.purge_auth_log(before).unwrap(), 0);
+        let after = chrono::Utc::now().timestamp_millis() + 1;
+        assert_eq!(db.purge_auth_log(after).unwrap(), 3);
+        assert_eq!(db.get_auth_log_count().unwrap(), 0);
+    }
+
+    // ===== Shell history tests =====
+
+    fn shell_entry(id: &str, command: &str, group: &str) -> HistoryEntry {
+        HistoryEntry {
+            id: id.into(),
+            command: command.into(),
+            cwd: "/tmp".into(),
+            exit_code: 0,
+            duration_ms: 1,
+            session_id: "s1".into(),
+            machine_id: "m1".into(),
+            hostname: "host".into(),
+            timestamp: 1000,
+            shell: "zsh".into(),
+            group_name: group.into(),
+            truncated: false,
+            unknown_duration: false,
+        }
+    }
+
+    #[test]
+    fn aliases_ordered_by_recent_update() {
+        let (db, _dir) = setup();
+        db.add_alias("aa", "echo a", "default", "m1").unwrap();
+        db.add_alias("bb", "echo b", "default", "m1").unwrap();
+        db.add_alias("cc", "echo c", "default", "m1").unwrap();
+        let aa = db.get_alias_by_name("aa", "default").unwrap().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        db.update_alias(aa.id, "echo A", None, None, "m1").unwrap();
+
+        let groups = ["default".to_string()];
+        let names = |order| -> Vec<String> {
+            db.get_aliases_by_groups_ordered(&groups, order)
+                .unwrap()
+                .into_iter()
+                .map(|a| a.name)
+                .collect()
+        };
+        assert_eq!(names(AliasOrder::Name), ["aa", "bb", "cc"]);
+        assert_eq!(names(AliasOrder::Recent)[0], "aa");
+    }
+
+    #[test]
+    fn command_head_counts_group_by_first_word() {
+        let (db, _dir) = setup();
+        for (i, command) in ["gs", "gs -s", " gs", "git status", "ll"].into_iter().enumerate() {
+            db.insert_history_entry(&shell_entry(&format!("h{i}"), command, "default"))
+                .unwrap();
+        }
+        let counts = db.command_head_counts().unwrap();
+        assert_eq!(counts["gs"], 3);
+        assert_eq!(counts["git"], 1);
+        assert_eq!(counts["ll"], 1);
+    }
+
+    #[test]
+    fn machine_activity_counts_recent_history_per_machine() {
+        let (db, _dir) = setup();
+        let mut entries = Vec::new();
+        for (i, (machine, timestamp)) in [("m1", 500), ("m1", 1000), ("m1", 2000), ("m2", 100)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut e = shell_entry(&format!("h{i}"), "ls", "default");
+            e.machine_id = machine.into();
+            e.timestamp = timestamp;
+            entries.push(e);
+        }
+        assert_eq!(db.insert_history_batch_count(&entries), 4);
+
+        let activity = db.machine_activity(1000).unwrap();
+        assert_eq!(
+            activity["m1"],
+            MachineActivity { recent_commands: 2, last_command_at: Some(2000) }
+        );
+        assert_eq!(
+            activity["m2"],
+            MachineActivity { recent_commands: 0, last_command_at: Some(100) }
+        );
+        assert!(!activity.contains_key("m3"));
+    }
+
+    #[test]
+    fn insert_history_batch_returns_new_ids() {
+        let (db, _dir) = setup();
+        db.insert_history_entry(&shell_entry("h2", "ls", "default"))
+            .unwrap();
+
+        let batch = vec![
+            shell_entry("h1", "pwd", "default"),
+            shell_entry("h2", "ls", "default"),
+            shell_entry("h3", "cd", "default"),
+            shell_entry("h1", "pwd", "default"),
+        ];
+        assert_eq!(db.insert_history_batch(&batch), vec!["h1", "h3"]);
+        assert_eq!(db.insert_history_batch_count(&batch), 0);
+    }
+
+    #[test]
+    fn search_history_filters_by_group() {
+        let (db, _dir) = setup();
+        db.insert_history_entry(&shell_entry("h1", "make deploy", "work"))
+            .unwrap();
+        db.insert_history_entry(&shell_entry("h2", "make game", "personal"))
+            .unwrap();
+
+        let work = db
+            .search_history("make", None, None, None, Some("work"), 10, 0)
+            .unwrap();
+        assert_eq!(work.len(), 1);
+        assert_eq!(work[0].id, "h1");
+
+        let all = db
+            .search_history("make", None, None, None, None, 10, 0)
+            .unwrap();
+        assert_eq!(all.len(), 2);
+    }
+
+    #[test]
+    fn prune_history_applies_per_group_retention() {
+        let (db, _dir) = setup();
+        let day = 86_400_000;
+        let now = 1_000 * day;
+        for (id, group, age_days) in [
+            ("s-new", "scratch", 1),
+            ("s-old", "scratch", 8),
+            ("w-mid", "work", 300),
+            ("w-old", "work", 400),
+            ("d-old", "default", 400),
+        ] {
+            let mut e = shell_entry(id, "ls", group);
+            e.timestamp = now - age_days * day;
+            db.insert_history_entry(&e).unwrap();
+            db.add_history_pending(&e).unwrap();
+        }
+
+        let deleted = db
+            .prune_history(|group| match group {
+                "scratch" => Some(now - 7 * day),
+                "work" => Some(now - 365 * day),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(deleted, 2);
+        let mut left: Vec<String> = db
+            .history_in_range(None, None, None, 10)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        left.sort();
+        assert_eq!(left, ["d-old", "s-new", "w-mid"]);
+        assert_eq!(db.get_pending_count().unwrap(), 3);
+    }
+
+    #[test]
+    fn archive_history_deletes_only_after_the_archive_succeeds() {
+        let (db, _dir) = setup();
+        for (id, timestamp) in [("old-b", 200), ("old-a", 100), ("new", 900)] {
+            let mut e = shell_entry(id, "ls", "default");
+            e.timestamp = timestamp;
+            db.insert_history_entry(&e).unwrap();
+            db.add_history_pending(&e).unwrap();
+        }
+        // Queued for push without a local copy: not part of the archive
+        db.add_history_pending(&shell_entry("queued-only", "pwd", "default"))
+            .unwrap();
+
+        let failed = db.archive_history(500, |_| anyhow::bail!("disk full"));
+        assert!(failed.is_err());
+        assert_eq!(db.get_history_count().unwrap(), 3);
+
+        let mut archived = Vec::new();
+        let moved = db
+            .archive_history(500, |entries| {
+                archived = entries.iter().map(|e| e.id.clone()).collect();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(moved, 2);
+        assert_eq!(archived, ["old-a", "old-b"]);
+        assert_eq!(db.get_history_count().unwrap(), 1);
+        assert_eq!(db.get_pending_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn search_history_order_reverses_results() {
+        let (db, _dir) = setup();
+        for (id, ts) in [("a", 1_000), ("b", 2_000), ("c", 3_000)] {
+            let mut e = shell_entry(id, "make", "default");
+            e.timestamp = ts;
+            db.insert_history_entry(&e).unwrap();
+        }
+        let ids = |order| -> Vec<String> {
+            db.search_history_ordered("make", None, None, None, None, 2, 0, order)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.id)
+                .collect()
+        };
+        assert_eq!(ids(HistoryOrder::NewestFirst), ["c", "b"]);
+        assert_eq!(ids(HistoryOrder::OldestFirst), ["a", "b"]);
+        assert_eq!(HistoryOrder::default().reversed(), HistoryOrder::OldestFirst);
+    }
+
+    #[test]
+    fn history_in_range_narrows_to_window() {
+        let (db, _dir) = setup();
+        for (id, ts) in [("a", 1_000), ("b", 2_000), ("c", 3_000)] {
+            let mut e = shell_entry(id, "ls", "default");
+            e.timestamp = ts;
+            db.insert_history_entry(&e).unwrap();
+        }
+
+        let ids = |after, before| -> Vec<String> {
+            db.history_in_range(None, after, before, 10)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.id)
+                .collect()
+        };
+        assert_eq!(ids(None, None), vec!["c", "b", "a"]);
+        // `after` is inclusive, `before` exclusive
+        assert_eq!(ids(Some(2_000), Some(3_000)), vec!["b"]);
+        assert_eq!(ids(None, Some(2_000)), vec!["a"]);
+        assert!(ids(None, Some(i64::MIN)).is_empty());
+        assert!(db
+            .history_in_range(Some("work"), None, None, 10)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn requeue_history_queues_own_entries_in_window() {
+        let (db, _dir) = setup();
+        let mut old = shell_entry("old", "ls", "default");
+        old.timestamp = 500;
+        let recent = shell_entry("recent", "make", "default");
+        let mut other_machine = shell_entry("theirs", "pwd", "default");
+        other_machine.machine_id = "m2".into();
+        let private = shell_entry("private", "vault login", "secret");
+        let not_secret = |e: &HistoryEntry| (e.group_name != "secret").then(|| e.clone());
+        for e in [&old, &recent, &other_machine, &private] {
+            db.insert_history_entry(e).unwrap();
+        }
+
+        let queued = db
+            .requeue_history("m1", Some(1000), not_secret)
+            .unwrap();
+        assert_eq!(queued, 1);
+        let pending = db.get_pending_history(10).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, recent.id);
+        assert_eq!(pending[0].command, recent.command);
+        assert_eq!(pending[0].timestamp, recent.timestamp);
+
+        // Whole history; already-queued entries are not duplicated.
+        assert_eq!(db.requeue_history("m1", None, not_secret).unwrap(), 1);
+        let mut ids: Vec<String> = db
+            .get_pending_history(10)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["old", "recent"]);
+    }
+
+    #[test]
+    fn compact_pending_history_evicts_oldest_first() {
+        let (db, _dir) = setup();
+        for i in 0..5 {
+            db.insert_history_entry(&shell_entry(&format!("e{i}"), "ls", "default"))
+                .unwrap();
+            db.add_history_pending(&shell_entry(&format!("e{i}"), "ls", "default"))
+                .unwrap();
+        }
+        assert_eq!(db.get_pending_count().unwrap(), 5);
+
+        assert_eq!(db.compact_pending_history(10).unwrap(), 0);
+        assert_eq!(db.compact_pending_history(3).unwrap(), 2);
+        assert_eq!(db.get_pending_count().unwrap(), 3);
+        let kept: Vec<String> = db
+            .get_pending_history(10)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(kept, ["e2", "e3", "e4"]);
+        // Evicted entries are still in history.
+        assert_eq!(db.get_history_count().unwrap(), 5);
+
+        assert_eq!(db.compact_pending_history(0).unwrap(), 3);
+        assert_eq!(db.get_pending_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn open_adds_truncated_column_to_old_history_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE history (
+                    id TEXT PRIMARY KEY, command TEXT NOT NULL, cwd TEXT NOT NULL,
+                    exit_code INTEGER NOT NULL DEFAULT 0, duration_ms INTEGER NOT NULL DEFAULT 0,
+                    session_id TEXT NOT NULL, machine_id TEXT NOT NULL, hostname TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL, shell TEXT NOT NULL DEFAULT 'bash',
+                    group_name TEXT NOT NULL DEFAULT 'default'
+                );
+                INSERT INTO history VALUES ('h1', 'ls', '/', 0, 1, 's1', 'm1', 'host', 1000, 'zsh', 'default');",
+            )
+            .unwrap();
+        }
+
+        let db = SyncDatabase::open(path.to_str().unwrap()).unwrap();
+        let rows = db.search_history("ls", None, None, None, None, 10, 0).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert!(!rows[0].truncated);
+        assert!(!rows[0].unknown_duration);
+    }
+
+    #[test]
+    fn legacy_import_is_idempotent() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_path = dir.path().join("node.db");
+        {
+            let conn = Connection::open(&old_path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE machines (id INTEGER PRIMARY KEY, machine_id TEXT, hostname TEXT, groups TEXT,
+                    os_type TEXT, auth_token TEXT, last_seen INTEGER, created_at INTEGER);
+                CREATE TABLE aliases (id INTEGER PRIMARY KEY, name TEXT, command TEXT, group_name TEXT,
+                    created_by_machine TEXT, created_at INTEGER, updated_at INTEGER, version INTEGER);
+                CREATE TABLE sync_history (id INTEGER PRIMARY KEY, timestamp INTEGER, machine_id TEXT,
+                    action TEXT, alias_name TEXT, alias_command TEXT, group_name TEXT);
+                CREATE TABLE conflicts (id INTEGER PRIMARY KEY, alias_name TEXT, group_name TEXT,
+                    local_command TEXT, remote_command TEXT, machine_id TEXT, created_at INTEGER,
+                    resolved BOOLEAN, resolution TEXT);
+                INSERT INTO machines VALUES (1, 'm1', 'laptop', '[\"default\"]', NULL, 'tok1', 10, 5);
+                INSERT INTO aliases VALUES (1, 'gs', 'git status', 'default', 'm1', 100, 200, 3);
+                INSERT INTO aliases VALUES (2, 'll', 'ls -la', 'default', 'm1', 100, 100, 1);
+                INSERT INTO sync_history VALUES (1, 100, 'm1', 'add', 'gs', 'git status', 'default');
+                INSERT INTO sync_history VALUES (2, 200, 'm1', 'update', 'gs', 'git status', 'default');
+                INSERT INTO conflicts VALUES (1, 'gs', 'default', 'git st', 'git status', 'm1', 150, 0, NULL);",
+            )
+            .unwrap();
+        }
+        let db = SyncDatabase::open(dir.path().join("new.db").to_str().unwrap()).unwrap();
+
+        let first = db.import_legacy(&old_path).unwrap();
+        assert_eq!(
+            first,
+            LegacyImport {
+                machines: 1,
+                aliases: 2,
+                sync_history: 2,
+                conflicts: 1,
+                skipped: 0
+            }
+        );
+        let gs = db.get_alias_by_name("gs", "default").unwrap().unwrap();
+        assert_eq!((gs.version, gs.updated_at), (3, 200));
+        let machine = db.get_machine_by_token("tok1").unwrap().unwrap();
+        assert_eq!(machine.groups, ["default"]);
+
+        // A re-run after a failure copies nothing twice
+        let second = db.import_legacy(&old_path).unwrap();
+        assert_eq!(
+            second,
+            LegacyImport {
+                skipped: 6,
+                ..Default::default()
+            }
+        );
+        assert_eq!(db.get_all_aliases().unwrap().len(), 2);
+        assert_eq!(db.get_all_machines().unwrap().len(), 1);
+        assert_eq!(db.count_conflicts("m1", true).unwrap(), 1);
+        let conn = db.raw_connection().lock().unwrap();
+        let history: i64 = conn
+            .query_row("SELECT COUNT(*) FROM sync_history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(history, 2);
+    }
+
+    #[test]
+    fn concurrent_writers_wait_for_the_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("busy.db");
+        let path = path.to_str().unwrap().to_string();
+        let holder = SyncDatabase::open(&path).unwrap();
+        let writer = SyncDatabase::open(&path).unwrap();
+
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let hold = std::thread::spawn(move || {
+            let conn = holder.raw_connection().lock().unwrap();
+            conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+            conn.execute(
+                "INSERT INTO history_pending (id, entry_json, created_at) VALUES ('p1', '{}', 1)",
+                [],
+            )
+            .unwrap();
+            locked_tx.send(()).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            conn.execute_batch("COMMIT").unwrap();
+        });
+
+        locked_rx.recv().unwrap();
+        let write = std::thread::spawn(move || {
+            writer.insert_history_entry(&shell_entry("h1", "ls", "default"))?;
+            writer.get_history_count()
+        });
+
+        hold.join().unwrap();
+        assert_eq!(write.join().unwrap().unwrap(), 1);
+    }
+
+    #[test]
+    fn reads_do_not_wait_for_a_held_write() {
+        let (db, _dir) = setup();
+        let db = std::sync::Arc::new(db);
+        db.insert_history_entry(&shell_entry("h1", "ls", "default"))
+            .unwrap();
+
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let hold = {
+            let db = std::sync::Arc::clone(&db);
+            std::thread::spawn(move || {
+                let conn = db.raw_connection().lock().unwrap();
+                conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+                conn.execute("DELETE FROM history", []).unwrap();
+                locked_tx.send(()).unwrap();
+                // Keep the write open until the readers are finished.
+                let _ = done_rx.recv_timeout(std::time::Duration::from_secs(5));
+                conn.execute_batch("COMMIT").unwrap();
+            })
+        };
+        locked_rx.recv().unwrap();
+
+        // With one shared connection these would queue behind the writer.
+        let started = std::time::Instant::now();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = std::sync::Arc::clone(&db);
+                std::thread::spawn(move || db.get_history_count().unwrap())
+            })
+            .collect();
+        for reader in readers {
+            // The uncommitted delete is not visible to readers.
+            assert_eq!(reader.join().unwrap(), 1);
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+
+        done_tx.send(()).unwrap();
+        hold.join().unwrap();
+        assert_eq!(db.get_history_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn checkpoint_truncates_wal() {
+        let (db, dir) = setup();
+        db.insert_history_entry(&shell_entry("h1", "ls", "default"))
+            .unwrap();
+        db.checkpoint().unwrap();
+        let wal = dir.path().join("test.db-wal");
+        assert_eq!(std::fs::metadata(wal).map(|m| m.len()).unwrap_or(0), 0);
+    }
+
+    fn insert_twice(mode: crate::config::HistoryIdMode) -> i64 {
+        let (db, _dir) = setup();
+        for _ in 0..2 {
+            let id = mode.entry_id("cargo test", "/tmp", "s1", 1000);
+            db.insert_history_entry(&shell_entry(&id, "cargo test", "default"))
+                .unwrap();
+        }
+        db.get_history_count().unwrap()
+    }
+
+    #[test]
+    fn content_hash_ids_dedupe_identical_entries() {
+        assert_eq!(insert_twice(crate::config::HistoryIdMode::ContentHash), 1);
+    }
+
+    #[test]
+    fn random_ids_keep_identical_entries() {
+        assert_eq!(insert_twice(crate::config::HistoryIdMode::Random), 2);
+    }
 }
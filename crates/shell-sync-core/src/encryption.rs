@@ -1,7 +1,7 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use aes_gcm::aead::{Aead, KeyInit, OsRng};
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
 use aes_gcm::{Aes256Gcm, Nonce};
 use base64::engine::general_purpose::STANDARD as B64;
 use base64::Engine;
@@ -27,6 +27,9 @@ pub enum EncryptionError {
 
 type Result<T> = std::result::Result<T, EncryptionError>;
 
+/// Start of a group key exported with [`KeyManager::export_group_key`].
+const EXPORT_PREFIX: &str = "shell-sync-key:v1:";
+
 /// Manages X25519 keypair and per-group AES-256-GCM keys.
 pub struct KeyManager {
     keys_dir: PathBuf,
@@ -53,7 +56,7 @@ impl KeyManager {
     }
 
     /// Generate or load the X25519 keypair from disk.
-    fn init_keypair(keys_dir: &PathBuf) -> Result<(StaticSecret, PublicKey)> {
+    fn init_keypair(keys_dir: &Path) -> Result<(StaticSecret, PublicKey)> {
         let priv_path = keys_dir.join("private.key");
         let pub_path = keys_dir.join("public.key");
 
@@ -185,6 +188,42 @@ impl KeyManager {
         wrapped_b64: &str,
         sender_pubkey_b64: &str,
     ) -> Result<()> {
+        let key = self.open_wrapped_key(wrapped_b64, sender_pubkey_b64)?;
+        self.store_group_key(group_name, key)
+    }
+
+    /// Export a group key for manual transfer to the machine with
+    /// `recipient_pubkey_b64`: the wrapped key together with this machine's
+    /// public key, so the recipient can install it without a key exchange.
+    pub fn export_group_key(&self, group_name: &str, recipient_pubkey_b64: &str) -> Result<String> {
+        let wrapped = self.wrap_group_key(group_name, recipient_pubkey_b64)?;
+        Ok(format!(
+            "{EXPORT_PREFIX}{}:{wrapped}",
+            self.public_key_b64()
+        ))
+    }
+
+    /// Install a group key from [`export_group_key`](Self::export_group_key)
+    /// output. Returns `false` if the same key was already installed; a
+    /// different key for the group is never replaced.
+    pub fn import_group_key(&mut self, group_name: &str, exported: &str) -> Result<bool> {
+        let (sender, wrapped) = exported
+            .trim()
+            .strip_prefix(EXPORT_PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(|| EncryptionError::KeyExchangeError("Not an exported group key".into()))?;
+        let key = self.open_wrapped_key(wrapped, sender)?;
+        match self.group_keys.get(group_name) {
+            Some(existing) if *existing == key => Ok(false),
+            Some(_) => Err(EncryptionError::KeyExchangeError(format!(
+                "A different key for group '{group_name}' is already installed"
+            ))),
+            None => self.store_group_key(group_name, key).map(|()| true),
+        }
+    }
+
+    /// Decrypt a key wrapped for this machine by `sender_pubkey_b64`.
+    fn open_wrapped_key(&self, wrapped_b64: &str, sender_pubkey_b64: &str) -> Result<[u8; 32]> {
         let sender_pub = decode_public_key(sender_pubkey_b64)?;
         let shared_secret = self.private_key.diffie_hellman(&sender_pub);
         let aes_key = derive_aes_key(shared_secret.as_bytes());
@@ -210,8 +249,11 @@ impl KeyManager {
 
         let mut key = [0u8; 32];
         key.copy_from_slice(&plaintext);
+        Ok(key)
+    }
 
-        // Save to disk
+    /// Save a group key to disk and keep it in memory.
+    fn store_group_key(&mut self, group_name: &str, key: [u8; 32]) -> Result<()> {
         let groups_dir = self.keys_dir.join("groups");
         std::fs::create_dir_all(&groups_dir)
             .map_err(|e| EncryptionError::KeyFileError(format!("Create groups dir: {e}")))?;
@@ -229,6 +271,16 @@ impl KeyManager {
 
 /// Encrypt plaintext with AES-256-GCM. Returns (ciphertext, nonce).
 pub fn encrypt_field(key: &[u8; 32], plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    encrypt_field_with_aad(key, plaintext, b"")
+}
+
+/// [`encrypt_field`] bound to associated data: decryption fails unless it is
+/// given the same `aad`.
+pub fn encrypt_field_with_aad(
+    key: &[u8; 32],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>)> {
     let cipher = Aes256Gcm::new_from_slice(key)
         .map_err(|e| EncryptionError::EncryptFailed(e.to_string()))?;
 
@@ -237,7 +289,7 @@ pub fn encrypt_field(key: &[u8; 32], plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u
     let nonce = Nonce::from_slice(&nonce_bytes);
 
     let ciphertext = cipher
-        .encrypt(nonce, plaintext)
+        .encrypt(nonce, Payload { msg: plaintext, aad })
         .map_err(|e| EncryptionError::EncryptFailed(e.to_string()))?;
 
     Ok((ciphertext, nonce_bytes.to_vec()))
@@ -245,12 +297,22 @@ pub fn encrypt_field(key: &[u8; 32], plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u
 
 /// Decrypt ciphertext with AES-256-GCM.
 pub fn decrypt_field(key: &[u8; 32], ciphertext: &[u8], nonce: &[u8]) -> Result<Vec<u8>> {
+    decrypt_field_with_aad(key, ciphertext, nonce, b"")
+}
+
+/// Decrypt a ciphertext made by [`encrypt_field_with_aad`] with the same `aad`.
+pub fn decrypt_field_with_aad(
+    key: &[u8; 32],
+    ciphertext: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
     let cipher = Aes256Gcm::new_from_slice(key)
         .map_err(|e| EncryptionError::DecryptFailed(e.to_string()))?;
 
     let nonce = Nonce::from_slice(nonce);
     let plaintext = cipher
-        .decrypt(nonce, ciphertext)
+        .decrypt(nonce, Payload { msg: ciphertext, aad })
         .map_err(|e| EncryptionError::DecryptFailed(e.to_string()))?;
 
     Ok(plaintext)
@@ -258,12 +320,25 @@ pub fn decrypt_field(key: &[u8; 32], ciphertext: &[u8], nonce: &[u8]) -> Result<
 
 /// Encrypt a string, returning (base64_ciphertext, base64_nonce).
 pub fn encrypt_string(key: &[u8; 32], text: &str) -> Result<(String, String)> {
-    let (ct, nonce) = encrypt_field(key, text.as_bytes())?;
+    encrypt_string_with_aad(key, text, b"")
+}
+
+fn encrypt_string_with_aad(key: &[u8; 32], text: &str, aad: &[u8]) -> Result<(String, String)> {
+    let (ct, nonce) = encrypt_field_with_aad(key, text.as_bytes(), aad)?;
     Ok((B64.encode(&ct), B64.encode(&nonce)))
 }
 
 /// Decrypt a base64-encoded ciphertext and nonce back to a string.
 pub fn decrypt_string(key: &[u8; 32], b64_ct: &str, b64_nonce: &str) -> Result<String> {
+    decrypt_string_with_aad(key, b64_ct, b64_nonce, b"")
+}
+
+fn decrypt_string_with_aad(
+    key: &[u8; 32],
+    b64_ct: &str,
+    b64_nonce: &str,
+    aad: &[u8],
+) -> Result<String> {
     let ct = B64
         .decode(b64_ct)
         .map_err(|e| EncryptionError::DecryptFailed(format!("Base64 ciphertext: {e}")))?;
@@ -271,7 +346,7 @@ pub fn decrypt_string(key: &[u8; 32], b64_ct: &str, b64_nonce: &str) -> Result<S
         .decode(b64_nonce)
         .map_err(|e| EncryptionError::DecryptFailed(format!("Base64 nonce: {e}")))?;
 
-    let plaintext = decrypt_field(key, &ct, &nonce)?;
+    let plaintext = decrypt_field_with_aad(key, &ct, &nonce, aad)?;
     String::from_utf8(plaintext)
         .map_err(|e| EncryptionError::DecryptFailed(format!("UTF-8 decode: {e}")))
 }
@@ -280,18 +355,28 @@ pub fn decrypt_string(key: &[u8; 32], b64_ct: &str, b64_nonce: &str) -> Result<S
 
 use crate::models::{Alias, EncryptedAlias, EncryptedHistoryEntry, HistoryEntry};
 
+/// Associated data for one encrypted history field: the field name and the
+/// entry id, so a ciphertext only decrypts in the slot it was made for.
+fn history_field_aad(field: &str, entry_id: &str) -> Vec<u8> {
+    format!("shell-sync/history/{field}\0{entry_id}").into_bytes()
+}
+
 /// Encrypt a HistoryEntry for wire transmission.
 /// Encrypts: command, cwd, exit_code, duration_ms, hostname.
-/// Each field gets its own random nonce stored as a JSON array in `nonces`.
+/// Each field gets its own random nonce stored as a JSON array in `nonces`,
+/// and is bound to its field name and the entry id (see [`history_field_aad`]).
 pub fn encrypt_history_entry(
     key: &[u8; 32],
     entry: &HistoryEntry,
 ) -> Result<EncryptedHistoryEntry> {
-    let (ct_command, n_command) = encrypt_string(key, &entry.command)?;
-    let (ct_cwd, n_cwd) = encrypt_string(key, &entry.cwd)?;
-    let (ct_exit, n_exit) = encrypt_string(key, &entry.exit_code.to_string())?;
-    let (ct_dur, n_dur) = encrypt_string(key, &entry.duration_ms.to_string())?;
-    let (ct_host, n_host) = encrypt_string(key, &entry.hostname)?;
+    let enc = |field: &str, text: &str| {
+        encrypt_string_with_aad(key, text, &history_field_aad(field, &entry.id))
+    };
+    let (ct_command, n_command) = enc("command", &entry.command)?;
+    let (ct_cwd, n_cwd) = enc("cwd", &entry.cwd)?;
+    let (ct_exit, n_exit) = enc("exit_code", &entry.exit_code.to_string())?;
+    let (ct_dur, n_dur) = enc("duration_ms", &entry.duration_ms.to_string())?;
+    let (ct_host, n_host) = enc("hostname", &entry.hostname)?;
 
     let nonces = serde_json::json!([n_command, n_cwd, n_exit, n_dur, n_host]);
 
@@ -308,6 +393,8 @@ pub fn encrypt_history_entry(
         shell: entry.shell.clone(),
         group_name: entry.group_name.clone(),
         nonces: nonces.to_string(),
+        truncated: entry.truncated,
+        unknown_duration: entry.unknown_duration,
     })
 }
 
@@ -322,15 +409,18 @@ pub fn decrypt_history_entry(key: &[u8; 32], enc: &EncryptedHistoryEntry) -> Res
         ));
     }
 
-    let command = decrypt_string(key, &enc.command, &nonces[0])?;
-    let cwd = decrypt_string(key, &enc.cwd, &nonces[1])?;
-    let exit_code: i32 = decrypt_string(key, &enc.exit_code, &nonces[2])?
+    let dec = |field: &str, ct: &str, nonce: &str| {
+        decrypt_string_with_aad(key, ct, nonce, &history_field_aad(field, &enc.id))
+    };
+    let command = dec("command", &enc.command, &nonces[0])?;
+    let cwd = dec("cwd", &enc.cwd, &nonces[1])?;
+    let exit_code: i32 = dec("exit_code", &enc.exit_code, &nonces[2])?
         .parse()
         .map_err(|e| EncryptionError::DecryptFailed(format!("Parse exit_code: {e}")))?;
-    let duration_ms: i64 = decrypt_string(key, &enc.duration_ms, &nonces[3])?
+    let duration_ms: i64 = dec("duration_ms", &enc.duration_ms, &nonces[3])?
         .parse()
         .map_err(|e| EncryptionError::DecryptFailed(format!("Parse duration_ms: {e}")))?;
-    let hostname = decrypt_string(key, &enc.hostname, &nonces[4])?;
+    let hostname = dec("hostname", &enc.hostname, &nonces[4])?;
 
     Ok(HistoryEntry {
         id: enc.id.clone(),
@@ -344,6 +434,8 @@ pub fn decrypt_history_entry(key: &[u8; 32], enc: &EncryptedHistoryEntry) -> Res
         timestamp: enc.timestamp,
         shell: enc.shell.clone(),
         group_name: enc.group_name.clone(),
+        truncated: enc.truncated,
+        unknown_duration: enc.unknown_duration,
     })
 }
 
@@ -361,6 +453,10 @@ pub fn encrypt_alias(key: &[u8; 32], alias: &Alias) -> Result<EncryptedAlias> {
         updated_at: alias.updated_at,
         version: alias.version,
         nonce,
+        pinned: alias.pinned,
+        expand: alias.expand,
+        template: alias.template,
+        shells: alias.shells.clone(),
     })
 }
 
@@ -377,6 +473,10 @@ pub fn decrypt_alias(key: &[u8; 32], enc: &EncryptedAlias) -> Result<Alias> {
         created_at: enc.created_at,
         updated_at: enc.updated_at,
         version: enc.version,
+        pinned: enc.pinned,
+        expand: enc.expand,
+        template: enc.template,
+        shells: enc.shells.clone(),
     })
 }
 
@@ -407,6 +507,14 @@ fn derive_aes_key(shared_secret: &[u8]) -> [u8; 32] {
     key
 }
 
+/// Short, non-reversible identifier for an auth token, safe to store in logs.
+///
+/// Returns the first 12 hex characters of the token's SHA-256 digest.
+pub fn token_fingerprint(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    digest.iter().take(6).map(|b| format!("{:02x}", b)).collect()
+}
+
 /// Set file permissions (Unix only).
 fn set_file_permissions(path: &std::path::Path, mode: u32) -> Result<()> {
     #[cfg(unix)]
@@ -525,6 +633,69 @@ mod tests {
         );
     }
 
+    #[test]
+    fn exported_group_key_imports_and_decrypts_aliases() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sender = KeyManager::new(dir.path().join("a")).unwrap();
+        sender.create_group_key("team").unwrap();
+        let alias = Alias {
+            id: 7,
+            name: "gs".into(),
+            command: "git status --short".into(),
+            group_name: "team".into(),
+            created_by_machine: "machine-a".into(),
+            created_at: 1000,
+            updated_at: 1000,
+            version: 1,
+            pinned: false,
+            expand: false,
+            template: false,
+            shells: Vec::new(),
+        };
+        let encrypted = encrypt_alias(sender.get_group_key("team").unwrap(), &alias).unwrap();
+
+        let keys_b = dir.path().join("b");
+        let mut recipient = KeyManager::new(keys_b.clone()).unwrap();
+        let exported = sender
+            .export_group_key("team", &recipient.public_key_b64())
+            .unwrap();
+        assert!(recipient
+            .import_group_key("team", &format!("{exported}\n"))
+            .unwrap());
+        assert!(!recipient.import_group_key("team", &exported).unwrap());
+
+        // The key survives a restart and reads the sender's aliases
+        let recipient = KeyManager::new(keys_b).unwrap();
+        let key = recipient.get_group_key("team").unwrap();
+        assert_eq!(
+            decrypt_alias(key, &encrypted).unwrap().command,
+            alias.command
+        );
+    }
+
+    #[test]
+    fn import_rejects_foreign_or_conflicting_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sender = KeyManager::new(dir.path().join("a")).unwrap();
+        sender.create_group_key("team").unwrap();
+        let mut recipient = KeyManager::new(dir.path().join("b")).unwrap();
+        let mut bystander = KeyManager::new(dir.path().join("c")).unwrap();
+
+        // Wrapped for someone else
+        let exported = sender
+            .export_group_key("team", &recipient.public_key_b64())
+            .unwrap();
+        assert!(bystander.import_group_key("team", &exported).is_err());
+        assert!(!bystander.has_group_key("team"));
+        assert!(recipient.import_group_key("team", "not-a-key").is_err());
+
+        // A group that already has another key keeps it
+        let own = recipient.create_group_key("team").unwrap();
+        let err = recipient.import_group_key("team", &exported).unwrap_err();
+        assert!(err.to_string().contains("already installed"));
+        assert_eq!(recipient.get_group_key("team"), Some(&own));
+    }
+
     #[test]
     fn encrypt_decrypt_history_entry_roundtrip() {
         let mut key = [0u8; 32];
@@ -542,6 +713,8 @@ mod tests {
             timestamp: 1700000000,
             shell: "zsh".into(),
             group_name: "default".into(),
+            truncated: false,
+            unknown_duration: false,
         };
 
         let encrypted = encrypt_history_entry(&key, &entry).unwrap();
@@ -567,6 +740,48 @@ mod tests {
         assert_eq!(decrypted.hostname, entry.hostname);
     }
 
+    #[test]
+    fn history_fields_are_bound_to_their_slot_and_entry() {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        let entry = HistoryEntry {
+            id: "abc-123".into(),
+            command: "cat ~/.ssh/id_ed25519".into(),
+            cwd: "/home/user".into(),
+            exit_code: 0,
+            duration_ms: 5,
+            session_id: "sess-1".into(),
+            machine_id: "machine-1".into(),
+            hostname: "my-laptop".into(),
+            timestamp: 1700000000,
+            shell: "zsh".into(),
+            group_name: "default".into(),
+            truncated: false,
+            unknown_duration: false,
+        };
+        let encrypted = encrypt_history_entry(&key, &entry).unwrap();
+        let mut nonces: Vec<String> = serde_json::from_str(&encrypted.nonces).unwrap();
+
+        // A relay swaps the command and cwd ciphertexts (with their nonces)
+        let mut swapped = encrypted.clone();
+        std::mem::swap(&mut swapped.command, &mut swapped.cwd);
+        nonces.swap(0, 1);
+        swapped.nonces = serde_json::to_string(&nonces).unwrap();
+        assert!(decrypt_history_entry(&key, &swapped).is_err());
+
+        // Unbound fields, as entries were encrypted before, swap unnoticed
+        let (ct_command, n_command) = encrypt_string(&key, &entry.command).unwrap();
+        let as_cwd = decrypt_string(&key, &ct_command, &n_command).unwrap();
+        assert_eq!(as_cwd, entry.command);
+
+        // Or moves the fields onto another entry
+        let mut moved = encrypted.clone();
+        moved.id = "other-entry".into();
+        assert!(decrypt_history_entry(&key, &moved).is_err());
+
+        assert_eq!(decrypt_history_entry(&key, &encrypted).unwrap().command, entry.command);
+    }
+
     #[test]
     fn encrypt_decrypt_alias_roundtrip() {
         let mut key = [0u8; 32];
@@ -581,6 +796,10 @@ mod tests {
             created_at: 1000,
             updated_at: 2000,
             version: 3,
+            pinned: false,
+            expand: false,
+            template: false,
+            shells: Vec::new(),
         };
 
         let encrypted = encrypt_alias(&key, &alias).unwrap();
@@ -613,4 +832,13 @@ mod tests {
             assert_eq!(pub_meta.permissions().mode() & 0o777, 0o600);
         }
     }
+
+    #[test]
+    fn token_fingerprint_is_short_and_stable() {
+        let fp = token_fingerprint("secret-token");
+        assert_eq!(fp.len(), 12);
+        assert_eq!(fp, token_fingerprint("secret-token"));
+        assert_ne!(fp, token_fingerprint("other-token"));
+        assert!(!fp.contains("secret"));
+    }
 }
@@ -1,22 +1,70 @@
+use crate::config::CaptureEndpoint;
 use crate::shell::ShellType;
 
 /// Generate shell hooks for the given shell type that capture command history
-/// and send it to the local daemon via Unix socket.
-pub fn generate_hooks(shell: ShellType, socket_path: &str, session_id: &str) -> String {
+/// and send it to the local daemon at `endpoint` (a Unix socket, or a port on
+/// 127.0.0.1).
+///
+/// If `SHELL_SYNC_GROUP` is set in the shell, the hooks forward it so the
+/// command is attributed to that group instead of the daemon's default.
+///
+/// The command is sent base64-encoded as `command_b64`, so multi-line input
+/// (heredocs, loops) keeps its newlines inside the one-line JSON payload.
+/// Over TCP each payload also carries the capture secret, which ends up in
+/// the generated script; keep the file private.
+pub fn generate_hooks(shell: ShellType, endpoint: &CaptureEndpoint, session_id: &str) -> String {
     match shell {
-        ShellType::Zsh => generate_zsh_hooks(socket_path, session_id),
-        ShellType::Bash => generate_bash_hooks(socket_path, session_id),
-        ShellType::Fish => generate_fish_hooks(socket_path, session_id),
+        ShellType::Zsh => generate_zsh_hooks(&Target::new(endpoint, false), session_id),
+        ShellType::Bash => generate_bash_hooks(&Target::new(endpoint, false), session_id),
+        ShellType::Fish => generate_fish_hooks(&Target::new(endpoint, true), session_id),
     }
 }
 
-fn generate_zsh_hooks(socket_path: &str, session_id: &str) -> String {
+/// The endpoint-specific pieces of a hook script.
+struct Target {
+    /// Sets the variable the other pieces refer to.
+    var: String,
+    /// Extra condition (joined with `&&`) that the daemon is listening.
+    ready: &'static str,
+    /// Command that reads the payload on stdin and delivers it.
+    send: &'static str,
+}
+
+impl Target {
+    fn new(endpoint: &CaptureEndpoint, fish: bool) -> Self {
+        let set = |name: &str, value: String| match fish {
+            true => format!("set -g {name} \"{value}\""),
+            false => format!("{name}=\"{value}\""),
+        };
+        match endpoint {
+            CaptureEndpoint::Unix(path) => Self {
+                var: set("_shell_sync_socket", path.display().to_string()),
+                ready: match fish {
+                    true => "; and test -S \"$_shell_sync_socket\"",
+                    false => " && -S \"$_shell_sync_socket\"",
+                },
+                send: "nc -U -w1 \"$_shell_sync_socket\"",
+            },
+            CaptureEndpoint::Tcp { port, secret } => Self {
+                var: format!(
+                    "{}\n{}",
+                    set("_shell_sync_port", port.to_string()),
+                    set("_shell_sync_secret", secret.clone())
+                ),
+                ready: "",
+                send: "nc -w1 127.0.0.1 \"$_shell_sync_port\"",
+            },
+        }
+    }
+}
+
+fn generate_zsh_hooks(target: &Target, session_id: &str) -> String {
     format!(
         r#"# Shell Sync history hooks for zsh
 # Auto-generated — do not edit manually
 
 _shell_sync_session_id="{session_id}"
-_shell_sync_socket="{socket_path}"
+{var}
 _shell_sync_cmd_start=0
 
 _shell_sync_preexec() {{
@@ -26,18 +74,20 @@ _shell_sync_preexec() {{
 
 _shell_sync_precmd() {{
     local exit_code=$?
-    if [[ -n "$_shell_sync_last_cmd" && -S "$_shell_sync_socket" ]]; then
+    if [[ -n "$_shell_sync_last_cmd"{ready} ]]; then
         local end=$EPOCHREALTIME
         local duration_ms=$(( (${{end%.*}} - ${{_shell_sync_cmd_start%.*}}) * 1000 + (10#${{end#*.}} - 10#${{_shell_sync_cmd_start#*.}}) / 1000 ))
         [[ $duration_ms -lt 0 ]] && duration_ms=0
         local payload
-        payload=$(printf '{{"command":"%s","cwd":"%s","exit_code":%d,"duration_ms":%d,"session_id":"%s","shell":"zsh"}}' \
-            "$(echo "$_shell_sync_last_cmd" | sed 's/\\/\\\\/g; s/"/\\"/g')" \
+        payload=$(printf '{{"command_b64":"%s","cwd":"%s","exit_code":%d,"duration_ms":%d,"session_id":"%s","shell":"zsh","group_name":"%s","secret":"%s"}}' \
+            "$(printf '%s' "$_shell_sync_last_cmd" | base64 | tr -d '\n')" \
             "$(pwd | sed 's/\\/\\\\/g; s/"/\\"/g')" \
             "$exit_code" \
             "$duration_ms" \
-            "$_shell_sync_session_id")
-        echo "$payload" | nc -U -w1 "$_shell_sync_socket" 2>/dev/null &!
+            "$_shell_sync_session_id" \
+            "${{SHELL_SYNC_GROUP:-}}" \
+            "${{_shell_sync_secret:-}}")
+        echo "$payload" | {send} 2>/dev/null &!
     fi
     _shell_sync_last_cmd=""
 }}
@@ -60,17 +110,19 @@ zle -N __shell_sync_search
 bindkey '^R' __shell_sync_search
 "#,
         session_id = session_id,
-        socket_path = socket_path,
+        var = target.var,
+        ready = target.ready,
+        send = target.send,
     )
 }
 
-fn generate_bash_hooks(socket_path: &str, session_id: &str) -> String {
+fn generate_bash_hooks(target: &Target, session_id: &str) -> String {
     format!(
         r#"# Shell Sync history hooks for bash
 # Auto-generated — do not edit manually
 
 _shell_sync_session_id="{session_id}"
-_shell_sync_socket="{socket_path}"
+{var}
 _shell_sync_cmd_start=0
 _shell_sync_last_cmd=""
 
@@ -83,18 +135,20 @@ _shell_sync_debug_trap() {{
 
 _shell_sync_prompt_command() {{
     local exit_code=$?
-    if [[ -n "$_shell_sync_last_cmd" && -S "$_shell_sync_socket" ]]; then
+    if [[ -n "$_shell_sync_last_cmd"{ready} ]]; then
         local end=$SECONDS
         local duration_ms=$(( (end - _shell_sync_cmd_start) * 1000 ))
         [[ $duration_ms -lt 0 ]] && duration_ms=0
         local payload
-        payload=$(printf '{{"command":"%s","cwd":"%s","exit_code":%d,"duration_ms":%d,"session_id":"%s","shell":"bash"}}' \
-            "$(echo "$_shell_sync_last_cmd" | sed 's/\\/\\\\/g; s/"/\\"/g')" \
+        payload=$(printf '{{"command_b64":"%s","cwd":"%s","exit_code":%d,"duration_ms":%d,"session_id":"%s","shell":"bash","group_name":"%s","secret":"%s"}}' \
+            "$(printf '%s' "$_shell_sync_last_cmd" | base64 | tr -d '\n')" \
             "$(pwd | sed 's/\\/\\\\/g; s/"/\\"/g')" \
             "$exit_code" \
             "$duration_ms" \
-            "$_shell_sync_session_id")
-        echo "$payload" | nc -U -w1 "$_shell_sync_socket" 2>/dev/null &
+            "$_shell_sync_session_id" \
+            "${{SHELL_SYNC_GROUP:-}}" \
+            "${{_shell_sync_secret:-}}")
+        echo "$payload" | {send} 2>/dev/null &
     fi
     _shell_sync_last_cmd=""
 }}
@@ -114,17 +168,19 @@ __shell_sync_search() {{
 bind -x '"\C-r": __shell_sync_search'
 "#,
         session_id = session_id,
-        socket_path = socket_path,
+        var = target.var,
+        ready = target.ready,
+        send = target.send,
     )
 }
 
-fn generate_fish_hooks(socket_path: &str, session_id: &str) -> String {
+fn generate_fish_hooks(target: &Target, session_id: &str) -> String {
     format!(
         r#"# Shell Sync history hooks for fish
 # Auto-generated — do not edit manually
 
 set -g _shell_sync_session_id "{session_id}"
-set -g _shell_sync_socket "{socket_path}"
+{var}
 set -g _shell_sync_cmd_start 0
 
 function _shell_sync_preexec --on-event fish_preexec
@@ -134,21 +190,23 @@ end
 
 function _shell_sync_postexec --on-event fish_postexec
     set -l exit_code $status
-    if test -n "$_shell_sync_last_cmd"; and test -S "$_shell_sync_socket"
+    if test -n "$_shell_sync_last_cmd"{ready}
         set -l end_time (date +%s)
         set -l duration_ms (math "($end_time - $_shell_sync_cmd_start) * 1000")
         if test $duration_ms -lt 0
             set duration_ms 0
         end
-        set -l escaped_cmd (string replace -a '\\' '\\\\' -- "$_shell_sync_last_cmd" | string replace -a '"' '\\"')
+        set -l encoded_cmd (printf '%s' "$_shell_sync_last_cmd" | base64 | tr -d '\n')
         set -l escaped_cwd (string replace -a '\\' '\\\\' -- (pwd) | string replace -a '"' '\\"')
-        set -l payload (printf '{{"command":"%s","cwd":"%s","exit_code":%d,"duration_ms":%d,"session_id":"%s","shell":"fish"}}' \
-            "$escaped_cmd" \
+        set -l payload (printf '{{"command_b64":"%s","cwd":"%s","exit_code":%d,"duration_ms":%d,"session_id":"%s","shell":"fish","group_name":"%s","secret":"%s"}}' \
+            "$encoded_cmd" \
             "$escaped_cwd" \
             $exit_code \
             $duration_ms \
-            "$_shell_sync_session_id")
-        echo "$payload" | nc -U -w1 "$_shell_sync_socket" 2>/dev/null &
+            "$_shell_sync_session_id" \
+            "$SHELL_SYNC_GROUP" \
+            "$_shell_sync_secret")
+        echo "$payload" | {send} 2>/dev/null &
     end
     set -g _shell_sync_last_cmd ""
 end
@@ -164,7 +222,9 @@ end
 bind \cr __shell_sync_search
 "#,
         session_id = session_id,
-        socket_path = socket_path,
+        var = target.var,
+        ready = target.ready,
+        send = target.send,
     )
 }
 
@@ -172,9 +232,13 @@ bind \cr __shell_sync_search
 mod tests {
     use super::*;
 
+    fn unix(path: &str) -> CaptureEndpoint {
+        CaptureEndpoint::Unix(path.into())
+    }
+
     #[test]
     fn zsh_hooks_contain_preexec_precmd() {
-        let hooks = generate_hooks(ShellType::Zsh, "/tmp/test.sock", "sess-123");
+        let hooks = generate_hooks(ShellType::Zsh, &unix("/tmp/test.sock"), "sess-123");
         assert!(hooks.contains("preexec"));
         assert!(hooks.contains("precmd"));
         assert!(hooks.contains("sess-123"));
@@ -183,7 +247,7 @@ mod tests {
 
     #[test]
     fn bash_hooks_contain_debug_trap() {
-        let hooks = generate_hooks(ShellType::Bash, "/tmp/test.sock", "sess-123");
+        let hooks = generate_hooks(ShellType::Bash, &unix("/tmp/test.sock"), "sess-123");
         assert!(hooks.contains("DEBUG"));
         assert!(hooks.contains("PROMPT_COMMAND"));
         assert!(hooks.contains("sess-123"));
@@ -191,17 +255,53 @@ mod tests {
 
     #[test]
     fn fish_hooks_contain_events() {
-        let hooks = generate_hooks(ShellType::Fish, "/tmp/test.sock", "sess-123");
+        let hooks = generate_hooks(ShellType::Fish, &unix("/tmp/test.sock"), "sess-123");
         assert!(hooks.contains("fish_preexec"));
         assert!(hooks.contains("fish_postexec"));
         assert!(hooks.contains("sess-123"));
     }
 
+    #[test]
+    fn hooks_forward_group_env() {
+        for shell in [ShellType::Zsh, ShellType::Bash, ShellType::Fish] {
+            let hooks = generate_hooks(shell, &unix("/tmp/test.sock"), "s1");
+            assert!(hooks.contains(r#""group_name":"%s""#), "{:?}", shell);
+            assert!(hooks.contains("SHELL_SYNC_GROUP"), "{:?}", shell);
+        }
+    }
+
+    #[test]
+    fn hooks_send_the_command_base64_encoded() {
+        for shell in [ShellType::Zsh, ShellType::Bash, ShellType::Fish] {
+            let hooks = generate_hooks(shell, &unix("/tmp/test.sock"), "s1");
+            assert!(hooks.contains(r#"{"command_b64":"%s""#), "{:?}", shell);
+            assert!(hooks.contains("| base64 | tr -d '\\n'"), "{:?}", shell);
+        }
+    }
+
+    #[test]
+    fn tcp_hooks_send_to_the_local_port() {
+        let endpoint = CaptureEndpoint::Tcp {
+            port: 18889,
+            secret: "0123abcd".into(),
+        };
+        for shell in [ShellType::Zsh, ShellType::Bash, ShellType::Fish] {
+            let hooks = generate_hooks(shell, &endpoint, "s1");
+            assert!(hooks.contains("_shell_sync_port"), "{:?}", shell);
+            assert!(hooks.contains("18889"), "{:?}", shell);
+            assert!(hooks.contains("_shell_sync_secret") && hooks.contains("0123abcd"));
+            assert!(hooks.contains(r#""secret":"%s""#), "{:?}", shell);
+            assert!(hooks.contains(r#"nc -w1 127.0.0.1 "$_shell_sync_port""#), "{:?}", shell);
+            assert!(!hooks.contains("nc -U"), "{:?}", shell);
+            assert!(!hooks.contains("_shell_sync_socket"), "{:?}", shell);
+        }
+    }
+
     #[test]
     fn hooks_include_socket_path() {
         let socket = "/home/user/.shell-sync/sock";
         for shell in [ShellType::Zsh, ShellType::Bash, ShellType::Fish] {
-            let hooks = generate_hooks(shell, socket, "s1");
+            let hooks = generate_hooks(shell, &unix(socket), "s1");
             assert!(
                 hooks.contains(socket),
                 "Shell {:?} missing socket path",
@@ -1,9 +1,12 @@
+pub mod color;
 pub mod config;
 pub mod db;
 pub mod encryption;
 pub mod hooks;
+pub mod lint;
 pub mod models;
 pub mod protocol;
 pub mod secrets;
 pub mod shell;
 pub mod stats;
+pub mod tz;
@@ -0,0 +1,260 @@
+//! Checks for alias definitions that misbehave once a group is shared:
+//! names that shadow commands, aliases that expand back into themselves, and
+//! names a target shell rejects.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+
+use serde::Serialize;
+
+use crate::models::Alias;
+use crate::shell::{resolve_command, CommandTarget, ShellType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Probably intended, like `ls='ls -la'`.
+    Info,
+    /// Works, but surprises someone.
+    Warning,
+    /// The alias does not work in at least one shell.
+    Error,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintFinding {
+    pub name: String,
+    pub group: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Names fish refuses as function names, which is what its `alias` defines.
+#[rustfmt::skip]
+const FISH_RESERVED: &[&str] = &[
+    "!", "[", "and", "argparse", "begin", "break", "builtin", "case", "command", "continue",
+    "else", "end", "eval", "exec", "for", "function", "if", "not", "or", "read", "return",
+    "set", "status", "string", "switch", "test", "time", "while",
+];
+
+/// Lint every alias (optionally only in `group`), most severe first. Commands
+/// are looked up in `path`, a `$PATH`-style list.
+pub fn lint_aliases(
+    aliases: &[Alias],
+    group: Option<&str>,
+    path: Option<&OsStr>,
+) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+    for alias in aliases
+        .iter()
+        .filter(|a| group.is_none_or(|g| a.group_name == g))
+    {
+        let mut report = |severity, message: String| {
+            findings.push(LintFinding {
+                name: alias.name.clone(),
+                group: alias.group_name.clone(),
+                severity,
+                message,
+            })
+        };
+
+        for shell in [ShellType::Bash, ShellType::Zsh, ShellType::Fish] {
+            if alias.applies_to(shell) {
+                if let Some(problem) = invalid_name(&alias.name, shell) {
+                    report(Severity::Error, format!("{} in {}", problem, shell.name()));
+                }
+            }
+        }
+
+        let wraps_itself = first_word(&alias.command) == Some(alias.name.as_str());
+        match resolve_command(&alias.name, &HashMap::new(), path).target {
+            CommandTarget::Builtin(_) if !wraps_itself => report(
+                Severity::Warning,
+                format!("shadows the shell builtin `{}`", alias.name),
+            ),
+            CommandTarget::Executable(bin) if !wraps_itself => report(
+                Severity::Warning,
+                format!("shadows {} with a different command", bin.display()),
+            ),
+            CommandTarget::Builtin(_) | CommandTarget::Executable(_) => report(
+                Severity::Info,
+                format!("wraps the `{}` command", alias.name),
+            ),
+            CommandTarget::NotFound(_) => {}
+        }
+
+        if alias.command.trim() == alias.name {
+            report(
+                Severity::Warning,
+                "expands to itself, which loops in fish".to_string(),
+            );
+        } else if let Some(through) = loop_through(alias, aliases) {
+            report(
+                Severity::Warning,
+                format!("expands back into itself through {}", through.join(" -> ")),
+            );
+        }
+    }
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    findings
+}
+
+/// Why `shell` rejects `name` as an alias, if it does.
+fn invalid_name(name: &str, shell: ShellType) -> Option<&'static str> {
+    if name.is_empty() {
+        return Some("an empty name is not allowed");
+    }
+    if name.starts_with('-') {
+        return Some("a leading '-' is read as an option");
+    }
+    match shell {
+        ShellType::Fish if FISH_RESERVED.contains(&name) => Some("the name is reserved"),
+        ShellType::Fish if name.chars().all(|c| c.is_ascii_digit()) => {
+            Some("a numeric name is not a valid function name")
+        }
+        ShellType::Fish if name.contains('/') => Some("'/' is not allowed"),
+        ShellType::Bash | ShellType::Zsh
+            if name
+                .chars()
+                .any(|c| c.is_whitespace() || "=/$`'\"\\".contains(c)) =>
+        {
+            Some("the name contains a character aliases cannot use")
+        }
+        _ => None,
+    }
+}
+
+/// The first word of `command` after any `VAR=value` assignments.
+fn first_word(command: &str) -> Option<&str> {
+    command
+        .split_whitespace()
+        .find(|w| !crate::shell::is_assignment(w))
+}
+
+/// The other aliases in the same group that `alias` passes through before
+/// its expansion names it again.
+fn loop_through(alias: &Alias, aliases: &[Alias]) -> Option<Vec<String>> {
+    let group: HashMap<String, String> = aliases
+        .iter()
+        .filter(|a| a.group_name == alias.group_name)
+        .map(|a| (a.name.clone(), a.command.clone()))
+        .collect();
+    let resolution = resolve_command(&alias.name, &group, None);
+    let word = match resolution.target {
+        CommandTarget::Builtin(word) | CommandTarget::NotFound(word) => word,
+        CommandTarget::Executable(_) => return None,
+    };
+    (word == alias.name && resolution.chain.len() > 1).then(|| {
+        resolution.chain[1..]
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alias(name: &str, command: &str) -> Alias {
+        Alias {
+            id: 0,
+            name: name.into(),
+            command: command.into(),
+            group_name: "default".into(),
+            created_by_machine: "m1".into(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            pinned: false,
+            expand: false,
+            template: false,
+            shells: Vec::new(),
+        }
+    }
+
+    fn findings_for<'a>(findings: &'a [LintFinding], name: &str) -> Vec<&'a LintFinding> {
+        findings.iter().filter(|f| f.name == name).collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flags_an_alias_shadowing_a_binary() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bin = tempfile::tempdir().unwrap();
+        for name in ["ls", "grep"] {
+            let path = bin.path().join(name);
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let aliases = [
+            alias("ls", "ls -la"),
+            alias("grep", "rg"),
+            alias("cd", "z"),
+            alias("gs", "git status"),
+        ];
+        let findings = lint_aliases(&aliases, None, Some(bin.path().as_os_str()));
+
+        let grep = findings_for(&findings, "grep");
+        assert_eq!(grep.len(), 1);
+        assert_eq!(grep[0].severity, Severity::Warning);
+        assert!(grep[0].message.contains("grep with a different command"));
+        assert_eq!(findings_for(&findings, "ls")[0].severity, Severity::Info);
+        assert!(findings_for(&findings, "cd")[0]
+            .message
+            .contains("builtin `cd`"));
+        assert!(findings_for(&findings, "gs").is_empty());
+        assert_eq!(findings.last().unwrap().severity, Severity::Info);
+    }
+
+    #[test]
+    fn flags_a_name_fish_rejects() {
+        let mut bash_only = alias("switch", "git switch");
+        bash_only.shells = vec!["bash".into()];
+        let aliases = [alias("status", "git status"), bash_only, alias("-x", "ls")];
+        let findings = lint_aliases(&aliases, None, None);
+
+        let status = findings_for(&findings, "status");
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].severity, Severity::Error);
+        assert_eq!(status[0].message, "the name is reserved in fish");
+        assert!(findings_for(&findings, "switch").is_empty());
+        assert_eq!(findings_for(&findings, "-x").len(), 3);
+    }
+
+    #[test]
+    fn flags_aliases_that_expand_into_themselves() {
+        let aliases = [
+            alias("grep", "grep"),
+            alias("a", "b --flag"),
+            alias("b", "a"),
+            alias("ll", "ls -la"),
+        ];
+        let findings = lint_aliases(&aliases, Some("default"), None);
+
+        assert!(findings_for(&findings, "grep")[0]
+            .message
+            .contains("loops in fish"));
+        assert_eq!(
+            findings_for(&findings, "a")[0].message,
+            "expands back into itself through b"
+        );
+        assert_eq!(
+            findings_for(&findings, "b")[0].message,
+            "expands back into itself through a"
+        );
+        assert!(findings_for(&findings, "ll").is_empty());
+        assert!(lint_aliases(&aliases, Some("work"), None).is_empty());
+    }
+}
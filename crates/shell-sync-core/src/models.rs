@@ -11,6 +11,27 @@ pub struct Alias {
     pub created_at: i64,
     pub updated_at: i64,
     pub version: i64,
+    /// Listed first by `ls`.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Written double-quoted so the shell expands `$VAR` and `$(...)` when
+    /// the alias is defined; otherwise single-quoted and kept literal.
+    #[serde(default)]
+    pub expand: bool,
+    /// Written as a shell function that fills `{{placeholder}}`s in the
+    /// command from its arguments, prompting for any that are missing.
+    #[serde(default)]
+    pub template: bool,
+    /// Shells (`zsh`, `bash`, `fish`) the alias is written for; empty means all.
+    #[serde(default)]
+    pub shells: Vec<String>,
+}
+
+impl Alias {
+    /// Whether the alias should be defined in `shell`.
+    pub fn applies_to(&self, shell: crate::shell::ShellType) -> bool {
+        self.shells.is_empty() || self.shells.iter().any(|s| s == shell.name())
+    }
 }
 
 /// A registered machine in the sync network.
@@ -26,6 +47,38 @@ pub struct Machine {
     pub created_at: i64,
     #[serde(default)]
     pub public_key: Option<String>,
+    /// Groups whose aliases this machine can see but not change.
+    #[serde(default)]
+    pub read_only_groups: Vec<String>,
+}
+
+impl Machine {
+    pub fn permission(&self, group: &str) -> GroupPermission {
+        if self.read_only_groups.iter().any(|g| g == group) {
+            GroupPermission::Read
+        } else {
+            GroupPermission::Write
+        }
+    }
+}
+
+/// What a member machine may do with a group's aliases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupPermission {
+    Read,
+    #[default]
+    Write,
+}
+
+/// How much shell history the server holds from one machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineActivity {
+    /// Commands run since the cutoff passed to
+    /// [`SyncDatabase::machine_activity`](crate::db::SyncDatabase::machine_activity).
+    pub recent_commands: i64,
+    /// Timestamp of the machine's latest command; `None` if it has none.
+    pub last_command_at: Option<i64>,
 }
 
 /// A conflict between local and remote alias versions.
@@ -40,6 +93,17 @@ pub struct Conflict {
     pub created_at: i64,
     pub resolved: bool,
     pub resolution: Option<String>,
+    /// When the conflict was resolved; `None` while open and for conflicts
+    /// resolved before this was recorded.
+    #[serde(default)]
+    pub resolved_at: Option<i64>,
+}
+
+/// Number of open and resolved conflicts in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictCounts {
+    pub open: i64,
+    pub resolved: i64,
 }
 
 /// A record of a sync action in history.
@@ -54,6 +118,51 @@ pub struct SyncHistoryEntry {
     pub group_name: Option<String>,
 }
 
+/// One authentication attempt recorded in the server's audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthLogEntry {
+    pub id: i64,
+    pub timestamp: i64,
+    /// Set when the token matched a machine.
+    pub machine_id: Option<String>,
+    /// Truncated SHA-256 of an unrecognized token; the raw token is never stored.
+    pub token_hash: Option<String>,
+    /// `success` or `failure`.
+    pub result: String,
+    pub client_ip: String,
+    /// `http` or `ws`.
+    pub source: String,
+}
+
+/// A group with its current membership counts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupInfo {
+    pub name: String,
+    pub alias_count: i64,
+    pub machine_count: i64,
+    /// Set for groups created explicitly; `None` for groups that only exist
+    /// because an alias or machine references them.
+    pub created_at: Option<i64>,
+}
+
+/// Outcome of [`SyncDatabase::delete_group`](crate::db::SyncDatabase::delete_group).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupDeletion {
+    Deleted { aliases_removed: i64 },
+    NotFound,
+    /// The group still has members and `force` was not set.
+    NotEmpty { aliases: i64, machines: i64 },
+}
+
+/// Outcome of [`SyncDatabase::reassign_aliases`](crate::db::SyncDatabase::reassign_aliases).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AliasReassignment {
+    /// Names of the aliases moved.
+    pub moved: Vec<String>,
+    /// Names left behind because the target group already has them.
+    pub skipped: Vec<String>,
+}
+
 /// Response returned when registering a new machine.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegisterResponse {
@@ -80,12 +189,48 @@ pub struct AddAliasRequest {
     pub command: String,
     #[serde(default = "default_group")]
     pub group: String,
+    /// When the add was made, for adds replayed from a client's offline
+    /// queue; one made before the alias was deleted is rejected as stale.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+    /// See [`Alias::expand`].
+    #[serde(default)]
+    pub expand: bool,
+    /// See [`Alias::template`].
+    #[serde(default)]
+    pub template: bool,
+    /// See [`Alias::shells`].
+    #[serde(default)]
+    pub shells: Vec<String>,
 }
 
 /// Request body for updating an alias.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateAliasRequest {
     pub command: String,
+    /// Version the client last saw; a mismatch means another machine edited the alias first.
+    #[serde(default)]
+    pub expected_version: Option<i64>,
+    /// When the client made this edit (epoch ms); defaults to when the server receives it.
+    #[serde(default)]
+    pub updated_at: Option<i64>,
+    /// Change [`Alias::expand`]; left as is when omitted.
+    #[serde(default)]
+    pub expand: Option<bool>,
+    /// Change [`Alias::template`]; left as is when omitted.
+    #[serde(default)]
+    pub template: Option<bool>,
+    /// Replace [`Alias::shells`] (empty for all shells); left as is when omitted.
+    #[serde(default)]
+    pub shells: Option<Vec<String>>,
+}
+
+/// Request body for the admin machine edit, `PATCH /api/machines/:machine_id`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateMachineRequest {
+    /// Permission to set per group; groups not listed keep theirs.
+    #[serde(default)]
+    pub permissions: std::collections::BTreeMap<String, GroupPermission>,
 }
 
 /// Request body for resolving a conflict.
@@ -95,6 +240,26 @@ pub struct ResolveConflictRequest {
     pub resolution: String,
 }
 
+/// Request body for purging resolved conflicts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeConflictsRequest {
+    /// Only conflicts resolved at least this long ago are deleted.
+    pub older_than_ms: i64,
+}
+
+/// Request body for creating a group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateGroupRequest {
+    pub name: String,
+}
+
+/// Request body for moving all aliases from one group to another.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReassignAliasesRequest {
+    pub from: String,
+    pub to: String,
+}
+
 /// Request body for bulk import.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImportRequest {
@@ -107,6 +272,12 @@ pub struct ImportRequest {
 pub struct ImportAlias {
     pub name: String,
     pub command: String,
+    #[serde(default)]
+    pub expand: bool,
+    #[serde(default)]
+    pub template: bool,
+    #[serde(default)]
+    pub shells: Vec<String>,
 }
 
 /// A shell history entry that can be synced across machines.
@@ -123,17 +294,52 @@ pub struct HistoryEntry {
     pub timestamp: i64,
     pub shell: String,
     pub group_name: String,
+    /// Set when `command` was cut to the client's `max_command_len`.
+    #[serde(default)]
+    pub truncated: bool,
+    /// Set when the hook reported a negative duration or one above the
+    /// client's `max_duration_secs` (e.g. across suspend); `duration_ms` is 0.
+    #[serde(default)]
+    pub unknown_duration: bool,
 }
 
-/// Payload sent from shell hooks via Unix socket.
+/// Payload sent from shell hooks via Unix socket, one JSON object per line.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryHookPayload {
+    /// Plain command; older hooks send this and cannot carry newlines.
+    #[serde(default)]
     pub command: String,
+    /// Base64 of the command's bytes, so multi-line commands survive the
+    /// line framing. Takes precedence over `command`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_b64: Option<String>,
     pub cwd: String,
     pub exit_code: i32,
     pub duration_ms: i64,
     pub session_id: String,
     pub shell: String,
+    /// Group to attribute the command to (from `SHELL_SYNC_GROUP`); empty means
+    /// the daemon's default group.
+    #[serde(default)]
+    pub group_name: String,
+    /// The capture secret; required over TCP (see
+    /// [`crate::config::CaptureEndpoint::Tcp`]).
+    #[serde(default)]
+    pub secret: String,
+}
+
+impl HistoryHookPayload {
+    /// The command as typed, decoded from `command_b64` when present.
+    pub fn decoded_command(&self) -> anyhow::Result<String> {
+        use base64::Engine;
+
+        let Some(encoded) = &self.command_b64 else {
+            return Ok(self.command.clone());
+        };
+        let bytes = base64::engine::general_purpose::STANDARD.decode(encoded.trim())?;
+        let command = String::from_utf8_lossy(&bytes);
+        Ok(command.trim_end_matches('\n').to_string())
+    }
 }
 
 /// Encrypted version of HistoryEntry for wire transmission.
@@ -152,6 +358,10 @@ pub struct EncryptedHistoryEntry {
     pub shell: String,       // plaintext
     pub group_name: String,  // plaintext (routing)
     pub nonces: String,      // JSON array of base64 nonces for each encrypted field
+    #[serde(default)]
+    pub truncated: bool, // plaintext
+    #[serde(default)]
+    pub unknown_duration: bool, // plaintext
 }
 
 /// Encrypted version of Alias for wire transmission.
@@ -166,6 +376,14 @@ pub struct EncryptedAlias {
     pub updated_at: i64,
     pub version: i64,
     pub nonce: String, // base64 nonce for command field
+    #[serde(default)]
+    pub pinned: bool, // plaintext
+    #[serde(default)]
+    pub expand: bool, // plaintext
+    #[serde(default)]
+    pub template: bool, // plaintext
+    #[serde(default)]
+    pub shells: Vec<String>, // plaintext
 }
 
 fn default_group() -> String {
@@ -187,6 +405,10 @@ mod tests {
             created_at: 1000,
             updated_at: 2000,
             version: 3,
+            pinned: false,
+            expand: false,
+            template: false,
+            shells: Vec::new(),
         };
         let json = serde_json::to_string(&alias).unwrap();
         let parsed: Alias = serde_json::from_str(&json).unwrap();
@@ -218,6 +440,40 @@ mod tests {
         assert_eq!(req.group, "default");
     }
 
+    #[test]
+    fn hook_payload_group_is_optional() {
+        let without: HistoryHookPayload = serde_json::from_str(
+            r#"{"command":"ls","cwd":"/","exit_code":0,"duration_ms":1,"session_id":"s","shell":"zsh"}"#,
+        )
+        .unwrap();
+        assert_eq!(without.group_name, "");
+
+        let with: HistoryHookPayload = serde_json::from_str(
+            r#"{"command":"ls","cwd":"/","exit_code":0,"duration_ms":1,"session_id":"s","shell":"zsh","group_name":"work"}"#,
+        )
+        .unwrap();
+        assert_eq!(with.group_name, "work");
+    }
+
+    #[test]
+    fn hook_payload_decodes_base64_command() {
+        // printf 'for f in *; do\n  echo "$f"\ndone' | base64
+        let payload: HistoryHookPayload = serde_json::from_str(
+            r#"{"command_b64":"Zm9yIGYgaW4gKjsgZG8KICBlY2hvICIkZiIKZG9uZQ==","cwd":"/","exit_code":0,"duration_ms":1,"session_id":"s","shell":"bash"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            payload.decoded_command().unwrap(),
+            "for f in *; do\n  echo \"$f\"\ndone"
+        );
+
+        let bad = HistoryHookPayload {
+            command_b64: Some("not base64!".into()),
+            ..payload
+        };
+        assert!(bad.decoded_command().is_err());
+    }
+
     #[test]
     fn register_request_optional_os() {
         let with: RegisterRequest =
@@ -1,25 +1,42 @@
-use crate::models::HistoryEntry;
+use crate::models::{EncryptedHistoryEntry, HistoryEntry};
 use serde::{Deserialize, Serialize};
 
 /// Messages sent from client to server over WebSocket.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ClientMessage {
+    /// `watch` marks a read-only observer (e.g. `shell-history --follow`) that
+    /// receives broadcasts alongside the machine's daemon instead of replacing it.
     #[serde(rename = "auth")]
-    Auth { token: String },
+    Auth {
+        token: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        watch: bool,
+    },
     #[serde(rename = "ping")]
     Ping,
+    /// Reply to the server's `ping` event.
+    #[serde(rename = "pong")]
+    Pong,
     #[serde(rename = "history_batch")]
-    HistoryBatch { entries: Vec<HistoryEntry> },
+    HistoryBatch {
+        entries: HistoryBatchEntries,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        encrypted: bool,
+    },
     #[serde(rename = "history_query")]
     HistoryQuery {
+        #[serde(default)]
         after_timestamp: i64,
+        #[serde(default = "default_group_name")]
         group_name: String,
+        #[serde(default = "default_query_limit")]
         limit: i64,
     },
     #[serde(rename = "key_request")]
     KeyRequest {
         group_name: String,
+        #[serde(alias = "requester_public_key")]
         public_key: String,
     },
     #[serde(rename = "key_response")]
@@ -30,6 +47,23 @@ pub enum ClientMessage {
     },
 }
 
+/// Entries of a `history_batch`: plaintext, or encrypted with a group key
+/// (sent with `"encrypted": true`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HistoryBatchEntries {
+    Plain(Vec<HistoryEntry>),
+    Encrypted(Vec<EncryptedHistoryEntry>),
+}
+
+fn default_group_name() -> String {
+    "default".to_string()
+}
+
+fn default_query_limit() -> i64 {
+    100
+}
+
 /// Events sent from server to client over WebSocket.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "event")]
@@ -48,6 +82,9 @@ pub enum ServerEvent {
     SyncRequired { data: serde_json::Value },
     #[serde(rename = "pong")]
     Pong { data: PongData },
+    /// Liveness check; the client answers with a `pong` message.
+    #[serde(rename = "ping")]
+    Ping { data: PongData },
     #[serde(rename = "history_sync")]
     HistorySync { data: HistorySyncData },
     #[serde(rename = "history_page")]
@@ -56,6 +93,9 @@ pub enum ServerEvent {
     KeyRequestEvent { data: KeyRequestData },
     #[serde(rename = "key_response")]
     KeyResponseEvent { data: KeyResponseData },
+    /// The last client message was not understood; the connection stays open.
+    #[serde(rename = "error")]
+    Error { data: ErrorData },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,6 +109,11 @@ pub struct AuthFailedData {
     pub error: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorData {
+    pub error: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PongData {
     pub timestamp: i64,
@@ -100,6 +145,39 @@ pub struct KeyResponseData {
     pub sender_public_key: String,
 }
 
+/// Features a server lists at `GET /api/version`. Clients check for one
+/// before a request that an older server would ignore or misread.
+pub mod features {
+    /// `POST /api/import?dry_run=true`.
+    pub const IMPORT_DRY_RUN: &str = "import_dry_run";
+    /// Aliases limited to some shells ([`crate::models::Alias::shells`]).
+    pub const ALIAS_SHELLS: &str = "alias_shells";
+    /// Alias commands stored encrypted, as uploaded by `encrypt-migrate`.
+    pub const ENCRYPTED_ALIASES: &str = "encrypted_aliases";
+    /// Encrypted `history_batch` entries.
+    pub const ENCRYPTED_HISTORY: &str = "encrypted_history";
+    /// `key_request` / `key_response` relayed between group members.
+    pub const KEY_EXCHANGE: &str = "key_exchange";
+    /// `conflict_strategy` in the server config.
+    pub const CONFLICT_STRATEGIES: &str = "conflict_strategies";
+    /// `limit`, `offset` and `total` on `GET /api/conflicts`.
+    pub const CONFLICT_PAGING: &str = "conflict_paging";
+}
+
+/// Response of `GET /api/version`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerVersion {
+    pub version: String,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl ServerVersion {
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,6 +186,7 @@ mod tests {
     fn client_auth_serializes() {
         let msg = ClientMessage::Auth {
             token: "abc".into(),
+            watch: false,
         };
         let json = serde_json::to_string(&msg).unwrap();
         assert_eq!(json, r#"{"type":"auth","token":"abc"}"#);
@@ -117,11 +196,15 @@ mod tests {
     fn client_auth_roundtrip() {
         let msg = ClientMessage::Auth {
             token: "tok-123".into(),
+            watch: true,
         };
         let json = serde_json::to_string(&msg).unwrap();
         let parsed: ClientMessage = serde_json::from_str(&json).unwrap();
         match parsed {
-            ClientMessage::Auth { token } => assert_eq!(token, "tok-123"),
+            ClientMessage::Auth { token, watch } => {
+                assert_eq!(token, "tok-123");
+                assert!(watch);
+            }
             _ => panic!("Expected Auth variant"),
         }
     }
@@ -131,6 +214,8 @@ mod tests {
         let msg = ClientMessage::Ping;
         let json = serde_json::to_string(&msg).unwrap();
         assert_eq!(json, r#"{"type":"ping"}"#);
+        let json = serde_json::to_string(&ClientMessage::Pong).unwrap();
+        assert_eq!(json, r#"{"type":"pong"}"#);
     }
 
     #[test]
@@ -198,6 +283,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn history_batch_accepts_plain_and_encrypted_entries() {
+        let plain = r#"{"type":"history_batch","entries":[{"id":"h1","command":"ls","cwd":"/",
+            "exit_code":0,"duration_ms":5,"session_id":"s","machine_id":"m","hostname":"h",
+            "timestamp":1,"shell":"zsh","group_name":"default"}]}"#;
+        match serde_json::from_str::<ClientMessage>(plain).unwrap() {
+            ClientMessage::HistoryBatch {
+                entries: HistoryBatchEntries::Plain(entries),
+                encrypted: false,
+            } => assert_eq!(entries[0].command, "ls"),
+            other => panic!("Expected plain HistoryBatch, got {other:?}"),
+        }
+
+        let encrypted = r#"{"type":"history_batch","encrypted":true,"entries":[{"id":"h1",
+            "command":"Y3Q=","cwd":"Y3Q=","exit_code":"Y3Q=","duration_ms":"Y3Q=",
+            "session_id":"s","machine_id":"m","hostname":"Y3Q=","timestamp":1,"shell":"zsh",
+            "group_name":"default","nonces":"[]"}]}"#;
+        match serde_json::from_str::<ClientMessage>(encrypted).unwrap() {
+            ClientMessage::HistoryBatch {
+                entries: HistoryBatchEntries::Encrypted(entries),
+                encrypted: true,
+            } => assert_eq!(entries[0].group_name, "default"),
+            other => panic!("Expected encrypted HistoryBatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn key_request_accepts_requester_public_key() {
+        let msg = r#"{"type":"key_request","group_name":"work","requester_public_key":"pk"}"#;
+        match serde_json::from_str::<ClientMessage>(msg).unwrap() {
+            ClientMessage::KeyRequest { public_key, .. } => assert_eq!(public_key, "pk"),
+            other => panic!("Expected KeyRequest, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn missing_field_fails() {
+        let result = serde_json::from_str::<ClientMessage>(r#"{"type":"auth"}"#);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn unknown_type_fails() {
         let result = serde_json::from_str::<ClientMessage>(r#"{"type":"bogus"}"#);
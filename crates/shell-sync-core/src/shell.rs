@@ -1,5 +1,7 @@
 use std::path::PathBuf;
 
+use crate::models::Alias;
+
 /// Detected shell type for the current user.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShellType {
@@ -9,6 +11,22 @@ pub enum ShellType {
 }
 
 impl ShellType {
+    /// Lowercase name, as used in [`Alias::shells`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShellType::Zsh => "zsh",
+            ShellType::Bash => "bash",
+            ShellType::Fish => "fish",
+        }
+    }
+
+    /// The shell called `name` (`zsh`, `bash` or `fish`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        [ShellType::Zsh, ShellType::Bash, ShellType::Fish]
+            .into_iter()
+            .find(|s| s.name() == name)
+    }
+
     /// File extension for the alias output file.
     pub fn alias_extension(&self) -> &str {
         match self {
@@ -34,22 +52,279 @@ impl ShellType {
     /// Generate the source line to add to the shell RC file.
     pub fn source_line(&self, alias_file: &str) -> String {
         match self {
-            ShellType::Fish => format!("source \"{}\"", alias_file),
+            ShellType::Fish => format!("test -f \"{}\"; and source \"{}\"", alias_file, alias_file),
             _ => format!("[ -f \"{}\" ] && source \"{}\"", alias_file, alias_file),
         }
     }
 
+    /// How to load `shell-sync export --eval` output into the running shell.
+    pub fn eval_hint(&self) -> &'static str {
+        match self {
+            ShellType::Fish => "shell-sync export --eval | source",
+            _ => "eval \"$(shell-sync export --eval)\"",
+        }
+    }
+
     /// Format a single alias line for this shell type.
     pub fn format_alias(&self, name: &str, command: &str) -> String {
         match self {
-            ShellType::Fish => {
-                format!("alias {} '{}'", name, command.replace('\'', "\\'"))
-            }
-            _ => {
-                let escaped = command.replace('\'', "'\\''");
-                format!("alias {}='{}'", name, escaped)
+            ShellType::Fish => format!("alias {} {}", name, self.single_quote(command)),
+            _ => format!("alias {}={}", name, self.single_quote(command)),
+        }
+    }
+
+    /// `text` as one single-quoted word, taken literally by this shell.
+    pub fn single_quote(&self, text: &str) -> String {
+        match self {
+            // Inside fish single quotes only `\\` and `\'` are escapes.
+            ShellType::Fish => format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'")),
+            _ => format!("'{}'", text.replace('\'', "'\\''")),
+        }
+    }
+
+    /// Format an alias double-quoted, so `$VAR`, `$(...)` and backticks are
+    /// expanded by the shell when the alias is defined. `\` and `"` are
+    /// escaped, so both are kept as written.
+    pub fn format_expanding_alias(&self, name: &str, command: &str) -> String {
+        let escaped = command.replace('\\', "\\\\").replace('"', "\\\"");
+        match self {
+            ShellType::Fish => format!("alias {} \"{}\"", name, escaped),
+            _ => format!("alias {}=\"{}\"", name, escaped),
+        }
+    }
+
+    /// Format a synced alias, double-quoted if its `expand` flag is set.
+    pub fn format_synced_alias(&self, alias: &Alias) -> String {
+        if alias.expand {
+            self.format_expanding_alias(&alias.name, &alias.command)
+        } else {
+            self.format_alias(&alias.name, &alias.command)
+        }
+    }
+}
+
+/// Parse one alias definition back into `(name, command)`.
+///
+/// Understands what [`ShellType::format_alias`] writes for every shell —
+/// `alias name='cmd'` (with `'\''` escapes), double-quoted values, and fish's
+/// `alias name 'cmd'` — plus bare `name=cmd` lines, whose value is taken
+/// verbatim. Returns `None` for anything else, such as comments.
+pub fn parse_alias_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let rest = line.strip_prefix("alias ").unwrap_or(line).trim_start();
+
+    let name_end = rest.find(|c: char| c == '=' || c.is_whitespace())?;
+    let (name, tail) = rest.split_at(name_end);
+    if name.is_empty() {
+        return None;
+    }
+
+    let command = if let Some(value) = tail.strip_prefix('=') {
+        if value.starts_with(['\'', '"']) {
+            unquote_posix(value)?
+        } else {
+            value.trim().to_string()
+        }
+    } else {
+        // fish: `alias name 'cmd'` or `alias name "cmd"`
+        let value = tail.trim_start();
+        match value.strip_prefix('\'') {
+            Some(quoted) => unquote_fish_single(quoted)?,
+            None if value.starts_with('"') => unquote_fish_double(&value[1..])?,
+            None if !value.is_empty() && line.starts_with("alias ") => value.to_string(),
+            None => return None,
+        }
+    };
+    Some((name.to_string(), command))
+}
+
+/// Parse every alias definition in a shell file, skipping other lines.
+pub fn parse_alias_file(content: &str) -> Vec<(String, String)> {
+    content.lines().filter_map(parse_alias_line).collect()
+}
+
+/// Decode one POSIX shell word made of quoted and unquoted pieces, e.g.
+/// `'it'\''s'`. Stops at the first unquoted whitespace.
+fn unquote_posix(word: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => loop {
+                match chars.next()? {
+                    '\'' => break,
+                    c => out.push(c),
+                }
+            },
+            '"' => loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => match chars.next()? {
+                        c @ ('"' | '\\' | '$' | '`') => out.push(c),
+                        c => {
+                            out.push('\\');
+                            out.push(c);
+                        }
+                    },
+                    c => out.push(c),
+                }
+            },
+            '\\' => out.push(chars.next()?),
+            c if c.is_whitespace() => break,
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+/// Decode the inside of a fish single-quoted string (after the opening quote),
+/// where only `\\` and `\'` are escapes.
+fn unquote_fish_single(quoted: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = quoted.chars();
+    loop {
+        match chars.next()? {
+            '\'' => return Some(out),
+            '\\' => match chars.next()? {
+                c @ ('\\' | '\'') => out.push(c),
+                c => {
+                    out.push('\\');
+                    out.push(c);
+                }
+            },
+            c => out.push(c),
+        }
+    }
+}
+
+/// Decode the inside of a fish double-quoted string (after the opening quote),
+/// where `\\`, `\"` and `\$` are escapes.
+fn unquote_fish_double(quoted: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = quoted.chars();
+    loop {
+        match chars.next()? {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                c @ ('\\' | '"' | '$') => out.push(c),
+                c => {
+                    out.push('\\');
+                    out.push(c);
+                }
+            },
+            c => out.push(c),
+        }
+    }
+}
+
+/// Builtins and keywords common to bash, zsh and fish; `command -v` reports
+/// these by name rather than as a path.
+#[rustfmt::skip]
+const SHELL_BUILTINS: &[&str] = &[
+    ".", ":", "[", "alias", "bg", "bind", "break", "builtin", "cd", "command", "continue",
+    "declare", "dirs", "disown", "echo", "eval", "exec", "exit", "export", "false", "fc", "fg",
+    "getopts", "hash", "history", "jobs", "kill", "let", "local", "popd", "printf", "pushd",
+    "pwd", "read", "readonly", "return", "set", "shift", "source", "test", "time", "times",
+    "trap", "true", "type", "typeset", "ulimit", "umask", "unalias", "unset", "wait", "if",
+    "then", "else", "fi", "for", "while", "until", "do", "done", "case", "esac", "function",
+    "{", "}", "!", "abbr", "and", "or", "not", "begin", "end", "functions", "string", "math",
+];
+
+/// What the first word of an alias command ends up running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandTarget {
+    Builtin(String),
+    Executable(PathBuf),
+    NotFound(String),
+}
+
+/// How an alias command resolves, without running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandResolution {
+    /// Aliases expanded on the way, as `(name, command)`, outermost first.
+    pub chain: Vec<(String, String)>,
+    pub target: CommandTarget,
+}
+
+/// Resolve the first word of `command` the way `command -v` would, following
+/// other `aliases` and searching `path` (a `$PATH`-style list).
+///
+/// Leading `VAR=value` assignments are skipped and a leading `\` suppresses
+/// alias expansion. As in bash, an alias is not expanded again inside its own
+/// expansion, so `ls='ls -la'` resolves to the `ls` binary.
+pub fn resolve_command(
+    command: &str,
+    aliases: &std::collections::HashMap<String, String>,
+    path: Option<&std::ffi::OsStr>,
+) -> CommandResolution {
+    let mut chain: Vec<(String, String)> = Vec::new();
+    let mut current = command.to_string();
+    loop {
+        let Some(raw) = current
+            .split_whitespace()
+            .find(|w| !is_assignment(w))
+            .map(str::to_string)
+        else {
+            return CommandResolution {
+                chain,
+                target: CommandTarget::NotFound(String::new()),
+            };
+        };
+        let escaped = raw.starts_with('\\');
+        let word = unquote_posix(raw.trim_start_matches('\\')).unwrap_or(raw.clone());
+
+        if !escaped && !chain.iter().any(|(name, _)| *name == word) {
+            if let Some(next) = aliases.get(&word) {
+                chain.push((word, next.clone()));
+                current = next.clone();
+                continue;
             }
         }
+
+        let target = if SHELL_BUILTINS.contains(&word.as_str()) {
+            CommandTarget::Builtin(word)
+        } else if word.contains('/') {
+            let candidate = PathBuf::from(&word);
+            if is_executable(&candidate) {
+                CommandTarget::Executable(candidate)
+            } else {
+                CommandTarget::NotFound(word)
+            }
+        } else {
+            path.into_iter()
+                .flat_map(std::env::split_paths)
+                .map(|dir| dir.join(&word))
+                .find(|candidate| is_executable(candidate))
+                .map(CommandTarget::Executable)
+                .unwrap_or(CommandTarget::NotFound(word))
+        };
+        return CommandResolution { chain, target };
+    }
+}
+
+pub(crate) fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+fn is_executable(path: &std::path::Path) -> bool {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        meta.is_file() && meta.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        meta.is_file()
     }
 }
 
@@ -122,6 +397,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fish_format_escapes_backslashes() {
+        assert_eq!(
+            ShellType::Fish.format_alias("bs", r"echo \\"),
+            r"alias bs 'echo \\\\'"
+        );
+    }
+
     #[test]
     fn zsh_source_line_has_guard() {
         let line = ShellType::Zsh.source_line("/tmp/aliases.sh");
@@ -131,12 +414,145 @@ mod tests {
     }
 
     #[test]
-    fn fish_source_line_plain() {
+    fn fish_source_line_has_guard() {
         let line = ShellType::Fish.source_line("/tmp/aliases.fish");
-        assert_eq!(line, r#"source "/tmp/aliases.fish""#);
+        assert_eq!(
+            line,
+            r#"test -f "/tmp/aliases.fish"; and source "/tmp/aliases.fish""#
+        );
         assert!(!line.contains("[ -f"));
     }
 
+    #[test]
+    fn parse_round_trips_format_alias() {
+        let tricky = r#"echo "$HOME" 'it'\''s' \; a && b # done \"#;
+        for shell in [ShellType::Bash, ShellType::Zsh, ShellType::Fish] {
+            for command in ["git status", "echo 'hi'", tricky, ""] {
+                let line = shell.format_alias("x", command);
+                assert_eq!(
+                    parse_alias_line(&line),
+                    Some(("x".to_string(), command.to_string())),
+                    "{:?}: {}",
+                    shell,
+                    line
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn expanding_alias_is_double_quoted() {
+        let mut alias = Alias {
+            id: 1,
+            name: "proj".into(),
+            command: r#"cd $HOME/src && echo "$(date)" \o/"#.into(),
+            group_name: "default".into(),
+            created_by_machine: "m1".into(),
+            created_at: 0,
+            updated_at: 0,
+            version: 1,
+            pinned: false,
+            expand: true,
+            template: false,
+            shells: Vec::new(),
+        };
+        assert_eq!(
+            ShellType::Bash.format_synced_alias(&alias),
+            r#"alias proj="cd $HOME/src && echo \"$(date)\" \\o/""#
+        );
+        assert_eq!(
+            ShellType::Fish.format_synced_alias(&alias),
+            r#"alias proj "cd $HOME/src && echo \"$(date)\" \\o/""#
+        );
+        for shell in [ShellType::Bash, ShellType::Fish] {
+            let line = shell.format_synced_alias(&alias);
+            assert_eq!(parse_alias_line(&line).unwrap().1, alias.command, "{line}");
+        }
+
+        alias.expand = false;
+        assert_eq!(
+            ShellType::Zsh.format_synced_alias(&alias),
+            ShellType::Zsh.format_alias(&alias.name, &alias.command)
+        );
+        assert!(ShellType::Zsh
+            .format_synced_alias(&alias)
+            .starts_with("alias proj='cd $HOME"));
+    }
+
+    #[test]
+    fn parse_other_alias_forms() {
+        let parsed = parse_alias_line;
+        assert_eq!(parsed("ll=ls -la"), Some(("ll".into(), "ls -la".into())));
+        assert_eq!(
+            parsed(r#"alias g="git \"log\"""#),
+            Some(("g".into(), r#"git "log""#.into()))
+        );
+        assert_eq!(
+            parsed("alias k=kubectl"),
+            Some(("k".into(), "kubectl".into()))
+        );
+        assert_eq!(parsed("# alias x='y'"), None);
+        assert_eq!(parsed("#!/bin/bash"), None);
+        assert_eq!(parsed("export PATH"), None);
+        assert_eq!(parsed("alias broken='unterminated"), None);
+
+        let file = "#!/bin/bash\n# header\n\nalias a='1'\nalias b='2'\n";
+        assert_eq!(parse_alias_file(file).len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_command_against_stub_path() {
+        use std::collections::HashMap;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = (dir.path().join("a"), dir.path().join("b"));
+        for d in [&first, &second] {
+            std::fs::create_dir(d).unwrap();
+        }
+        // Only the executable copy counts, even though the other is earlier on PATH.
+        std::fs::write(first.join("tool"), "").unwrap();
+        std::fs::write(second.join("tool"), "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(second.join("tool"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        let path = std::env::join_paths([&first, &second]).unwrap();
+        let expected = CommandTarget::Executable(second.join("tool"));
+
+        let aliases: HashMap<String, String> = [
+            ("t", "tool --verbose"),
+            ("tt", "t again"),
+            ("tool", "tool --color"),
+        ]
+        .into_iter()
+        .map(|(n, c)| (n.to_string(), c.to_string()))
+        .collect();
+        let resolve = |cmd: &str| resolve_command(cmd, &aliases, Some(&path));
+
+        let r = resolve("tt");
+        assert_eq!(r.target, expected);
+        let names: Vec<&str> = r.chain.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["tt", "t", "tool"]);
+
+        assert!(resolve(r"\tool -x").chain.is_empty());
+        assert_eq!(resolve(r"\tool -x").target, expected);
+        assert_eq!(resolve("LANG=C DEBUG=1 tool").chain.len(), 1);
+        assert_eq!(
+            resolve("cd /tmp && tool").target,
+            CommandTarget::Builtin("cd".into())
+        );
+        assert_eq!(
+            resolve("nope --help").target,
+            CommandTarget::NotFound("nope".into())
+        );
+        let absolute = second.join("tool").display().to_string();
+        assert_eq!(resolve(&absolute).target, expected);
+        assert_eq!(
+            resolve_command("tool", &aliases, None).target,
+            CommandTarget::NotFound("tool".into())
+        );
+    }
+
     #[test]
     fn detect_shell_from_env() {
         assert_eq!(detect_shell_from("/bin/zsh"), ShellType::Zsh);
@@ -1,4 +1,5 @@
 use crate::db::SyncDatabase;
+use crate::tz::DisplayTz;
 use chrono::{Datelike, Timelike};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -10,6 +11,9 @@ pub struct StatsResult {
     pub success_rate: f64,
     pub top_commands: Vec<(String, i64)>,
     pub top_prefixes: Vec<(String, i64)>,
+    /// Top program + subcommand pairs, e.g. `git status`, `docker compose`.
+    #[serde(default)]
+    pub top_subcommands: Vec<(String, i64)>,
     pub avg_duration_ms: f64,
     pub median_duration_ms: i64,
     pub p95_duration_ms: i64,
@@ -17,48 +21,72 @@ pub struct StatsResult {
     pub daily_distribution: Vec<i64>,
     pub per_directory: Vec<(String, i64)>,
     pub per_machine: Vec<(String, i64)>,
+    /// Busiest sessions as (session_id, command_count, total_duration_ms).
+    pub per_session: Vec<(String, i64, i64)>,
     pub streak_days: i64,
 }
 
+/// Label used in `per_session` for entries captured without a session id.
+pub const NO_SESSION: &str = "(none)";
+
 #[derive(Debug, Clone)]
 pub struct StatsFilter {
     pub after_timestamp: Option<i64>,
+    /// Exclusive upper bound (epoch ms).
+    pub before_timestamp: Option<i64>,
     pub machine_id: Option<String>,
     pub group_name: Option<String>,
     pub directory: Option<String>,
+    /// Zone used for hour-of-day, day-of-week and streak bucketing.
+    pub tz: DisplayTz,
+    /// Group `top_commands` by [`normalize_command`] instead of the exact line.
+    pub normalize: bool,
 }
 
-/// Compute shell usage statistics from the local history database.
-pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<StatsResult> {
-    let conn = db.raw_connection();
-    let conn = conn.lock().unwrap();
-
-    // Build WHERE clause
+/// SQL conditions and their parameters (numbered from `?1`) for a filter.
+fn filter_conditions(filter: &StatsFilter) -> (Vec<String>, Vec<Box<dyn rusqlite::types::ToSql>>) {
     let mut conditions = Vec::new();
     let mut param_values: Vec<Box<dyn rusqlite::types::ToSql>> = Vec::new();
-    let mut idx = 1;
 
     if let Some(after) = filter.after_timestamp {
-        conditions.push(format!("timestamp >= ?{idx}"));
         param_values.push(Box::new(after));
-        idx += 1;
+        conditions.push(format!("timestamp >= ?{}", param_values.len()));
+    }
+    if let Some(before) = filter.before_timestamp {
+        param_values.push(Box::new(before));
+        conditions.push(format!("timestamp < ?{}", param_values.len()));
     }
     if let Some(ref mid) = filter.machine_id {
-        conditions.push(format!("machine_id = ?{idx}"));
         param_values.push(Box::new(mid.clone()));
-        idx += 1;
+        conditions.push(format!("machine_id = ?{}", param_values.len()));
     }
     if let Some(ref group) = filter.group_name {
-        conditions.push(format!("group_name = ?{idx}"));
         param_values.push(Box::new(group.clone()));
-        idx += 1;
+        conditions.push(format!("group_name = ?{}", param_values.len()));
     }
     if let Some(ref dir) = filter.directory {
-        conditions.push(format!("cwd = ?{idx}"));
         param_values.push(Box::new(dir.clone()));
-        // idx not needed after last use
+        conditions.push(format!("cwd = ?{}", param_values.len()));
     }
+    (conditions, param_values)
+}
 
+/// Median and 95th percentile of durations sorted ascending; zeros when empty.
+fn median_and_p95(sorted: &[i64]) -> (i64, i64) {
+    if sorted.is_empty() {
+        return (0, 0);
+    }
+    let median = sorted[sorted.len() / 2];
+    let p95_idx = ((sorted.len() as f64) * 0.95).ceil() as usize;
+    (median, sorted[p95_idx.min(sorted.len() - 1)])
+}
+
+/// Compute shell usage statistics from the local history database.
+pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<StatsResult> {
+    let conn = db.raw_connection();
+    let conn = conn.lock().unwrap();
+
+    let (conditions, param_values) = filter_conditions(filter);
     let where_clause = if conditions.is_empty() {
         String::new()
     } else {
@@ -84,6 +112,7 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
             success_rate: 0.0,
             top_commands: vec![],
             top_prefixes: vec![],
+            top_subcommands: vec![],
             avg_duration_ms: 0.0,
             median_duration_ms: 0,
             p95_duration_ms: 0,
@@ -91,6 +120,7 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
             daily_distribution: vec![0; 7],
             per_directory: vec![],
             per_machine: vec![],
+            per_session: vec![],
             streak_days: 0,
         });
     }
@@ -125,8 +155,18 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
         0.0
     };
 
-    // Top 10 commands (full command string)
-    let top_commands = {
+    // Top 10 commands (full command string, or its normalized template)
+    let top_commands = if filter.normalize {
+        let sql = format!("SELECT command FROM history {where_clause}");
+        let mut stmt = conn.prepare(&sql)?;
+        let mut counts: HashMap<String, i64> = HashMap::new();
+        let mut rows = stmt.query(params_ref.as_slice())?;
+        while let Some(row) = rows.next()? {
+            let cmd: String = row.get(0)?;
+            *counts.entry(normalize_command(&cmd)).or_insert(0) += 1;
+        }
+        top_counts(counts)
+    } else {
         let sql = format!(
             "SELECT command, COUNT(*) as cnt FROM history {where_clause} GROUP BY command ORDER BY cnt DESC LIMIT 10"
         );
@@ -139,29 +179,39 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
         rows
     };
 
-    // Top 10 prefixes (first word of command)
-    let top_prefixes = {
+    // Top 10 prefixes (program) and subcommands (program + subcommand)
+    let (top_prefixes, top_subcommands) = {
         let sql = format!("SELECT command FROM history {where_clause}");
         let mut stmt = conn.prepare(&sql)?;
         let mut prefix_counts: HashMap<String, i64> = HashMap::new();
+        let mut subcommand_counts: HashMap<String, i64> = HashMap::new();
         let mut rows = stmt.query(params_ref.as_slice())?;
         while let Some(row) = rows.next()? {
             let cmd: String = row.get(0)?;
-            let prefix = cmd.split_whitespace().next().unwrap_or("").to_string();
-            if !prefix.is_empty() {
-                *prefix_counts.entry(prefix).or_insert(0) += 1;
+            if let Some((program, subcommand)) = command_head(&cmd) {
+                let key = match subcommand {
+                    Some(sub) => format!("{program} {sub}"),
+                    None => program.clone(),
+                };
+                *subcommand_counts.entry(key).or_insert(0) += 1;
+                *prefix_counts.entry(program).or_insert(0) += 1;
             }
         }
-        let mut sorted: Vec<(String, i64)> = prefix_counts.into_iter().collect();
-        sorted.sort_by(|a, b| b.1.cmp(&a.1));
-        sorted.truncate(10);
-        sorted
+        (top_counts(prefix_counts), top_counts(subcommand_counts))
     };
 
-    // Duration stats
+    // Duration stats, leaving out durations the hook could not measure
+    let duration_where = format!(
+        "{where_clause} {} unknown_duration = 0",
+        if conditions.is_empty() {
+            "WHERE"
+        } else {
+            "AND"
+        }
+    );
     let avg_duration_ms: f64 = conn
         .query_row(
-            &format!("SELECT AVG(duration_ms) FROM history {where_clause}"),
+            &format!("SELECT AVG(duration_ms) FROM history {duration_where}"),
             params_ref.as_slice(),
             |row| row.get(0),
         )
@@ -170,20 +220,13 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
     // Collect all durations for median and p95
     let (median_duration_ms, p95_duration_ms) = {
         let sql =
-            format!("SELECT duration_ms FROM history {where_clause} ORDER BY duration_ms ASC");
+            format!("SELECT duration_ms FROM history {duration_where} ORDER BY duration_ms ASC");
         let mut stmt = conn.prepare(&sql)?;
         let durations: Vec<i64> = stmt
             .query_map(params_ref.as_slice(), |row| row.get(0))?
             .collect::<Result<Vec<_>, _>>()?;
 
-        if durations.is_empty() {
-            (0i64, 0i64)
-        } else {
-            let median = durations[durations.len() / 2];
-            let p95_idx = ((durations.len() as f64) * 0.95).ceil() as usize;
-            let p95 = durations[p95_idx.min(durations.len() - 1)];
-            (median, p95)
-        }
+        median_and_p95(&durations)
     };
 
     // Hourly distribution (24 buckets)
@@ -194,8 +237,8 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
         let mut rows = stmt.query(params_ref.as_slice())?;
         while let Some(row) = rows.next()? {
             let ts: i64 = row.get(0)?;
-            if let Some(dt) = chrono::DateTime::from_timestamp_millis(ts) {
-                let hour = dt.time().hour() as usize;
+            if let Some(dt) = filter.tz.localize(ts) {
+                let hour = dt.hour() as usize;
                 hours[hour] += 1;
             }
         }
@@ -210,7 +253,7 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
         let mut rows = stmt.query(params_ref.as_slice())?;
         while let Some(row) = rows.next()? {
             let ts: i64 = row.get(0)?;
-            if let Some(dt) = chrono::DateTime::from_timestamp_millis(ts) {
+            if let Some(dt) = filter.tz.localize(ts) {
                 let day = dt.weekday().num_days_from_monday() as usize;
                 days[day] += 1;
             }
@@ -246,35 +289,50 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
         result
     };
 
-    // Streak days — consecutive days with at least one command (counting back from today)
-    let streak_days = {
+    // Per session (top 10 by command count); hooks that ran without a session id
+    // are grouped under "(none)"
+    let per_session = {
         let sql = format!(
-            "SELECT DISTINCT date(timestamp / 1000, 'unixepoch') as d FROM history {where_clause} ORDER BY d DESC"
+            "SELECT COALESCE(NULLIF(session_id, ''), '{NO_SESSION}') AS sid, COUNT(*) AS cnt, \
+             COALESCE(SUM(duration_ms), 0) FROM history {where_clause} \
+             GROUP BY sid ORDER BY cnt DESC, sid ASC LIMIT 10"
         );
         let mut stmt = conn.prepare(&sql)?;
-        let dates: Vec<String> = stmt
-            .query_map(params_ref.as_slice(), |row| row.get(0))?
+        let result = stmt
+            .query_map(params_ref.as_slice(), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
             .collect::<Result<Vec<_>, _>>()?;
+        result
+    };
 
-        if dates.is_empty() {
-            0
-        } else {
-            let mut streak = 1i64;
-            for i in 1..dates.len() {
-                let prev = chrono::NaiveDate::parse_from_str(&dates[i - 1], "%Y-%m-%d");
-                let curr = chrono::NaiveDate::parse_from_str(&dates[i], "%Y-%m-%d");
-                if let (Ok(p), Ok(c)) = (prev, curr) {
-                    if p - c == chrono::Duration::days(1) {
-                        streak += 1;
-                    } else {
-                        break;
-                    }
-                } else {
-                    break;
-                }
+    // Streak days — consecutive days with at least one command (counting back from the latest)
+    let streak_days = {
+        let sql = format!("SELECT timestamp FROM history {where_clause}");
+        let mut stmt = conn.prepare(&sql)?;
+        let mut dates = std::collections::BTreeSet::new();
+        let mut rows = stmt.query(params_ref.as_slice())?;
+        while let Some(row) = rows.next()? {
+            let ts: i64 = row.get(0)?;
+            if let Some(dt) = filter.tz.localize(ts) {
+                dates.insert(dt.date());
+            }
+        }
+
+        let mut streak = 0i64;
+        let mut expected: Option<chrono::NaiveDate> = None;
+        for date in dates.iter().rev() {
+            if expected.is_some_and(|e| *date != e) {
+                break;
             }
-            streak
+            streak += 1;
+            expected = date.pred_opt();
         }
+        streak
     };
 
     Ok(StatsResult {
@@ -283,6 +341,7 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
         success_rate,
         top_commands,
         top_prefixes,
+        top_subcommands,
         avg_duration_ms,
         median_duration_ms,
         p95_duration_ms,
@@ -290,36 +349,753 @@ pub fn compute_stats(db: &SyncDatabase, filter: &StatsFilter) -> anyhow::Result<
         daily_distribution,
         per_directory,
         per_machine,
+        per_session,
         streak_days,
     })
 }
 
+/// The ten largest counts, ties broken by name so output is stable.
+fn top_counts(counts: HashMap<String, i64>) -> Vec<(String, i64)> {
+    let mut sorted: Vec<(String, i64)> = counts.into_iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    sorted.truncate(10);
+    sorted
+}
+
+/// The program a command runs and its subcommand, if the second word looks
+/// like one (`git status`, `docker compose`). Leading `VAR=value` assignments
+/// are skipped; flags, paths and file names are not subcommands, so
+/// `ls -la` and `vim main.rs` bucket as just `ls` and `vim`.
+fn command_head(command: &str) -> Option<(String, Option<String>)> {
+    let mut words = command_words(command)
+        .into_iter()
+        .skip_while(|w| crate::shell::is_assignment(w));
+    let program = words.next().filter(|p| !p.is_empty())?;
+    let subcommand = words.next().filter(|w| {
+        w.starts_with(|c: char| c.is_ascii_alphabetic())
+            && w.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    });
+    Some((program, subcommand))
+}
+
+/// `command` with volatile arguments replaced by placeholders, so runs that
+/// differ only in an id or path count together: `kubectl logs pod-7f9c2`
+/// becomes `kubectl logs pod-<id>`. Leading `VAR=value` assignments, the
+/// program name and flag names are kept;
+/// UUIDs become `<uuid>`, numbers `<n>`, hex hashes `<hash>`, paths `<path>`,
+/// and a `-`/`_` suffix containing a digit `<id>`.
+pub fn normalize_command(command: &str) -> String {
+    let mut words = command.split_whitespace().peekable();
+    let mut out = Vec::new();
+    while let Some(word) = words.next_if(|w| crate::shell::is_assignment(w)) {
+        out.push(word.to_string());
+    }
+    out.extend(words.next().map(str::to_string));
+    for word in words {
+        let normalized = match word.split_once('=') {
+            Some((key, value)) if word.starts_with('-') || crate::shell::is_assignment(word) => {
+                format!("{key}={}", normalize_word(value))
+            }
+            _ if word.starts_with('-') => word.to_string(),
+            _ => normalize_word(word),
+        };
+        out.push(normalized);
+    }
+    out.join(" ")
+}
+
+fn normalize_word(word: &str) -> String {
+    let is_hex = |s: &str| s.chars().all(|c| c.is_ascii_hexdigit());
+    let has_digit = |s: &str| s.chars().any(|c| c.is_ascii_digit());
+    let uuid_groups: Vec<&str> = word.split('-').collect();
+    if uuid_groups.iter().map(|g| g.len()).eq([8, 4, 4, 4, 12])
+        && uuid_groups.iter().all(|g| is_hex(g))
+    {
+        return "<uuid>".into();
+    }
+    if !word.is_empty() && word.chars().all(|c| c.is_ascii_digit()) {
+        return "<n>".into();
+    }
+    if word.len() >= 7 && is_hex(word) && has_digit(word) {
+        return "<hash>".into();
+    }
+    if word.contains('/') || word.starts_with('~') {
+        return "<path>".into();
+    }
+    // name-<random>: keep the leading segments without digits
+    let mut start = 0;
+    for (i, segment) in word.split(['-', '_']).enumerate() {
+        if i > 0 && has_digit(segment) {
+            return format!("{}<id>", &word[..start]);
+        }
+        start += segment.len() + 1;
+    }
+    word.to_string()
+}
+
+/// Unquoted words of the first simple command in `command`, stopping at an
+/// unquoted `|`, `;` or `&`.
+fn command_words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            '|' | ';' | '&' => break,
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Days covered by [`CommandDetail::recent_daily`].
+pub const RECENT_DAYS: usize = 14;
+
+/// Usage of one exact command line, for drilling down from the top-commands list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandDetail {
+    pub command: String,
+    /// Runs of exactly this command line.
+    pub count: i64,
+    /// Runs of this command followed by arguments (`git status --short` for
+    /// `git status`), which are not included in the other figures.
+    pub with_arguments_count: i64,
+    pub success_rate: f64,
+    pub median_duration_ms: i64,
+    pub p95_duration_ms: i64,
+    /// Directories it ran in, most frequent first (top 10).
+    pub directories: Vec<(String, i64)>,
+    pub first_run: Option<i64>,
+    pub last_run: Option<i64>,
+    /// Runs per day over the last [`RECENT_DAYS`] days, oldest first, ending today.
+    pub recent_daily: Vec<i64>,
+}
+
+/// Aggregate the runs of `command` (matched exactly) within `filter`.
+/// `now_ms` is the end of the [`CommandDetail::recent_daily`] window.
+pub fn command_detail(
+    db: &SyncDatabase,
+    command: &str,
+    filter: &StatsFilter,
+    now_ms: i64,
+) -> anyhow::Result<CommandDetail> {
+    let conn = db.raw_connection();
+    let conn = conn.lock().unwrap();
+
+    let (mut conditions, mut param_values) = filter_conditions(filter);
+    let base_conditions = conditions.clone();
+    param_values.push(Box::new(command.to_string()));
+    let cmd_param = param_values.len();
+    conditions.push(format!("command = ?{cmd_param}"));
+    let where_clause = format!("WHERE {}", conditions.join(" AND "));
+    let params_ref: Vec<&dyn rusqlite::types::ToSql> =
+        param_values.iter().map(|p| p.as_ref()).collect();
+
+    let (count, successes, first_run, last_run): (i64, i64, Option<i64>, Option<i64>) = conn
+        .query_row(
+            &format!(
+                "SELECT COUNT(*), COALESCE(SUM(exit_code = 0), 0), MIN(timestamp), MAX(timestamp) \
+                 FROM history {where_clause}"
+            ),
+            params_ref.as_slice(),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+
+    // Same command with arguments: the command line followed by a space.
+    let mut with_args = base_conditions;
+    with_args.push(format!(
+        "substr(command, 1, length(?{cmd_param}) + 1) = ?{cmd_param} || ' '"
+    ));
+    let with_arguments_count: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM history WHERE {}", with_args.join(" AND ")),
+        params_ref.as_slice(),
+        |row| row.get(0),
+    )?;
+
+    let durations: Vec<i64> = conn
+        .prepare(&format!(
+            "SELECT duration_ms FROM history {where_clause} AND unknown_duration = 0 \
+             ORDER BY duration_ms ASC"
+        ))?
+        .query_map(params_ref.as_slice(), |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let (median_duration_ms, p95_duration_ms) = median_and_p95(&durations);
+
+    let directories = conn
+        .prepare(&format!(
+            "SELECT cwd, COUNT(*) AS cnt FROM history {where_clause} \
+             GROUP BY cwd ORDER BY cnt DESC, cwd ASC LIMIT 10"
+        ))?
+        .query_map(params_ref.as_slice(), |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut recent_daily = vec![0i64; RECENT_DAYS];
+    if let Some(today) = filter.tz.localize(now_ms).map(|dt| dt.date()) {
+        let mut stmt = conn.prepare(&format!("SELECT timestamp FROM history {where_clause}"))?;
+        let mut rows = stmt.query(params_ref.as_slice())?;
+        while let Some(row) = rows.next()? {
+            let Some(day) = filter.tz.localize(row.get(0)?).map(|dt| dt.date()) else {
+                continue;
+            };
+            let age = (today - day).num_days();
+            if (0..RECENT_DAYS as i64).contains(&age) {
+                recent_daily[RECENT_DAYS - 1 - age as usize] += 1;
+            }
+        }
+    }
+
+    Ok(CommandDetail {
+        command: command.to_string(),
+        count,
+        with_arguments_count,
+        success_rate: if count > 0 {
+            successes as f64 / count as f64 * 100.0
+        } else {
+            0.0
+        },
+        median_duration_ms,
+        p95_duration_ms,
+        directories,
+        first_run,
+        last_run,
+        recent_daily,
+    })
+}
+
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One block character per value scaled to the maximum; zero is shown as `·`
+/// so an idle day is distinguishable from a quiet one.
+pub fn sparkline(values: &[i64]) -> String {
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&v| {
+            if v <= 0 || max <= 0 {
+                '·'
+            } else {
+                SPARK_LEVELS[(v * (SPARK_LEVELS.len() as i64 - 1) / max) as usize]
+            }
+        })
+        .collect()
+}
+
+const HEATMAP_CELL: usize = 16;
+const HEATMAP_GAP: usize = 2;
+const HEATMAP_LEFT: usize = 36;
+const HEATMAP_TOP: usize = 20;
+const HEATMAP_COLORS: [&str; 5] = ["#ebedf0", "#9be9a8", "#40c463", "#30a14e", "#216e39"];
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Render a weekday × hour-of-day activity heatmap as a standalone SVG document.
+///
+/// Only the marginal hourly and daily distributions are available, so each cell's
+/// weight is `daily[d] * hourly[h]`, i.e. the activity expected if the time of day
+/// does not depend on the weekday. Empty stats produce a grid of blank cells.
+pub fn render_heatmap_svg(stats: &StatsResult) -> String {
+    let step = HEATMAP_CELL + HEATMAP_GAP;
+    let width = HEATMAP_LEFT + 24 * step;
+    let height = HEATMAP_TOP + 7 * step;
+
+    let weight = |day: usize, hour: usize| {
+        stats.daily_distribution.get(day).copied().unwrap_or(0)
+            * stats.hourly_distribution.get(hour).copied().unwrap_or(0)
+    };
+    let max = (0..7)
+        .flat_map(|d| (0..24).map(move |h| (d, h)))
+        .map(|(d, h)| weight(d, h))
+        .max()
+        .unwrap_or(0);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\" font-family=\"sans-serif\" font-size=\"10\">\n"
+    );
+    for hour in (0..24).step_by(3) {
+        let x = HEATMAP_LEFT + hour * step;
+        svg.push_str(&format!("  <text x=\"{x}\" y=\"12\">{hour:02}</text>\n"));
+    }
+    for (day, label) in WEEKDAYS.iter().enumerate() {
+        let y = HEATMAP_TOP + day * step;
+        svg.push_str(&format!(
+            "  <text x=\"0\" y=\"{}\">{label}</text>\n",
+            y + HEATMAP_CELL - 4
+        ));
+        for hour in 0..24 {
+            let value = weight(day, hour);
+            let level = if max == 0 || value == 0 {
+                0
+            } else {
+                // Map (0, max] onto levels 1..=4, rounding up.
+                ((value * 4 + max - 1) / max) as usize
+            };
+            let x = HEATMAP_LEFT + hour * step;
+            svg.push_str(&format!(
+                "  <rect class=\"cell\" x=\"{x}\" y=\"{y}\" width=\"{HEATMAP_CELL}\" height=\"{HEATMAP_CELL}\" \
+                 rx=\"2\" fill=\"{}\"><title>{label} {hour:02}:00</title></rect>\n",
+                HEATMAP_COLORS[level]
+            ));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
 /// Parse a human-readable duration string into a Unix timestamp threshold (in ms).
 /// Supports: "7d", "30d", "1y", "all"
 pub fn parse_last_filter(last: &str) -> Option<i64> {
-    let last = last.trim().to_lowercase();
-    if last == "all" {
+    let period = parse_period_ms(last)?;
+    Some(chrono::Utc::now().timestamp_millis() - period)
+}
+
+/// Length in ms of a period like "7d", "2w", "6m" (30 days) or "1y" (365
+/// days). `None` for "all" and anything unparseable, including periods that
+/// are not positive or do not fit in an `i64`.
+pub fn parse_period_ms(period: &str) -> Option<i64> {
+    let period = period.trim().to_lowercase();
+    if period == "all" {
         return None;
     }
 
-    let (num, unit) = last.split_at(last.len().saturating_sub(1));
-    let num: i64 = num.parse().ok()?;
+    let unit = period.chars().last()?;
+    let num: i64 = period[..period.len() - unit.len_utf8()].parse().ok()?;
+    if num <= 0 {
+        return None;
+    }
 
-    let seconds = match unit {
-        "d" => num * 86400,
-        "w" => num * 7 * 86400,
-        "m" => num * 30 * 86400,
-        "y" => num * 365 * 86400,
+    let unit_ms: i64 = match unit {
+        'd' => 86_400_000,
+        'w' => 7 * 86_400_000,
+        'm' => 30 * 86_400_000,
+        'y' => 365 * 86_400_000,
         _ => return None,
     };
+    num.checked_mul(unit_ms)
+}
 
-    let now = chrono::Utc::now().timestamp_millis();
-    Some(now - seconds * 1000)
+/// Parse an absolute time bound into epoch ms. Accepts RFC 3339
+/// (`2024-01-15T12:30:00Z`), a date and time in `tz` (`2024-01-15 12:30`,
+/// `2024-01-15T12:30:00`), or a bare date (`2024-01-15`, midnight in `tz`).
+pub fn parse_time_bound(spec: &str, tz: DisplayTz) -> Option<i64> {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    let spec = spec.trim();
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(spec) {
+        return Some(dt.timestamp_millis());
+    }
+    let local = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(spec, fmt).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(spec, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })?;
+    tz.to_utc_millis(local)
+}
+
+/// Combine `--last` with `--after`/`--before` into `[after, before)` bounds.
+/// The later of the two lower bounds wins.
+pub fn parse_time_range(
+    last: &str,
+    after: Option<&str>,
+    before: Option<&str>,
+    tz: DisplayTz,
+) -> anyhow::Result<(Option<i64>, Option<i64>)> {
+    let bound = |flag: &str, spec: &str| {
+        parse_time_bound(spec, tz).ok_or_else(|| {
+            anyhow::anyhow!("Invalid --{flag} '{spec}': expected YYYY-MM-DD or an ISO-8601 time")
+        })
+    };
+    let after = after.map(|a| bound("after", a)).transpose()?;
+    let before = before.map(|b| bound("before", b)).transpose()?;
+    let after = match (parse_last_filter(last), after) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    if let (Some(a), Some(b)) = (after, before) {
+        if a >= b {
+            anyhow::bail!("The time range is empty: --after must be earlier than --before");
+        }
+    }
+    Ok((after, before))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::HistoryEntry;
+
+    fn history_entry(id: &str, command: &str, timestamp: i64) -> HistoryEntry {
+        HistoryEntry {
+            id: id.into(),
+            command: command.into(),
+            cwd: "/tmp".into(),
+            exit_code: 0,
+            duration_ms: 10,
+            session_id: "s1".into(),
+            machine_id: "m1".into(),
+            hostname: "host".into(),
+            timestamp,
+            shell: "zsh".into(),
+            group_name: "default".into(),
+            truncated: false,
+            unknown_duration: false,
+        }
+    }
+
+    fn unfiltered(tz: DisplayTz) -> StatsFilter {
+        StatsFilter {
+            after_timestamp: None,
+            before_timestamp: None,
+            machine_id: None,
+            group_name: None,
+            directory: None,
+            tz,
+            normalize: false,
+        }
+    }
+
+    #[test]
+    fn command_head_skips_env_and_flags() {
+        let head = |cmd: &str| command_head(cmd).map(|(p, s)| (p, s.unwrap_or_default()));
+        let pair = |p: &str, s: &str| Some((p.to_string(), s.to_string()));
+        assert_eq!(head("git status -sb"), pair("git", "status"));
+        assert_eq!(head("FOO=bar BAZ=\"a b\" docker compose up"), pair("docker", "compose"));
+        assert_eq!(head("ls -la"), pair("ls", ""));
+        assert_eq!(head("vim src/main.rs"), pair("vim", ""));
+        assert_eq!(head("git 'commit' -m 'x y'"), pair("git", "commit"));
+        assert_eq!(head("echo 'a b'|wc"), pair("echo", ""));
+        assert_eq!(head("make; git push"), pair("make", ""));
+        assert_eq!(head("FOO=1"), None);
+        assert_eq!(head("   "), None);
+    }
+
+    #[test]
+    fn normalize_command_templatizes_volatile_args() {
+        let cases = [
+            ("kubectl logs pod-abc123 -f", "kubectl logs pod-<id> -f"),
+            ("kubectl logs web-7f9c4b8d5-x2k4p", "kubectl logs web-<id>"),
+            ("git show 3f2a9c1e", "git show <hash>"),
+            ("docker rm 0d3c2f8e-1b2a-4c5d-9e8f-7a6b5c4d3e2f", "docker rm <uuid>"),
+            ("kill -9 4312", "kill -9 <n>"),
+            ("head --lines=20 ./src/main.rs", "head --lines=<n> <path>"),
+            ("PORT=8080 cargo run 3000", "PORT=8080 cargo run <n>"),
+            ("cargo test", "cargo test"),
+        ];
+        for (raw, normalized) in cases {
+            assert_eq!(normalize_command(raw), normalized, "{raw}");
+        }
+    }
+
+    #[test]
+    fn normalize_collapses_top_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let commands = [
+            "kubectl logs pod-abc123",
+            "kubectl logs pod-9x8y7z",
+            "kubectl logs pod-q1w2e3",
+            "ls",
+        ];
+        for (i, cmd) in commands.iter().enumerate() {
+            db.insert_history_entry(&history_entry(&i.to_string(), cmd, 1_000 + i as i64))
+                .unwrap();
+        }
+
+        let mut filter = unfiltered(DisplayTz::Local);
+        let raw = compute_stats(&db, &filter).unwrap();
+        assert_eq!(raw.top_commands.len(), 4);
+
+        filter.normalize = true;
+        let stats = compute_stats(&db, &filter).unwrap();
+        assert_eq!(
+            stats.top_commands,
+            [("kubectl logs pod-<id>".to_string(), 3), ("ls".to_string(), 1)]
+        );
+        assert_eq!(stats.unique_commands, 4, "only the top list is normalized");
+    }
+
+    #[test]
+    fn top_subcommands_bucket_first_two_words() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let commands = [
+            "git status",
+            "git status -sb",
+            "GIT_PAGER=cat git log --oneline",
+            "git --version",
+            "docker compose up -d",
+            "ls -la",
+        ];
+        for (i, cmd) in commands.iter().enumerate() {
+            db.insert_history_entry(&history_entry(&i.to_string(), cmd, 1_000 + i as i64))
+                .unwrap();
+        }
+
+        let stats = compute_stats(&db, &unfiltered(DisplayTz::Local)).unwrap();
+        assert_eq!(
+            stats.top_subcommands,
+            [
+                ("git status".to_string(), 2),
+                ("docker compose".to_string(), 1),
+                ("git".to_string(), 1),
+                ("git log".to_string(), 1),
+                ("ls".to_string(), 1),
+            ]
+        );
+        assert_eq!(stats.top_prefixes[0], ("git".to_string(), 4));
+    }
+
+    #[test]
+    fn hourly_distribution_uses_display_tz() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        // 2024-01-15 23:30 UTC, a Monday
+        db.insert_history_entry(&history_entry("a", "ls", 1_705_361_400_000))
+            .unwrap();
+
+        let utc = compute_stats(&db, &unfiltered(DisplayTz::parse("utc").unwrap())).unwrap();
+        assert_eq!(utc.hourly_distribution[23], 1);
+        assert_eq!(utc.daily_distribution[0], 1);
+
+        // +02:00 pushes it past midnight into Tuesday 01:30
+        let east = compute_stats(&db, &unfiltered(DisplayTz::parse("+02:00").unwrap())).unwrap();
+        assert_eq!(east.hourly_distribution[1], 1);
+        assert_eq!(east.daily_distribution[1], 1);
+    }
+
+    #[test]
+    fn streak_counts_consecutive_local_days() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("s.db").to_str().unwrap()).unwrap();
+        let day = 86_400_000;
+        // 2024-01-15 12:30 UTC, then the next two days, then a gap
+        let base = 1_705_321_800_000;
+        for (i, offset) in [0, 1, 2, 5].iter().enumerate() {
+            db.insert_history_entry(&history_entry(&format!("e{i}"), "ls", base + offset * day))
+                .unwrap();
+        }
+        let stats = compute_stats(&db, &unfiltered(DisplayTz::parse("utc").unwrap())).unwrap();
+        assert_eq!(stats.streak_days, 1);
+
+        db.insert_history_entry(&history_entry("e4", "ls", base + 4 * day))
+            .unwrap();
+        db.insert_history_entry(&history_entry("e5", "ls", base + 3 * day))
+            .unwrap();
+        let stats = compute_stats(&db, &unfiltered(DisplayTz::parse("utc").unwrap())).unwrap();
+        assert_eq!(stats.streak_days, 6);
+    }
+
+    #[test]
+    fn per_session_aggregates_counts_and_durations() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let seed = [
+            ("a1", "s-long", 100),
+            ("a2", "s-long", 250),
+            ("a3", "s-long", 50),
+            ("b1", "s-short", 1000),
+            ("c1", "", 7),
+            ("c2", "", 3),
+        ];
+        for (i, (id, session, duration)) in seed.iter().enumerate() {
+            let mut entry = history_entry(id, "make", 1_705_321_800_000 + i as i64);
+            entry.session_id = session.to_string();
+            entry.duration_ms = *duration;
+            db.insert_history_entry(&entry).unwrap();
+        }
+
+        let stats = compute_stats(&db, &unfiltered(DisplayTz::Local)).unwrap();
+        assert_eq!(
+            stats.per_session,
+            vec![
+                ("s-long".to_string(), 3, 400),
+                (NO_SESSION.to_string(), 2, 10),
+                ("s-short".to_string(), 1, 1000),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_durations_are_left_out_of_duration_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        for (id, duration, unknown) in [("a", 100, false), ("b", 300, false), ("c", 0, true)] {
+            let mut entry = history_entry(id, "make", 1_705_321_800_000);
+            entry.duration_ms = duration;
+            entry.unknown_duration = unknown;
+            db.insert_history_entry(&entry).unwrap();
+        }
+
+        let filter = unfiltered(DisplayTz::Local);
+        let stats = compute_stats(&db, &filter).unwrap();
+        assert_eq!(stats.total_commands, 3);
+        assert_eq!(stats.avg_duration_ms, 200.0);
+        assert_eq!(stats.median_duration_ms, 300);
+        let detail = command_detail(&db, "make", &filter, 1_705_321_800_000).unwrap();
+        assert_eq!(detail.count, 3);
+        assert_eq!(detail.median_duration_ms, 300);
+    }
+
+    #[test]
+    fn command_detail_matches_exact_command_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        // 2024-01-15 12:30 UTC
+        let now = 1_705_321_800_000;
+        let midnight = 1_705_276_800_000;
+        let day = 86_400_000;
+        let seed = [
+            ("a", "git status", "/src/app", 0, 10, now),
+            ("b", "git status", "/src/app", 0, 30, now - day),
+            ("c", "git status", "/src/lib", 1, 20, now - 3 * day),
+            ("d", "git status", "/src/app", 0, 500, now - 40 * day),
+            ("e", "git status --short", "/src/app", 0, 5, now),
+            ("f", "git", "/src/app", 1, 1, now),
+            ("g", "git statuses", "/src/app", 0, 1, now),
+            ("h", "cargo build", "/src/app", 0, 1, midnight - 1),
+            ("i", "cargo build", "/src/app", 0, 1, midnight),
+        ];
+        for (id, command, cwd, exit_code, duration, ts) in seed {
+            let mut e = history_entry(id, command, ts);
+            e.cwd = cwd.into();
+            e.exit_code = exit_code;
+            e.duration_ms = duration;
+            db.insert_history_entry(&e).unwrap();
+        }
+
+        let filter = unfiltered(DisplayTz::parse("utc").unwrap());
+        let detail = command_detail(&db, "git status", &filter, now).unwrap();
+        assert_eq!(detail.count, 4);
+        assert_eq!(detail.with_arguments_count, 1);
+        assert_eq!(detail.success_rate, 75.0);
+        assert_eq!(detail.median_duration_ms, 30);
+        assert_eq!(detail.p95_duration_ms, 500);
+        assert_eq!(
+            detail.directories,
+            vec![("/src/app".to_string(), 3), ("/src/lib".to_string(), 1)]
+        );
+        assert_eq!(detail.first_run, Some(now - 40 * day));
+        assert_eq!(detail.last_run, Some(now));
+        assert_eq!(detail.recent_daily.len(), RECENT_DAYS);
+        // The 40-day-old run falls outside the window.
+        assert_eq!(detail.recent_daily.iter().sum::<i64>(), 3);
+        assert_eq!(detail.recent_daily[RECENT_DAYS - 1], 1);
+
+        // The bare prefix is a different command.
+        // Days split at midnight in the display zone
+        let cargo = command_detail(&db, "cargo build", &filter, now).unwrap();
+        assert_eq!(cargo.recent_daily[RECENT_DAYS - 1], 1);
+        assert_eq!(cargo.recent_daily[RECENT_DAYS - 2], 1);
+        let plus2 = StatsFilter {
+            tz: DisplayTz::parse("+02:00").unwrap(),
+            ..filter.clone()
+        };
+        let cargo = command_detail(&db, "cargo build", &plus2, now).unwrap();
+        assert_eq!(cargo.recent_daily[RECENT_DAYS - 1], 2);
+
+        let bare = command_detail(&db, "git", &filter, now).unwrap();
+        assert_eq!(bare.count, 1);
+        assert_eq!(bare.with_arguments_count, 6);
+
+        // The time filter applies too.
+        let recent = StatsFilter {
+            after_timestamp: Some(now - 7 * day),
+            ..filter
+        };
+        assert_eq!(
+            command_detail(&db, "git status", &recent, now)
+                .unwrap()
+                .count,
+            3
+        );
+
+        let none = command_detail(&db, "git stat", &recent, now).unwrap();
+        assert_eq!(none.count, 0);
+        assert_eq!(none.first_run, None);
+    }
+
+    #[test]
+    fn sparkline_scales_to_max() {
+        assert_eq!(sparkline(&[0, 1, 4, 8]), "·▁▄█");
+        assert_eq!(sparkline(&[0, 0]), "··");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    /// Minimal well-formedness check: every opened element is closed in order.
+    fn assert_balanced_xml(svg: &str) {
+        let tag = regex::Regex::new(r"<(/?)([a-zA-Z]+)[^>]*?(/?)>").unwrap();
+        let mut stack = Vec::new();
+        for cap in tag.captures_iter(svg) {
+            let name = cap[2].to_string();
+            if &cap[1] == "/" {
+                assert_eq!(stack.pop(), Some(name), "mismatched closing tag");
+            } else if &cap[3] != "/" {
+                stack.push(name);
+            }
+        }
+        assert!(stack.is_empty(), "unclosed tags: {stack:?}");
+    }
+
+    #[test]
+    fn heatmap_svg_has_a_cell_per_weekday_hour() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        // 2024-01-15 12:30 UTC (a Monday)
+        db.insert_history_entry(&history_entry("h1", "ls", 1_705_321_800_000))
+            .unwrap();
+        let stats = compute_stats(&db, &unfiltered(DisplayTz::parse("utc").unwrap())).unwrap();
+
+        let svg = render_heatmap_svg(&stats);
+        assert!(svg.starts_with("<svg"));
+        assert_balanced_xml(&svg);
+        assert_eq!(svg.matches("<rect class=\"cell\"").count(), 24 * 7);
+        assert_eq!(svg.matches(HEATMAP_COLORS[4]).count(), 1);
+    }
+
+    #[test]
+    fn heatmap_svg_for_empty_stats_is_blank() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let stats = compute_stats(&db, &unfiltered(DisplayTz::Local)).unwrap();
+
+        let svg = render_heatmap_svg(&stats);
+        assert_balanced_xml(&svg);
+        assert_eq!(svg.matches("<rect class=\"cell\"").count(), 24 * 7);
+        assert_eq!(svg.matches(HEATMAP_COLORS[0]).count(), 24 * 7);
+    }
 
     #[test]
     fn parse_last_7d() {
@@ -346,6 +1122,62 @@ mod tests {
         assert!((diff - 365 * 86400 * 1000).abs() < 1000);
     }
 
+    #[test]
+    fn parse_time_bound_formats() {
+        let utc = DisplayTz::parse("utc").unwrap();
+        // 2024-01-15 00:00:00 UTC
+        let midnight = 1_705_276_800_000;
+        assert_eq!(parse_time_bound("2024-01-15", utc), Some(midnight));
+        assert_eq!(
+            parse_time_bound("2024-01-15 12:30", utc),
+            Some(midnight + 45_000_000)
+        );
+        assert_eq!(
+            parse_time_bound("2024-01-15T12:30:00+02:00", utc),
+            Some(midnight + 45_000_000 - 7_200_000)
+        );
+        let plus2 = DisplayTz::parse("+02:00").unwrap();
+        assert_eq!(parse_time_bound("2024-01-15", plus2), Some(midnight - 7_200_000));
+        assert_eq!(parse_time_bound("15/01/2024", utc), None);
+    }
+
+    #[test]
+    fn parse_time_range_composes_last_and_bounds() {
+        let utc = DisplayTz::parse("utc").unwrap();
+        let (after, before) =
+            parse_time_range("all", Some("2024-01-15"), Some("2024-01-16"), utc).unwrap();
+        assert_eq!(after, Some(1_705_276_800_000));
+        assert_eq!(before, Some(1_705_363_200_000));
+
+        // A recent --last is later than an old --after, so it wins
+        let (after, _) = parse_time_range("7d", Some("2020-01-01"), None, utc).unwrap();
+        assert!(after.unwrap() > 1_705_276_800_000);
+
+        assert!(parse_time_range("all", Some("2024-01-16"), Some("2024-01-15"), utc).is_err());
+        assert!(parse_time_range("all", Some("yesterday"), None, utc).is_err());
+    }
+
+    #[test]
+    fn range_filter_narrows_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let day = 86_400_000;
+        let start = 1_705_276_800_000;
+        for (i, cmd) in ["ls", "make", "git push"].iter().enumerate() {
+            db.insert_history_entry(&history_entry(&format!("h{i}"), cmd, start + i as i64 * day))
+                .unwrap();
+        }
+        let tz = DisplayTz::parse("utc").unwrap();
+        let filter = StatsFilter {
+            after_timestamp: Some(start + day),
+            before_timestamp: Some(start + 2 * day),
+            ..unfiltered(tz)
+        };
+        let stats = compute_stats(&db, &filter).unwrap();
+        assert_eq!(stats.total_commands, 1);
+        assert_eq!(stats.top_commands[0].0, "make");
+    }
+
     #[test]
     fn parse_last_all() {
         assert!(parse_last_filter("all").is_none());
@@ -355,4 +1187,12 @@ mod tests {
     fn parse_last_invalid() {
         assert!(parse_last_filter("foo").is_none());
     }
+
+    #[test]
+    fn period_must_be_positive_and_in_range() {
+        assert_eq!(parse_period_ms("2w"), Some(14 * 86_400_000));
+        for bad in ["0d", "-7d", "+0w", "99999999999999999y", "7", "d", "7é", ""] {
+            assert_eq!(parse_period_ms(bad), None, "{bad}");
+        }
+    }
 }
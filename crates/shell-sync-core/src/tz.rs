@@ -0,0 +1,153 @@
+use chrono::{FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+
+/// Time zone used when rendering timestamps for humans.
+///
+/// Stored timestamps are always UTC epoch milliseconds; this only affects display
+/// and local-time bucketing (e.g. stats by hour of day).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayTz {
+    /// The system's local time zone (DST-aware).
+    #[default]
+    Local,
+    /// A fixed UTC offset.
+    Fixed(FixedOffset),
+}
+
+impl DisplayTz {
+    /// Resolve the display zone from `SHELL_SYNC_TZ`, falling back to local time.
+    pub fn from_env() -> Self {
+        std::env::var("SHELL_SYNC_TZ")
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or_default()
+    }
+
+    /// Parse a zone spec: `local`, `utc`/`z`, or a fixed offset like `+05:30`, `-0800`, `+2`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim();
+        match spec.to_lowercase().as_str() {
+            "" | "local" => return Some(Self::Local),
+            "utc" | "z" => return Some(Self::Fixed(FixedOffset::east_opt(0)?)),
+            _ => {}
+        }
+
+        let (sign, rest) = match spec.as_bytes().first()? {
+            b'+' => (1, &spec[1..]),
+            b'-' => (-1, &spec[1..]),
+            _ => return None,
+        };
+        if !rest.bytes().all(|b| b.is_ascii_digit() || b == b':') {
+            return None;
+        }
+        // Only ASCII from here on, so byte offsets are char boundaries
+        let (hours, minutes) = match rest.split_once(':') {
+            Some((h, m)) if m.len() == 2 => (h, m),
+            Some(_) => return None,
+            None if rest.len() == 4 => rest.split_at(2),
+            None => (rest, "0"),
+        };
+        if hours.is_empty() || hours.len() > 2 {
+            return None;
+        }
+        let hours: i32 = hours.parse().ok()?;
+        let minutes: i32 = minutes.parse().ok()?;
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).map(Self::Fixed)
+    }
+
+    /// Convert a UTC epoch-millisecond timestamp to wall-clock time in this zone.
+    pub fn localize(&self, ts_ms: i64) -> Option<NaiveDateTime> {
+        let utc = Utc.timestamp_millis_opt(ts_ms).single()?;
+        Some(match self {
+            Self::Local => utc.with_timezone(&Local).naive_local(),
+            Self::Fixed(offset) => utc.with_timezone(offset).naive_local(),
+        })
+    }
+
+    /// Convert wall-clock time in this zone to a UTC epoch-millisecond timestamp.
+    /// Ambiguous local times (DST fall-back) resolve to the earlier instant.
+    pub fn to_utc_millis(&self, dt: NaiveDateTime) -> Option<i64> {
+        match self {
+            Self::Local => Local
+                .from_local_datetime(&dt)
+                .earliest()
+                .map(|t| t.timestamp_millis()),
+            Self::Fixed(offset) => offset
+                .from_local_datetime(&dt)
+                .earliest()
+                .map(|t| t.timestamp_millis()),
+        }
+    }
+
+    /// Format a UTC epoch-millisecond timestamp with a strftime-style pattern.
+    pub fn format(&self, ts_ms: i64, fmt: &str) -> String {
+        self.localize(ts_ms)
+            .map(|dt| dt.format(fmt).to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+
+    // 2024-01-15 12:30:00 UTC
+    const NOON_UTC: i64 = 1_705_321_800_000;
+
+    #[test]
+    fn parse_named_zones() {
+        assert_eq!(DisplayTz::parse("local"), Some(DisplayTz::Local));
+        assert_eq!(
+            DisplayTz::parse("UTC"),
+            Some(DisplayTz::Fixed(FixedOffset::east_opt(0).unwrap()))
+        );
+    }
+
+    #[test]
+    fn parse_fixed_offsets() {
+        let ist = FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap();
+        assert_eq!(DisplayTz::parse("+05:30"), Some(DisplayTz::Fixed(ist)));
+        assert_eq!(DisplayTz::parse("+0530"), Some(DisplayTz::Fixed(ist)));
+
+        let pst = FixedOffset::west_opt(8 * 3600).unwrap();
+        assert_eq!(DisplayTz::parse("-08:00"), Some(DisplayTz::Fixed(pst)));
+        assert_eq!(DisplayTz::parse("-8"), Some(DisplayTz::Fixed(pst)));
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        assert!(DisplayTz::parse("America/New_York").is_none());
+        assert!(DisplayTz::parse("+25:00").is_none());
+        assert!(DisplayTz::parse("+05:99").is_none());
+        assert!(DisplayTz::parse("+é12").is_none());
+        assert!(DisplayTz::parse("+1é").is_none());
+        assert!(DisplayTz::parse("+-5").is_none());
+        assert!(DisplayTz::parse("+123").is_none());
+    }
+
+    #[test]
+    fn renders_at_offset_hour() {
+        let tz = DisplayTz::parse("+05:30").unwrap();
+        assert_eq!(tz.localize(NOON_UTC).unwrap().hour(), 18);
+        assert_eq!(tz.format(NOON_UTC, "%H:%M"), "18:00");
+
+        let tz = DisplayTz::parse("-08:00").unwrap();
+        assert_eq!(tz.format(NOON_UTC, "%Y-%m-%d %H:%M"), "2024-01-15 04:30");
+    }
+
+    #[test]
+    fn to_utc_millis_inverts_localize() {
+        let tz = DisplayTz::parse("+05:30").unwrap();
+        let local = tz.localize(NOON_UTC).unwrap();
+        assert_eq!(tz.to_utc_millis(local), Some(NOON_UTC));
+    }
+
+    #[test]
+    fn offset_can_cross_midnight() {
+        let tz = DisplayTz::parse("+13:00").unwrap();
+        assert_eq!(tz.format(NOON_UTC, "%m-%d %H:%M"), "01-16 01:30");
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use shell_sync_core::db::SyncDatabase;
+use shell_sync_core::models::Alias;
+
+/// Group sets remembered before the cache starts over.
+const MAX_ENTRIES: usize = 64;
+
+/// In-memory copy of [`SyncDatabase::get_aliases_by_groups`] results, keyed
+/// by group set and dropped whenever an alias changes.
+///
+/// Every machine in a group re-fetches its aliases on `sync_required`, so
+/// without this a single edit fans out into one identical query per machine.
+#[derive(Default)]
+pub struct AliasCache {
+    entries: Mutex<HashMap<Vec<String>, Arc<Vec<Alias>>>>,
+    /// Bumped by [`invalidate`](Self::invalidate) so a load that raced with a
+    /// write is not cached.
+    generation: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl AliasCache {
+    /// Live aliases in `groups`, from the cache or the database.
+    pub fn get_aliases_by_groups(
+        &self,
+        db: &SyncDatabase,
+        groups: &[String],
+    ) -> anyhow::Result<Arc<Vec<Alias>>> {
+        let mut key = groups.to_vec();
+        key.sort();
+        key.dedup();
+        if let Some(aliases) = self.entries.lock().unwrap().get(&key) {
+            return Ok(Arc::clone(aliases));
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let generation = self.generation.load(Ordering::Acquire);
+        let aliases = Arc::new(db.get_aliases_by_groups(&key)?);
+
+        let mut entries = self.entries.lock().unwrap();
+        if self.generation.load(Ordering::Acquire) == generation {
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+            entries.insert(key, Arc::clone(&aliases));
+        }
+        Ok(aliases)
+    }
+
+    /// Forget everything; call after any alias is added, changed or removed.
+    pub fn invalidate(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        entries.clear();
+    }
+
+    /// Lookups that had to go to the database.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(aliases: &[Alias]) -> Vec<&str> {
+        aliases.iter().map(|a| a.name.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_between_writes_hit_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        db.add_alias("gs", "git status", "default", "m1").unwrap();
+        let cache = AliasCache::default();
+        let groups = vec!["work".to_string(), "default".to_string()];
+
+        assert_eq!(
+            names(&cache.get_aliases_by_groups(&db, &groups).unwrap()),
+            ["gs"]
+        );
+        // Same set in another order is the same entry
+        let reordered = vec!["default".to_string(), "work".to_string()];
+        cache.get_aliases_by_groups(&db, &reordered).unwrap();
+        cache.get_aliases_by_groups(&db, &groups).unwrap();
+        assert_eq!(cache.misses(), 1);
+
+        cache
+            .get_aliases_by_groups(&db, &["default".to_string()])
+            .unwrap();
+        assert_eq!(cache.misses(), 2);
+    }
+
+    #[test]
+    fn invalidate_serves_updated_aliases() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        db.add_alias("gs", "git status", "default", "m1").unwrap();
+        let cache = AliasCache::default();
+        let groups = vec!["default".to_string()];
+        cache.get_aliases_by_groups(&db, &groups).unwrap();
+
+        db.add_alias("ll", "ls -la", "default", "m1").unwrap();
+        cache.invalidate();
+        let aliases = cache.get_aliases_by_groups(&db, &groups).unwrap();
+        assert_eq!(names(&aliases), ["gs", "ll"]);
+        assert_eq!(cache.misses(), 2);
+    }
+}
@@ -1,25 +1,49 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
-    extract::{Path, Query, State},
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     Json,
 };
 use serde::Deserialize;
-use shell_sync_core::db::SyncDatabase;
+use shell_sync_core::config::{ConflictStrategy, ServerConfig};
+use shell_sync_core::db::{with_query_deadline, AliasOrder, SyncDatabase};
+use shell_sync_core::encryption::token_fingerprint;
 use shell_sync_core::models::*;
-use shell_sync_core::secrets::check_for_secrets;
-use tracing::error;
+use shell_sync_core::protocol::{features, ServerVersion};
+use shell_sync_core::secrets::{check_for_secrets, constant_time_eq, CommandBlocklist};
+use shell_sync_core::shell::ShellType;
+use tracing::{error, warn};
 
+use crate::alias_cache::AliasCache;
 use crate::git_backup::GitBackup;
-use crate::ws::WsHub;
+use crate::ws::{HubMessage, WsHub};
 
 /// Shared application state passed to all route handlers.
 pub struct AppState {
     pub db: Arc<SyncDatabase>,
     pub hub: Arc<WsHub>,
     pub git_backup: Arc<GitBackup>,
+    pub config: ServerConfig,
+    /// Compiled `blocked_command_patterns`.
+    pub blocklist: CommandBlocklist,
+    pub alias_cache: AliasCache,
+}
+
+impl AppState {
+    /// Record that aliases changed: queue a git backup and drop cached lists.
+    pub fn aliases_changed(&self) {
+        self.git_backup.mark_dirty();
+        self.alias_cache.invalidate();
+    }
 }
 
 // ---------- helpers ----------
@@ -28,35 +52,210 @@ fn err(status: StatusCode, msg: &str) -> (StatusCode, Json<serde_json::Value>) {
     (status, Json(serde_json::json!({ "error": msg })))
 }
 
+/// Reject a command matching one of the server's blocked patterns for `group`.
+fn check_blocked_command(
+    state: &AppState,
+    group: &str,
+    command: &str,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    match state.blocklist.blocked_by(group, command) {
+        Some(pattern) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": format!("Command matches blocked pattern '{pattern}'"),
+                "code": "blocked_command",
+                "pattern": pattern,
+            })),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Reject shell names other than `zsh`, `bash` and `fish` in [`Alias::shells`].
+fn check_shells(shells: &[String]) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    match shells.iter().find(|s| ShellType::from_name(s).is_none()) {
+        Some(unknown) => Err(err(
+            StatusCode::BAD_REQUEST,
+            &format!("Unknown shell '{unknown}'. Use zsh, bash or fish."),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Restrict a just-written alias to `shells`; an empty list leaves it as is.
+fn restrict_shells(
+    state: &AppState,
+    alias: Alias,
+    shells: &[String],
+) -> Result<Alias, (StatusCode, Json<serde_json::Value>)> {
+    if shells.is_empty() {
+        return Ok(alias);
+    }
+    state
+        .db
+        .set_alias_shells(alias.id, shells)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
+        .ok_or_else(|| err(StatusCode::NOT_FOUND, "Alias not found"))
+}
+
+/// Internal header carrying the peer address from [`stamp_peer_ip`] to handlers.
+const PEER_IP_HEADER: &str = "x-shell-sync-peer";
+
+/// Middleware: record the connecting peer's IP in [`PEER_IP_HEADER`] so
+/// `authenticate` can log it. Any client-supplied value is discarded.
+pub async fn stamp_peer_ip(
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    req.headers_mut().remove(PEER_IP_HEADER);
+    if let Some(ConnectInfo(addr)) = connect_info {
+        if let Ok(value) = HeaderValue::from_str(&addr.ip().to_string()) {
+            req.headers_mut().insert(PEER_IP_HEADER, value);
+        }
+    }
+    next.run(req).await
+}
+
+/// Middleware: answer 503 once a read has taken longer than `deadline`,
+/// whether a handler is waiting on slow work or running a slow query.
+/// Handlers query SQLite without yielding, so queries are interrupted through
+/// [`with_query_deadline`] as well. Writes always run to completion, since
+/// cutting one short could answer 503 after a partial write. A zero deadline
+/// never expires.
+pub async fn enforce_deadline(
+    State(deadline): State<Duration>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if deadline.is_zero() || !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return next.run(req).await;
+    }
+    use std::future::Future;
+
+    let path = req.uri().path().to_string();
+    let until = std::time::Instant::now() + deadline;
+    let mut handler = std::pin::pin!(next.run(req));
+    let handler =
+        std::future::poll_fn(|cx| with_query_deadline(until, || handler.as_mut().poll(cx)));
+    match tokio::time::timeout(deadline, handler).await {
+        // An interrupted query fails the handler right at the deadline
+        Ok(response) if std::time::Instant::now() < until => response,
+        _ => {
+            warn!(path = %path, secs = deadline.as_secs(), "Request exceeded its deadline");
+            err(StatusCode::SERVICE_UNAVAILABLE, "Request timed out").into_response()
+        }
+    }
+}
+
+fn client_ip(headers: &HeaderMap) -> &str {
+    headers
+        .get(PEER_IP_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("unknown")
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|auth| auth.strip_prefix("Bearer "))
+}
+
 /// Extract and validate the Bearer token, returning the authenticated Machine.
+/// Every attempt is recorded in the auth audit log.
 fn authenticate(
     headers: &HeaderMap,
     db: &SyncDatabase,
 ) -> Result<Machine, (StatusCode, Json<serde_json::Value>)> {
-    let auth = headers
-        .get("authorization")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
+    authenticate_token(bearer_token(headers), headers, db, "http")
+}
+
+/// Validate `token` from wherever the transport carries it, logging the
+/// attempt under `source`.
+fn authenticate_token(
+    token: Option<&str>,
+    headers: &HeaderMap,
+    db: &SyncDatabase,
+    source: &str,
+) -> Result<Machine, (StatusCode, Json<serde_json::Value>)> {
+    let ip = client_ip(headers);
 
-    if !auth.starts_with("Bearer ") {
+    let Some(token) = token else {
+        let _ = db.log_auth(None, None, false, ip, source);
         return Err(err(
             StatusCode::UNAUTHORIZED,
             "Missing or invalid authorization header",
         ));
-    }
+    };
 
-    let token = &auth[7..];
     let machine = db
         .get_machine_by_token(token)
-        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
-        .ok_or_else(|| err(StatusCode::UNAUTHORIZED, "Invalid authentication token"))?;
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    let Some(machine) = machine else {
+        let _ = db.log_auth(None, Some(&token_fingerprint(token)), false, ip, source);
+        return Err(err(StatusCode::UNAUTHORIZED, "Invalid authentication token"));
+    };
 
+    let _ = db.log_auth(Some(&machine.machine_id), None, true, ip, source);
     let _ = db.update_machine_last_seen(&machine.machine_id);
     Ok(machine)
 }
 
+/// Check the Bearer token against the configured admin token.
+fn authenticate_admin(
+    headers: &HeaderMap,
+    config: &ServerConfig,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let Some(expected) = config.admin_token.as_deref().filter(|t| !t.is_empty()) else {
+        return Err(err(
+            StatusCode::FORBIDDEN,
+            "Admin endpoints are disabled; start the server with --admin-token",
+        ));
+    };
+    match bearer_token(headers) {
+        Some(token) if constant_time_eq(token, expected) => Ok(()),
+        _ => Err(err(StatusCode::UNAUTHORIZED, "Invalid admin token")),
+    }
+}
+
+/// Refuse a change to `group`'s aliases from a machine with read-only
+/// access to it.
+fn require_write(
+    machine: &Machine,
+    group: &str,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    if machine.permission(group) == GroupPermission::Read {
+        return Err(err(
+            StatusCode::FORBIDDEN,
+            &format!("Machine has read-only access to group '{}'", group),
+        ));
+    }
+    Ok(())
+}
+
 // ---------- routes ----------
 
+/// What this server supports, for [`version`]. Encrypted alias storage is not
+/// implemented and encrypted history batches are not stored, so
+/// [`features::ENCRYPTED_ALIASES`] and [`features::ENCRYPTED_HISTORY`] are
+/// not listed.
+pub const FEATURES: &[&str] = &[
+    features::IMPORT_DRY_RUN,
+    features::ALIAS_SHELLS,
+    features::KEY_EXCHANGE,
+    features::CONFLICT_STRATEGIES,
+    features::CONFLICT_PAGING,
+];
+
+/// GET /api/version
+pub async fn version() -> Json<ServerVersion> {
+    Json(ServerVersion {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        features: FEATURES.iter().map(|f| f.to_string()).collect(),
+    })
+}
+
 /// GET /api/health
 pub async fn health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     let active = state.hub.client_count().await;
@@ -112,19 +311,41 @@ pub async fn register(
     }))
 }
 
-/// GET /api/aliases
+#[derive(Deserialize)]
+pub struct AliasesQuery {
+    /// `name` (default) or `recent` (most recently updated first).
+    pub sort: Option<String>,
+}
+
+/// GET /api/aliases?sort=
 pub async fn get_aliases(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
+    Query(query): Query<AliasesQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     let machine = authenticate(&headers, &state.db)?;
-    let aliases = state
-        .db
-        .get_aliases_by_groups(&machine.groups)
-        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    let order = match query.sort.as_deref() {
+        None | Some("name") => AliasOrder::Name,
+        Some("recent") => AliasOrder::Recent,
+        Some(other) => {
+            return Err(err(
+                StatusCode::BAD_REQUEST,
+                &format!("Unknown sort '{other}' (expected name or recent)"),
+            ))
+        }
+    };
+    // Only the default order is cached; it is what every daemon fetches
+    let aliases = match order {
+        AliasOrder::Name => state.alias_cache.get_aliases_by_groups(&state.db, &machine.groups),
+        _ => state
+            .db
+            .get_aliases_by_groups_ordered(&machine.groups, order)
+            .map(Arc::new),
+    }
+    .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
     let count = aliases.len();
     Ok(Json(serde_json::json!({
-        "aliases": aliases,
+        "aliases": *aliases,
         "groups": machine.groups,
         "count": count
     })))
@@ -170,25 +391,39 @@ pub async fn add_alias(
             &format!("Machine does not belong to group '{}'", body.group),
         ));
     }
+    require_write(&machine, &body.group)?;
+
+    check_blocked_command(&state, &body.group, &body.command)?;
+    check_shells(&body.shells)?;
 
     let alias = state
         .db
-        .add_alias(&body.name, &body.command, &body.group, &machine.machine_id)
+        .add_alias_at(
+            &body.name,
+            &body.command,
+            &body.group,
+            &machine.machine_id,
+            body.created_at,
+            body.expand,
+            body.template,
+        )
         .map_err(|e| {
-            if e.to_string().contains("already exists") {
-                err(StatusCode::CONFLICT, &e.to_string())
+            let msg = e.to_string();
+            if msg.contains("already exists") || msg.contains("was deleted") {
+                err(StatusCode::CONFLICT, &msg)
             } else {
-                err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+                err(StatusCode::INTERNAL_SERVER_ERROR, &msg)
             }
         })?;
+    let alias = restrict_shells(&state, alias, &body.shells)?;
 
-    state.git_backup.mark_dirty();
+    state.aliases_changed();
 
     state
         .hub
         .broadcast_to_groups(
             &state.db,
-            &[body.group.clone()],
+            std::slice::from_ref(&body.group),
             "alias_added",
             serde_json::to_value(&alias).unwrap_or_default(),
             Some(&machine.machine_id),
@@ -221,6 +456,7 @@ pub async fn update_alias(
         .get_alias_by_id(id)
         .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
         .ok_or_else(|| err(StatusCode::NOT_FOUND, "Alias not found"))?;
+    require_write(&machine, &existing.group_name)?;
 
     if check_for_secrets(&existing.name, &body.command) {
         return Err(err(
@@ -229,28 +465,146 @@ pub async fn update_alias(
         ));
     }
 
+    check_blocked_command(&state, &existing.group_name, &body.command)?;
+    if let Some(shells) = &body.shells {
+        check_shells(shells)?;
+    }
+
+    if body
+        .expected_version
+        .is_some_and(|expected| expected != existing.version)
+    {
+        return settle_stale_update(&state, &machine, &existing, &body).await;
+    }
+
+    let updated = apply_alias_update(&state, &machine, id, &body).await?;
+
+    Ok(Json(
+        serde_json::json!({ "message": "Alias updated successfully", "alias": updated }),
+    ))
+}
+
+/// Write an alias update, back it up and notify the other machines in its group.
+async fn apply_alias_update(
+    state: &AppState,
+    machine: &Machine,
+    id: i64,
+    body: &UpdateAliasRequest,
+) -> Result<Alias, (StatusCode, Json<serde_json::Value>)> {
     let updated = state
         .db
-        .update_alias(id, &body.command, &machine.machine_id)
+        .update_alias(id, &body.command, body.expand, body.template, &machine.machine_id)
         .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
         .ok_or_else(|| err(StatusCode::NOT_FOUND, "Alias not found"))?;
+    let updated = match &body.shells {
+        Some(shells) => state
+            .db
+            .set_alias_shells(id, shells)
+            .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
+            .ok_or_else(|| err(StatusCode::NOT_FOUND, "Alias not found"))?,
+        None => updated,
+    };
 
-    state.git_backup.mark_dirty();
+    state.aliases_changed();
 
     state
         .hub
         .broadcast_to_groups(
             &state.db,
-            &[updated.group_name.clone()],
+            std::slice::from_ref(&updated.group_name),
             "alias_updated",
             serde_json::to_value(&updated).unwrap_or_default(),
             Some(&machine.machine_id),
         )
         .await;
 
-    Ok(Json(
-        serde_json::json!({ "message": "Alias updated successfully", "alias": updated }),
-    ))
+    Ok(updated)
+}
+
+/// Settle an update made against a stale alias version using the configured
+/// conflict strategy. Every outcome is recorded in the conflicts table.
+async fn settle_stale_update(
+    state: &AppState,
+    machine: &Machine,
+    existing: &Alias,
+    body: &UpdateAliasRequest,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let strategy = state.config.conflict_strategy;
+
+    let apply = match strategy {
+        ConflictStrategy::Manual => {
+            let conflict_id = state
+                .db
+                .create_conflict(
+                    &existing.name,
+                    &existing.group_name,
+                    &existing.command,
+                    &body.command,
+                    &machine.machine_id,
+                )
+                .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+            return Err((
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({
+                    "error": "Alias was modified by another machine; resolve the conflict to continue",
+                    "conflict_id": conflict_id,
+                    "alias": existing,
+                })),
+            ));
+        }
+        ConflictStrategy::LastWriteWins => {
+            let incoming = body
+                .updated_at
+                .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+            incoming >= existing.updated_at
+        }
+        ConflictStrategy::FirstWriteWins => false,
+    };
+
+    let resolution = format!(
+        "{}:{}",
+        strategy.as_str(),
+        if apply { "applied" } else { "rejected" }
+    );
+    let conflict_id = state
+        .db
+        .record_resolved_conflict(
+            &existing.name,
+            &existing.group_name,
+            &existing.command,
+            &body.command,
+            &machine.machine_id,
+            &resolution,
+        )
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+
+    tracing::info!(
+        alias = %existing.name,
+        group = %existing.group_name,
+        machine_id = %machine.machine_id,
+        resolution = %resolution,
+        "Auto-resolved alias conflict"
+    );
+
+    if !apply {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": format!("Alias was modified by another machine; kept the other edit ({})", strategy.as_str()),
+                "conflict_id": conflict_id,
+                "resolution": resolution,
+                "alias": existing,
+            })),
+        ));
+    }
+
+    let updated = apply_alias_update(state, machine, existing.id, body).await?;
+    Ok(Json(serde_json::json!({
+        "message": "Alias updated successfully",
+        "alias": updated,
+        "conflict_id": conflict_id,
+        "resolution": resolution,
+    })))
 }
 
 /// DELETE /api/aliases/:id
@@ -266,6 +620,7 @@ pub async fn delete_alias(
         .get_alias_by_id(id)
         .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
         .ok_or_else(|| err(StatusCode::NOT_FOUND, "Alias not found"))?;
+    require_write(&machine, &alias.group_name)?;
 
     let deleted = state
         .db
@@ -276,13 +631,13 @@ pub async fn delete_alias(
         return Err(err(StatusCode::NOT_FOUND, "Alias not found"));
     }
 
-    state.git_backup.mark_dirty();
+    state.aliases_changed();
 
     state
         .hub
         .broadcast_to_groups(
             &state.db,
-            &[alias.group_name.clone()],
+            std::slice::from_ref(&alias.group_name),
             "alias_deleted",
             serde_json::json!({ "id": id, "name": alias.name }),
             Some(&machine.machine_id),
@@ -295,16 +650,42 @@ pub async fn delete_alias(
 }
 
 #[derive(Deserialize)]
-pub struct DeleteByNameQuery {
+pub struct AliasNameQuery {
     pub group: Option<String>,
 }
 
+/// GET /api/aliases/name/:name
+pub async fn get_alias_by_name(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Query(query): Query<AliasNameQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let machine = authenticate(&headers, &state.db)?;
+    let group = query.group.as_deref().unwrap_or("default");
+
+    if !machine.groups.iter().any(|g| g == group) {
+        return Err(err(
+            StatusCode::FORBIDDEN,
+            &format!("Machine does not belong to group '{}'", group),
+        ));
+    }
+
+    let alias = state
+        .db
+        .get_alias_by_name(&name, group)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
+        .ok_or_else(|| err(StatusCode::NOT_FOUND, "Alias not found"))?;
+
+    Ok(Json(serde_json::json!({ "alias": alias })))
+}
+
 /// DELETE /api/aliases/name/:name
 pub async fn delete_alias_by_name(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
     Path(name): Path<String>,
-    Query(query): Query<DeleteByNameQuery>,
+    Query(query): Query<AliasNameQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     let machine = authenticate(&headers, &state.db)?;
     let group = query.group.as_deref().unwrap_or("default");
@@ -314,6 +695,7 @@ pub async fn delete_alias_by_name(
         .get_alias_by_name(&name, group)
         .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
         .ok_or_else(|| err(StatusCode::NOT_FOUND, "Alias not found"))?;
+    require_write(&machine, group)?;
 
     let deleted = state
         .db
@@ -324,7 +706,7 @@ pub async fn delete_alias_by_name(
         return Err(err(StatusCode::NOT_FOUND, "Alias not found"));
     }
 
-    state.git_backup.mark_dirty();
+    state.aliases_changed();
 
     state
         .hub
@@ -342,20 +724,130 @@ pub async fn delete_alias_by_name(
     ))
 }
 
-/// GET /api/conflicts
+/// POST /api/aliases/name/:name/pin
+pub async fn pin_alias(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Query(query): Query<AliasNameQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    set_alias_pinned(&state, &headers, &name, &query, true).await
+}
+
+/// DELETE /api/aliases/name/:name/pin
+pub async fn unpin_alias(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Query(query): Query<AliasNameQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    set_alias_pinned(&state, &headers, &name, &query, false).await
+}
+
+async fn set_alias_pinned(
+    state: &AppState,
+    headers: &HeaderMap,
+    name: &str,
+    query: &AliasNameQuery,
+    pinned: bool,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let machine = authenticate(headers, &state.db)?;
+    let group = query.group.as_deref().unwrap_or("default");
+
+    if !machine.groups.iter().any(|g| g == group) {
+        return Err(err(
+            StatusCode::FORBIDDEN,
+            &format!("Machine does not belong to group '{}'", group),
+        ));
+    }
+    require_write(&machine, group)?;
+
+    let alias = state
+        .db
+        .set_alias_pinned(name, group, pinned, &machine.machine_id)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
+        .ok_or_else(|| err(StatusCode::NOT_FOUND, "Alias not found"))?;
+
+    state.aliases_changed();
+
+    state
+        .hub
+        .broadcast_to_groups(
+            &state.db,
+            std::slice::from_ref(&alias.group_name),
+            "alias_updated",
+            serde_json::to_value(&alias).unwrap_or_default(),
+            Some(&machine.machine_id),
+        )
+        .await;
+
+    Ok(Json(serde_json::json!({ "alias": alias })))
+}
+
+#[derive(Deserialize)]
+pub struct ConflictsQuery {
+    #[serde(default)]
+    pub include_resolved: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// GET /api/conflicts?include_resolved=&limit=&offset=
+///
+/// `count` is the size of this page and `total` the number of matching
+/// conflicts; `counts` covers all of this machine's conflicts, open and
+/// resolved.
 pub async fn get_conflicts(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
+    Query(query): Query<ConflictsQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     let machine = authenticate(&headers, &state.db)?;
+    let limit = query
+        .limit
+        .unwrap_or(shell_sync_core::db::DEFAULT_CONFLICT_LIMIT)
+        .clamp(1, 500);
+    let offset = query.offset.unwrap_or(0).max(0);
     let conflicts = state
         .db
-        .get_conflicts_by_machine(&machine.machine_id)
+        .get_conflicts_filtered(&machine.machine_id, query.include_resolved, limit, offset)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    let total = state
+        .db
+        .count_conflicts(&machine.machine_id, query.include_resolved)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    let counts = state
+        .db
+        .get_conflict_counts(&machine.machine_id)
         .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
     let count = conflicts.len();
-    Ok(Json(
-        serde_json::json!({ "conflicts": conflicts, "count": count }),
-    ))
+    Ok(Json(serde_json::json!({
+        "conflicts": conflicts,
+        "count": count,
+        "total": total,
+        "limit": limit,
+        "offset": offset,
+        "counts": counts,
+    })))
+}
+
+/// POST /api/conflicts/purge
+///
+/// Only the calling machine's conflicts are purged.
+pub async fn purge_conflicts(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(body): Json<PurgeConflictsRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let machine = authenticate(&headers, &state.db)?;
+    if body.older_than_ms < 0 {
+        return Err(err(StatusCode::BAD_REQUEST, "older_than_ms must not be negative"));
+    }
+    let deleted = state
+        .db
+        .delete_resolved_conflicts(&machine.machine_id, body.older_than_ms)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    Ok(Json(serde_json::json!({ "deleted": deleted })))
 }
 
 /// POST /api/conflicts/resolve
@@ -380,10 +872,18 @@ pub async fn resolve_conflict(
     }
 }
 
-/// POST /api/import
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    /// Report what would be added or rejected without inserting anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// POST /api/import?dry_run=
 pub async fn import_aliases(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
+    Query(query): Query<ImportQuery>,
     Json(body): Json<ImportRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     let machine = authenticate(&headers, &state.db)?;
@@ -394,9 +894,13 @@ pub async fn import_aliases(
             &format!("Machine does not belong to group '{}'", body.group),
         ));
     }
+    require_write(&machine, &body.group)?;
 
     let mut added = Vec::new();
     let mut failed = Vec::new();
+    // Names a dry run has already counted as added, so a repeat collides
+    // as it would in a real import.
+    let mut previewed = std::collections::HashSet::new();
 
     for import_alias in &body.aliases {
         if check_for_secrets(&import_alias.name, &import_alias.command) {
@@ -406,25 +910,75 @@ pub async fn import_aliases(
             }));
             continue;
         }
-        match state.db.add_alias(
+        if let Err((_, Json(mut rejection))) =
+            check_blocked_command(&state, &body.group, &import_alias.command)
+        {
+            rejection["name"] = serde_json::json!(import_alias.name);
+            failed.push(rejection);
+            continue;
+        }
+        if let Err((_, Json(mut rejection))) = check_shells(&import_alias.shells) {
+            rejection["name"] = serde_json::json!(import_alias.name);
+            failed.push(rejection);
+            continue;
+        }
+        if query.dry_run {
+            let exists = state
+                .db
+                .get_alias_by_name(&import_alias.name, &body.group)
+                .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
+                .is_some();
+            if exists || !previewed.insert(import_alias.name.as_str()) {
+                failed.push(serde_json::json!({
+                    "name": import_alias.name,
+                    "error": format!(
+                        "Alias '{}' already exists in group '{}'",
+                        import_alias.name, body.group
+                    ),
+                }));
+            } else {
+                added.push(serde_json::json!({
+                    "name": import_alias.name,
+                    "command": import_alias.command,
+                }));
+            }
+            continue;
+        }
+        match state.db.add_alias_at(
             &import_alias.name,
             &import_alias.command,
             &body.group,
             &machine.machine_id,
+            None,
+            import_alias.expand,
+            import_alias.template,
         ) {
-            Ok(alias) => added.push(alias),
+            Ok(alias) => {
+                let alias = restrict_shells(&state, alias, &import_alias.shells)?;
+                added.push(serde_json::to_value(alias).unwrap_or_default())
+            }
             Err(e) => failed
                 .push(serde_json::json!({ "name": import_alias.name, "error": e.to_string() })),
         }
     }
 
+    if query.dry_run {
+        return Ok(Json(serde_json::json!({
+            "message": "Dry run; nothing was imported",
+            "dry_run": true,
+            "added": added.len(),
+            "failed": failed.len(),
+            "results": { "added": added, "failed": failed }
+        })));
+    }
+
     if !added.is_empty() {
-        state.git_backup.mark_dirty();
+        state.aliases_changed();
         state
             .hub
             .broadcast_to_groups(
                 &state.db,
-                &[body.group.clone()],
+                std::slice::from_ref(&body.group),
                 "sync_required",
                 serde_json::json!({ "message": "Bulk import completed", "count": added.len() }),
                 Some(&machine.machine_id),
@@ -440,53 +994,316 @@ pub async fn import_aliases(
     })))
 }
 
-#[derive(Deserialize)]
-pub struct HistoryQuery {
-    pub limit: Option<i64>,
+/// GET /api/aliases/orphans
+///
+/// Aliases in groups that no machine belongs to and that were never created
+/// explicitly, so nothing receives them. Machines get the number per group;
+/// the aliases themselves, which belong to nobody's groups, need the admin
+/// token.
+pub async fn get_orphaned_aliases(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let admin = authenticate_admin(&headers, &state.config).is_ok();
+    if !admin {
+        authenticate(&headers, &state.db)?;
+    }
+    let orphans = state
+        .db
+        .known_groups()
+        .and_then(|known| state.db.find_orphaned_aliases(&known))
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+
+    let mut groups: std::collections::BTreeMap<&str, usize> = Default::default();
+    for alias in &orphans {
+        *groups.entry(alias.group_name.as_str()).or_default() += 1;
+    }
+    let mut body = serde_json::json!({ "groups": groups, "count": orphans.len() });
+    if admin {
+        body["aliases"] = serde_json::json!(orphans);
+    }
+    Ok(Json(body))
 }
 
-/// GET /api/history
-pub async fn get_history(
+/// POST /api/aliases/reassign
+pub async fn reassign_aliases(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    Query(query): Query<HistoryQuery>,
+    Json(body): Json<ReassignAliasesRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let _machine = authenticate(&headers, &state.db)?;
-    let limit = query.limit.unwrap_or(100);
-    let history = state
+    let machine = authenticate(&headers, &state.db)?;
+    if body.from == body.to {
+        return Err(err(StatusCode::BAD_REQUEST, "Source and target group are the same"));
+    }
+    if !machine.groups.contains(&body.to) {
+        return Err(err(
+            StatusCode::FORBIDDEN,
+            &format!("Machine does not belong to group '{}'", body.to),
+        ));
+    }
+    // A group others still use is theirs to empty, as with deleting it
+    let from_members = state
         .db
-        .get_history(limit)
+        .get_machines_by_group(&body.from)
         .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
-    let count = history.len();
-    Ok(Json(
-        serde_json::json!({ "history": history, "count": count }),
-    ))
+    if !from_members.is_empty() && !machine.groups.contains(&body.from) {
+        return Err(err(
+            StatusCode::FORBIDDEN,
+            &format!("Machine does not belong to group '{}'", body.from),
+        ));
+    }
+    require_write(&machine, &body.from)?;
+    require_write(&machine, &body.to)?;
+    let moving = state
+        .db
+        .get_aliases_by_groups(std::slice::from_ref(&body.from))
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    for alias in &moving {
+        check_blocked_command(&state, &body.to, &alias.command)?;
+    }
+
+    let outcome = state
+        .db
+        .reassign_aliases(&body.from, &body.to, &machine.machine_id)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    if !outcome.moved.is_empty() {
+        state.aliases_changed();
+        state
+            .hub
+            .broadcast_to_groups(
+                &state.db,
+                &[body.from.clone(), body.to.clone()],
+                "sync_required",
+                serde_json::json!({ "message": "Aliases reassigned", "count": outcome.moved.len() }),
+                None,
+            )
+            .await;
+    }
+    Ok(Json(outcome))
 }
 
-/// GET /api/machines
-pub async fn get_machines(
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// GET /api/audit (admin)
+pub async fn get_audit_log(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
+    Query(query): Query<AuditQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let _machine = authenticate(&headers, &state.db)?;
-    let machines = state
+    authenticate_admin(&headers, &state.config)?;
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let offset = query.offset.unwrap_or(0).max(0);
+    let entries = state
         .db
-        .get_all_machines()
+        .get_auth_log(limit, offset)
         .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
-
-    // Hide auth tokens
-    let sanitized: Vec<serde_json::Value> = machines
+    let total = state
+        .db
+        .get_auth_log_count()
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    Ok(Json(serde_json::json!({
+        "entries": entries,
+        "limit": limit,
+        "offset": offset,
+        "total": total,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub action: Option<String>,
+    pub machine_id: Option<String>,
+}
+
+/// GET /api/history
+pub async fn get_history(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<HistoryQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let _machine = authenticate(&headers, &state.db)?;
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let offset = query.offset.unwrap_or(0).max(0);
+    let history = state
+        .db
+        .get_history_filtered(
+            limit,
+            offset,
+            query.action.as_deref(),
+            query.machine_id.as_deref(),
+        )
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    let count = history.len();
+    Ok(Json(serde_json::json!({
+        "history": history,
+        "count": count,
+        "limit": limit,
+        "offset": offset,
+    })))
+}
+
+/// GET /api/groups
+///
+/// Only the groups the calling machine belongs to.
+pub async fn get_groups(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let machine = authenticate(&headers, &state.db)?;
+    let mut groups = state
+        .db
+        .list_groups()
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    groups.retain(|g| machine.groups.contains(&g.name));
+    Ok(Json(serde_json::json!({ "groups": groups })))
+}
+
+/// POST /api/groups
+pub async fn create_group(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(body): Json<CreateGroupRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let _machine = authenticate(&headers, &state.db)?;
+
+    let valid_name = !body.name.is_empty()
+        && body
+            .name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '-');
+    if !valid_name {
+        return Err(err(
+            StatusCode::BAD_REQUEST,
+            "Invalid group name. Use only letters, numbers, underscore, dot, and dash.",
+        ));
+    }
+
+    let created = state
+        .db
+        .create_group(&body.name)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    if !created {
+        return Err(err(
+            StatusCode::CONFLICT,
+            &format!("Group '{}' already exists", body.name),
+        ));
+    }
+
+    Ok(Json(serde_json::json!({
+        "message": "Group created successfully",
+        "name": body.name,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct DeleteGroupQuery {
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// DELETE /api/groups/:name?force=
+///
+/// Allowed to members of the group with write access, or with the admin
+/// token (for groups nobody belongs to any more).
+pub async fn delete_group(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Query(query): Query<DeleteGroupQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let caller = if authenticate_admin(&headers, &state.config).is_ok() {
+        None
+    } else {
+        let machine = authenticate(&headers, &state.db)?;
+        if !machine.groups.contains(&name) {
+            return Err(err(
+                StatusCode::FORBIDDEN,
+                &format!("Machine does not belong to group '{}'", name),
+            ));
+        }
+        require_write(&machine, &name)?;
+        Some(machine.machine_id)
+    };
+
+    let members = state
+        .db
+        .get_machines_by_group(&name)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+
+    let outcome = state
+        .db
+        .delete_group(&name, query.force)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+
+    match outcome {
+        GroupDeletion::NotFound => Err(err(StatusCode::NOT_FOUND, "Group not found")),
+        GroupDeletion::NotEmpty { aliases, machines } => Err(err(
+            StatusCode::CONFLICT,
+            &format!(
+                "Group '{}' still has {} alias(es) and {} machine(s); use force to delete anyway",
+                name, aliases, machines
+            ),
+        )),
+        GroupDeletion::Deleted { aliases_removed } => {
+            if aliases_removed > 0 {
+                state.aliases_changed();
+            }
+            // Former members no longer see the group; have them drop its aliases.
+            let msg = serde_json::json!({ "event": "sync_required", "data": { "group": name } })
+                .to_string();
+            for m in members
+                .iter()
+                .filter(|m| Some(&m.machine_id) != caller.as_ref())
+            {
+                state.hub.send_to_machine(&m.machine_id, &msg).await;
+            }
+            Ok(Json(serde_json::json!({
+                "message": "Group deleted successfully",
+                "aliases_removed": aliases_removed,
+            })))
+        }
+    }
+}
+
+/// GET /api/machines
+pub async fn get_machines(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let _machine = authenticate(&headers, &state.db)?;
+    let machines = state
+        .db
+        .get_all_machines()
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+    let day_ago = chrono::Utc::now().timestamp_millis() - 24 * 3600 * 1000;
+    let activity = state
+        .db
+        .machine_activity(day_ago)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+
+    // Hide auth tokens
+    let sanitized: Vec<serde_json::Value> = machines
         .iter()
         .map(|m| {
+            let activity = activity.get(&m.machine_id).copied().unwrap_or_default();
             serde_json::json!({
                 "id": m.id,
                 "machine_id": m.machine_id,
                 "hostname": m.hostname,
                 "groups": m.groups,
+                "read_only_groups": m.read_only_groups,
                 "os_type": m.os_type,
                 "auth_token": "***",
                 "last_seen": m.last_seen,
                 "created_at": m.created_at,
+                "recent_commands": activity.recent_commands,
+                "last_command_at": activity.last_command_at,
             })
         })
         .collect();
@@ -496,12 +1313,59 @@ pub async fn get_machines(
     ))
 }
 
-/// POST /api/git/sync
+/// PATCH /api/machines/:machine_id (admin)
+///
+/// Sets the machine's per-group permissions: `read` members can list a
+/// group's aliases but not add, change or delete them.
+pub async fn update_machine(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(machine_id): Path<String>,
+    Json(body): Json<UpdateMachineRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    authenticate_admin(&headers, &state.config)?;
+
+    let mut machine = state
+        .db
+        .get_machine_by_id(&machine_id)
+        .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
+        .ok_or_else(|| err(StatusCode::NOT_FOUND, "Machine not found"))?;
+    for (group, permission) in &body.permissions {
+        machine = state
+            .db
+            .set_group_permission(&machine_id, group, *permission)
+            .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?
+            .ok_or_else(|| err(StatusCode::NOT_FOUND, "Machine not found"))?;
+    }
+
+    Ok(Json(serde_json::json!({
+        "machine_id": machine.machine_id,
+        "hostname": machine.hostname,
+        "groups": machine.groups,
+        "read_only_groups": machine.read_only_groups,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct GitSyncQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// POST /api/git/sync?dry_run=
 pub async fn force_git_sync(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
+    Query(query): Query<GitSyncQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     let _machine = authenticate(&headers, &state.db)?;
+    if query.dry_run {
+        let plan = state
+            .git_backup
+            .sync_to_git_plan()
+            .map_err(|e| err(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+        return Ok(Json(serde_json::json!({ "plan": plan })));
+    }
     state
         .git_backup
         .force_sync()
@@ -516,12 +1380,69 @@ pub struct ShellHistoryQuery {
     pub limit: Option<i64>,
 }
 
+const NDJSON: &str = "application/x-ndjson";
+
+fn wants_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains(NDJSON))
+}
+
+/// Stream history entries as newline-delimited JSON, one entry per line, as
+/// they are read from the database.
+///
+/// The response is returned before the rows are read, so the route's deadline
+/// layer never sees the query; `deadline` bounds it here instead, cutting the
+/// stream short once it passes. A zero deadline never expires.
+fn stream_shell_history(
+    db: Arc<SyncDatabase>,
+    after: i64,
+    group: String,
+    limit: i64,
+    deadline: Duration,
+) -> Response {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Bytes>(64);
+    let until = std::time::Instant::now() + deadline;
+
+    tokio::task::spawn_blocking(move || {
+        let produce = || {
+            db.for_each_history_after_timestamp(after, &group, limit, |entry| {
+                let Ok(mut line) = serde_json::to_vec(&entry) else {
+                    return false;
+                };
+                line.push(b'\n');
+                // A send error means the client went away.
+                tx.blocking_send(Bytes::from(line)).is_ok()
+            })
+        };
+        let result = if deadline.is_zero() {
+            produce()
+        } else {
+            with_query_deadline(until, produce)
+        };
+        if let Err(e) = result {
+            error!("Streaming shell history failed: {e}");
+        }
+    });
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|line| (Ok::<_, std::convert::Infallible>(line), rx))
+    });
+    ([(header::CONTENT_TYPE, NDJSON)], Body::from_stream(stream)).into_response()
+}
+
 /// GET /api/shell-history
+///
+/// With `Accept: application/x-ndjson` the page is streamed one entry per line
+/// instead of returned as a single JSON object.
 pub async fn get_shell_history(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
     Query(query): Query<ShellHistoryQuery>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
     let machine = authenticate(&headers, &state.db)?;
     let after = query.after_timestamp.unwrap_or(0);
     let group = query.group.as_deref().unwrap_or("default");
@@ -534,6 +1455,16 @@ pub async fn get_shell_history(
         ));
     }
 
+    if wants_ndjson(&headers) {
+        return Ok(stream_shell_history(
+            Arc::clone(&state.db),
+            after,
+            group.to_string(),
+            limit,
+            Duration::from_secs(state.config.slow_request_timeout_secs),
+        ));
+    }
+
     let entries = state
         .db
         .get_history_after_timestamp(after, group, limit)
@@ -546,7 +1477,49 @@ pub async fn get_shell_history(
         "entries": entries,
         "count": count,
         "has_more": has_more,
-    })))
+    }))
+    .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    pub token: Option<String>,
+}
+
+/// GET /api/events — Server-Sent Events carrying the group broadcasts
+/// (`alias_added`, `sync_required`, ...) WebSocket clients receive, for
+/// networks whose proxies block WebSockets. `EventSource` cannot set headers,
+/// so the token may be passed as `?token=` instead.
+///
+/// The stream is one-way: history is still pushed through the REST API.
+pub async fn events(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<EventsQuery>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let token = query.token.as_deref().or(bearer_token(&headers));
+    let machine = authenticate_token(token, &headers, &state.db, "sse")?;
+
+    let subscription = state.hub.subscribe();
+    let stream = futures_util::stream::unfold(
+        (subscription, machine.machine_id),
+        |(mut subscription, machine_id)| async move {
+            let event = match subscription.recv(&machine_id).await? {
+                HubMessage::Event { event, message } => {
+                    Event::default().event(event).data(message)
+                }
+                HubMessage::Missed => {
+                    let resync = serde_json::json!({
+                        "event": "sync_required",
+                        "data": { "reason": "lagged" }
+                    });
+                    Event::default().event("sync_required").data(resync.to_string())
+                }
+            };
+            Some((Ok::<_, std::convert::Infallible>(event), (subscription, machine_id)))
+        },
+    );
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()).into_response())
 }
 
 #[cfg(test)]
@@ -607,6 +1580,10 @@ mod tests {
     }
 
     async fn test_app() -> (axum::Router, tempfile::TempDir) {
+        test_app_with_config(ServerConfig::default()).await
+    }
+
+    async fn test_app_with_config(config: ServerConfig) -> (axum::Router, tempfile::TempDir) {
         let dir = tempfile::tempdir().unwrap();
         let db = Arc::new(
             shell_sync_core::db::SyncDatabase::open(dir.path().join("test.db").to_str().unwrap())
@@ -621,6 +1598,9 @@ mod tests {
             db,
             hub,
             git_backup,
+            blocklist: CommandBlocklist::from_config(&config).unwrap(),
+            alias_cache: Default::default(),
+            config,
         });
         (build_router(state), dir)
     }
@@ -657,6 +1637,66 @@ mod tests {
         (token, id)
     }
 
+    #[tokio::test]
+    async fn events_stream_group_broadcasts() {
+        let (app, _dir) = test_app().await;
+        let watcher = do_register(&app, "laptop", &["default"]).await;
+        let writer = do_register(&app, "desktop", &["default"]).await;
+
+        let resp = app.clone().oneshot(get("/api/events")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let uri = format!("/api/events?token={watcher}");
+        let resp = app.clone().oneshot(get(&uri)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "text/event-stream");
+        let mut body = resp.into_body();
+
+        let alias = serde_json::json!({
+            "name": "gs", "command": "git status", "group": "default",
+        });
+        app.clone()
+            .oneshot(post_json_auth("/api/aliases", &writer, &alias))
+            .await
+            .unwrap();
+
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+            .await
+            .expect("no event within 5s")
+            .unwrap()
+            .unwrap();
+        let text = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+        assert!(text.starts_with("event: alias_added\ndata: "), "{text}");
+        assert!(text.contains("git status"));
+    }
+
+    #[tokio::test]
+    async fn version_lists_crate_version_and_features() {
+        let (app, _dir) = test_app().await;
+        let resp = app.oneshot(get("/api/version")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        let listed: Vec<&str> = json["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f.as_str().unwrap())
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                "import_dry_run",
+                "alias_shells",
+                "key_exchange",
+                "conflict_strategies",
+                "conflict_paging"
+            ]
+        );
+        assert!(!listed.contains(&features::ENCRYPTED_ALIASES));
+        assert!(!listed.contains(&features::ENCRYPTED_HISTORY));
+    }
+
     #[tokio::test]
     async fn health_200() {
         let (app, _dir) = test_app().await;
@@ -691,6 +1731,33 @@ mod tests {
         assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
     }
 
+    #[tokio::test]
+    async fn alias_list_reflects_writes_after_cached_read() {
+        let (app, _dir) = test_app().await;
+        let (token, id) = setup_with_alias(&app).await;
+        let list = |app: axum::Router| {
+            let req = get_auth("/api/aliases", &token);
+            async move { body_json(app.oneshot(req).await.unwrap()).await["aliases"].clone() }
+        };
+        assert_eq!(list(app.clone()).await.as_array().unwrap().len(), 1);
+
+        let body = serde_json::json!({ "name": "ll", "command": "ls -la", "group": "default" });
+        app.clone()
+            .oneshot(post_json_auth("/api/aliases", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(list(app.clone()).await.as_array().unwrap().len(), 2);
+
+        let body = serde_json::json!({ "command": "git status -sb" });
+        let uri = format!("/api/aliases/{id}");
+        app.clone()
+            .oneshot(put_json_auth(&uri, &token, &body))
+            .await
+            .unwrap();
+        let aliases = list(app.clone()).await;
+        assert_eq!(aliases[0]["command"], "git status -sb");
+    }
+
     #[tokio::test]
     async fn get_aliases_requires_auth() {
         let (app, _dir) = test_app().await;
@@ -770,6 +1837,48 @@ mod tests {
         assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
     }
 
+    #[tokio::test]
+    async fn add_alias_blocked_command_rejected() {
+        let config = ServerConfig {
+            blocked_command_patterns: vec![r"rm\s+-rf\s+/(\s|$)".into()],
+            group_blocked_command_patterns: [("team".to_string(), vec![r"^sudo\b".to_string()])]
+                .into(),
+            ..Default::default()
+        };
+        let (app, _dir) = test_app_with_config(config).await;
+        let token = do_register(&app, "test-host", &["default", "team"]).await;
+
+        let add = |name: &str, command: &str, group: &str| {
+            let body = serde_json::json!({ "name": name, "command": command, "group": group });
+            app.clone()
+                .oneshot(post_json_auth("/api/aliases", &token, &body))
+        };
+        let resp = add("nuke", "rm -rf /", "default").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let json = body_json(resp).await;
+        assert_eq!(json["code"], "blocked_command");
+        assert_eq!(json["pattern"], r"rm\s+-rf\s+/(\s|$)");
+
+        let resp = add("up", "sudo apt upgrade", "team").await.unwrap();
+        assert_eq!(body_json(resp).await["code"], "blocked_command");
+
+        // Group patterns only apply to their group; safe commands pass
+        let resp = add("up", "sudo apt upgrade", "default").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = add("clean", "rm -rf ./build", "team").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let id = body_json(resp).await["alias"]["id"].as_i64().unwrap();
+
+        let body = serde_json::json!({ "command": "sudo rm -rf ./build" });
+        let resp = app
+            .clone()
+            .oneshot(put_json_auth(&format!("/api/aliases/{id}"), &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(body_json(resp).await["code"], "blocked_command");
+    }
+
     #[tokio::test]
     async fn add_alias_wrong_group_403() {
         let (app, _dir) = test_app().await;
@@ -804,6 +1913,44 @@ mod tests {
         assert_eq!(resp.status(), StatusCode::CONFLICT);
     }
 
+    #[tokio::test]
+    async fn alias_shells_are_stored_validated_and_updated() {
+        let (app, _dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        let body = serde_json::json!({
+            "name": "gs", "command": "git status", "group": "default", "shells": ["zsh"],
+        });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/aliases", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["alias"]["shells"], serde_json::json!(["zsh"]));
+        let id = json["alias"]["id"].as_i64().unwrap();
+
+        let bad = serde_json::json!({
+            "name": "ll", "command": "ls -la", "group": "default", "shells": ["tcsh"],
+        });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/aliases", &token, &bad))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(body_json(resp).await["error"].as_str().unwrap().contains("tcsh"));
+
+        // Leaving `shells` out keeps them; an empty list clears them
+        let uri = format!("/api/aliases/{id}");
+        let keep = serde_json::json!({ "command": "git status -sb" });
+        let resp = app.clone().oneshot(put_json_auth(&uri, &token, &keep)).await.unwrap();
+        assert_eq!(body_json(resp).await["alias"]["shells"], serde_json::json!(["zsh"]));
+        let clear = serde_json::json!({ "command": "git status -sb", "shells": [] });
+        let resp = app.clone().oneshot(put_json_auth(&uri, &token, &clear)).await.unwrap();
+        assert_eq!(body_json(resp).await["alias"]["shells"], serde_json::json!([]));
+    }
+
     #[tokio::test]
     async fn update_alias_success() {
         let (app, _dir) = test_app().await;
@@ -823,109 +1970,759 @@ mod tests {
         assert_eq!(json["alias"]["command"], "git status -sb");
     }
 
-    #[tokio::test]
-    async fn delete_alias_success() {
-        let (app, _dir) = test_app().await;
-        let (token, alias_id) = setup_with_alias(&app).await;
+    /// Two machines edit the same alias from version 1: the first update lands,
+    /// the second is stale. Returns the second response and the db for inspection.
+    async fn competing_updates(
+        strategy: ConflictStrategy,
+        second_updated_at: Option<i64>,
+    ) -> (StatusCode, serde_json::Value, SyncDatabase, tempfile::TempDir) {
+        let config = ServerConfig {
+            conflict_strategy: strategy,
+            ..Default::default()
+        };
+        let (app, dir) = test_app_with_config(config).await;
+        let (token_a, alias_id) = setup_with_alias(&app).await;
+        let token_b = do_register(&app, "other-host", &["default"]).await;
+        let uri = format!("/api/aliases/{}", alias_id);
+
+        let first = serde_json::json!({ "command": "git status -sb", "expected_version": 1 });
         let resp = app
             .clone()
-            .oneshot(delete_auth(&format!("/api/aliases/{}", alias_id), &token))
+            .oneshot(put_json_auth(&uri, &token_a, &first))
             .await
             .unwrap();
         assert_eq!(resp.status(), StatusCode::OK);
-    }
 
-    #[tokio::test]
-    async fn delete_alias_not_found() {
-        let (app, _dir) = test_app().await;
-        let token = do_register(&app, "test-host", &["default"]).await;
+        let mut second = serde_json::json!({ "command": "git status --short", "expected_version": 1 });
+        if let Some(ts) = second_updated_at {
+            second["updated_at"] = ts.into();
+        }
         let resp = app
             .clone()
-            .oneshot(delete_auth("/api/aliases/99999", &token))
+            .oneshot(put_json_auth(&uri, &token_b, &second))
             .await
             .unwrap();
-        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let status = resp.status();
+        let json = body_json(resp).await;
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        (status, json, db, dir)
     }
 
     #[tokio::test]
-    async fn import_aliases_success() {
-        let (app, _dir) = test_app().await;
-        let token = do_register(&app, "test-host", &["default"]).await;
-        let body = serde_json::json!({
-            "aliases": [
-                { "name": "gs", "command": "git status" },
-                { "name": "gl", "command": "git log --oneline" },
-                { "name": "gp", "command": "git push" },
-            ],
-            "group": "default",
-        });
-        let resp = app
-            .clone()
-            .oneshot(post_json_auth("/api/import", &token, &body))
-            .await
-            .unwrap();
-        assert_eq!(resp.status(), StatusCode::OK);
-        let json = body_json(resp).await;
-        assert_eq!(json["added"], 3);
-        assert_eq!(json["failed"], 0);
-        assert_eq!(json["results"]["added"].as_array().unwrap().len(), 3);
+    async fn stale_update_manual_creates_pending_conflict() {
+        let (status, json, db, _dir) = competing_updates(ConflictStrategy::Manual, None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let conflict = db.get_conflict(json["conflict_id"].as_i64().unwrap()).unwrap().unwrap();
+        assert!(!conflict.resolved);
+        assert_eq!(conflict.local_command, "git status -sb");
+        assert_eq!(conflict.remote_command, "git status --short");
+        let alias = db.get_alias_by_name("gs", "default").unwrap().unwrap();
+        assert_eq!(alias.command, "git status -sb");
     }
 
     #[tokio::test]
-    async fn import_aliases_partial_duplicates() {
-        let (app, _dir) = test_app().await;
+    async fn conflicts_are_paged_with_a_total() {
+        let (app, dir) = test_app().await;
         let token = do_register(&app, "test-host", &["default"]).await;
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let machine_id = db.get_machine_by_token(&token).unwrap().unwrap().machine_id;
+        for i in 0..120 {
+            db.create_conflict(&format!("a{i:03}"), "default", "x", "y", &machine_id)
+                .unwrap();
+        }
 
-        // Pre-add one alias so it becomes a duplicate during import
+        let page = |uri: &'static str| {
+            let app = app.clone();
+            let token = token.clone();
+            async move { body_json(app.oneshot(get_auth(uri, &token)).await.unwrap()).await }
+        };
+        let names = |json: &serde_json::Value| -> Vec<String> {
+            json["conflicts"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| c["alias_name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        // Default page is capped at 100, newest first
+        let first = page("/api/conflicts").await;
+        assert_eq!(first["count"], 100);
+        assert_eq!(first["total"], 120);
+        assert_eq!(names(&first)[0], "a119");
+        assert_eq!(names(&first)[99], "a020");
+
+        let last = page("/api/conflicts?limit=50&offset=100").await;
+        assert_eq!(last["count"], 20);
+        assert_eq!(last["total"], 120);
+        assert_eq!(names(&last).first().unwrap(), "a019");
+        assert_eq!(names(&last).last().unwrap(), "a000");
+
+        let past_end = page("/api/conflicts?limit=50&offset=150").await;
+        assert_eq!(past_end["count"], 0);
+        assert_eq!(past_end["total"], 120);
+    }
+
+    #[tokio::test]
+    async fn conflicts_include_resolved_and_purge() {
+        let (app, dir) = test_app().await;
+        let (token, _) = setup_with_alias(&app).await;
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let machine_id = db.get_machine_by_token(&token).unwrap().unwrap().machine_id;
+        db.create_conflict("gs", "default", "a", "b", &machine_id).unwrap();
+        db.record_resolved_conflict("gs", "default", "a", "c", &machine_id, "keep_local")
+            .unwrap();
+        let other = db
+            .record_resolved_conflict("gs", "default", "a", "d", "other-machine", "keep_local")
+            .unwrap();
+
+        let resp = app.clone().oneshot(get_auth("/api/conflicts", &token)).await.unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["count"], 1);
+        assert_eq!(json["counts"], serde_json::json!({ "open": 1, "resolved": 1 }));
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/conflicts?include_resolved=true", &token))
+            .await
+            .unwrap();
+        assert_eq!(body_json(resp).await["count"], 2);
+
+        // Just resolved, so a 30 day cutoff keeps it.
+        let month = serde_json::json!({ "older_than_ms": 30 * 86_400_000i64 });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/conflicts/purge", &token, &month))
+            .await
+            .unwrap();
+        assert_eq!(body_json(resp).await["deleted"], 0);
+
+        let now = serde_json::json!({ "older_than_ms": 0 });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/conflicts/purge", &token, &now))
+            .await
+            .unwrap();
+        assert_eq!(body_json(resp).await["deleted"], 1);
+        let counts = db.get_conflict_counts(&machine_id).unwrap();
+        assert_eq!((counts.open, counts.resolved), (1, 0));
+        assert!(db.get_conflict(other).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn stale_update_last_write_wins_applies_newer_edit() {
+        let (status, json, db, _dir) =
+            competing_updates(ConflictStrategy::LastWriteWins, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["resolution"], "last_write_wins:applied");
+        let conflict = db.get_conflict(json["conflict_id"].as_i64().unwrap()).unwrap().unwrap();
+        assert!(conflict.resolved);
+        let alias = db.get_alias_by_name("gs", "default").unwrap().unwrap();
+        assert_eq!(alias.command, "git status --short");
+        assert_eq!(alias.version, 3);
+    }
+
+    #[tokio::test]
+    async fn stale_update_last_write_wins_rejects_older_edit() {
+        let (status, json, db, _dir) =
+            competing_updates(ConflictStrategy::LastWriteWins, Some(1)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(json["resolution"], "last_write_wins:rejected");
+        let alias = db.get_alias_by_name("gs", "default").unwrap().unwrap();
+        assert_eq!(alias.command, "git status -sb");
+    }
+
+    #[tokio::test]
+    async fn stale_update_first_write_wins_keeps_first_edit() {
+        let (status, json, db, _dir) =
+            competing_updates(ConflictStrategy::FirstWriteWins, None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(json["resolution"], "first_write_wins:rejected");
+        let conflict = db.get_conflict(json["conflict_id"].as_i64().unwrap()).unwrap().unwrap();
+        assert!(conflict.resolved);
+        assert_eq!(conflict.remote_command, "git status --short");
+        let alias = db.get_alias_by_name("gs", "default").unwrap().unwrap();
+        assert_eq!(alias.command, "git status -sb");
+        assert_eq!(alias.version, 2);
+    }
+
+    async fn audit_entries(app: &axum::Router, admin: &str) -> Vec<serde_json::Value> {
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/audit", admin))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        body_json(resp).await["entries"].as_array().unwrap().clone()
+    }
+
+    fn admin_config() -> ServerConfig {
+        ServerConfig {
+            admin_token: Some("admin-secret".into()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn read_only_member_can_list_but_not_change_group_aliases() {
+        let (app, _dir) = test_app_with_config(admin_config()).await;
+        let owner = do_register(&app, "owner", &["prod"]).await;
+        let viewer = do_register(&app, "viewer", &["prod"]).await;
+        let add = |name: &str| {
+            serde_json::json!({ "name": name, "command": "make deploy", "group": "prod" })
+        };
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/aliases", &owner, &add("deploy")))
+            .await
+            .unwrap();
+        let alias_id = body_json(resp).await["alias"]["id"].as_i64().unwrap();
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/machines", &owner))
+            .await
+            .unwrap();
+        let machines = body_json(resp).await["machines"]
+            .as_array()
+            .unwrap()
+            .clone();
+        let viewer_id = machines.iter().find(|m| m["hostname"] == "viewer").unwrap()["machine_id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let set_permission = |token: &str, permission: &str| {
+            Request::patch(format!("/api/machines/{viewer_id}"))
+                .header("content-type", "application/json")
+                .header("authorization", auth_header(token))
+                .body(Body::from(
+                    serde_json::json!({ "permissions": { "prod": permission } }).to_string(),
+                ))
+                .unwrap()
+        };
+
+        let resp = app
+            .clone()
+            .oneshot(set_permission(&owner, "read"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = app
+            .clone()
+            .oneshot(set_permission("admin-secret", "read"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            body_json(resp).await["read_only_groups"],
+            serde_json::json!(["prod"])
+        );
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases", &viewer))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_json(resp).await["aliases"][0]["name"], "deploy");
+
+        let update = serde_json::json!({ "command": "make release" });
+        let denied = [
+            post_json_auth("/api/aliases", &viewer, &add("release")),
+            put_json_auth(&format!("/api/aliases/{alias_id}"), &viewer, &update),
+            delete_auth(&format!("/api/aliases/{alias_id}"), &viewer),
+            delete_auth("/api/aliases/name/deploy?group=prod", &viewer),
+            delete_auth("/api/groups/prod?force=true", &viewer),
+        ];
+        for req in denied {
+            let resp = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+            assert!(body_json(resp).await["error"]
+                .as_str()
+                .unwrap()
+                .contains("read-only"));
+        }
+
+        let resp = app
+            .clone()
+            .oneshot(set_permission("admin-secret", "write"))
+            .await
+            .unwrap();
+        assert_eq!(
+            body_json(resp).await["read_only_groups"],
+            serde_json::json!([])
+        );
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/aliases", &viewer, &add("release")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn successful_auth_is_logged() {
+        let (app, _dir) = test_app_with_config(admin_config()).await;
+        let app = app.layer(axum::extract::connect_info::MockConnectInfo(
+            SocketAddr::from(([10, 1, 2, 3], 5555)),
+        ));
+        let token = do_register(&app, "test-host", &["default"]).await;
+        let req = Request::get("/api/aliases")
+            .header("authorization", auth_header(&token))
+            .header(PEER_IP_HEADER, "6.6.6.6")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let entries = audit_entries(&app, "admin-secret").await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["result"], "success");
+        assert_eq!(entries[0]["source"], "http");
+        assert!(entries[0]["machine_id"].is_string());
+        assert!(entries[0]["token_hash"].is_null());
+        // The peer address wins over a spoofed header
+        assert_eq!(entries[0]["client_ip"], "10.1.2.3");
+    }
+
+    #[tokio::test]
+    async fn failed_auth_logs_token_hash_only() {
+        let (app, _dir) = test_app_with_config(admin_config()).await;
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases", "stolen-token-value"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let entries = audit_entries(&app, "admin-secret").await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["result"], "failure");
+        assert!(entries[0]["machine_id"].is_null());
+        assert_eq!(
+            entries[0]["token_hash"],
+            token_fingerprint("stolen-token-value")
+        );
+        assert!(!serde_json::to_string(&entries).unwrap().contains("stolen-token-value"));
+    }
+
+    #[tokio::test]
+    async fn pin_and_unpin_alias() {
+        let (app, _dir) = test_app().await;
+        let (token, _id) = setup_with_alias(&app).await;
+
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth(
+                "/api/aliases/name/gs/pin?group=default",
+                &token,
+                &serde_json::json!({}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_json(resp).await["alias"]["pinned"], true);
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases", &token))
+            .await
+            .unwrap();
+        assert_eq!(body_json(resp).await["aliases"][0]["pinned"], true);
+
+        let resp = app
+            .clone()
+            .oneshot(delete_auth("/api/aliases/name/gs/pin", &token))
+            .await
+            .unwrap();
+        assert_eq!(body_json(resp).await["alias"]["pinned"], false);
+
+        let resp = app
+            .oneshot(delete_auth("/api/aliases/name/nope/pin", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn sync_history_filters_and_pages() {
+        let (app, _dir) = test_app().await;
+        let (token, id) = setup_with_alias(&app).await;
+        let body = serde_json::json!({ "name": "ll", "command": "ls -l", "group": "default" });
+        app.clone()
+            .oneshot(post_json_auth("/api/aliases", &token, &body))
+            .await
+            .unwrap();
+        app.clone()
+            .oneshot(delete_auth(&format!("/api/aliases/{id}"), &token))
+            .await
+            .unwrap();
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/history?action=add", &token))
+            .await
+            .unwrap();
+        let json = body_json(resp).await;
+        let names: Vec<_> = json["history"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|h| h["alias_name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["ll", "gs"]);
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/history?limit=1&offset=1", &token))
+            .await
+            .unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["count"], 1);
+        assert_eq!(json["offset"], 1);
+        assert_eq!(json["history"][0]["alias_name"], "ll");
+    }
+
+    #[tokio::test]
+    async fn audit_log_pagination() {
+        let (app, _dir) = test_app_with_config(admin_config()).await;
+        for i in 0..3 {
+            app.clone()
+                .oneshot(get_auth("/api/aliases", &format!("bad-{i}")))
+                .await
+                .unwrap();
+        }
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/audit?limit=2&offset=2", "admin-secret"))
+            .await
+            .unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["total"], 3);
+        let entries = json["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["token_hash"], token_fingerprint("bad-0"));
+    }
+
+    #[tokio::test]
+    async fn audit_log_requires_admin() {
+        let (app, _dir) = test_app().await;
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/audit", "anything"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let (app, _dir) = test_app_with_config(admin_config()).await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/audit", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn get_alias_by_name_hit() {
+        let (app, _dir) = test_app().await;
+        let (token, alias_id) = setup_with_alias(&app).await;
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases/name/gs?group=default", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["alias"]["id"], alias_id);
+        assert_eq!(json["alias"]["command"], "git status");
+    }
+
+    #[tokio::test]
+    async fn get_alias_by_name_miss_404() {
+        let (app, _dir) = test_app().await;
+        let (token, _) = setup_with_alias(&app).await;
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases/name/nope", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn get_alias_by_name_wrong_group_403() {
+        let (app, _dir) = test_app().await;
+        let (token, _) = setup_with_alias(&app).await;
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases/name/gs?group=secret", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn delete_alias_success() {
+        let (app, _dir) = test_app().await;
+        let (token, alias_id) = setup_with_alias(&app).await;
+        let resp = app
+            .clone()
+            .oneshot(delete_auth(&format!("/api/aliases/{}", alias_id), &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn queued_add_older_than_delete_is_rejected() {
+        let (app, _dir) = test_app().await;
+        let (token, alias_id) = setup_with_alias(&app).await;
+        let queued_at = chrono::Utc::now().timestamp_millis() - 60_000;
+        app.clone()
+            .oneshot(delete_auth(&format!("/api/aliases/{}", alias_id), &token))
+            .await
+            .unwrap();
+
+        let stale = serde_json::json!({
+            "name": "gs", "command": "git status", "group": "default", "created_at": queued_at,
+        });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/aliases", &token, &stale))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases", &token))
+            .await
+            .unwrap();
+        assert_eq!(body_json(resp).await["count"], 0);
+
+        // Re-adding it by hand is still allowed.
+        let fresh = serde_json::json!({ "name": "gs", "command": "git status -sb" });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/aliases", &token, &fresh))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn delete_alias_not_found() {
+        let (app, _dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        let resp = app
+            .clone()
+            .oneshot(delete_auth("/api/aliases/99999", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn import_aliases_success() {
+        let (app, _dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        let body = serde_json::json!({
+            "aliases": [
+                { "name": "gs", "command": "git status" },
+                { "name": "gl", "command": "git log --oneline" },
+                { "name": "gp", "command": "git push" },
+            ],
+            "group": "default",
+        });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/import", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["added"], 3);
+        assert_eq!(json["failed"], 0);
+        assert_eq!(json["results"]["added"].as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn import_aliases_partial_duplicates() {
+        let (app, _dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+
+        // Pre-add one alias so it becomes a duplicate during import
+        let pre = serde_json::json!({ "name": "gs", "command": "git status", "group": "default" });
+        app.clone()
+            .oneshot(post_json_auth("/api/aliases", &token, &pre))
+            .await
+            .unwrap();
+
+        // Import 3 aliases: gs (dup), gl (new), gp (new)
+        let body = serde_json::json!({
+            "aliases": [
+                { "name": "gs", "command": "git status" },
+                { "name": "gl", "command": "git log --oneline" },
+                { "name": "gp", "command": "git push" },
+            ],
+            "group": "default",
+        });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/import", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["added"], 2);
+        assert_eq!(json["failed"], 1);
+
+        // The failed entry should name the duplicate
+        let failed = json["results"]["failed"].as_array().unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0]["name"], "gs");
+        assert!(failed[0]["error"]
+            .as_str()
+            .unwrap()
+            .contains("already exists"));
+    }
+
+    #[tokio::test]
+    async fn import_dry_run_reports_collisions_and_inserts_nothing() {
+        let (app, dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
         let pre = serde_json::json!({ "name": "gs", "command": "git status", "group": "default" });
         app.clone()
             .oneshot(post_json_auth("/api/aliases", &token, &pre))
             .await
             .unwrap();
 
-        // Import 3 aliases: gs (dup), gl (new), gp (new)
-        let body = serde_json::json!({
-            "aliases": [
-                { "name": "gs", "command": "git status" },
-                { "name": "gl", "command": "git log --oneline" },
-                { "name": "gp", "command": "git push" },
-            ],
+        let body = serde_json::json!({
+            "aliases": [
+                { "name": "gs", "command": "git status -sb" },
+                { "name": "gl", "command": "git log --oneline" },
+                { "name": "gl", "command": "git log" },
+                { "name": "db_password", "command": "echo hunter2" },
+            ],
+            "group": "default",
+        });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/import?dry_run=true", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["dry_run"], true);
+        assert_eq!(json["added"], 1);
+        assert_eq!(json["results"]["added"][0]["name"], "gl");
+        let failed: Vec<&str> = json["results"]["failed"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(failed, ["gs", "gl", "db_password"]);
+        assert!(json["results"]["failed"][0]["error"]
+            .as_str()
+            .unwrap()
+            .contains("already exists"));
+
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let names: Vec<String> = db
+            .get_aliases_by_groups(&["default".to_string()])
+            .unwrap()
+            .into_iter()
+            .map(|a| a.name)
+            .collect();
+        assert_eq!(names, ["gs"]);
+        assert_eq!(
+            db.get_alias_by_name("gs", "default").unwrap().unwrap().command,
+            "git status"
+        );
+    }
+
+    #[tokio::test]
+    async fn import_aliases_empty_list() {
+        let (app, _dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        let body = serde_json::json!({ "aliases": [], "group": "default" });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/import", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["added"], 0);
+        assert_eq!(json["failed"], 0);
+    }
+
+    #[tokio::test]
+    async fn import_body_over_limit_is_413() {
+        let (app, _dir) = test_app_with_config(ServerConfig {
+            max_bulk_body_bytes: 4096,
+            ..Default::default()
+        })
+        .await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+
+        let small = serde_json::json!({
+            "aliases": [{ "name": "gs", "command": "git status" }],
+            "group": "default",
+        });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/import", &token, &small))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let big = serde_json::json!({
+            "aliases": [{ "name": "big", "command": "x".repeat(5000) }],
             "group": "default",
         });
         let resp = app
             .clone()
-            .oneshot(post_json_auth("/api/import", &token, &body))
+            .oneshot(post_json_auth("/api/import", &token, &big))
             .await
             .unwrap();
-        assert_eq!(resp.status(), StatusCode::OK);
-        let json = body_json(resp).await;
-        assert_eq!(json["added"], 2);
-        assert_eq!(json["failed"], 1);
-
-        // The failed entry should name the duplicate
-        let failed = json["results"]["failed"].as_array().unwrap();
-        assert_eq!(failed.len(), 1);
-        assert_eq!(failed[0]["name"], "gs");
-        assert!(failed[0]["error"]
-            .as_str()
-            .unwrap()
-            .contains("already exists"));
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
     }
 
     #[tokio::test]
-    async fn import_aliases_empty_list() {
-        let (app, _dir) = test_app().await;
+    async fn register_has_its_own_smaller_limit() {
+        let (app, _dir) = test_app_with_config(ServerConfig {
+            max_register_body_bytes: 512,
+            ..Default::default()
+        })
+        .await;
+        let body = serde_json::json!({
+            "hostname": "h".repeat(1024),
+            "groups": ["default"],
+        });
+        let resp = app
+            .clone()
+            .oneshot(post_json("/api/register", &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // The same size is fine on routes with the general limit.
         let token = do_register(&app, "test-host", &["default"]).await;
-        let body = serde_json::json!({ "aliases": [], "group": "default" });
+        let alias = serde_json::json!({
+            "name": "long",
+            "command": "h".repeat(1024),
+            "group": "default",
+        });
         let resp = app
             .clone()
-            .oneshot(post_json_auth("/api/import", &token, &body))
+            .oneshot(post_json_auth("/api/aliases", &token, &alias))
             .await
             .unwrap();
-        assert_eq!(resp.status(), StatusCode::OK);
-        let json = body_json(resp).await;
-        assert_eq!(json["added"], 0);
-        assert_eq!(json["failed"], 0);
+        assert!(resp.status().is_success(), "{}", resp.status());
     }
 
     #[tokio::test]
@@ -974,6 +2771,35 @@ mod tests {
             .contains("secret"));
     }
 
+    #[tokio::test]
+    async fn import_aliases_rejects_blocked_commands() {
+        let config = ServerConfig {
+            blocked_command_patterns: vec![r"rm\s+-rf\s+/(\s|$)".into()],
+            ..Default::default()
+        };
+        let (app, _dir) = test_app_with_config(config).await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        let body = serde_json::json!({
+            "aliases": [
+                { "name": "gs", "command": "git status" },
+                { "name": "nuke", "command": "rm -rf / --no-preserve-root" },
+            ],
+            "group": "default",
+        });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/import", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["added"], 1);
+        assert_eq!(json["failed"], 1);
+        let failed = &json["results"]["failed"][0];
+        assert_eq!(failed["name"], "nuke");
+        assert_eq!(failed["code"], "blocked_command");
+    }
+
     #[tokio::test]
     async fn import_aliases_wrong_group_403() {
         let (app, _dir) = test_app().await;
@@ -1022,6 +2848,311 @@ mod tests {
         assert_eq!(json["failed"], 2);
     }
 
+    #[tokio::test]
+    async fn groups_list_with_counts() {
+        let (app, _dir) = test_app().await;
+        let (token, _) = setup_with_alias(&app).await;
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/groups", &token, &serde_json::json!({ "name": "ops" })))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let ops = do_register(&app, "ops-host", &["default", "ops"]).await;
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/groups", &ops))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        let groups = json["groups"].as_array().unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0]["name"], "default");
+        assert_eq!(groups[0]["alias_count"], 1);
+        assert_eq!(groups[0]["machine_count"], 2);
+        assert_eq!(groups[1]["name"], "ops");
+        assert_eq!(groups[1]["alias_count"], 0);
+
+        // Groups the caller is not in are not listed
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/groups", &token))
+            .await
+            .unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["groups"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn create_group_duplicate_409() {
+        let (app, _dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        let body = serde_json::json!({ "name": "ops" });
+        app.clone()
+            .oneshot(post_json_auth("/api/groups", &token, &body))
+            .await
+            .unwrap();
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/groups", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn delete_group_guard_and_force() {
+        let (app, _dir) = test_app_with_config(admin_config()).await;
+        let (token, _) = setup_with_alias(&app).await;
+
+        let resp = app
+            .clone()
+            .oneshot(delete_auth("/api/groups/default", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        let resp = app
+            .clone()
+            .oneshot(delete_auth("/api/groups/default?force=true", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_json(resp).await["aliases_removed"], 1);
+
+        // The machine left the group along with it
+        let resp = app
+            .clone()
+            .oneshot(delete_auth("/api/groups/default", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = app
+            .clone()
+            .oneshot(delete_auth("/api/groups/default", "admin-secret"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn delete_group_of_other_machines_403() {
+        let (app, _dir) = test_app().await;
+        let (_owner, _) = setup_with_alias(&app).await;
+        let outsider = do_register(&app, "other-host", &["work"]).await;
+        let resp = app
+            .clone()
+            .oneshot(delete_auth("/api/groups/default?force=true", &outsider))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn delete_group_without_members_needs_admin() {
+        let (app, _dir) = test_app_with_config(admin_config()).await;
+        let (owner, _) = setup_with_alias(&app).await;
+        // A group nobody belongs to
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/groups", &owner, &serde_json::json!({ "name": "ops" })))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = app
+            .clone()
+            .oneshot(delete_auth("/api/groups/ops", &owner))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = app
+            .clone()
+            .oneshot(delete_auth("/api/groups/ops", "admin-secret"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    fn seed_shell_history(dir: &tempfile::TempDir, count: usize) {
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        for i in 0..count {
+            db.insert_history_entry(&HistoryEntry {
+                timestamp: 1000 + i as i64,
+                ..crate::test_entry(&format!("h{i}"), &format!("echo {i}"))
+            })
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn shell_history_streams_ndjson() {
+        let (app, dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        seed_shell_history(&dir, 3);
+
+        let req = Request::get("/api/shell-history?group=default")
+            .header("authorization", auth_header(&token))
+            .header("accept", "application/x-ndjson")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "application/x-ndjson");
+
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        let entries: Vec<HistoryEntry> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].command, "echo 0");
+        assert_eq!(entries[2].timestamp, 1002);
+    }
+
+    #[tokio::test]
+    async fn shell_history_stream_stops_at_the_deadline() {
+        let dir = tempfile::tempdir().unwrap();
+        seed_shell_history(&dir, 500);
+        let db =
+            Arc::new(SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap());
+        let lines = |deadline| {
+            let db = Arc::clone(&db);
+            async move {
+                let resp = stream_shell_history(db, 0, "default".into(), 1000, deadline);
+                let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+                bytes.iter().filter(|&&b| b == b'\n').count()
+            }
+        };
+
+        assert_eq!(lines(Duration::ZERO).await, 500);
+        assert!(lines(Duration::from_nanos(1)).await < 500);
+    }
+
+    #[tokio::test]
+    async fn shell_history_defaults_to_json_object() {
+        let (app, dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        seed_shell_history(&dir, 2);
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/shell-history?group=default", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["count"], 2);
+    }
+
+    #[tokio::test]
+    async fn get_aliases_sorts_by_recent_update() {
+        let (app, _dir) = test_app().await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        for name in ["aa", "bb"] {
+            let body = serde_json::json!({ "name": name, "command": "ls", "group": "default" });
+            let resp = app
+                .clone()
+                .oneshot(post_json_auth("/api/aliases", &token, &body))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+
+        let names = |json: serde_json::Value| -> Vec<String> {
+            json["aliases"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|a| a["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases?sort=recent", &token))
+            .await
+            .unwrap();
+        assert_eq!(names(body_json(resp).await), ["bb", "aa"]);
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases", &token))
+            .await
+            .unwrap();
+        assert_eq!(names(body_json(resp).await), ["aa", "bb"]);
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases?sort=size", &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn orphaned_aliases_can_be_listed_and_reassigned() {
+        let (app, dir) = test_app_with_config(admin_config()).await;
+        let token = do_register(&app, "test-host", &["default"]).await;
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        db.add_alias("k", "kubectl", "old-team", "m0").unwrap();
+        db.add_alias("gs", "git status", "default", "m0").unwrap();
+
+        // Machines see how many, not what
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases/orphans", &token))
+            .await
+            .unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["count"], 1);
+        assert_eq!(json["groups"], serde_json::json!({ "old-team": 1 }));
+        assert!(json.get("aliases").is_none());
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases/orphans", "admin-secret"))
+            .await
+            .unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["count"], 1);
+        assert_eq!(json["aliases"][0]["name"], "k");
+        assert_eq!(json["aliases"][0]["group_name"], "old-team");
+
+        let body = serde_json::json!({ "from": "old-team", "to": "work" });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/aliases/reassign", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let body = serde_json::json!({ "from": "old-team", "to": "default" });
+        let resp = app
+            .clone()
+            .oneshot(post_json_auth("/api/aliases/reassign", &token, &body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let json = body_json(resp).await;
+        assert_eq!(json["moved"], serde_json::json!(["k"]));
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases/orphans", &token))
+            .await
+            .unwrap();
+        assert_eq!(body_json(resp).await["count"], 0);
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/aliases", &token))
+            .await
+            .unwrap();
+        assert_eq!(body_json(resp).await["count"], 2);
+    }
+
     #[tokio::test]
     async fn get_machines_hides_tokens() {
         let (app, _dir) = test_app().await;
@@ -1038,4 +3169,44 @@ mod tests {
             assert_eq!(m["auth_token"], "***");
         }
     }
+
+    #[tokio::test]
+    async fn get_machines_reports_recent_activity() {
+        let (app, dir) = test_app().await;
+        let token = do_register(&app, "busy", &["default"]).await;
+        do_register(&app, "dormant", &["default"]).await;
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let busy = db.get_machine_by_token(&token).unwrap().unwrap().machine_id;
+        let now = chrono::Utc::now().timestamp_millis();
+        // Two commands today and one from last week
+        for (i, age_hours) in [1, 2, 24 * 7].into_iter().enumerate() {
+            db.insert_history_entry(&HistoryEntry {
+                machine_id: busy.clone(),
+                hostname: "busy".into(),
+                timestamp: now - age_hours * 3600 * 1000,
+                ..crate::test_entry(&format!("h{i}"), "make")
+            })
+            .unwrap();
+        }
+
+        let resp = app
+            .clone()
+            .oneshot(get_auth("/api/machines", &token))
+            .await
+            .unwrap();
+        let json = body_json(resp).await;
+        let machine = |host: &str| {
+            json["machines"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|m| m["hostname"] == host)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(machine("busy")["recent_commands"], 2);
+        assert_eq!(machine("busy")["last_command_at"], now - 3600 * 1000);
+        assert_eq!(machine("dormant")["recent_commands"], 0);
+        assert!(machine("dormant")["last_command_at"].is_null());
+    }
 }
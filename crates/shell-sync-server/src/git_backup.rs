@@ -1,11 +1,28 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use git2::{Repository, Signature};
+use serde::Serialize;
 use shell_sync_core::db::SyncDatabase;
+use shell_sync_core::models::Alias;
+use shell_sync_core::shell::ShellType;
 use tracing::{error, info};
 
+type GroupedAliases = HashMap<String, Vec<Alias>>;
+
+/// What a sync would change, relative to the repository root.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncPlan {
+    /// Group files that would be created or rewritten with different aliases.
+    pub written: Vec<String>,
+    /// Files of groups that no longer have aliases.
+    pub removed: Vec<String>,
+    /// Whether the sync would produce a commit.
+    pub would_commit: bool,
+}
+
 /// Manages periodic git backups of all aliases.
 pub struct GitBackup {
     db: Arc<SyncDatabase>,
@@ -55,25 +72,54 @@ impl GitBackup {
         self.sync_to_git()
     }
 
+    /// Report what [`sync_to_git`](Self::sync_to_git) would write, remove and
+    /// commit, without touching the repository.
+    pub fn sync_to_git_plan(&self) -> anyhow::Result<SyncPlan> {
+        let grouped = group_aliases(&self.db.get_all_aliases()?);
+        let aliases_dir = self.repo_path.join("aliases");
+
+        let mut plan = SyncPlan::default();
+        for (group_name, group_aliases) in &grouped {
+            let path = aliases_dir.join(format!("{}.sh", group_name));
+            let content = generate_alias_file(group_name, group_aliases);
+            let unchanged = std::fs::read_to_string(&path)
+                .is_ok_and(|existing| same_ignoring_timestamps(&existing, &content));
+            if !unchanged {
+                plan.written.push(format!("aliases/{}.sh", group_name));
+            }
+        }
+        for path in obsolete_files(&aliases_dir, &grouped) {
+            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                plan.removed.push(format!("aliases/{}", name));
+            }
+        }
+        plan.written.sort();
+        plan.removed.sort();
+
+        let has_head = Repository::open(&self.repo_path).is_ok_and(|repo| repo.head().is_ok());
+        plan.would_commit = !has_head || !plan.written.is_empty() || !plan.removed.is_empty();
+        Ok(plan)
+    }
+
     /// Write alias files and commit if there are pending changes.
+    ///
+    /// Skipped when no alias changed since the last sync, so the generated
+    /// timestamps alone never produce a commit.
     pub fn sync_to_git(&self) -> anyhow::Result<()> {
         if !self.pending_changes.load(Ordering::Relaxed) {
             return Ok(());
         }
 
+        if !self.sync_to_git_plan()?.would_commit {
+            info!("No alias changes to back up");
+            self.pending_changes.store(false, Ordering::Relaxed);
+            return Ok(());
+        }
+
         info!("Starting sync to git...");
 
         let aliases = self.db.get_all_aliases()?;
-
-        // Group aliases by group_name
-        let mut grouped: std::collections::HashMap<String, Vec<shell_sync_core::models::Alias>> =
-            std::collections::HashMap::new();
-        for alias in &aliases {
-            grouped
-                .entry(alias.group_name.clone())
-                .or_default()
-                .push(alias.clone());
-        }
+        let grouped = group_aliases(&aliases);
 
         let aliases_dir = self.repo_path.join("aliases");
         std::fs::create_dir_all(&aliases_dir)?;
@@ -86,18 +132,9 @@ impl GitBackup {
         }
 
         // Remove files for groups that no longer exist
-        if let Ok(entries) = std::fs::read_dir(&aliases_dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.extension().is_some_and(|e| e == "sh") {
-                    if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                        if !grouped.contains_key(stem) {
-                            let _ = std::fs::remove_file(&path);
-                            info!(file = %path.display(), "Removed obsolete alias file");
-                        }
-                    }
-                }
-            }
+        for path in obsolete_files(&aliases_dir, &grouped) {
+            let _ = std::fs::remove_file(&path);
+            info!(file = %path.display(), "Removed obsolete alias file");
         }
 
         // Write summary
@@ -111,11 +148,7 @@ impl GitBackup {
         Ok(())
     }
 
-    fn git_commit(
-        &self,
-        aliases: &[shell_sync_core::models::Alias],
-        grouped: &std::collections::HashMap<String, Vec<shell_sync_core::models::Alias>>,
-    ) -> anyhow::Result<()> {
+    fn git_commit(&self, aliases: &[Alias], grouped: &GroupedAliases) -> anyhow::Result<()> {
         let repo = Repository::open(&self.repo_path)?;
 
         // Add all files to index
@@ -183,7 +216,53 @@ impl GitBackup {
     }
 }
 
-fn generate_alias_file(group_name: &str, aliases: &[shell_sync_core::models::Alias]) -> String {
+fn group_aliases(aliases: &[Alias]) -> GroupedAliases {
+    let mut grouped = GroupedAliases::new();
+    for alias in aliases {
+        grouped
+            .entry(alias.group_name.clone())
+            .or_default()
+            .push(alias.clone());
+    }
+    grouped
+}
+
+/// `.sh` files in `aliases_dir` whose group has no aliases any more.
+fn obsolete_files(aliases_dir: &Path, grouped: &GroupedAliases) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(aliases_dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().is_some_and(|e| e == "sh")
+                && path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|stem| !grouped.contains_key(stem))
+        })
+        .collect()
+}
+
+/// Compare generated files while ignoring their "Auto-generated on" line.
+fn same_ignoring_timestamps(a: &str, b: &str) -> bool {
+    let content = |s: &str| {
+        s.lines()
+            .filter(|l| !l.starts_with("# Auto-generated on "))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    content(a) == content(b)
+}
+
+/// A bash-syntax file of the group's aliases. Aliases limited to shells that
+/// read neither bash nor zsh syntax (fish) are left out.
+fn generate_alias_file(group_name: &str, aliases: &[Alias]) -> String {
+    let aliases: Vec<&Alias> = aliases
+        .iter()
+        .filter(|a| a.applies_to(ShellType::Bash) || a.applies_to(ShellType::Zsh))
+        .collect();
     let mut out = format!(
         "#!/bin/bash\n# Shell Sync - {} group\n# Auto-generated on {}\n# Total aliases: {}\n\n",
         group_name,
@@ -200,9 +279,7 @@ fn generate_alias_file(group_name: &str, aliases: &[shell_sync_core::models::Ali
 }
 
 /// Generate a markdown summary of alias groups. Visible for testing.
-fn generate_summary(
-    grouped: &std::collections::HashMap<String, Vec<shell_sync_core::models::Alias>>,
-) -> String {
+fn generate_summary(grouped: &GroupedAliases) -> String {
     let total: usize = grouped.values().map(|v| v.len()).sum();
     let mut out = format!(
         "# Shell Sync - Alias Summary\n\nLast updated: {}\n\n## Statistics\n\n- Total groups: {}\n- Total aliases: {}\n\n## Groups\n\n",
@@ -230,7 +307,6 @@ fn generate_summary(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use shell_sync_core::models::Alias;
 
     fn make_alias(name: &str, command: &str, group: &str) -> Alias {
         Alias {
@@ -242,6 +318,10 @@ mod tests {
             created_at: 1000,
             updated_at: 1000,
             version: 1,
+            pinned: false,
+            expand: false,
+            template: false,
+            shells: Vec::new(),
         }
     }
 
@@ -264,6 +344,18 @@ mod tests {
         assert!(content.contains("alias gp='git push'"));
     }
 
+    #[test]
+    fn alias_file_leaves_out_fish_only_aliases() {
+        let mut fish = make_alias("ll", "ls -la", "default");
+        fish.shells = vec!["fish".into()];
+        let mut zsh = make_alias("gs", "git status", "default");
+        zsh.shells = vec!["zsh".into()];
+        let content = generate_alias_file("default", &[fish, zsh]);
+        assert!(content.contains("alias gs='git status'"));
+        assert!(!content.contains("alias ll="));
+        assert!(content.contains("Total aliases: 1"));
+    }
+
     #[test]
     fn alias_file_escapes_quotes() {
         let aliases = vec![make_alias("say", "echo 'hello'", "default")];
@@ -273,7 +365,7 @@ mod tests {
 
     #[test]
     fn summary_contains_stats() {
-        let mut grouped = std::collections::HashMap::new();
+        let mut grouped = GroupedAliases::new();
         grouped.insert(
             "default".to_string(),
             vec![make_alias("gs", "git status", "default")],
@@ -291,4 +383,47 @@ mod tests {
         assert!(summary.contains("### default"));
         assert!(summary.contains("### work"));
     }
+
+    fn backup_with_db() -> (GitBackup, Arc<SyncDatabase>, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let db =
+            Arc::new(SyncDatabase::open(dir.path().join("db.sqlite").to_str().unwrap()).unwrap());
+        db.register_machine("m1", "host", &["default".into()], "linux", "tok", None)
+            .unwrap();
+        let backup = GitBackup::new(db.clone(), dir.path().join("repo").to_str().unwrap());
+        backup.initialize().unwrap();
+        (backup, db, dir)
+    }
+
+    #[test]
+    fn plan_reports_added_and_obsolete_group_files() {
+        let (backup, db, _dir) = backup_with_db();
+        db.add_alias("gs", "git status", "default", "m1").unwrap();
+        backup.force_sync().unwrap();
+        assert_eq!(
+            backup.sync_to_git_plan().unwrap(),
+            SyncPlan::default(),
+            "nothing changed since the last sync"
+        );
+
+        db.add_alias("k", "kubectl", "work", "m1").unwrap();
+        db.delete_alias_by_name("gs", "default", "m1").unwrap();
+        let plan = backup.sync_to_git_plan().unwrap();
+        assert_eq!(plan.written, vec!["aliases/work.sh"]);
+        assert_eq!(plan.removed, vec!["aliases/default.sh"]);
+        assert!(plan.would_commit);
+
+        // Planning leaves the working tree alone
+        let aliases_dir = backup.repo_path.join("aliases");
+        assert!(aliases_dir.join("default.sh").exists());
+        assert!(!aliases_dir.join("work.sh").exists());
+    }
+
+    #[test]
+    fn first_sync_always_commits() {
+        let (backup, _db, _dir) = backup_with_db();
+        let plan = backup.sync_to_git_plan().unwrap();
+        assert!(plan.written.is_empty());
+        assert!(plan.would_commit);
+    }
 }
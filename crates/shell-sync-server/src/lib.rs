@@ -1,5 +1,27 @@
+pub mod alias_cache;
 pub mod api;
 pub mod git_backup;
 pub mod mdns;
+pub mod pidfile;
 pub mod server;
 pub mod ws;
+
+/// A history entry for tests; override fields with struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_entry(id: &str, command: &str) -> shell_sync_core::models::HistoryEntry {
+    shell_sync_core::models::HistoryEntry {
+        id: id.into(),
+        command: command.into(),
+        cwd: "/tmp".into(),
+        exit_code: 0,
+        duration_ms: 1,
+        session_id: "s1".into(),
+        machine_id: "m1".into(),
+        hostname: "host".into(),
+        timestamp: 1000,
+        shell: "zsh".into(),
+        group_name: "default".into(),
+        truncated: false,
+        unknown_duration: false,
+    }
+}
@@ -0,0 +1,234 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// PID file for a running server, removed again when dropped.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write the current process id to `path`.
+    ///
+    /// Fails if the file names a process that is still alive; a stale file left
+    /// behind by a crashed server is replaced.
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        if let Some(pid) = read_pid(path) {
+            if pid != std::process::id() as i32 && is_running(pid) {
+                anyhow::bail!("Server already running (PID {pid}, see {})", path.display());
+            }
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, std::process::id().to_string())
+            .with_context(|| format!("Failed to write PID file {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Read the process id stored in a PID file.
+pub fn read_pid(path: &Path) -> Option<i32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Whether a process with this id exists.
+#[cfg(unix)]
+pub fn is_running(pid: i32) -> bool {
+    // Signal 0 only checks that the process exists and may be signalled.
+    pid > 0 && unsafe { libc::kill(pid, 0) == 0 }
+}
+
+/// Without a way to probe other processes every PID file is treated as stale.
+#[cfg(not(unix))]
+pub fn is_running(_pid: i32) -> bool {
+    false
+}
+
+/// systemd sets `INVOCATION_ID` for the processes of a unit; such services
+/// must stay in the foreground so systemd can supervise them.
+pub fn running_under_systemd() -> bool {
+    std::env::var_os("INVOCATION_ID").is_some_and(|v| !v.is_empty())
+}
+
+/// Start `program` in a new session with stdio detached from the terminal,
+/// without waiting for it. Output is appended to `log` if given, else discarded.
+#[cfg(unix)]
+pub fn spawn_detached(
+    program: &Path,
+    args: &[OsString],
+    log: Option<&Path>,
+) -> anyhow::Result<std::process::Child> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let (stdout, stderr) = match log {
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open log file {}", path.display()))?;
+            (Stdio::from(file.try_clone()?), Stdio::from(file))
+        }
+        None => (Stdio::null(), Stdio::null()),
+    };
+
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr);
+    // Detach from the controlling terminal so closing it does not kill the server.
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    cmd.spawn()
+        .with_context(|| format!("Failed to start {}", program.display()))
+}
+
+/// Wait until `child` has written its id to the PID file at `path`, which the
+/// server does only once it is listening. Fails if the child exits first or
+/// `timeout` passes.
+pub fn wait_for_start(
+    child: &mut std::process::Child,
+    path: &Path,
+    timeout: std::time::Duration,
+) -> anyhow::Result<()> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        if read_pid(path) == Some(child.id() as i32) {
+            return Ok(());
+        }
+        if let Some(status) = child.try_wait()? {
+            anyhow::bail!("Server exited during startup ({status})");
+        }
+        if std::time::Instant::now() >= deadline {
+            anyhow::bail!("Server did not start within {}s", timeout.as_secs());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+}
+
+/// Ask the process in `path` to shut down and wait for it to exit.
+///
+/// Returns `Ok(false)` if no server was running. A stale PID file is removed.
+#[cfg(unix)]
+pub fn stop(path: &Path, timeout: std::time::Duration) -> anyhow::Result<bool> {
+    let Some(pid) = read_pid(path) else {
+        return Ok(false);
+    };
+    if !is_running(pid) {
+        let _ = std::fs::remove_file(path);
+        return Ok(false);
+    }
+
+    unsafe {
+        libc::kill(pid, libc::SIGTERM);
+    }
+
+    let deadline = std::time::Instant::now() + timeout;
+    while is_running(pid) {
+        if std::time::Instant::now() >= deadline {
+            anyhow::bail!("Server (PID {pid}) did not exit within {}s", timeout.as_secs());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    // The server removes its own PID file on clean shutdown.
+    let _ = std::fs::remove_file(path);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn pid_file_written_and_removed_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run/server.pid");
+
+        let guard = PidFile::create(&path).unwrap();
+        assert_eq!(read_pid(&path), Some(std::process::id() as i32));
+        drop(guard);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_pid_file_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.pid");
+        // Far above any default pid_max, so never a live process.
+        std::fs::write(&path, "999999999").unwrap();
+        assert!(!stop(&path, Duration::from_secs(1)).unwrap());
+        assert!(!path.exists());
+
+        std::fs::write(&path, "999999999").unwrap();
+        let _guard = PidFile::create(&path).unwrap();
+        assert_eq!(read_pid(&path), Some(std::process::id() as i32));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn startup_is_confirmed_by_pid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.pid");
+        let sh = Path::new("/bin/sh");
+
+        let script = format!("echo $$ > '{}'; sleep 30", path.display());
+        let mut child = spawn_detached(sh, &["-c".into(), script.into()], None).unwrap();
+        wait_for_start(&mut child, &path, Duration::from_secs(5)).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let mut child = spawn_detached(sh, &["-c".into(), "exit 3".into()], None).unwrap();
+        let err = wait_for_start(&mut child, &path, Duration::from_secs(5)).unwrap_err();
+        assert!(err.to_string().contains("exited during startup"), "{err}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detached_process_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.pid");
+
+        let mut child =
+            spawn_detached(Path::new("/bin/sh"), &["-c".into(), "sleep 30".into()], None).unwrap();
+        let pid = child.id() as i32;
+        std::fs::write(&path, pid.to_string()).unwrap();
+
+        assert!(is_running(pid));
+        // New session: the child leads its own session.
+        assert_eq!(unsafe { libc::getsid(pid) }, pid);
+        assert!(PidFile::create(&path).is_err());
+
+        // Reap the child as it exits so it does not linger as a zombie.
+        let stopper = {
+            let path = path.clone();
+            std::thread::spawn(move || stop(&path, Duration::from_secs(5)))
+        };
+        child.wait().unwrap();
+        assert!(stopper.join().unwrap().unwrap());
+        assert!(!path.exists());
+        assert!(!stop(&path, Duration::from_secs(1)).unwrap());
+    }
+}
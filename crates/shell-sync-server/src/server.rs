@@ -1,17 +1,21 @@
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
-    extract::{ws::WebSocketUpgrade, State},
-    http::{header, StatusCode, Uri},
+    extract::{ws::WebSocketUpgrade, ConnectInfo, DefaultBodyLimit, State},
+    http::{header, HeaderValue, Method, StatusCode, Uri},
+    middleware::from_fn_with_state,
     response::{Html, IntoResponse, Response},
-    routing::{delete, get, post, put},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use rust_embed::Embed;
 use shell_sync_core::config::ServerConfig;
-use shell_sync_core::db::SyncDatabase;
-use tower_http::cors::CorsLayer;
-use tracing::info;
+use shell_sync_core::db::{DbOptions, SyncDatabase};
+use shell_sync_core::secrets::CommandBlocklist;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing::{info, warn};
 
 use crate::api::{self, AppState};
 use crate::git_backup::GitBackup;
@@ -21,35 +25,168 @@ use crate::ws::{self, WsHub};
 #[folder = "../../web-ui/dist"]
 struct WebAssets;
 
+/// Build the CORS policy from the configured origins.
+pub fn cors_layer(config: &ServerConfig) -> CorsLayer {
+    let layer = CorsLayer::new()
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]);
+
+    if config.cors_any {
+        return layer.allow_origin(AllowOrigin::any());
+    }
+
+    let origins: Vec<String> = if config.cors_allowed_origins.is_empty() {
+        vec![
+            format!("http://localhost:{}", config.port),
+            format!("http://127.0.0.1:{}", config.port),
+        ]
+    } else {
+        config.cors_allowed_origins.clone()
+    };
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|o| {
+            let value = HeaderValue::from_str(o.trim_end_matches('/')).ok();
+            if value.is_none() {
+                warn!(origin = %o, "Ignoring invalid CORS origin");
+            }
+            value
+        })
+        .collect();
+    layer.allow_origin(AllowOrigin::list(origins))
+}
+
 /// Build the Axum router with all API routes and WebSocket handler.
 pub fn build_router(state: Arc<AppState>) -> Router {
-    Router::new()
-        // REST API
+    let cors = cors_layer(&state.config);
+    let register_limit = DefaultBodyLimit::max(state.config.max_register_body_bytes);
+    let bulk_limit = DefaultBodyLimit::max(state.config.max_bulk_body_bytes);
+    let deadline = Duration::from_secs(state.config.request_timeout_secs);
+    let slow_deadline = Duration::from_secs(state.config.slow_request_timeout_secs);
+    let api = Router::new()
         .route("/api/health", get(api::health))
-        .route("/api/register", post(api::register))
+        .route("/api/version", get(api::version))
+        .route("/api/register", post(api::register).layer(register_limit))
         .route("/api/aliases", get(api::get_aliases).post(api::add_alias))
+        .route("/api/aliases/orphans", get(api::get_orphaned_aliases))
+        .route("/api/aliases/reassign", post(api::reassign_aliases))
         .route(
             "/api/aliases/:id",
             put(api::update_alias).delete(api::delete_alias),
         )
-        .route("/api/aliases/name/:name", delete(api::delete_alias_by_name))
+        .route(
+            "/api/aliases/name/:name",
+            get(api::get_alias_by_name).delete(api::delete_alias_by_name),
+        )
+        .route(
+            "/api/aliases/name/:name/pin",
+            post(api::pin_alias).delete(api::unpin_alias),
+        )
         .route("/api/conflicts", get(api::get_conflicts))
         .route("/api/conflicts/resolve", post(api::resolve_conflict))
-        .route("/api/import", post(api::import_aliases))
-        .route("/api/history", get(api::get_history))
+        .route("/api/conflicts/purge", post(api::purge_conflicts))
+        .route("/api/import", post(api::import_aliases).layer(bulk_limit))
+        .route("/api/groups", get(api::get_groups).post(api::create_group))
+        .route("/api/groups/:name", delete(api::delete_group))
         .route("/api/machines", get(api::get_machines))
+        .route("/api/machines/:machine_id", patch(api::update_machine))
         .route("/api/git/sync", post(api::force_git_sync))
+        .route_layer(from_fn_with_state(deadline, api::enforce_deadline));
+    // Listings that can scan many rows
+    let slow_api = Router::new()
+        .route("/api/history", get(api::get_history))
         .route("/api/shell-history", get(api::get_shell_history))
+        .route("/api/audit", get(api::get_audit_log))
+        .route_layer(from_fn_with_state(slow_deadline, api::enforce_deadline));
+    Router::new()
+        // REST API
+        .merge(api)
+        .merge(slow_api)
+        // Long-lived streams have no deadline
+        .route("/api/events", get(api::events))
         // WebSocket
         .route("/ws", get(ws_upgrade))
-        .layer(CorsLayer::permissive())
+        .layer(DefaultBodyLimit::max(state.config.max_body_bytes))
+        .layer(axum::middleware::from_fn(api::stamp_peer_ip))
+        .layer(cors)
         .with_state(state)
 }
 
+/// Periodically checkpoint the WAL so it does not grow without bound.
+fn spawn_wal_checkpoints(db: Arc<SyncDatabase>, interval_secs: u64) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+        interval.tick().await; // Skip first immediate tick
+        loop {
+            interval.tick().await;
+            let db = Arc::clone(&db);
+            match tokio::task::spawn_blocking(move || db.checkpoint()).await {
+                Ok(Err(e)) => warn!("WAL checkpoint failed: {e}"),
+                Err(e) => warn!("WAL checkpoint task failed: {e}"),
+                Ok(Ok(())) => {}
+            }
+        }
+    })
+}
+
+/// Hourly, purge alias tombstones older than `days`.
+fn spawn_tombstone_purge(db: Arc<SyncDatabase>, days: u64) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            let cutoff = chrono::Utc::now().timestamp_millis() - days as i64 * 86_400_000;
+            let db = Arc::clone(&db);
+            match tokio::task::spawn_blocking(move || db.purge_alias_tombstones(cutoff)).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(n)) => info!(purged = n, "Purged old alias tombstones"),
+                Ok(Err(e)) => warn!("Alias tombstone purge failed: {e}"),
+                Err(e) => warn!("Alias tombstone purge task failed: {e}"),
+            }
+        }
+    })
+}
+
+/// Hourly, drop audit log entries older than `days`; every authenticated
+/// request adds one.
+fn spawn_auth_log_purge(db: Arc<SyncDatabase>, days: u64) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            let cutoff = chrono::Utc::now().timestamp_millis() - days as i64 * 86_400_000;
+            let db = Arc::clone(&db);
+            match tokio::task::spawn_blocking(move || db.purge_auth_log(cutoff)).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(n)) => info!(purged = n, "Purged old audit log entries"),
+                Ok(Err(e)) => warn!("Audit log purge failed: {e}"),
+                Err(e) => warn!("Audit log purge task failed: {e}"),
+            }
+        }
+    })
+}
+
 /// Build and start the shell-sync server.
 pub async fn run(config: ServerConfig) -> anyhow::Result<()> {
-    let db = Arc::new(SyncDatabase::open(&config.db_path)?);
-    let hub = Arc::new(WsHub::new());
+    let blocklist = CommandBlocklist::from_config(&config)?;
+    let db = Arc::new(SyncDatabase::open_with_options(
+        &config.db_path,
+        DbOptions {
+            busy_timeout_ms: config.db_busy_timeout_ms,
+            pool_size: config.db_pool_size,
+        },
+    )?);
+    let hub = Arc::new(
+        WsHub::with_limits(config.ws_channel_capacity, config.ws_overflow_policy)
+            .with_ping_interval(config.ws_ping_interval_secs),
+    );
     let git_backup = Arc::new(GitBackup::new(Arc::clone(&db), &config.git_repo_path));
 
     git_backup.initialize()?;
@@ -57,6 +194,16 @@ pub async fn run(config: ServerConfig) -> anyhow::Result<()> {
     // Spawn periodic git sync
     let _sync_handle = git_backup.spawn_periodic_sync(config.git_sync_interval_secs);
 
+    if config.wal_checkpoint_interval_secs > 0 {
+        spawn_wal_checkpoints(Arc::clone(&db), config.wal_checkpoint_interval_secs);
+    }
+    if config.alias_tombstone_days > 0 {
+        spawn_tombstone_purge(Arc::clone(&db), config.alias_tombstone_days);
+    }
+    if config.auth_log_days > 0 {
+        spawn_auth_log_purge(Arc::clone(&db), config.auth_log_days);
+    }
+
     // Start mDNS broadcast
     let _mdns = if config.mdns_enabled {
         match crate::mdns::start_broadcast(config.port) {
@@ -74,20 +221,34 @@ pub async fn run(config: ServerConfig) -> anyhow::Result<()> {
         db: Arc::clone(&db),
         hub: Arc::clone(&hub),
         git_backup: Arc::clone(&git_backup),
+        blocklist,
+        alias_cache: Default::default(),
+        config: config.clone(),
     });
 
-    let mut app = build_router(state);
+    let mut app = build_router(Arc::clone(&state));
 
     // Embed web UI if enabled
     if config.web_ui_enabled {
         app = app.fallback(serve_embedded);
     }
 
-    let addr = format!("0.0.0.0:{}", config.port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let listener = bind_listener(&config).await?;
+    let addr = listener.local_addr()?;
+    // Written only once the port is ours: `server` waits for it before
+    // reporting a background start as successful.
+    let _pid_file = match &config.pid_file {
+        Some(path) => Some(crate::pidfile::PidFile::create(path)?),
+        None => None,
+    };
+    let display_host = if addr.ip().is_unspecified() {
+        "localhost".to_string()
+    } else {
+        addr.ip().to_string()
+    };
 
     info!(
-        port = config.port,
+        addr = %addr,
         db = %config.db_path,
         git = %config.git_repo_path,
         "Shell Sync server started"
@@ -97,9 +258,10 @@ pub async fn run(config: ServerConfig) -> anyhow::Result<()> {
     println!("=================================");
     println!("  Shell Sync Service Started");
     println!("=================================");
-    println!("  REST API: http://localhost:{}", config.port);
-    println!("  WebSocket: ws://localhost:{}/ws", config.port);
-    println!("  Web UI: http://localhost:{}/", config.port);
+    println!("  Listening: {}", addr);
+    println!("  REST API: http://{}:{}", display_host, addr.port());
+    println!("  WebSocket: ws://{}:{}/ws", display_host, addr.port());
+    println!("  Web UI: http://{}:{}/", display_host, addr.port());
     println!("  Database: {}", config.db_path);
     println!("  Git Repo: {}", config.git_repo_path);
     println!(
@@ -113,16 +275,109 @@ pub async fn run(config: ServerConfig) -> anyhow::Result<()> {
     println!("=================================");
     println!();
 
-    axum::serve(listener, app).await?;
+    serve(listener, app, &state, shutdown_signal()).await?;
 
+    info!("Shell Sync server stopped");
     Ok(())
 }
 
-/// WebSocket upgrade handler at GET /ws.
-async fn ws_upgrade(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| {
-        ws::handle_ws(socket, Arc::clone(&state.db), Arc::clone(&state.hub))
+/// Serve `app` until `shutdown` resolves, then drain: event streams end,
+/// in-flight requests finish, WebSocket clients are closed and unsaved alias
+/// changes are committed to git.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    state: &AppState,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown({
+        let hub = Arc::clone(&state.hub);
+        async move {
+            shutdown.await;
+            hub.close_streams();
+        }
     })
+    .await?;
+
+    let closed = state.hub.close_all().await;
+    if closed > 0 {
+        info!(clients = closed, "Closed WebSocket connections");
+    }
+
+    if state.git_backup.has_pending_changes() {
+        let git_backup = Arc::clone(&state.git_backup);
+        match tokio::task::spawn_blocking(move || git_backup.sync_to_git()).await {
+            Ok(Ok(())) => info!("Flushed pending git backup"),
+            Ok(Err(e)) => warn!("Final git sync failed: {e}"),
+            Err(e) => warn!("Final git sync task failed: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Resolve on Ctrl+C or SIGTERM (sent by `shell-sync server-stop`).
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.ok();
+    };
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    info!("Received shutdown signal");
+}
+
+/// Resolve the socket address the server should listen on.
+pub fn listen_addr(config: &ServerConfig) -> anyhow::Result<SocketAddr> {
+    let ip: IpAddr = config.bind_address.trim().parse().map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid bind address '{}': expected an IP address such as 127.0.0.1, 0.0.0.0 or ::1",
+            config.bind_address
+        )
+    })?;
+    Ok(SocketAddr::new(ip, config.port))
+}
+
+/// Bind the TCP listener for the configured address and port.
+pub async fn bind_listener(config: &ServerConfig) -> anyhow::Result<tokio::net::TcpListener> {
+    let addr = listen_addr(config)?;
+    tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to bind {}: {}", addr, e))
+}
+
+/// WebSocket upgrade handler at GET /ws.
+async fn ws_upgrade(
+    ws: WebSocketUpgrade,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let client_ip = connect_info
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let max_message = state.config.max_bulk_body_bytes;
+    ws.max_message_size(max_message)
+        .max_frame_size(max_message)
+        .on_upgrade(move |socket| {
+            ws::handle_ws(
+                socket,
+                Arc::clone(&state.db),
+                Arc::clone(&state.hub),
+                client_ip,
+            )
+        })
 }
 
 /// Serve embedded web UI assets with SPA fallback.
@@ -147,3 +402,274 @@ async fn serve_embedded(uri: Uri) -> Response {
 
     (StatusCode::NOT_FOUND, "Not Found").into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn config_with_bind(bind_address: &str) -> ServerConfig {
+        ServerConfig {
+            bind_address: bind_address.into(),
+            port: 0,
+            ..Default::default()
+        }
+    }
+
+    fn test_state(dir: &tempfile::TempDir, config: ServerConfig) -> Arc<AppState> {
+        let db = Arc::new(
+            SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap(),
+        );
+        let git_dir = dir.path().join("git");
+        std::fs::create_dir_all(&git_dir).unwrap();
+        let git_backup = Arc::new(GitBackup::new(Arc::clone(&db), git_dir.to_str().unwrap()));
+        Arc::new(AppState {
+            db,
+            hub: Arc::new(WsHub::new()),
+            git_backup,
+            blocklist: Default::default(),
+            alias_cache: Default::default(),
+            config,
+        })
+    }
+
+    async fn health_with_origin(config: ServerConfig, origin: &str) -> Option<String> {
+        use tower::ServiceExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let app = build_router(test_state(&dir, config));
+        let req = axum::http::Request::get("/api/health")
+            .header(header::ORIGIN, origin)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        resp.headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn slow_request_times_out_with_503() {
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    "done"
+                }),
+            )
+            .route("/fast", get(|| async { "done" }))
+            .route_layer(from_fn_with_state(
+                Duration::from_millis(50),
+                api::enforce_deadline,
+            ));
+        let request = |path| {
+            axum::http::Request::get(path)
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+
+        let started = std::time::Instant::now();
+        let resp = app.clone().oneshot(request("/slow")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "Request timed out");
+
+        let resp = app.oneshot(request("/fast")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn slow_query_is_interrupted_but_writes_finish() {
+        use tower::ServiceExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(SyncDatabase::open(dir.path().join("t.db").to_str().unwrap()).unwrap());
+        // Never yields, like every handler that queries the database
+        let count = move || {
+            let conn = db.raw_connection().lock().unwrap();
+            let counted = conn.query_row(
+                "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 100000000)
+                 SELECT COUNT(*) FROM c",
+                [],
+                |row| row.get::<_, i64>(0),
+            );
+            async move { counted.map_or_else(|e| e.to_string(), |n| n.to_string()) }
+        };
+        let app = Router::new()
+            .route("/count", get(count))
+            .route(
+                "/write",
+                axum::routing::post(|| async {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    "done"
+                }),
+            )
+            .route_layer(from_fn_with_state(
+                Duration::from_millis(50),
+                api::enforce_deadline,
+            ));
+
+        let started = std::time::Instant::now();
+        let req = axum::http::Request::get("/count")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let req = axum::http::Request::post("/write")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        assert_eq!(app.oneshot(req).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn zero_deadline_never_expires() {
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    "done"
+                }),
+            )
+            .route_layer(from_fn_with_state(Duration::ZERO, api::enforce_deadline));
+        let req = axum::http::Request::get("/slow")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        assert_eq!(app.oneshot(req).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn cors_allows_only_listed_origins() {
+        let config = ServerConfig {
+            cors_allowed_origins: vec!["https://ui.example.com".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            health_with_origin(config.clone(), "https://ui.example.com").await,
+            Some("https://ui.example.com".to_string())
+        );
+        assert_eq!(health_with_origin(config, "https://evil.example").await, None);
+    }
+
+    #[tokio::test]
+    async fn cors_defaults_to_localhost() {
+        let config = ServerConfig::default();
+        assert_eq!(
+            health_with_origin(config.clone(), "http://localhost:8888").await,
+            Some("http://localhost:8888".to_string())
+        );
+        assert_eq!(health_with_origin(config, "http://192.168.1.50:8888").await, None);
+    }
+
+    #[tokio::test]
+    async fn cors_any_allows_everything() {
+        let config = ServerConfig {
+            cors_any: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            health_with_origin(config, "https://anywhere.example").await,
+            Some("*".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn cors_preflight_allows_patch() {
+        use tower::ServiceExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let app = build_router(test_state(&dir, ServerConfig::default()));
+        let req = axum::http::Request::options("/api/machines/m1")
+            .header(header::ORIGIN, "http://localhost:8888")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PATCH")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        let allowed = resp.headers()[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(allowed.contains("PATCH"), "{allowed}");
+    }
+
+    #[tokio::test]
+    async fn shutdown_returns_and_flushes_pending_git_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir, ServerConfig::default());
+        state.git_backup.initialize().unwrap();
+        state
+            .db
+            .add_alias("gs", "git status", "default", "m1")
+            .unwrap();
+        state.git_backup.mark_dirty();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let app = build_router(Arc::clone(&state));
+        let server_state = Arc::clone(&state);
+        let server = tokio::spawn(async move {
+            serve(listener, app, &server_state, async {
+                stopped.await.ok();
+            })
+            .await
+        });
+
+        stop.send(()).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("serve returns after shutdown")
+            .unwrap()
+            .unwrap();
+
+        assert!(!state.git_backup.has_pending_changes());
+        let repo = git2::Repository::open(dir.path().join("git")).unwrap();
+        assert!(repo.head().is_ok());
+        assert!(dir.path().join("git/aliases/default.sh").exists());
+    }
+
+    #[tokio::test]
+    async fn router_binds_to_loopback() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir, ServerConfig::default());
+
+        let listener = bind_listener(&config_with_bind("127.0.0.1")).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(addr.ip().is_loopback());
+        tokio::spawn(async move {
+            axum::serve(listener, build_router(state)).await.unwrap();
+        });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /api/health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    }
+
+    #[test]
+    fn invalid_bind_address_is_rejected() {
+        let err = listen_addr(&config_with_bind("not-an-ip")).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("Invalid bind address 'not-an-ip'"), "{msg}");
+    }
+
+    #[test]
+    fn listen_addr_accepts_ipv4_and_ipv6() {
+        let v4 = listen_addr(&config_with_bind("127.0.0.1")).unwrap();
+        assert_eq!(v4.to_string(), "127.0.0.1:0");
+        let v6 = listen_addr(&config_with_bind("::1")).unwrap();
+        assert!(v6.ip().is_loopback());
+    }
+}
@@ -1,40 +1,170 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 
 use axum::extract::ws::{Message, WebSocket};
 use futures_util::{SinkExt, StreamExt};
+use shell_sync_core::config::WsOverflowPolicy;
 use shell_sync_core::db::SyncDatabase;
-use tokio::sync::{mpsc, RwLock};
+use shell_sync_core::encryption::token_fingerprint;
+use shell_sync_core::models::HistoryEntry;
+use shell_sync_core::protocol::{ClientMessage, ErrorData, HistoryBatchEntries, ServerEvent};
+use tokio::sync::{broadcast, mpsc, watch, Notify, RwLock};
 use tracing::{info, warn};
 
+/// Events that only tell the client to re-fetch aliases, so any number of
+/// them can be replaced by one `sync_required`.
+const RESYNC_EVENTS: &[&str] = &[
+    "alias_added",
+    "alias_updated",
+    "alias_deleted",
+    "sync_required",
+];
+
+const DEFAULT_CHANNEL_CAPACITY: usize = 256;
+
+/// How long a closing connection may take to flush its queue.
+const CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Gives each watch-only connection its own hub key.
+static NEXT_WATCHER: AtomicU64 = AtomicU64::new(1);
+
 /// A connected WebSocket client.
 struct WsClient {
-    tx: mpsc::UnboundedSender<String>,
+    /// The authenticated machine; watch connections share it with the daemon.
+    machine_id: String,
+    tx: mpsc::Sender<String>,
+    /// Messages dropped because the outbound queue was full.
+    dropped: Arc<AtomicU64>,
+    /// Set when alias events were coalesced away; the forwarder then emits one `sync_required`.
+    resync_owed: Arc<AtomicBool>,
+    /// Signalled to make the connection handler close the socket.
+    evict: Arc<Notify>,
+}
+
+/// Outcome of queueing a message for one client.
+enum Delivery {
+    Sent,
+    Dropped,
+    Evict,
+}
+
+/// A group broadcast as seen by [`HubSubscription`]s.
+#[derive(Clone)]
+struct HubEvent {
+    machine_ids: Arc<HashSet<String>>,
+    event: String,
+    /// The `{"event", "data"}` message sent to WebSocket clients.
+    message: Arc<str>,
+}
+
+/// What a [`HubSubscription`] yields.
+#[derive(Debug, PartialEq)]
+pub enum HubMessage {
+    /// A broadcast addressed to the subscriber's machine.
+    Event { event: String, message: String },
+    /// The subscriber fell behind and events were skipped; it should re-sync.
+    Missed,
 }
 
-/// Hub managing all WebSocket connections, keyed by machine_id.
+/// Group broadcasts for a transport other than the hub's own WebSockets
+/// (see [`WsHub::subscribe`]).
+pub struct HubSubscription {
+    events: broadcast::Receiver<HubEvent>,
+    closing: watch::Receiver<bool>,
+}
+
+impl HubSubscription {
+    /// The next broadcast addressed to `machine_id`, or `None` once the hub
+    /// is shutting down.
+    pub async fn recv(&mut self, machine_id: &str) -> Option<HubMessage> {
+        loop {
+            let received = tokio::select! {
+                received = self.events.recv() => received,
+                _ = self.closing.wait_for(|closing| *closing) => return None,
+            };
+            match received {
+                Ok(e) if e.machine_ids.contains(machine_id) => {
+                    return Some(HubMessage::Event {
+                        event: e.event,
+                        message: e.message.to_string(),
+                    })
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(_)) => return Some(HubMessage::Missed),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Hub managing all WebSocket connections, keyed by machine_id (with a
+/// `#watch-N` suffix for watch-only connections).
 pub struct WsHub {
     clients: RwLock<HashMap<String, WsClient>>,
+    capacity: usize,
+    policy: WsOverflowPolicy,
+    /// How often each authenticated client is sent a `ping` event.
+    ping_interval: Option<std::time::Duration>,
+    /// Every group broadcast, for [`subscribe`](Self::subscribe).
+    events: broadcast::Sender<HubEvent>,
+    /// Set by [`close_streams`](Self::close_streams).
+    closing: watch::Sender<bool>,
+}
+
+impl Default for WsHub {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WsHub {
     pub fn new() -> Self {
+        Self::with_limits(DEFAULT_CHANNEL_CAPACITY, WsOverflowPolicy::default())
+    }
+
+    /// Create a hub whose clients each buffer at most `capacity` outbound messages.
+    pub fn with_limits(capacity: usize, policy: WsOverflowPolicy) -> Self {
         Self {
             clients: RwLock::new(HashMap::new()),
+            capacity: capacity.max(1),
+            policy,
+            ping_interval: None,
+            events: broadcast::channel(capacity.max(1)).0,
+            closing: watch::channel(false).0,
+        }
+    }
+
+    /// Ping authenticated clients every `secs` seconds (0 disables), so
+    /// clients notice a connection that died without a Close frame.
+    pub fn with_ping_interval(mut self, secs: u64) -> Self {
+        self.ping_interval = (secs > 0).then(|| std::time::Duration::from_secs(secs));
+        self
+    }
+
+    /// Receive group broadcasts outside a WebSocket connection, e.g. for
+    /// Server-Sent Events. Direct messages to one machine are not included.
+    pub fn subscribe(&self) -> HubSubscription {
+        HubSubscription {
+            events: self.events.subscribe(),
+            closing: self.closing.subscribe(),
         }
     }
 
+    /// End every [`HubSubscription`], so long-lived streams let a graceful
+    /// shutdown finish.
+    pub fn close_streams(&self) {
+        self.closing.send_replace(true);
+    }
+
     /// Register an authenticated client.
-    async fn add_client(&self, machine_id: String, tx: mpsc::UnboundedSender<String>) {
-        self.clients
-            .write()
-            .await
-            .insert(machine_id, WsClient { tx });
+    async fn add_client(&self, key: String, client: WsClient) {
+        self.clients.write().await.insert(key, client);
     }
 
     /// Remove a client on disconnect.
-    async fn remove_client(&self, machine_id: &str) {
-        self.clients.write().await.remove(machine_id);
+    async fn remove_client(&self, key: &str) {
+        self.clients.write().await.remove(key);
     }
 
     /// Number of connected clients.
@@ -42,6 +172,52 @@ impl WsHub {
         self.clients.read().await.len()
     }
 
+    /// Messages dropped for a connected client because its queue was full.
+    pub async fn dropped_count(&self, machine_id: &str) -> Option<u64> {
+        self.clients
+            .read()
+            .await
+            .get(machine_id)
+            .map(|c| c.dropped.load(Ordering::Relaxed))
+    }
+
+    /// Queue a message without waiting, applying the overflow policy if the queue is full.
+    fn deliver(&self, machine_id: &str, client: &WsClient, event: &str, msg: String) -> Delivery {
+        match client.tx.try_send(msg) {
+            Ok(()) => Delivery::Sent,
+            Err(mpsc::error::TrySendError::Closed(_)) => Delivery::Dropped,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                let dropped = client.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                match self.policy {
+                    WsOverflowPolicy::Disconnect => {
+                        warn!(machine_id, event, dropped, "WS client queue full, disconnecting");
+                        Delivery::Evict
+                    }
+                    WsOverflowPolicy::Coalesce => {
+                        if RESYNC_EVENTS.contains(&event) {
+                            client.resync_owed.store(true, Ordering::Relaxed);
+                        }
+                        warn!(machine_id, event, dropped, "WS client queue full, dropping message");
+                        Delivery::Dropped
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove clients that overflowed under the disconnect policy and close their sockets.
+    async fn evict(&self, machine_ids: Vec<String>) {
+        if machine_ids.is_empty() {
+            return;
+        }
+        let mut clients = self.clients.write().await;
+        for machine_id in machine_ids {
+            if let Some(client) = clients.remove(&machine_id) {
+                client.evict.notify_one();
+            }
+        }
+    }
+
     /// Broadcast an event to all machines in the given groups, excluding one machine.
     pub async fn broadcast_to_groups(
         &self,
@@ -65,16 +241,32 @@ impl WsHub {
         }
 
         let msg = serde_json::json!({ "event": event, "data": data }).to_string();
-        let clients = self.clients.read().await;
         let mut sent = 0;
+        let mut evicted = Vec::new();
 
-        for machine_id in &target_ids {
-            if let Some(client) = clients.get(machine_id) {
-                if client.tx.send(msg.clone()).is_ok() {
-                    sent += 1;
+        {
+            let clients = self.clients.read().await;
+            for (key, client) in clients.iter() {
+                if !target_ids.contains(&client.machine_id) {
+                    continue;
+                }
+                match self.deliver(key, client, event, msg.clone()) {
+                    Delivery::Sent => sent += 1,
+                    Delivery::Dropped => {}
+                    Delivery::Evict => evicted.push(key.clone()),
                 }
             }
         }
+        self.evict(evicted).await;
+
+        if !target_ids.is_empty() {
+            // Errors only when nobody is subscribed
+            let _ = self.events.send(HubEvent {
+                machine_ids: Arc::new(target_ids),
+                event: event.to_string(),
+                message: msg.into(),
+            });
+        }
 
         info!(
             event,
@@ -84,101 +276,189 @@ impl WsHub {
         );
     }
 
+    /// Disconnect every client, e.g. on server shutdown. Each connection
+    /// flushes its queued messages and sends a Close frame.
+    pub async fn close_all(&self) -> usize {
+        let clients: Vec<WsClient> = self.clients.write().await.drain().map(|(_, c)| c).collect();
+        for client in &clients {
+            client.evict.notify_one();
+        }
+        clients.len()
+    }
+
     /// Send a message to a specific machine by its machine_id.
     pub async fn send_to_machine(&self, machine_id: &str, msg: &str) -> bool {
-        let clients = self.clients.read().await;
-        if let Some(client) = clients.get(machine_id) {
-            client.tx.send(msg.to_string()).is_ok()
-        } else {
-            false
+        self.send_event(machine_id, "", msg).await
+    }
+
+    async fn send_event(&self, machine_id: &str, event: &str, msg: &str) -> bool {
+        let delivery = {
+            let clients = self.clients.read().await;
+            match clients.get(machine_id) {
+                Some(client) => self.deliver(machine_id, client, event, msg.to_string()),
+                None => return false,
+            }
+        };
+        match delivery {
+            Delivery::Sent => true,
+            Delivery::Dropped => false,
+            Delivery::Evict => {
+                self.evict(vec![machine_id.to_string()]).await;
+                false
+            }
         }
     }
 }
 
 /// Handle a single WebSocket connection through the auth flow and message loop.
-pub async fn handle_ws(socket: WebSocket, db: Arc<SyncDatabase>, hub: Arc<WsHub>) {
+pub async fn handle_ws(
+    socket: WebSocket,
+    db: Arc<SyncDatabase>,
+    hub: Arc<WsHub>,
+    client_ip: String,
+) {
     let (mut ws_tx, mut ws_rx) = socket.split();
     let mut machine_id: Option<String> = None;
+    let mut client_key: Option<String> = None;
     let mut machine_groups: Vec<String> = Vec::new();
 
-    // Create a channel for outbound messages
-    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    // Create a bounded channel for outbound messages
+    let (tx, mut rx) = mpsc::channel::<String>(hub.capacity);
+    let dropped = Arc::new(AtomicU64::new(0));
+    let resync_owed = Arc::new(AtomicBool::new(false));
+    let evict = Arc::new(Notify::new());
 
-    // Spawn a task to forward channel messages to the WebSocket
-    let send_task = tokio::spawn(async move {
+    // Spawn a task to forward channel messages to the WebSocket, closing it
+    // once every sender is gone
+    let owed = Arc::clone(&resync_owed);
+    let mut send_task = tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
-            if ws_tx.send(Message::Text(msg.into())).await.is_err() {
-                break;
+            if ws_tx.send(Message::Text(msg)).await.is_err() {
+                return;
+            }
+            // Stand in for any alias events dropped while the queue was full
+            if owed.swap(false, Ordering::Relaxed) {
+                let resync = serde_json::json!({
+                    "event": "sync_required",
+                    "data": { "reason": "coalesced" }
+                });
+                if ws_tx.send(Message::Text(resync.to_string())).await.is_err() {
+                    return;
+                }
             }
         }
+        let _ = ws_tx.send(Message::Close(None)).await;
     });
 
-    // Process incoming messages
-    while let Some(Ok(msg)) = ws_rx.next().await {
+    let mut ping = hub.ping_interval.map(|every| {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        interval
+    });
+
+    // Process incoming messages until the client leaves or is evicted
+    loop {
+        let msg = tokio::select! {
+            _ = evict.notified() => break,
+            _ = next_ping(&mut ping) => {
+                if machine_id.is_some() {
+                    // A full queue means frames are already flowing
+                    let _ = tx.try_send(ping_event());
+                }
+                continue;
+            }
+            msg = ws_rx.next() => match msg {
+                Some(Ok(msg)) => msg,
+                _ => break,
+            },
+        };
         let text = match msg {
             Message::Text(t) => t.to_string(),
             Message::Close(_) => break,
             _ => continue,
         };
 
-        let parsed: Result<serde_json::Value, _> = serde_json::from_str(&text);
-        let data = match parsed {
-            Ok(d) => d,
-            Err(_) => continue,
+        let msg = match parse_client_message(&text) {
+            Ok(msg) => msg,
+            Err(reply) => {
+                if !queue_reply(&tx, reply) {
+                    break;
+                }
+                continue;
+            }
         };
 
-        let msg_type = data.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-        match msg_type {
-            "auth" => {
-                let token = data.get("token").and_then(|v| v.as_str()).unwrap_or("");
-                match db.get_machine_by_token(token) {
-                    Ok(Some(m)) => {
-                        let mid = m.machine_id.clone();
-                        let _ = db.update_machine_last_seen(&mid);
-                        hub.add_client(mid.clone(), tx.clone()).await;
-                        machine_id = Some(mid.clone());
-                        machine_groups = m.groups.clone();
-
-                        let resp = serde_json::json!({
-                            "event": "auth_success",
-                            "data": { "machine_id": mid, "groups": m.groups }
-                        });
-                        let _ = tx.send(resp.to_string());
-                        info!(machine_id = %mid, hostname = %m.hostname, "WS authenticated");
-                    }
-                    _ => {
-                        let resp = serde_json::json!({
-                            "event": "auth_failed",
-                            "data": { "error": "Invalid token" }
-                        });
-                        let _ = tx.send(resp.to_string());
+        match msg {
+            ClientMessage::Auth { token, watch } => match db.get_machine_by_token(&token) {
+                Ok(Some(m)) => {
+                    let mid = m.machine_id.clone();
+                    let _ = db.log_auth(Some(&mid), None, true, &client_ip, "ws");
+                    let _ = db.update_machine_last_seen(&mid);
+                    let key = if watch {
+                        format!("{mid}#watch-{}", NEXT_WATCHER.fetch_add(1, Ordering::Relaxed))
+                    } else {
+                        mid.clone()
+                    };
+                    hub.add_client(
+                        key.clone(),
+                        WsClient {
+                            machine_id: mid.clone(),
+                            tx: tx.clone(),
+                            dropped: Arc::clone(&dropped),
+                            resync_owed: Arc::clone(&resync_owed),
+                            evict: Arc::clone(&evict),
+                        },
+                    )
+                    .await;
+                    client_key = Some(key);
+                    machine_id = Some(mid.clone());
+                    machine_groups = m.groups.clone();
+
+                    let resp = serde_json::json!({
+                        "event": "auth_success",
+                        "data": { "machine_id": mid, "groups": m.groups }
+                    });
+                    if !queue_reply(&tx, resp.to_string()) {
                         break;
                     }
+                    info!(machine_id = %mid, hostname = %m.hostname, "WS authenticated");
                 }
-            }
-            "ping" => {
+                _ => {
+                    let _ = db.log_auth(
+                        None,
+                        Some(&token_fingerprint(&token)),
+                        false,
+                        &client_ip,
+                        "ws",
+                    );
+                    let resp = serde_json::json!({
+                        "event": "auth_failed",
+                        "data": { "error": "Invalid token" }
+                    });
+                    queue_reply(&tx, resp.to_string());
+                    break;
+                }
+            },
+            ClientMessage::Ping => {
                 let resp = serde_json::json!({
                     "event": "pong",
                     "data": { "timestamp": chrono::Utc::now().timestamp_millis() }
                 });
-                let _ = tx.send(resp.to_string());
+                if !queue_reply(&tx, resp.to_string()) {
+                    break;
+                }
             }
-            "key_request" => {
+            // Receiving anything is all the liveness check needs
+            ClientMessage::Pong => {}
+            ClientMessage::KeyRequest {
+                group_name,
+                public_key,
+            } => {
                 if let Some(ref mid) = machine_id {
-                    let group_name = data
-                        .get("group_name")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-                    let public_key = data
-                        .get("public_key")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-
                     if !group_name.is_empty() && !public_key.is_empty() {
                         // Look up the sender's groups to verify membership
                         if let Ok(Some(sender)) = db.get_machine_by_id(mid) {
-                            if sender.groups.contains(&group_name.to_string()) {
+                            if sender.groups.contains(&group_name) {
                                 let event = serde_json::json!({
                                     "event": "key_request",
                                     "data": {
@@ -190,7 +470,7 @@ pub async fn handle_ws(socket: WebSocket, db: Arc<SyncDatabase>, hub: Arc<WsHub>
                                 let event_str = event.to_string();
 
                                 // Broadcast to other group members
-                                if let Ok(machines) = db.get_machines_by_group(group_name) {
+                                if let Ok(machines) = db.get_machines_by_group(&group_name) {
                                     for m in machines {
                                         if m.machine_id != *mid {
                                             hub.send_to_machine(&m.machine_id, &event_str).await;
@@ -203,21 +483,12 @@ pub async fn handle_ws(socket: WebSocket, db: Arc<SyncDatabase>, hub: Arc<WsHub>
                     }
                 }
             }
-            "key_response" => {
+            ClientMessage::KeyResponse {
+                group_name,
+                target_machine_id,
+                wrapped_key,
+            } => {
                 if let Some(ref mid) = machine_id {
-                    let group_name = data
-                        .get("group_name")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-                    let target_machine_id = data
-                        .get("target_machine_id")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-                    let wrapped_key = data
-                        .get("wrapped_key")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-
                     if !target_machine_id.is_empty() && !wrapped_key.is_empty() {
                         // Get the sender's public key to include in the response
                         let sender_public_key = if let Ok(Some(sender)) = db.get_machine_by_id(mid)
@@ -236,7 +507,7 @@ pub async fn handle_ws(socket: WebSocket, db: Arc<SyncDatabase>, hub: Arc<WsHub>
                             }
                         });
                         let sent = hub
-                            .send_to_machine(target_machine_id, &event.to_string())
+                            .send_to_machine(&target_machine_id, &event.to_string())
                             .await;
                         info!(
                             from = %mid,
@@ -248,16 +519,27 @@ pub async fn handle_ws(socket: WebSocket, db: Arc<SyncDatabase>, hub: Arc<WsHub>
                     }
                 }
             }
-            "history_batch" => {
+            ClientMessage::HistoryBatch { entries, .. } => {
                 if let Some(ref mid) = machine_id {
-                    let entries: Vec<shell_sync_core::models::HistoryEntry> =
-                        serde_json::from_value(data["entries"].clone()).unwrap_or_default();
+                    let entries = match entries {
+                        HistoryBatchEntries::Plain(entries) => entries,
+                        HistoryBatchEntries::Encrypted(entries) => {
+                            // Only group members hold the key, so the server cannot store these.
+                            warn!(machine_id = %mid, count = entries.len(), "Encrypted history batch skipped");
+                            continue;
+                        }
+                    };
                     if !entries.is_empty() {
-                        let count = db.insert_history_batch(&entries);
-                        info!(machine_id = %mid, count, "History batch received");
+                        let inserted = db.insert_history_batch(&entries);
+                        info!(
+                            machine_id = %mid,
+                            count = inserted.len(),
+                            duplicates = entries.len() - inserted.len(),
+                            "History batch received"
+                        );
 
-                        // Broadcast to group members
-                        if !machine_groups.is_empty() {
+                        let entries = unseen_entries(entries, inserted);
+                        if !entries.is_empty() && !machine_groups.is_empty() {
                             hub.broadcast_to_groups(
                                 &db,
                                 &machine_groups,
@@ -273,23 +555,14 @@ pub async fn handle_ws(socket: WebSocket, db: Arc<SyncDatabase>, hub: Arc<WsHub>
                     }
                 }
             }
-            "history_query" => {
+            ClientMessage::HistoryQuery {
+                after_timestamp,
+                group_name,
+                limit,
+            } => {
                 if let Some(ref _mid) = machine_id {
-                    let after_timestamp = data
-                        .get("after_timestamp")
-                        .and_then(|v| v.as_i64())
-                        .unwrap_or(0);
-                    let group_name = data
-                        .get("group_name")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("default");
-                    let limit = data
-                        .get("limit")
-                        .and_then(|v| v.as_i64())
-                        .unwrap_or(100)
-                        .min(1000);
-
-                    match db.get_history_after_timestamp(after_timestamp, group_name, limit) {
+                    let limit = limit.min(1000);
+                    match db.get_history_after_timestamp(after_timestamp, &group_name, limit) {
                         Ok(entries) => {
                             let has_more = entries.len() as i64 == limit;
                             let resp = serde_json::json!({
@@ -299,7 +572,9 @@ pub async fn handle_ws(socket: WebSocket, db: Arc<SyncDatabase>, hub: Arc<WsHub>
                                     "has_more": has_more,
                                 }
                             });
-                            let _ = tx.send(resp.to_string());
+                            if !queue_reply(&tx, resp.to_string()) {
+                                break;
+                            }
                         }
                         Err(e) => {
                             warn!("History query error: {e}");
@@ -307,17 +582,289 @@ pub async fn handle_ws(socket: WebSocket, db: Arc<SyncDatabase>, hub: Arc<WsHub>
                     }
                 }
             }
-            _ => {
-                warn!(msg_type, "Unknown WS message type");
-            }
         }
     }
 
     // Cleanup
-    if let Some(mid) = &machine_id {
-        hub.remove_client(mid).await;
-        info!(machine_id = %mid, "WS disconnected");
+    if let (Some(mid), Some(key)) = (&machine_id, &client_key) {
+        hub.remove_client(key).await;
+        info!(
+            machine_id = %mid,
+            dropped = dropped.load(Ordering::Relaxed),
+            "WS disconnected"
+        );
+    }
+
+    // With our sender gone the forwarder drains and closes; don't wait on a
+    // peer that stopped reading.
+    drop(tx);
+    let drained = tokio::time::timeout(CLOSE_TIMEOUT, &mut send_task).await;
+    if drained.is_err() {
+        send_task.abort();
+    }
+}
+
+/// The entries of a batch that were just stored, once each, so peers are
+/// sent only what they have not seen yet.
+fn unseen_entries(entries: Vec<HistoryEntry>, inserted: Vec<String>) -> Vec<HistoryEntry> {
+    let mut new_ids: HashSet<String> = inserted.into_iter().collect();
+    entries
+        .into_iter()
+        .filter(|e| new_ids.remove(&e.id))
+        .collect()
+}
+
+/// Queue a reply without waiting, so a peer that stopped reading cannot stall
+/// this connection's read loop. Returns false when the queue is full or
+/// closed; the caller then closes the connection.
+fn queue_reply(tx: &mpsc::Sender<String>, msg: String) -> bool {
+    match tx.try_send(msg) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!(error = %e, "WS reply queue unavailable, closing connection");
+            false
+        }
+    }
+}
+
+/// Wait for the next server ping, or forever when pings are disabled.
+async fn next_ping(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn ping_event() -> String {
+    let event = ServerEvent::Ping {
+        data: shell_sync_core::protocol::PongData {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+        },
+    };
+    serde_json::to_string(&event).unwrap_or_default()
+}
+
+/// Parse a client frame, or build the `error` event to send back when it is
+/// not a valid [`ClientMessage`].
+fn parse_client_message(text: &str) -> Result<ClientMessage, String> {
+    serde_json::from_str(text).map_err(|e| {
+        warn!("Invalid WS message: {e}");
+        let event = ServerEvent::Error {
+            data: ErrorData {
+                error: format!("Invalid message: {e}"),
+            },
+        };
+        serde_json::to_string(&event).unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn well_formed_message_parses() {
+        let msg = parse_client_message(r#"{"type":"auth","token":"tok"}"#).unwrap();
+        assert!(matches!(msg, ClientMessage::Auth { token, watch: false } if token == "tok"));
+        let msg = parse_client_message(r#"{"type":"history_query","after_timestamp":5}"#).unwrap();
+        assert!(matches!(
+            msg,
+            ClientMessage::HistoryQuery {
+                after_timestamp: 5,
+                limit: 100,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn malformed_message_gets_error_reply() {
+        for text in [
+            "not json",
+            r#"{"type":"bogus"}"#,
+            r#"{"type":"key_response","group_name":"g"}"#,
+        ] {
+            let reply = parse_client_message(text).unwrap_err();
+            let event: serde_json::Value = serde_json::from_str(&reply).unwrap();
+            assert_eq!(event["event"], "error");
+            assert!(event["data"]["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Invalid message"));
+        }
+    }
+
+    #[test]
+    fn batch_duplicates_are_broadcast_once() {
+        use crate::test_entry as entry;
+
+        let batch = vec![
+            entry("a", "ls"),
+            entry("seen", "pwd"),
+            entry("a", "ls"),
+            entry("b", "make"),
+        ];
+        let unseen = unseen_entries(batch, vec!["a".into(), "b".into()]);
+        let ids: Vec<&str> = unseen.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+    }
+
+    #[test]
+    fn replies_to_a_stalled_peer_do_not_wait() {
+        let (tx, mut rx) = mpsc::channel(2);
+        assert!(queue_reply(&tx, "a".into()));
+        assert!(queue_reply(&tx, "b".into()));
+        // Full: the caller closes the connection instead of blocking
+        assert!(!queue_reply(&tx, "c".into()));
+        assert_eq!(rx.try_recv().unwrap(), "a");
+        rx.close();
+        assert!(!queue_reply(&tx, "d".into()));
+    }
+
+    fn attach(hub_capacity: usize) -> (WsClient, mpsc::Receiver<String>) {
+        let (tx, rx) = mpsc::channel(hub_capacity);
+        let client = WsClient {
+            machine_id: "m1".into(),
+            tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+            resync_owed: Arc::new(AtomicBool::new(false)),
+            evict: Arc::new(Notify::new()),
+        };
+        (client, rx)
+    }
+
+    #[tokio::test]
+    async fn flooding_slow_client_disconnects_it() {
+        let hub = WsHub::with_limits(4, WsOverflowPolicy::Disconnect);
+        let (client, rx) = attach(hub.capacity);
+        let evict = Arc::clone(&client.evict);
+        hub.add_client("m1".into(), client).await;
+
+        let mut delivered = 0;
+        for i in 0..100 {
+            if hub.send_event("m1", "alias_updated", &format!("msg {i}")).await {
+                delivered += 1;
+            }
+        }
+
+        assert_eq!(delivered, 4);
+        assert_eq!(rx.len(), 4);
+        assert_eq!(hub.client_count().await, 0);
+        // The connection handler is told to close the socket
+        tokio::time::timeout(std::time::Duration::from_secs(1), evict.notified())
+            .await
+            .expect("client was not signalled to disconnect");
+    }
+
+    #[tokio::test]
+    async fn flooding_slow_client_coalesces_events() {
+        let hub = WsHub::with_limits(4, WsOverflowPolicy::Coalesce);
+        let (client, rx) = attach(hub.capacity);
+        let owed = Arc::clone(&client.resync_owed);
+        hub.add_client("m1".into(), client).await;
+
+        for i in 0..100 {
+            hub.send_event("m1", "alias_updated", &format!("msg {i}")).await;
+        }
+
+        assert_eq!(rx.len(), 4);
+        assert_eq!(hub.client_count().await, 1);
+        assert_eq!(hub.dropped_count("m1").await, Some(96));
+        assert!(owed.load(Ordering::Relaxed));
     }
 
-    send_task.abort();
+    #[tokio::test]
+    async fn non_alias_overflow_does_not_request_resync() {
+        let hub = WsHub::with_limits(1, WsOverflowPolicy::Coalesce);
+        let (client, _rx) = attach(hub.capacity);
+        let owed = Arc::clone(&client.resync_owed);
+        hub.add_client("m1".into(), client).await;
+
+        assert!(hub.send_to_machine("m1", "first").await);
+        assert!(!hub.send_to_machine("m1", "second").await);
+        assert_eq!(hub.dropped_count("m1").await, Some(1));
+        assert!(!owed.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn watch_connection_receives_broadcasts_beside_daemon() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let groups = vec!["default".to_string()];
+        db.register_machine("m1", "laptop", &groups, "linux", "tok1", None)
+            .unwrap();
+        db.register_machine("m2", "desktop", &groups, "linux", "tok2", None)
+            .unwrap();
+
+        let hub = WsHub::new();
+        let (daemon, mut daemon_rx) = attach(hub.capacity);
+        let (watcher, mut watcher_rx) = attach(hub.capacity);
+        hub.add_client("m1".into(), daemon).await;
+        hub.add_client("m1#watch-1".into(), watcher).await;
+
+        let data = serde_json::json!({ "entries": [] });
+        hub.broadcast_to_groups(&db, &groups, "history_sync", data, Some("m2"))
+            .await;
+        assert!(daemon_rx.try_recv().unwrap().contains("history_sync"));
+        assert!(watcher_rx.try_recv().unwrap().contains("history_sync"));
+
+        // Direct messages still go to the daemon only
+        assert!(hub.send_to_machine("m1", "key").await);
+        assert_eq!(daemon_rx.try_recv().unwrap(), "key");
+        assert!(watcher_rx.try_recv().is_err());
+
+        hub.remove_client("m1#watch-1").await;
+        assert_eq!(hub.client_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn subscription_receives_broadcasts_for_its_machine() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let work = vec!["work".to_string()];
+        db.register_machine("m1", "laptop", &work, "linux", "tok1", None)
+            .unwrap();
+        db.register_machine("m2", "desktop", &["home".to_string()], "linux", "tok2", None)
+            .unwrap();
+
+        let hub = WsHub::new();
+        let mut sub = hub.subscribe();
+        let home = vec!["home".to_string()];
+        hub.broadcast_to_groups(&db, &home, "alias_added", serde_json::json!({}), None)
+            .await;
+        let data = serde_json::json!({ "name": "gs" });
+        hub.broadcast_to_groups(&db, &work, "alias_added", data, None)
+            .await;
+
+        match sub.recv("m1").await {
+            Some(HubMessage::Event { event, message }) => {
+                assert_eq!(event, "alias_added");
+                assert!(message.contains("\"gs\""));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        hub.close_streams();
+        assert_eq!(sub.recv("m1").await, None);
+    }
+
+    #[tokio::test]
+    async fn close_all_evicts_every_client() {
+        let hub = WsHub::new();
+        let (daemon, _daemon_rx) = attach(hub.capacity);
+        let (watcher, _watcher_rx) = attach(hub.capacity);
+        let evicts = [Arc::clone(&daemon.evict), Arc::clone(&watcher.evict)];
+        hub.add_client("m1".into(), daemon).await;
+        hub.add_client("m1#watch-1".into(), watcher).await;
+
+        assert_eq!(hub.close_all().await, 2);
+        assert_eq!(hub.client_count().await, 0);
+        for evict in evicts {
+            tokio::time::timeout(std::time::Duration::from_secs(1), evict.notified())
+                .await
+                .unwrap();
+        }
+    }
 }
@@ -1,4 +1,9 @@
+use crate::search::{MatchExplain, MatchOptions, RankWeights, SearchResults};
+use crate::theme::Theme;
+use shell_sync_core::config::ClientConfig;
+use shell_sync_core::db::HistoryOrder;
 use shell_sync_core::models::HistoryEntry;
+use shell_sync_core::tz::DisplayTz;
 
 /// How the search query is matched against commands.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -29,6 +34,17 @@ impl SearchMode {
             Self::Regex => "REGEX",
         }
     }
+
+    /// Parse a mode name as written in config or `SHELL_SYNC_SEARCH_MODE`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "fuzzy" => Some(Self::Fuzzy),
+            "prefix" => Some(Self::Prefix),
+            "full" | "fulltext" => Some(Self::Fulltext),
+            "regex" => Some(Self::Regex),
+            _ => None,
+        }
+    }
 }
 
 /// Which subset of history entries to show.
@@ -38,6 +54,7 @@ pub enum FilterMode {
     Host,
     Session,
     Directory,
+    Group,
 }
 
 impl FilterMode {
@@ -47,7 +64,8 @@ impl FilterMode {
             Self::Global => Self::Host,
             Self::Host => Self::Session,
             Self::Session => Self::Directory,
-            Self::Directory => Self::Global,
+            Self::Directory => Self::Group,
+            Self::Group => Self::Global,
         }
     }
 
@@ -58,10 +76,109 @@ impl FilterMode {
             Self::Host => "HOST",
             Self::Session => "SESSION",
             Self::Directory => "DIR",
+            Self::Group => "GROUP",
+        }
+    }
+
+    /// Parse a filter name as written in config or `SHELL_SYNC_FILTER_MODE`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "global" => Some(Self::Global),
+            "host" => Some(Self::Host),
+            "session" => Some(Self::Session),
+            "dir" | "directory" => Some(Self::Directory),
+            "group" => Some(Self::Group),
+            _ => None,
+        }
+    }
+}
+
+/// What `search` prints for the accepted entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrintMode {
+    /// The command alone, ready to paste into the prompt.
+    #[default]
+    Command,
+    /// `cd <cwd> && <command>`, to run it where it was recorded.
+    CdAndCommand,
+    /// The full history entry as one JSON object per line.
+    Json,
+}
+
+impl PrintMode {
+    /// Output for `entries`; several accepted commands are chained with `&&`.
+    pub fn render(&self, entries: &[&HistoryEntry]) -> String {
+        match self {
+            Self::Command => entries
+                .iter()
+                .map(|e| e.command.as_str())
+                .collect::<Vec<_>>()
+                .join(" && "),
+            Self::CdAndCommand => entries
+                .iter()
+                .map(|e| format!("cd {} && {}", quote_path(&e.cwd), e.command))
+                .collect::<Vec<_>>()
+                .join(" && "),
+            Self::Json => entries
+                .iter()
+                .filter_map(|e| serde_json::to_string(e).ok())
+                .collect::<Vec<_>>()
+                .join("\n"),
         }
     }
 }
 
+/// Quote `path` for a POSIX shell unless it only has characters that need none.
+fn quote_path(path: &str) -> String {
+    let plain = !path.is_empty()
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-~+,@%:=".contains(c));
+    if plain {
+        path.to_string()
+    } else {
+        format!("'{}'", path.replace('\'', "'\\''"))
+    }
+}
+
+/// Progress of a `Ctrl+D` delete of the marked entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BulkDelete {
+    #[default]
+    Idle,
+    /// Waiting for the user to confirm with `y`.
+    Confirming,
+    /// Confirmed; the main loop performs the delete.
+    Confirmed,
+}
+
+/// The modes the search UI opens in: an environment override, else the
+/// config default, else Fuzzy/Global. Unrecognized values are ignored.
+pub fn start_modes(
+    config: Option<&ClientConfig>,
+    env: impl Fn(&str) -> Option<String>,
+) -> (SearchMode, FilterMode) {
+    let search = env("SHELL_SYNC_SEARCH_MODE")
+        .and_then(|v| SearchMode::parse(&v))
+        .or_else(|| {
+            config?
+                .default_search_mode
+                .as_deref()
+                .and_then(SearchMode::parse)
+        })
+        .unwrap_or(SearchMode::Fuzzy);
+    let filter = env("SHELL_SYNC_FILTER_MODE")
+        .and_then(|v| FilterMode::parse(&v))
+        .or_else(|| {
+            config?
+                .default_filter_mode
+                .as_deref()
+                .and_then(FilterMode::parse)
+        })
+        .unwrap_or(FilterMode::Global);
+    (search, filter)
+}
+
 /// Application state for the TUI search.
 pub struct App {
     /// Current search mode.
@@ -76,20 +193,53 @@ pub struct App {
     pub results: Vec<HistoryEntry>,
     /// Index of the selected result (0-based).
     pub selected: usize,
-    /// Total number of results available.
+    /// How many entries matched, including those beyond the displayed results.
     pub total_count: i64,
+    /// `total_count` is a lower bound because the search stopped scanning early.
+    pub total_is_partial: bool,
+    /// Why the current query could not be run (e.g. an invalid regex).
+    pub search_error: Option<String>,
     /// Whether running in inline mode (for shell integration).
     pub inline: bool,
-    /// The selected command to return on Enter (None if cancelled).
+    /// The text to print on Enter (None if cancelled), shaped by `print_mode`.
     pub chosen: Option<String>,
+    /// What accepting prints.
+    pub print_mode: PrintMode,
     /// Whether the app should quit.
     pub should_quit: bool,
     /// Current hostname for host-filter.
     pub current_hostname: String,
     /// Current session id for session-filter.
     pub current_session_id: String,
+    /// Commands recorded so far in the current session (shown in the SESSION footer).
+    pub session_command_count: i64,
     /// Current working directory for dir-filter.
     pub current_cwd: String,
+    /// Group for group-filter (from `--group` or `SHELL_SYNC_GROUP`).
+    pub current_group: String,
+    /// Time zone for rendering result timestamps.
+    pub tz: DisplayTz,
+    /// Usage weighting applied to fuzzy-mode ranking.
+    pub rank_weights: RankWeights,
+    /// Case-sensitivity and whole-word toggles for fulltext and regex modes.
+    pub match_options: MatchOptions,
+    /// Show each fuzzy match's score and matched characters after it.
+    pub explain: bool,
+    /// Why each of `results` matched, index for index; empty unless `explain`
+    /// is on and the query was fuzzy-matched.
+    pub explanations: Vec<MatchExplain>,
+    /// Timestamp order of results (ranked fuzzy matches keep their score order).
+    pub order: HistoryOrder,
+    /// Entry to select again once the next search completes.
+    pub reselect: Option<String>,
+    /// Colors and emphasis used when drawing.
+    pub theme: Theme,
+    /// Entries marked for a bulk action, in marking order.
+    pub marked: Vec<HistoryEntry>,
+    /// State of a pending bulk delete.
+    pub bulk_delete: BulkDelete,
+    /// Guidance shown in place of results, e.g. when there is no history yet.
+    pub notice: Option<String>,
 }
 
 impl App {
@@ -99,24 +249,74 @@ impl App {
         let cwd = std::env::current_dir()
             .map(|p| p.to_string_lossy().to_string())
             .unwrap_or_default();
+        let group = std::env::var("SHELL_SYNC_GROUP").unwrap_or_default();
+        let config = shell_sync_core::config::load_client_config().ok();
+        let (search_mode, filter_mode) =
+            start_modes(config.as_ref(), |key| std::env::var(key).ok());
 
         Self {
-            search_mode: SearchMode::Fuzzy,
-            filter_mode: FilterMode::Global,
+            search_mode,
+            filter_mode,
             input: initial_query.to_string(),
             cursor: initial_query.len(),
             results: Vec::new(),
             selected: 0,
             total_count: 0,
+            total_is_partial: false,
+            search_error: None,
             inline,
             chosen: None,
+            print_mode: PrintMode::default(),
             should_quit: false,
             current_hostname: hostname,
             current_session_id: session_id,
+            session_command_count: 0,
             current_cwd: cwd,
+            current_group: group,
+            tz: DisplayTz::from_env(),
+            rank_weights: RankWeights::default(),
+            match_options: MatchOptions::default(),
+            explain: false,
+            explanations: Vec::new(),
+            order: HistoryOrder::default(),
+            reselect: None,
+            theme: Theme::from_env(),
+            marked: Vec::new(),
+            bulk_delete: BulkDelete::Idle,
+            notice: None,
         }
     }
 
+    /// Store a complete list of results.
+    pub fn set_results(&mut self, outcome: Result<Vec<HistoryEntry>, regex::Error>) {
+        self.set_search_results(outcome.map(SearchResults::from));
+    }
+
+    /// Store the outcome of a search along with its match count, keeping only
+    /// the summary line of a regex compile error.
+    pub fn set_search_results(&mut self, outcome: Result<SearchResults, regex::Error>) {
+        let results = match outcome {
+            Ok(results) => {
+                self.search_error = None;
+                results
+            }
+            Err(e) => {
+                let msg = e.to_string();
+                let summary = msg.lines().last().unwrap_or_default();
+                self.search_error = Some(summary.trim_start_matches("error: ").to_string());
+                SearchResults::default()
+            }
+        };
+        self.results = results.entries;
+        self.explanations = if self.explain {
+            results.explain
+        } else {
+            Vec::new()
+        };
+        self.total_count = results.total as i64;
+        self.total_is_partial = results.partial;
+    }
+
     /// Returns the filter value string for the current filter mode.
     pub fn filter_value(&self) -> &str {
         match self.filter_mode {
@@ -124,9 +324,17 @@ impl App {
             FilterMode::Host => &self.current_hostname,
             FilterMode::Session => &self.current_session_id,
             FilterMode::Directory => &self.current_cwd,
+            FilterMode::Group => &self.current_group,
         }
     }
 
+    /// Scope results to a single group and switch to the group filter.
+    pub fn with_group(mut self, group: &str) -> Self {
+        self.current_group = group.to_string();
+        self.filter_mode = FilterMode::Group;
+        self
+    }
+
     /// Move selection up.
     pub fn select_previous(&mut self) {
         if self.selected > 0 {
@@ -141,14 +349,79 @@ impl App {
         }
     }
 
-    /// Accept the currently selected item.
+    /// Accept the marked entries, joined with `&&` in marking order, or the
+    /// selected one if nothing is marked.
     pub fn accept_selected(&mut self) {
-        if let Some(entry) = self.results.get(self.selected) {
-            self.chosen = Some(entry.command.clone());
+        let entries: Vec<&HistoryEntry> = if !self.marked.is_empty() {
+            self.marked.iter().collect()
+        } else {
+            self.results.get(self.selected).into_iter().collect()
+        };
+        if !entries.is_empty() {
+            self.chosen = Some(self.print_mode.render(&entries));
         }
         self.should_quit = true;
     }
 
+    /// Whether the entry with this id is marked.
+    pub fn is_marked(&self, id: &str) -> bool {
+        self.marked.iter().any(|e| e.id == id)
+    }
+
+    /// Mark or unmark the selected entry, then move down to the next one.
+    pub fn toggle_mark(&mut self) {
+        let Some(entry) = self.results.get(self.selected) else {
+            return;
+        };
+        if self.is_marked(&entry.id) {
+            let id = entry.id.clone();
+            self.marked.retain(|e| e.id != id);
+        } else {
+            self.marked.push(entry.clone());
+        }
+        self.select_next();
+    }
+
+    /// Ask to delete the marked entries; does nothing if none are marked.
+    pub fn request_bulk_delete(&mut self) {
+        if !self.marked.is_empty() {
+            self.bulk_delete = BulkDelete::Confirming;
+        }
+    }
+
+    /// Forget deleted entries: drop them from the results and the marks.
+    pub fn remove_entries(&mut self, ids: &[String]) {
+        let before = self.results.len();
+        if self.explanations.len() == before {
+            self.explanations = std::mem::take(&mut self.explanations)
+                .into_iter()
+                .zip(&self.results)
+                .filter(|(_, e)| !ids.contains(&e.id))
+                .map(|(why, _)| why)
+                .collect();
+        }
+        self.results.retain(|e| !ids.contains(&e.id));
+        self.marked.retain(|e| !ids.contains(&e.id));
+        self.total_count -= (before - self.results.len()) as i64;
+        self.selected = self.selected.min(self.results.len().saturating_sub(1));
+    }
+
+    /// Flip between newest-first and oldest-first, remembering the selected
+    /// entry so it stays selected after the search reruns.
+    pub fn toggle_order(&mut self) {
+        self.order = self.order.reversed();
+        self.reselect = self.results.get(self.selected).map(|e| e.id.clone());
+    }
+
+    /// After a search, select the remembered entry if it is still listed,
+    /// otherwise the top result.
+    pub fn restore_selection(&mut self) {
+        let id = self.reselect.take();
+        self.selected = id
+            .and_then(|id| self.results.iter().position(|e| e.id == id))
+            .unwrap_or(0);
+    }
+
     /// Cancel without selecting anything.
     pub fn cancel(&mut self) {
         self.chosen = None;
@@ -195,6 +468,48 @@ impl App {
                 .unwrap_or(self.input.len());
         }
     }
+
+    /// Move cursor to the start of the input.
+    pub fn move_cursor_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Move cursor to the end of the input.
+    pub fn move_cursor_end(&mut self) {
+        self.cursor = self.input.len();
+    }
+
+    /// Move cursor to the start of the previous word.
+    pub fn move_word_left(&mut self) {
+        self.cursor = self.prev_word_start();
+    }
+
+    /// Move cursor past the end of the next word.
+    pub fn move_word_right(&mut self) {
+        let after = &self.input[self.cursor..];
+        let word = after.trim_start();
+        let word_len = word.find(char::is_whitespace).unwrap_or(word.len());
+        self.cursor += after.len() - word.len() + word_len;
+    }
+
+    /// Delete the word before the cursor, along with any spaces after it.
+    pub fn delete_word_before(&mut self) {
+        let start = self.prev_word_start();
+        self.input.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    /// Byte offset where the word before the cursor begins. Words are runs of
+    /// non-whitespace, as with readline's `Ctrl+W`.
+    fn prev_word_start(&self) -> usize {
+        let before = self.input[..self.cursor].trim_end();
+        before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0)
+    }
 }
 
 fn hostname() -> String {
@@ -209,3 +524,234 @@ fn hostname() -> String {
                 .unwrap_or_else(|| "unknown".to_string())
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_at_end(query: &str) -> App {
+        App::new(query, false)
+    }
+
+    fn config_with_modes(search: &str, filter: &str) -> ClientConfig {
+        toml::from_str(&format!(
+            r#"
+            server_url = "http://localhost:8888"
+            machine_id = "m1"
+            auth_token = "t"
+            groups = ["default"]
+            hostname = "h"
+            default_search_mode = "{search}"
+            default_filter_mode = "{filter}"
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn start_modes_follow_config_then_env() {
+        let no_env = |_: &str| None;
+        assert_eq!(
+            start_modes(None, no_env),
+            (SearchMode::Fuzzy, FilterMode::Global)
+        );
+
+        let config = config_with_modes("regex", "HOST");
+        assert_eq!(
+            start_modes(Some(&config), no_env),
+            (SearchMode::Regex, FilterMode::Host)
+        );
+
+        let env = |key: &str| match key {
+            "SHELL_SYNC_SEARCH_MODE" => Some("prefix".to_string()),
+            "SHELL_SYNC_FILTER_MODE" => Some("dir".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            start_modes(Some(&config), env),
+            (SearchMode::Prefix, FilterMode::Directory)
+        );
+    }
+
+    #[test]
+    fn invalid_start_modes_fall_back() {
+        let config = config_with_modes("glob", "everywhere");
+        let bad_env = |_: &str| Some("nonsense".to_string());
+        assert_eq!(
+            start_modes(Some(&config), bad_env),
+            (SearchMode::Fuzzy, FilterMode::Global)
+        );
+
+        // A bad override does not hide a good config value.
+        let config = config_with_modes("fulltext", "session");
+        assert_eq!(
+            start_modes(Some(&config), bad_env),
+            (SearchMode::Fulltext, FilterMode::Session)
+        );
+    }
+
+    use crate::test_entry as entry;
+
+    #[test]
+    fn marking_toggles_and_accepts_in_order() {
+        let mut app = App::new("", false);
+        app.set_results(Ok(vec![entry("a", "make"), entry("b", "make test"), entry("c", "ls")]));
+
+        app.selected = 2;
+        app.toggle_mark();
+        assert_eq!(app.selected, 2, "stays on the last row");
+        app.selected = 0;
+        app.toggle_mark();
+        assert_eq!(app.selected, 1);
+        app.toggle_mark();
+        assert!(app.is_marked("a") && app.is_marked("b") && app.is_marked("c"));
+
+        // Toggling again unmarks.
+        app.selected = 1;
+        app.toggle_mark();
+        assert!(!app.is_marked("b"));
+
+        app.accept_selected();
+        assert_eq!(app.chosen.as_deref(), Some("ls && make"));
+    }
+
+    #[test]
+    fn print_modes_shape_accepted_output() {
+        let mut app = App::new("", false);
+        let mut spaced = entry("b", "make test");
+        spaced.cwd = "/home/me/it's here".into();
+        app.set_results(Ok(vec![entry("a", "cargo build"), spaced]));
+
+        app.accept_selected();
+        assert_eq!(app.chosen.as_deref(), Some("cargo build"));
+
+        app.print_mode = PrintMode::CdAndCommand;
+        app.accept_selected();
+        assert_eq!(app.chosen.as_deref(), Some("cd /tmp && cargo build"));
+        app.selected = 1;
+        app.accept_selected();
+        assert_eq!(
+            app.chosen.as_deref(),
+            Some(r"cd '/home/me/it'\''s here' && make test")
+        );
+
+        app.print_mode = PrintMode::Json;
+        app.selected = 0;
+        app.accept_selected();
+        let json: serde_json::Value = serde_json::from_str(app.chosen.as_deref().unwrap()).unwrap();
+        assert_eq!(json["command"], "cargo build");
+        assert_eq!(json["cwd"], "/tmp");
+        assert_eq!(json["exit_code"], 0);
+    }
+
+    #[test]
+    fn bulk_delete_needs_marks() {
+        let mut app = App::new("", false);
+        app.set_results(Ok(vec![entry("a", "make"), entry("b", "ls")]));
+        app.request_bulk_delete();
+        assert_eq!(app.bulk_delete, BulkDelete::Idle);
+
+        app.toggle_mark();
+        app.request_bulk_delete();
+        assert_eq!(app.bulk_delete, BulkDelete::Confirming);
+
+        app.selected = 1;
+        app.remove_entries(&["a".to_string()]);
+        assert!(app.marked.is_empty());
+        assert_eq!(app.selected, 0);
+        assert_eq!(app.total_count, 1);
+    }
+
+    #[test]
+    fn explanations_are_kept_only_in_explain_mode() {
+        let why = |score| MatchExplain {
+            score,
+            match_score: 100,
+            indices: vec![0],
+        };
+        let results = || SearchResults {
+            entries: vec![entry("a", "make"), entry("b", "ls")],
+            total: 2,
+            partial: false,
+            explain: vec![why(150.0), why(120.0)],
+        };
+        let mut app = App::new("", false);
+        app.set_search_results(Ok(results()));
+        assert!(app.explanations.is_empty());
+
+        app.explain = true;
+        app.set_search_results(Ok(results()));
+        assert_eq!(app.explanations, [why(150.0), why(120.0)]);
+
+        app.remove_entries(&["a".to_string()]);
+        assert_eq!(app.explanations, [why(120.0)]);
+    }
+
+    #[test]
+    fn toggling_order_keeps_the_selected_entry() {
+        let mut app = App::new("", false);
+        app.set_results(Ok(vec![entry("c", "ls"), entry("b", "make"), entry("a", "pwd")]));
+        app.selected = 1;
+
+        app.toggle_order();
+        assert_eq!(app.order, HistoryOrder::OldestFirst);
+        app.set_results(Ok(vec![entry("a", "pwd"), entry("b", "make"), entry("c", "ls")]));
+        app.restore_selection();
+        assert_eq!(app.results[app.selected].id, "b");
+
+        // A plain refresh goes back to the top
+        app.selected = 2;
+        app.restore_selection();
+        assert_eq!(app.selected, 0);
+    }
+
+    #[test]
+    fn word_movement_skips_runs_of_spaces() {
+        let mut app = app_at_end("git  commit -m  'héllo wörld'");
+        let len = app.input.len();
+
+        app.move_word_left();
+        assert_eq!(&app.input[app.cursor..], "wörld'");
+        app.move_word_left();
+        assert_eq!(&app.input[app.cursor..], "'héllo wörld'");
+        app.move_word_left();
+        assert_eq!(&app.input[app.cursor..], "-m  'héllo wörld'");
+
+        app.move_word_right();
+        assert_eq!(&app.input[..app.cursor], "git  commit -m");
+        app.move_word_right();
+        assert_eq!(&app.input[..app.cursor], "git  commit -m  'héllo");
+
+        app.move_cursor_home();
+        app.move_word_left();
+        assert_eq!(app.cursor, 0);
+        app.move_word_right();
+        assert_eq!(&app.input[..app.cursor], "git");
+
+        app.move_cursor_end();
+        assert_eq!(app.cursor, len);
+        app.move_word_right();
+        assert_eq!(app.cursor, len);
+    }
+
+    #[test]
+    fn delete_word_before_cursor() {
+        let mut app = app_at_end("ls  -la   naïve ");
+        app.delete_word_before();
+        assert_eq!(app.input, "ls  -la   ");
+        app.delete_word_before();
+        assert_eq!(app.input, "ls  ");
+
+        // Mid-input, only the part of the word before the cursor goes.
+        let mut app = app_at_end("echo naïve");
+        app.move_cursor_left();
+        app.move_cursor_left();
+        app.delete_word_before();
+        assert_eq!(app.input, "echo ve");
+        assert_eq!(app.cursor, "echo ".len());
+
+        app.move_cursor_home();
+        app.delete_word_before();
+        assert_eq!(app.input, "echo ve");
+    }
+}
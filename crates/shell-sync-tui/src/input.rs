@@ -1,4 +1,4 @@
-use crate::app::App;
+use crate::app::{App, BulkDelete};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use std::time::Duration;
 
@@ -20,6 +20,15 @@ pub fn handle_event(app: &mut App) -> anyhow::Result<bool> {
 fn handle_key(app: &mut App, key: KeyEvent) -> bool {
     let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
 
+    // While a bulk delete awaits confirmation, `y` confirms and any other key cancels.
+    if app.bulk_delete == BulkDelete::Confirming {
+        app.bulk_delete = match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => BulkDelete::Confirmed,
+            _ => BulkDelete::Idle,
+        };
+        return false;
+    }
+
     match (key.code, ctrl) {
         // Ctrl+C / Escape: cancel
         (KeyCode::Char('c'), true) | (KeyCode::Esc, _) => {
@@ -33,21 +42,62 @@ fn handle_key(app: &mut App, key: KeyEvent) -> bool {
             true
         }
 
+        // Ctrl+O: flip between newest-first and oldest-first
+        (KeyCode::Char('o'), true) => {
+            app.toggle_order();
+            true
+        }
+
         // Ctrl+S: cycle filter mode
         (KeyCode::Char('s'), true) => {
             app.filter_mode = app.filter_mode.next();
             true
         }
 
-        // Enter: accept selected
+        // Alt+I: toggle case sensitivity. Ctrl+I also works where the terminal
+        // reports it, but most send it as Tab, which marks the entry.
+        (KeyCode::Char('i'), true) => {
+            app.match_options.case_sensitive = !app.match_options.case_sensitive;
+            true
+        }
+        (KeyCode::Char('i'), false) if key.modifiers.contains(KeyModifiers::ALT) => {
+            app.match_options.case_sensitive = !app.match_options.case_sensitive;
+            true
+        }
+
+        // Alt+W: toggle whole-word matching
+        (KeyCode::Char('w'), false) if key.modifiers.contains(KeyModifiers::ALT) => {
+            app.match_options.whole_word = !app.match_options.whole_word;
+            true
+        }
+
+        // Alt+E: toggle showing why each fuzzy match matched
+        (KeyCode::Char('e'), false) if key.modifiers.contains(KeyModifiers::ALT) => {
+            app.explain = !app.explain;
+            true
+        }
+
+        // Ctrl+W: delete previous word
+        (KeyCode::Char('w'), true) => {
+            app.delete_word_before();
+            true
+        }
+
+        // Enter: accept the marked entries, or the selected one
         (KeyCode::Enter, _) => {
             app.accept_selected();
             false
         }
 
-        // Tab: same as Enter (paste selected for inline mode)
+        // Tab: mark/unmark the selected entry
         (KeyCode::Tab, _) => {
-            app.accept_selected();
+            app.toggle_mark();
+            false
+        }
+
+        // Ctrl+D: delete the marked entries (asks first)
+        (KeyCode::Char('d'), true) => {
+            app.request_bulk_delete();
             false
         }
 
@@ -63,6 +113,16 @@ fn handle_key(app: &mut App, key: KeyEvent) -> bool {
             false
         }
 
+        // Alt+Left / Alt+Right: move by word
+        (KeyCode::Left, _) if key.modifiers.contains(KeyModifiers::ALT) => {
+            app.move_word_left();
+            false
+        }
+        (KeyCode::Right, _) if key.modifiers.contains(KeyModifiers::ALT) => {
+            app.move_word_right();
+            false
+        }
+
         // Left arrow: move cursor left
         (KeyCode::Left, _) => {
             app.move_cursor_left();
@@ -88,15 +148,15 @@ fn handle_key(app: &mut App, key: KeyEvent) -> bool {
             true
         }
 
-        // Ctrl+A: move to start
-        (KeyCode::Char('a'), true) => {
-            app.cursor = 0;
+        // Home / Ctrl+A: move to start
+        (KeyCode::Home, _) | (KeyCode::Char('a'), true) => {
+            app.move_cursor_home();
             false
         }
 
-        // Ctrl+E: move to end
-        (KeyCode::Char('e'), true) => {
-            app.cursor = app.input.len();
+        // End / Ctrl+E: move to end
+        (KeyCode::End, _) | (KeyCode::Char('e'), true) => {
+            app.move_cursor_end();
             false
         }
 
@@ -1,9 +1,10 @@
 pub mod app;
 pub mod input;
 pub mod search;
+pub mod theme;
 pub mod ui;
 
-use app::App;
+use app::{App, BulkDelete, PrintMode};
 use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
@@ -12,29 +13,59 @@ use ratatui::{backend::CrosstermBackend, Terminal};
 use shell_sync_core::config::history_db_path;
 use shell_sync_core::db::SyncDatabase;
 use std::io;
+use std::path::Path;
 
 const SEARCH_LIMIT: i64 = 200;
 
+/// Shown instead of results when there is no history database yet.
+pub const NO_HISTORY_NOTICE: &str =
+    "No history yet. Run `shell-sync init-hooks` and start the daemon with `shell-sync connect`.";
+
+/// Open the history database at `path`, or `None` if it does not exist yet.
+///
+/// Never creates the file: an empty database here would only hide the fact
+/// that the daemon has not recorded anything.
+fn open_history(path: &Path) -> anyhow::Result<Option<SyncDatabase>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("History database path is not UTF-8: {}", path.display()))?;
+    Ok(Some(SyncDatabase::open(path)?))
+}
+
 /// Main entry point for the TUI search.
 ///
 /// Opens the history database, runs the interactive search loop, and
-/// prints the selected command to stdout (if any) when the user presses Enter.
-pub fn run_search(query: &str, inline: bool) -> anyhow::Result<()> {
-    let db_path = history_db_path();
-    let db = SyncDatabase::open(db_path.to_str().unwrap_or("history.db"))?;
+/// prints the selected entry to stdout (if any) as `print` asks when the
+/// user presses Enter. With `explain`, fuzzy matches start out showing their
+/// scores.
+pub fn run_search(
+    query: &str,
+    inline: bool,
+    group: Option<&str>,
+    print: PrintMode,
+    explain: bool,
+) -> anyhow::Result<()> {
+    let db = open_history(&history_db_path())?;
 
     let mut app = App::new(query, inline);
+    app.print_mode = print;
+    app.explain = explain;
+    match &db {
+        Some(db) if !app.current_session_id.is_empty() => {
+            app.session_command_count = db.get_session_history_count(&app.current_session_id)?;
+        }
+        Some(_) => {}
+        None => app.notice = Some(NO_HISTORY_NOTICE.to_string()),
+    }
+    if let Some(group) = group {
+        app = app.with_group(group);
+    }
 
     // Initial search
-    app.results = search::search(
-        &db,
-        &app.input,
-        app.search_mode,
-        app.filter_mode,
-        app.filter_value(),
-        SEARCH_LIMIT,
-    );
-    app.total_count = app.results.len() as i64;
+    refresh_results(&mut app, db.as_ref());
 
     // Setup terminal
     enable_raw_mode()?;
@@ -44,7 +75,7 @@ pub fn run_search(query: &str, inline: bool) -> anyhow::Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     // Main loop
-    let result = run_loop(&mut terminal, &mut app, &db);
+    let result = run_loop(&mut terminal, &mut app, db.as_ref());
 
     // Restore terminal
     disable_raw_mode()?;
@@ -53,7 +84,7 @@ pub fn run_search(query: &str, inline: bool) -> anyhow::Result<()> {
 
     result?;
 
-    // Print chosen command to stdout
+    // Print the chosen entry to stdout
     if let Some(cmd) = app.chosen {
         print!("{}", cmd);
     }
@@ -61,10 +92,40 @@ pub fn run_search(query: &str, inline: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Re-run the search for the current input, recording a bad regex instead of
+/// showing an empty list.
+fn refresh_results(app: &mut App, db: Option<&SyncDatabase>) {
+    let Some(db) = db else {
+        app.set_results(Ok(Vec::new()));
+        return;
+    };
+    let outcome = search::search_counted(
+        db,
+        &app.input,
+        app.search_mode,
+        app.filter_mode,
+        app.filter_value(),
+        SEARCH_LIMIT,
+        &app.rank_weights,
+        app.match_options,
+        app.order,
+    );
+    app.set_search_results(outcome);
+}
+
+/// Delete the marked entries from local history and from the results.
+fn delete_marked(app: &mut App, db: &SyncDatabase) -> anyhow::Result<usize> {
+    app.bulk_delete = BulkDelete::Idle;
+    let ids: Vec<String> = app.marked.iter().map(|e| e.id.clone()).collect();
+    let deleted = db.delete_history_entries(&ids)?;
+    app.remove_entries(&ids);
+    Ok(deleted)
+}
+
 fn run_loop(
     terminal: &mut Terminal<CrosstermBackend<io::Stderr>>,
     app: &mut App,
-    db: &SyncDatabase,
+    db: Option<&SyncDatabase>,
 ) -> anyhow::Result<()> {
     loop {
         terminal.draw(|frame| ui::draw(frame, app))?;
@@ -79,20 +140,100 @@ fn run_loop(
             break;
         }
 
+        if app.bulk_delete == BulkDelete::Confirmed {
+            match db {
+                Some(db) => {
+                    delete_marked(app, db)?;
+                }
+                None => app.bulk_delete = BulkDelete::Idle,
+            }
+        }
+
         if needs_search {
-            app.results = search::search(
-                db,
-                &app.input,
-                app.search_mode,
-                app.filter_mode,
-                app.filter_value(),
-                SEARCH_LIMIT,
-            );
-            app.total_count = app.results.len() as i64;
-            // Reset selection to top when results change
-            app.selected = 0;
+            refresh_results(app, db);
+            // Reset selection to top when results change, unless the order
+            // was flipped and the selected entry is still there
+            app.restore_selection();
         }
     }
 
     Ok(())
 }
+
+/// A history entry for tests; override fields with struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_entry(id: &str, command: &str) -> shell_sync_core::models::HistoryEntry {
+    shell_sync_core::models::HistoryEntry {
+        id: id.into(),
+        command: command.into(),
+        cwd: "/tmp".into(),
+        exit_code: 0,
+        duration_ms: 5,
+        session_id: "s1".into(),
+        machine_id: "m1".into(),
+        hostname: "host".into(),
+        timestamp: 1000,
+        shell: "zsh".into(),
+        group_name: "default".into(),
+        truncated: false,
+        unknown_duration: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_entry as entry;
+
+    #[test]
+    fn missing_history_db_is_not_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("history.db");
+        assert!(open_history(&missing).unwrap().is_none());
+        assert!(!missing.exists());
+
+        let mut app = App::new("git", false);
+        app.notice = Some(NO_HISTORY_NOTICE.to_string());
+        refresh_results(&mut app, None);
+        assert!(app.results.is_empty());
+        assert!(app.search_error.is_none());
+
+        SyncDatabase::open(missing.to_str().unwrap()).unwrap();
+        assert!(open_history(&missing).unwrap().is_some());
+    }
+
+    #[test]
+    fn bulk_delete_removes_exactly_the_marked_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let entries = [entry("a", "make"), entry("b", "ls"), entry("c", "pwd")];
+        for e in &entries {
+            db.insert_history_entry(e).unwrap();
+        }
+        db.add_history_pending(&entries[2]).unwrap();
+
+        let mut app = App::new("", false);
+        app.set_results(Ok(entries.to_vec()));
+        app.toggle_mark(); // a
+        app.select_next(); // skip b
+        app.toggle_mark(); // c
+        app.request_bulk_delete();
+        assert_eq!(app.bulk_delete, BulkDelete::Confirming);
+        app.bulk_delete = BulkDelete::Confirmed;
+
+        assert_eq!(delete_marked(&mut app, &db).unwrap(), 2);
+        assert_eq!(app.bulk_delete, BulkDelete::Idle);
+        assert!(app.marked.is_empty());
+        let left: Vec<&str> = app.results.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(left, ["b"]);
+
+        let in_db: Vec<String> = db
+            .search_history("", None, None, None, None, 10, 0)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(in_db, ["b"]);
+        assert_eq!(db.get_pending_count().unwrap(), 0);
+    }
+}
@@ -1,12 +1,131 @@
 use crate::app::{FilterMode, SearchMode};
 use nucleo::pattern::{CaseMatching, Normalization, Pattern};
 use nucleo::Matcher;
-use shell_sync_core::db::SyncDatabase;
+use shell_sync_core::db::{HistoryOrder, SyncDatabase};
 use shell_sync_core::models::HistoryEntry;
+use std::collections::HashMap;
+
+/// How fuzzy match quality is blended with usage when ranking results.
+///
+/// The final score is `match_score * (1 + frequency * ln(count) + recency * decay)`,
+/// where `decay` halves every `half_life_hours` since the command was last run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RankWeights {
+    /// Boost per natural-log of how many times the command appears.
+    pub frequency: f64,
+    /// Boost for a command run just now, fading with age.
+    pub recency: f64,
+    /// Hours for the recency boost to halve.
+    pub half_life_hours: f64,
+}
+
+impl Default for RankWeights {
+    fn default() -> Self {
+        Self {
+            frequency: 0.25,
+            recency: 0.5,
+            half_life_hours: 72.0,
+        }
+    }
+}
+
+/// Case and word-boundary options for fulltext and regex matching.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchOptions {
+    /// Match letter case exactly instead of ignoring it.
+    pub case_sensitive: bool,
+    /// Only match the query where it forms whole words (`\b` on both ends).
+    pub whole_word: bool,
+}
+
+impl MatchOptions {
+    /// Compile a regex pattern with these options applied.
+    fn compile(&self, pattern: &str) -> Result<regex::Regex, regex::Error> {
+        let pattern = if self.whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern.to_string()
+        };
+        regex::RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+    }
+}
+
+/// The matches shown for a query and how many there were in all.
+#[derive(Debug, Clone, Default)]
+pub struct SearchResults {
+    /// Matches to display, at most the search limit.
+    pub entries: Vec<HistoryEntry>,
+    /// Matches found before the limit was applied.
+    pub total: usize,
+    /// Only the most recent part of history was scanned, so `total` is a
+    /// lower bound.
+    pub partial: bool,
+    /// Why each of `entries` matched, index for index. Only fuzzy matches
+    /// for a non-empty query are scored; otherwise this is empty.
+    pub explain: Vec<MatchExplain>,
+}
+
+/// The score a fuzzy match ranked by and where the query matched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchExplain {
+    /// Match score after the frequency and recency boosts.
+    pub score: f64,
+    /// The raw nucleo score for the query alone.
+    pub match_score: u32,
+    /// Character positions in the command that the query matched, ascending.
+    pub indices: Vec<u32>,
+}
+
+impl MatchExplain {
+    /// The matched characters of `command`, in order.
+    pub fn matched(&self, command: &str) -> String {
+        command
+            .chars()
+            .enumerate()
+            .filter(|(i, _)| self.indices.binary_search(&(*i as u32)).is_ok())
+            .map(|(_, c)| c)
+            .collect()
+    }
+}
+
+impl SearchResults {
+    /// Keep the first `limit` of `matches`, remembering how many there were.
+    fn capped(matches: Vec<HistoryEntry>, limit: i64, partial: bool) -> Self {
+        let total = matches.len();
+        let mut entries = matches;
+        entries.truncate(limit as usize);
+        Self {
+            entries,
+            total,
+            partial,
+            explain: Vec::new(),
+        }
+    }
+
+    /// Whether more entries matched than are shown.
+    pub fn is_capped(&self) -> bool {
+        self.total > self.entries.len()
+    }
+}
+
+impl From<Vec<HistoryEntry>> for SearchResults {
+    fn from(entries: Vec<HistoryEntry>) -> Self {
+        Self {
+            total: entries.len(),
+            entries,
+            partial: false,
+            explain: Vec::new(),
+        }
+    }
+}
 
 /// Execute a search against the local history database.
 ///
-/// Returns matching entries (up to `limit`) for the given query, mode, and filter.
+/// Returns matching entries (up to `limit`) for the given query, mode, and filter,
+/// or the compile error if the query is not a valid pattern in regex mode.
+#[allow(clippy::too_many_arguments)]
 pub fn search(
     db: &SyncDatabase,
     query: &str,
@@ -14,171 +133,284 @@ pub fn search(
     filter: FilterMode,
     filter_value: &str,
     limit: i64,
-) -> Vec<HistoryEntry> {
+    weights: &RankWeights,
+    options: MatchOptions,
+) -> Result<Vec<HistoryEntry>, regex::Error> {
+    let order = HistoryOrder::NewestFirst;
+    search_counted(db, query, mode, filter, filter_value, limit, weights, options, order)
+        .map(|results| results.entries)
+}
+
+/// Like [`search`], also counting the matches beyond `limit`, in `order`.
+///
+/// Each mode scans the first `limit * 10` candidates in `order`, so past that
+/// the total is a lower bound (see [`SearchResults::partial`]). Fuzzy matches
+/// for a non-empty query are ranked by score, so `order` does not apply to them.
+#[allow(clippy::too_many_arguments)]
+pub fn search_counted(
+    db: &SyncDatabase,
+    query: &str,
+    mode: SearchMode,
+    filter: FilterMode,
+    filter_value: &str,
+    limit: i64,
+    weights: &RankWeights,
+    options: MatchOptions,
+    order: HistoryOrder,
+) -> Result<SearchResults, regex::Error> {
     // Build filter args from filter mode
-    let (machine_id, session_id, cwd) = match filter {
-        FilterMode::Global => (None, None, None),
+    let (machine_id, session_id, cwd, group) = match filter {
+        FilterMode::Global => (None, None, None, None),
         FilterMode::Host => {
             if filter_value.is_empty() {
-                (None, None, None)
+                (None, None, None, None)
             } else {
                 // Host filter: we match on hostname, but DB filters on machine_id.
                 // We'll do a broad SQL search then filter on hostname in post.
-                (None, None, None)
+                (None, None, None, None)
             }
         }
         FilterMode::Session => {
             if filter_value.is_empty() {
-                (None, None, None)
+                (None, None, None, None)
             } else {
-                (None, Some(filter_value), None)
+                (None, Some(filter_value), None, None)
             }
         }
         FilterMode::Directory => {
             if filter_value.is_empty() {
-                (None, None, None)
+                (None, None, None, None)
             } else {
-                (None, None, Some(filter_value))
+                (None, None, Some(filter_value), None)
+            }
+        }
+        FilterMode::Group => {
+            if filter_value.is_empty() {
+                (None, None, None, None)
+            } else {
+                (None, None, None, Some(filter_value))
             }
         }
     };
 
-    match mode {
-        SearchMode::Fuzzy => search_fuzzy(db, query, machine_id, session_id, cwd, filter, filter_value, limit),
-        SearchMode::Prefix => search_prefix(db, query, machine_id, session_id, cwd, filter, filter_value, limit),
-        SearchMode::Fulltext => search_fulltext(db, query, machine_id, session_id, cwd, filter, filter_value, limit),
-        SearchMode::Regex => search_regex(db, query, machine_id, session_id, cwd, filter, filter_value, limit),
-    }
+    Ok(match mode {
+        SearchMode::Fuzzy => search_fuzzy(db, query, machine_id, session_id, cwd, group, filter, filter_value, limit, weights, order),
+        SearchMode::Prefix => search_prefix(db, query, machine_id, session_id, cwd, group, filter, filter_value, limit, order),
+        SearchMode::Fulltext => search_fulltext(db, query, machine_id, session_id, cwd, group, filter, filter_value, limit, options, order),
+        SearchMode::Regex => search_regex(db, query, machine_id, session_id, cwd, group, filter, filter_value, limit, options, order)?,
+    })
 }
 
+#[allow(clippy::too_many_arguments)]
 fn search_fuzzy(
     db: &SyncDatabase,
     query: &str,
     _machine_id: Option<&str>,
     session_id: Option<&str>,
     cwd: Option<&str>,
+    group: Option<&str>,
     filter: FilterMode,
     filter_value: &str,
     limit: i64,
-) -> Vec<HistoryEntry> {
+    weights: &RankWeights,
+    order: HistoryOrder,
+) -> SearchResults {
+    // Fetch a broad set and rank with nucleo; ranking starts from the newest
+    let order = if query.is_empty() { order } else { HistoryOrder::NewestFirst };
+    let broad_limit = limit * 10;
+    let candidates = db
+        .search_history_ordered("", None, session_id, cwd, group, broad_limit, 0, order)
+        .unwrap_or_default();
+    let partial = candidates.len() as i64 >= broad_limit;
+
     if query.is_empty() {
         // No query: return most recent entries
-        return db
-            .search_history("", None, session_id, cwd, limit, 0)
-            .unwrap_or_default()
+        let matches = candidates
             .into_iter()
             .filter(|e| apply_host_filter(e, filter, filter_value))
             .collect();
+        return SearchResults::capped(matches, limit, partial);
     }
 
-    // Fetch a broad set and rank with nucleo
-    let broad_limit = limit * 10;
-    let candidates = db
-        .search_history("", None, session_id, cwd, broad_limit, 0)
-        .unwrap_or_default();
-
     let mut matcher = Matcher::new(nucleo::Config::DEFAULT);
     let pattern = Pattern::parse(query, CaseMatching::Smart, Normalization::Smart);
 
-    let mut scored: Vec<(i64, HistoryEntry)> = candidates
+    let scored: Vec<(u32, HistoryEntry)> = candidates
         .into_iter()
         .filter(|e| apply_host_filter(e, filter, filter_value))
         .filter_map(|entry| {
             let mut buf = Vec::new();
             let haystack = nucleo::Utf32Str::new(&entry.command, &mut buf);
             let score = pattern.score(haystack, &mut matcher)?;
-            Some((score as i64, entry))
+            Some((score, entry))
         })
         .collect();
 
-    // Sort by score descending, then by timestamp descending for ties
-    scored.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.timestamp.cmp(&a.1.timestamp)));
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let ranked = rank_matches(scored, weights, now_ms);
+    // Match positions are only worked out for the entries that are shown
+    let explain = ranked
+        .iter()
+        .take(limit as usize)
+        .map(|(score, match_score, entry)| {
+            let mut buf = Vec::new();
+            let haystack = nucleo::Utf32Str::new(&entry.command, &mut buf);
+            let mut indices = Vec::new();
+            pattern.indices(haystack, &mut matcher, &mut indices);
+            indices.sort_unstable();
+            indices.dedup();
+            MatchExplain {
+                score: *score,
+                match_score: *match_score,
+                indices,
+            }
+        })
+        .collect();
+    let entries = ranked.into_iter().map(|(_, _, e)| e).collect();
+    SearchResults {
+        explain,
+        ..SearchResults::capped(entries, limit, partial)
+    }
+}
 
-    scored
+/// Order fuzzy matches by match score boosted by how often and how recently
+/// each command was run. Ties break on newest timestamp, then id, so the
+/// order is stable across refreshes. Each entry comes back with its boosted
+/// and raw scores.
+fn rank_matches(
+    scored: Vec<(u32, HistoryEntry)>,
+    weights: &RankWeights,
+    now_ms: i64,
+) -> Vec<(f64, u32, HistoryEntry)> {
+    let mut usage: HashMap<&str, (u32, i64)> = HashMap::new();
+    for (_, entry) in &scored {
+        let slot = usage.entry(entry.command.as_str()).or_insert((0, i64::MIN));
+        slot.0 += 1;
+        slot.1 = slot.1.max(entry.timestamp);
+    }
+
+    let boosts: Vec<f64> = scored
+        .iter()
+        .map(|(score, entry)| {
+            let (count, last_run) = usage[entry.command.as_str()];
+            let age_hours = (now_ms - last_run).max(0) as f64 / 3_600_000.0;
+            let decay = if weights.half_life_hours > 0.0 {
+                0.5f64.powf(age_hours / weights.half_life_hours)
+            } else {
+                0.0
+            };
+            *score as f64
+                * (1.0 + weights.frequency * (count as f64).ln() + weights.recency * decay)
+        })
+        .collect();
+
+    let mut ranked: Vec<(f64, u32, HistoryEntry)> = boosts
         .into_iter()
-        .take(limit as usize)
-        .map(|(_, e)| e)
-        .collect()
+        .zip(scored)
+        .map(|(boost, (score, e))| (boost, score, e))
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.0.total_cmp(&a.0)
+            .then(b.2.timestamp.cmp(&a.2.timestamp))
+            .then(a.2.id.cmp(&b.2.id))
+    });
+    ranked
 }
 
+#[allow(clippy::too_many_arguments)]
 fn search_prefix(
     db: &SyncDatabase,
     query: &str,
     _machine_id: Option<&str>,
     session_id: Option<&str>,
     cwd: Option<&str>,
+    group: Option<&str>,
     filter: FilterMode,
     filter_value: &str,
     limit: i64,
-) -> Vec<HistoryEntry> {
-    if query.is_empty() {
-        return db
-            .search_history("", None, session_id, cwd, limit, 0)
-            .unwrap_or_default()
-            .into_iter()
-            .filter(|e| apply_host_filter(e, filter, filter_value))
-            .collect();
-    }
-
+    order: HistoryOrder,
+) -> SearchResults {
     // search_history uses LIKE '%query%', but for prefix we want LIKE 'query%'
     // We'll fetch broadly and filter in post for now, since we can't change the DB method.
     let broad_limit = limit * 10;
     let results = db
-        .search_history("", None, session_id, cwd, broad_limit, 0)
+        .search_history_ordered("", None, session_id, cwd, group, broad_limit, 0, order)
         .unwrap_or_default();
+    let partial = results.len() as i64 >= broad_limit;
 
-    results
+    let matches = results
         .into_iter()
         .filter(|e| apply_host_filter(e, filter, filter_value))
         .filter(|e| e.command.starts_with(query))
-        .take(limit as usize)
-        .collect()
+        .collect();
+    SearchResults::capped(matches, limit, partial)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn search_fulltext(
     db: &SyncDatabase,
     query: &str,
     _machine_id: Option<&str>,
     session_id: Option<&str>,
     cwd: Option<&str>,
+    group: Option<&str>,
     filter: FilterMode,
     filter_value: &str,
     limit: i64,
-) -> Vec<HistoryEntry> {
+    options: MatchOptions,
+    order: HistoryOrder,
+) -> SearchResults {
     // search_history already does LIKE '%query%' which is fulltext
-    db.search_history(query, None, session_id, cwd, limit, 0)
-        .unwrap_or_default()
+    let broad_limit = limit * 10;
+    let results = db
+        .search_history_ordered(query, None, session_id, cwd, group, broad_limit, 0, order)
+        .unwrap_or_default();
+    let partial = results.len() as i64 >= broad_limit;
+    let matches = results
         .into_iter()
-        .filter(|e| apply_host_filter(e, filter, filter_value))
-        .collect()
+        .filter(|e| apply_host_filter(e, filter, filter_value));
+
+    if query.is_empty() || options == MatchOptions::default() {
+        return SearchResults::capped(matches.collect(), limit, partial);
+    }
+
+    // LIKE ignores case and word boundaries, so it returns a superset of the
+    // matches; narrow it down with the equivalent literal regex.
+    let re = options
+        .compile(&regex::escape(query))
+        .expect("escaped query is a valid regex");
+    let matches = matches.filter(|e| re.is_match(&e.command)).collect();
+    SearchResults::capped(matches, limit, partial)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn search_regex(
     db: &SyncDatabase,
     query: &str,
     _machine_id: Option<&str>,
     session_id: Option<&str>,
     cwd: Option<&str>,
+    group: Option<&str>,
     filter: FilterMode,
     filter_value: &str,
     limit: i64,
-) -> Vec<HistoryEntry> {
-    let re = match regex::Regex::new(query) {
-        Ok(r) => r,
-        Err(_) => return Vec::new(),
-    };
+    options: MatchOptions,
+    order: HistoryOrder,
+) -> Result<SearchResults, regex::Error> {
+    let re = options.compile(query)?;
 
     let broad_limit = limit * 10;
     let results = db
-        .search_history("", None, session_id, cwd, broad_limit, 0)
+        .search_history_ordered("", None, session_id, cwd, group, broad_limit, 0, order)
         .unwrap_or_default();
+    let partial = results.len() as i64 >= broad_limit;
 
-    results
+    let matches = results
         .into_iter()
         .filter(|e| apply_host_filter(e, filter, filter_value))
         .filter(|e| re.is_match(&e.command))
-        .take(limit as usize)
-        .collect()
+        .collect();
+    Ok(SearchResults::capped(matches, limit, partial))
 }
 
 fn apply_host_filter(entry: &HistoryEntry, filter: FilterMode, filter_value: &str) -> bool {
@@ -187,3 +419,280 @@ fn apply_host_filter(entry: &HistoryEntry, filter: FilterMode, filter_value: &st
         _ => true,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::SearchMode;
+
+    const HOUR: i64 = 3_600_000;
+    const NOW: i64 = 1_705_321_800_000;
+
+    fn entry(id: &str, command: &str, timestamp: i64) -> HistoryEntry {
+        HistoryEntry {
+            timestamp,
+            ..crate::test_entry(id, command)
+        }
+    }
+
+    /// Rank `scored`, dropping the scores.
+    fn rank(
+        scored: Vec<(u32, HistoryEntry)>,
+        weights: &RankWeights,
+        now_ms: i64,
+    ) -> Vec<HistoryEntry> {
+        rank_matches(scored, weights, now_ms)
+            .into_iter()
+            .map(|(_, _, e)| e)
+            .collect()
+    }
+
+    fn commands(ranked: &[HistoryEntry]) -> Vec<&str> {
+        ranked.iter().map(|e| e.command.as_str()).collect()
+    }
+
+    #[test]
+    fn frequent_command_outranks_equal_match() {
+        let old = NOW - 1000 * HOUR;
+        let scored = vec![
+            (100, entry("a", "make build-rare", old)),
+            (100, entry("b", "make build-daily", old)),
+            (100, entry("c", "make build-daily", old - HOUR)),
+            (100, entry("d", "make build-daily", old - 2 * HOUR)),
+        ];
+        let ranked = rank(scored, &RankWeights::default(), NOW);
+        assert_eq!(ranked[0].command, "make build-daily");
+        assert_eq!(ranked.last().unwrap().command, "make build-rare");
+    }
+
+    #[test]
+    fn recent_command_outranks_equal_match() {
+        let scored = vec![
+            (100, entry("a", "cargo test --old", NOW - 500 * HOUR)),
+            (100, entry("b", "cargo test --new", NOW - HOUR)),
+        ];
+        let ranked = rank(scored, &RankWeights::default(), NOW);
+        assert_eq!(commands(&ranked), ["cargo test --new", "cargo test --old"]);
+    }
+
+    #[test]
+    fn zero_weights_fall_back_to_match_score() {
+        let weights = RankWeights {
+            frequency: 0.0,
+            recency: 0.0,
+            half_life_hours: 72.0,
+        };
+        let scored = vec![
+            (50, entry("a", "ls -la", NOW)),
+            (50, entry("b", "ls -la", NOW)),
+            (80, entry("c", "ls", NOW - 900 * HOUR)),
+        ];
+        let ranked = rank(scored, &weights, NOW);
+        assert_eq!(ranked[0].id, "c");
+    }
+
+    #[test]
+    fn ties_break_deterministically() {
+        let scored = || {
+            vec![
+                (100, entry("b", "git st", NOW - HOUR)),
+                (100, entry("a", "git sw", NOW - HOUR)),
+                (100, entry("c", "git sh", NOW)),
+            ]
+        };
+        let ranked = rank(scored(), &RankWeights::default(), NOW);
+        let ids: Vec<&str> = ranked.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["c", "a", "b"]);
+
+        let mut reversed = scored();
+        reversed.reverse();
+        let again = rank(reversed, &RankWeights::default(), NOW);
+        assert_eq!(commands(&ranked), commands(&again));
+    }
+
+    #[test]
+    fn counted_search_reports_matches_beyond_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        for i in 0..12 {
+            let e = entry(&format!("h{i}"), &format!("make t{i}"), NOW - i * HOUR);
+            db.insert_history_entry(&e).unwrap();
+        }
+        db.insert_history_entry(&entry("x", "ls", NOW)).unwrap();
+
+        let weights = RankWeights::default();
+        let options = MatchOptions::default();
+        for mode in [
+            SearchMode::Fuzzy,
+            SearchMode::Prefix,
+            SearchMode::Fulltext,
+            SearchMode::Regex,
+        ] {
+            let newest = HistoryOrder::NewestFirst;
+            let results = search_counted(
+                &db, "make", mode, FilterMode::Global, "", 5, &weights, options, newest,
+            )
+            .unwrap();
+            assert_eq!(results.entries.len(), 5, "{mode:?}");
+            assert_eq!(results.total, 12, "{mode:?}");
+            assert!(results.is_capped());
+            assert!(!results.partial);
+        }
+
+        // Scanning stops at limit * 10 candidates, so the total is a lower bound
+        let (fuzzy, newest) = (SearchMode::Fuzzy, HistoryOrder::NewestFirst);
+        let results =
+            search_counted(&db, "", fuzzy, FilterMode::Global, "", 1, &weights, options, newest)
+                .unwrap();
+        assert_eq!(results.total, 10);
+        assert!(results.partial);
+    }
+
+    #[test]
+    fn group_filter_limits_results_in_every_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        let mut work = entry("w1", "kubectl get pods", NOW);
+        work.group_name = "work".into();
+        let mut home = entry("p1", "kubectl get nodes", NOW - HOUR);
+        home.group_name = "personal".into();
+        db.insert_history_entry(&work).unwrap();
+        db.insert_history_entry(&home).unwrap();
+
+        let weights = RankWeights::default();
+        for (mode, query) in [
+            (SearchMode::Fuzzy, "kubectl"),
+            (SearchMode::Prefix, "kubectl"),
+            (SearchMode::Fulltext, "kubectl"),
+            (SearchMode::Regex, "^kubectl"),
+            (SearchMode::Fuzzy, ""),
+        ] {
+            let results = search(&db, query, mode, FilterMode::Group, "work", 10, &weights, MatchOptions::default()).unwrap();
+            let ids: Vec<&str> = results.iter().map(|e| e.id.as_str()).collect();
+            assert_eq!(ids, ["w1"], "{:?} {:?}", mode, query);
+
+            let all = search(&db, query, mode, FilterMode::Global, "", 10, &weights, MatchOptions::default()).unwrap();
+            assert_eq!(all.len(), 2, "{:?} {:?}", mode, query);
+        }
+    }
+
+    #[test]
+    fn group_filter_without_group_is_global() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        db.insert_history_entry(&entry("a", "ls", NOW)).unwrap();
+        let results = search(
+            &db,
+            "",
+            SearchMode::Fuzzy,
+            FilterMode::Group,
+            "",
+            10,
+            &RankWeights::default(),
+            MatchOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error_not_an_empty_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        db.insert_history_entry(&entry("a", "git commit -m wip", NOW)).unwrap();
+        let weights = RankWeights::default();
+
+        let err = search(&db, "commit (", SearchMode::Regex, FilterMode::Global, "", 10, &weights, MatchOptions::default());
+        assert!(err.is_err());
+
+        let ok = search(&db, "commit (-m)?", SearchMode::Regex, FilterMode::Global, "", 10, &weights, MatchOptions::default());
+        assert_eq!(ok.unwrap().len(), 1);
+
+        // Other modes treat the same text literally.
+        let fuzzy = search(&db, "commit (", SearchMode::Fulltext, FilterMode::Global, "", 10, &weights, MatchOptions::default());
+        assert!(fuzzy.unwrap().is_empty());
+    }
+
+    fn mixed_case_db(dir: &tempfile::TempDir) -> SyncDatabase {
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        db.insert_history_entry(&entry("lower", "make build", NOW)).unwrap();
+        db.insert_history_entry(&entry("upper", "make BUILD", NOW - HOUR)).unwrap();
+        db.insert_history_entry(&entry("suffix", "make builder", NOW - 2 * HOUR)).unwrap();
+        db
+    }
+
+    fn ids(results: Vec<HistoryEntry>) -> Vec<String> {
+        let mut ids: Vec<String> = results.into_iter().map(|e| e.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn case_toggle_changes_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = mixed_case_db(&dir);
+        let weights = RankWeights::default();
+        let sensitive = MatchOptions {
+            case_sensitive: true,
+            whole_word: false,
+        };
+
+        for (mode, query) in [(SearchMode::Fulltext, "build"), (SearchMode::Regex, "bu[i]ld")] {
+            let run = |options| {
+                ids(search(&db, query, mode, FilterMode::Global, "", 10, &weights, options).unwrap())
+            };
+            assert_eq!(run(MatchOptions::default()), ["lower", "suffix", "upper"], "{:?}", mode);
+            assert_eq!(run(sensitive), ["lower", "suffix"], "{:?}", mode);
+        }
+
+        let upper = search(&db, "BUILD", SearchMode::Fulltext, FilterMode::Global, "", 10, &weights, sensitive);
+        assert_eq!(ids(upper.unwrap()), ["upper"]);
+    }
+
+    #[test]
+    fn whole_word_toggle_skips_partial_words() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = mixed_case_db(&dir);
+        let weights = RankWeights::default();
+        let whole_word = MatchOptions {
+            case_sensitive: false,
+            whole_word: true,
+        };
+
+        for mode in [SearchMode::Fulltext, SearchMode::Regex] {
+            let results = search(&db, "build", mode, FilterMode::Global, "", 10, &weights, whole_word);
+            assert_eq!(ids(results.unwrap()), ["lower", "upper"], "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn fuzzy_results_carry_score_and_positions() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SyncDatabase::open(dir.path().join("h.db").to_str().unwrap()).unwrap();
+        db.insert_history_entry(&entry("a", "git status", NOW)).unwrap();
+        db.insert_history_entry(&entry("b", "git stash pop", NOW - HOUR)).unwrap();
+        db.insert_history_entry(&entry("c", "ls -la", NOW)).unwrap();
+
+        let weights = RankWeights::default();
+        let (options, newest) = (MatchOptions::default(), HistoryOrder::NewestFirst);
+        let run = |query, mode| {
+            search_counted(
+                &db, query, mode, FilterMode::Global, "", 10, &weights, options, newest,
+            )
+            .unwrap()
+        };
+
+        let results = run("gst", SearchMode::Fuzzy);
+        assert_eq!(results.entries.len(), 2);
+        assert_eq!(results.explain.len(), results.entries.len());
+        for (entry, why) in results.entries.iter().zip(&results.explain) {
+            assert!(why.match_score > 0);
+            assert!(why.score >= why.match_score as f64);
+            assert_eq!(why.matched(&entry.command), "gst");
+        }
+        assert_eq!(results.explain[0].indices, [0, 4, 5]);
+
+        assert!(run("", SearchMode::Fuzzy).explain.is_empty());
+        assert!(run("git", SearchMode::Fulltext).explain.is_empty());
+    }
+}
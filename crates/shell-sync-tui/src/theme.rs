@@ -0,0 +1,141 @@
+use ratatui::style::{Color, Modifier, Style};
+
+/// Styles used by the search UI, so the palette is not hard-coded in `ui`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// `[FUZZY]`-style search mode indicator.
+    pub mode_label: Style,
+    /// `[GLOBAL]`-style filter indicator.
+    pub filter_label: Style,
+    /// Failed exit codes and the invalid-regex hint.
+    pub error: Style,
+    /// Successful exit codes.
+    pub success: Style,
+    /// Notices such as `[truncated]` and the active filter in the footer.
+    pub warning: Style,
+    /// Command text.
+    pub command: Style,
+    /// Command text of the selected row.
+    pub command_selected: Style,
+    /// Secondary details: duration, time, directory, key help.
+    pub muted: Style,
+    /// Background of the selected row.
+    pub selected_row: Style,
+    /// Active match toggles in the footer.
+    pub toggles: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            mode_label: Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            filter_label: Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            error: Style::default().fg(Color::Red),
+            success: Style::default().fg(Color::Green),
+            warning: Style::default().fg(Color::Yellow),
+            command: Style::default().fg(Color::White),
+            command_selected: Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            muted: Style::default().fg(Color::DarkGray),
+            selected_row: Style::default().bg(Color::DarkGray),
+            toggles: Style::default().fg(Color::Magenta),
+        }
+    }
+}
+
+impl Theme {
+    /// No colors at all; emphasis comes from bold and reverse video only.
+    pub fn monochrome() -> Self {
+        let plain = Style::default();
+        let bold = plain.add_modifier(Modifier::BOLD);
+        Self {
+            mode_label: bold,
+            filter_label: bold,
+            error: bold,
+            success: plain,
+            warning: plain,
+            command: plain,
+            command_selected: bold,
+            muted: plain,
+            selected_row: plain.add_modifier(Modifier::REVERSED),
+            toggles: plain,
+        }
+    }
+
+    /// Blue/orange instead of green/red, distinguishable with red-green color blindness.
+    pub fn colorblind() -> Self {
+        const ORANGE: Color = Color::Indexed(208);
+        Self {
+            error: Style::default().fg(ORANGE),
+            success: Style::default().fg(Color::Blue),
+            warning: Style::default().fg(Color::Magenta),
+            filter_label: Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+            ..Self::default()
+        }
+    }
+
+    /// Parse a theme name: `default`, `monochrome` (or `mono`), `colorblind`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "default" => Some(Self::default()),
+            "mono" | "monochrome" => Some(Self::monochrome()),
+            "colorblind" => Some(Self::colorblind()),
+            _ => None,
+        }
+    }
+
+    /// Monochrome when color is disabled (`--no-color` / `NO_COLOR`), otherwise
+    /// the theme named by `SHELL_SYNC_THEME`, falling back to the default.
+    pub fn from_env() -> Self {
+        if !shell_sync_core::color::enabled() {
+            return Self::monochrome();
+        }
+        std::env::var("SHELL_SYNC_THEME")
+            .ok()
+            .and_then(|name| Self::from_name(&name))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn styles(theme: &Theme) -> [Style; 10] {
+        [
+            theme.mode_label,
+            theme.filter_label,
+            theme.error,
+            theme.success,
+            theme.warning,
+            theme.command,
+            theme.command_selected,
+            theme.muted,
+            theme.selected_row,
+            theme.toggles,
+        ]
+    }
+
+    #[test]
+    fn monochrome_sets_no_colors() {
+        for style in styles(&Theme::monochrome()) {
+            assert_eq!(style.fg, None);
+            assert_eq!(style.bg, None);
+        }
+    }
+
+    #[test]
+    fn colorblind_avoids_red_and_green() {
+        let theme = Theme::colorblind();
+        for style in styles(&theme) {
+            assert!(!matches!(style.fg, Some(Color::Red | Color::Green)));
+        }
+        assert_ne!(theme.error, theme.success);
+    }
+
+    #[test]
+    fn theme_names() {
+        assert_eq!(Theme::from_name("Mono"), Some(Theme::monochrome()));
+        assert_eq!(Theme::from_name("colorblind"), Some(Theme::colorblind()));
+        assert!(Theme::from_name("solarized").is_none());
+    }
+}
@@ -1,15 +1,23 @@
 use crate::app::App;
-use chrono::{TimeZone, Utc};
+use crate::search::MatchExplain;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, List, ListItem, Paragraph},
     Frame,
 };
+use shell_sync_core::tz::DisplayTz;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Rows the full layout needs: a bordered input bar, results list and footer.
+const FULL_LAYOUT_ROWS: u16 = 9;
 
 /// Render the entire TUI to the given frame.
 pub fn draw(frame: &mut Frame, app: &App) {
+    if frame.area().height < FULL_LAYOUT_ROWS {
+        draw_compact(frame, app);
+        return;
+    }
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -19,49 +27,102 @@ pub fn draw(frame: &mut Frame, app: &App) {
         ])
         .split(frame.area());
 
-    draw_input_bar(frame, app, chunks[0]);
-    draw_results(frame, app, chunks[1]);
+    draw_input_bar(frame, app, chunks[0], Borders::ALL);
+    draw_results(frame, app, chunks[1], Borders::ALL);
     draw_footer(frame, app, chunks[2]);
 }
 
-fn draw_input_bar(frame: &mut Frame, app: &App, area: Rect) {
+/// Layout for terminals too short for borders and a footer: the query on the
+/// first row and as many results as fit below it. A pending bulk delete
+/// keeps its prompt on the last row.
+fn draw_compact(frame: &mut Frame, app: &App) {
+    let area = frame.area();
+    let confirming = app.bulk_delete == crate::app::BulkDelete::Confirming;
+    let prompt_rows = u16::from(confirming && area.height > 1);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(prompt_rows),
+        ])
+        .split(area);
+
+    draw_input_bar(frame, app, chunks[0], Borders::NONE);
+    draw_results(frame, app, chunks[1], Borders::NONE);
+    if prompt_rows > 0 {
+        frame.render_widget(Paragraph::new(delete_prompt(app)), chunks[2]);
+    }
+}
+
+fn draw_input_bar(frame: &mut Frame, app: &App, area: Rect, borders: Borders) {
+    if area.height == 0 {
+        return;
+    }
     let search_label = format!("[{}]", app.search_mode.label());
     let filter_label = format!("[{}]", app.filter_mode.label());
 
-    let input_line = Line::from(vec![
+    let mut spans = vec![
         Span::styled(
             &search_label,
-            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            app.theme.mode_label,
         ),
         Span::raw(" "),
         Span::styled(
             &filter_label,
-            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            app.theme.filter_label,
         ),
         Span::raw(" > "),
         Span::raw(&app.input),
-    ]);
+    ];
 
-    let input_widget = Paragraph::new(input_line).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .title(" shell-sync search "),
-    );
+    let mut block = Block::default().borders(borders);
+    if borders == Borders::ALL {
+        block = block.title(" shell-sync search ");
+    }
+    if let Some(err) = &app.search_error {
+        spans.push(Span::styled(
+            format!("  invalid regex: {}", err),
+            app.theme.error,
+        ));
+        block = block.border_style(app.theme.error);
+    }
+
+    let input_widget = Paragraph::new(Line::from(spans)).block(block);
     frame.render_widget(input_widget, area);
 
     // Place cursor
-    let cursor_x = area.x
-        + 1 // border
-        + search_label.len() as u16
+    let border = u16::from(borders == Borders::ALL);
+    let cursor_x = area.x + border + cursor_column(&search_label, &filter_label, app);
+    let cursor_y = area.y + border;
+    frame.set_cursor_position((cursor_x, cursor_y));
+}
+
+/// Display column of the cursor within the input line, measured in terminal
+/// cells rather than bytes so wide (CJK, emoji) input lines up correctly.
+fn cursor_column(search_label: &str, filter_label: &str, app: &App) -> u16 {
+    let width = search_label.width()
         + 1 // space
-        + filter_label.len() as u16
+        + filter_label.width()
         + 3 // " > "
-        + app.input[..app.cursor].len() as u16;
-    let cursor_y = area.y + 1;
-    frame.set_cursor_position((cursor_x, cursor_y));
+        + app.input[..app.cursor].width();
+    width as u16
 }
 
-fn draw_results(frame: &mut Frame, app: &App, area: Rect) {
+fn draw_results(frame: &mut Frame, app: &App, area: Rect, borders: Borders) {
+    let bordered = borders == Borders::ALL;
+    if let Some(notice) = &app.notice {
+        let mut block = Block::default().borders(borders);
+        if bordered {
+            block = block.title(" Results ");
+        }
+        let text = Paragraph::new(Line::from(Span::styled(notice.as_str(), app.theme.warning)))
+            .block(block)
+            .wrap(ratatui::widgets::Wrap { trim: true });
+        frame.render_widget(text, area);
+        return;
+    }
+
     let items: Vec<ListItem> = app
         .results
         .iter()
@@ -70,73 +131,156 @@ fn draw_results(frame: &mut Frame, app: &App, area: Rect) {
             let is_selected = i == app.selected;
 
             let exit_style = if entry.exit_code != 0 {
-                Style::default().fg(Color::Red)
+                app.theme.error
             } else {
-                Style::default().fg(Color::Green)
+                app.theme.success
             };
 
             let duration = format_duration(entry.duration_ms);
-            let time = format_timestamp(entry.timestamp);
-
-            let line = Line::from(vec![
-                Span::styled(
-                    &entry.command,
-                    if is_selected {
-                        Style::default()
-                            .fg(Color::White)
-                            .add_modifier(Modifier::BOLD)
-                    } else {
-                        Style::default().fg(Color::White)
-                    },
-                ),
+            let time = format_timestamp(entry.timestamp, &app.tz);
+
+            let mut spans = Vec::new();
+            if !app.marked.is_empty() {
+                spans.push(if app.is_marked(&entry.id) {
+                    Span::styled("* ", app.theme.warning)
+                } else {
+                    Span::raw("  ")
+                });
+            }
+            spans.push(Span::styled(
+                &entry.command,
+                if is_selected {
+                    app.theme.command_selected
+                } else {
+                    app.theme.command
+                },
+            ));
+            if entry.truncated {
+                spans.push(Span::styled(" [truncated]", app.theme.warning));
+            }
+            spans.extend([
                 Span::raw("  "),
                 Span::styled(format!("E{}", entry.exit_code), exit_style),
                 Span::raw("  "),
-                Span::styled(duration, Style::default().fg(Color::DarkGray)),
+                Span::styled(duration, app.theme.muted),
                 Span::raw("  "),
-                Span::styled(time, Style::default().fg(Color::DarkGray)),
+                Span::styled(time, app.theme.muted),
                 Span::raw("  "),
-                Span::styled(
-                    truncate_cwd(&entry.cwd, 30),
-                    Style::default().fg(Color::DarkGray),
-                ),
+                Span::styled(truncate_cwd(&entry.cwd, 30), app.theme.muted),
             ]);
+            if let Some(why) = app.explanations.get(i) {
+                spans.push(Span::styled(
+                    explain_label(why, &entry.command),
+                    app.theme.muted,
+                ));
+            }
+            let line = Line::from(spans);
 
             if is_selected {
-                ListItem::new(line).style(Style::default().bg(Color::DarkGray))
+                ListItem::new(line).style(app.theme.selected_row)
             } else {
                 ListItem::new(line)
             }
         })
         .collect();
 
-    let title = format!(" Results ({}) ", app.results.len());
-    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    let mut block = Block::default().borders(borders);
+    if bordered {
+        block = block.title(results_title(app));
+    }
+    let list = List::new(items).block(block);
 
     frame.render_widget(list, area);
 }
 
 fn draw_footer(frame: &mut Frame, app: &App, area: Rect) {
+    if app.bulk_delete == crate::app::BulkDelete::Confirming {
+        let footer =
+            Paragraph::new(delete_prompt(app)).block(Block::default().borders(Borders::ALL));
+        frame.render_widget(footer, area);
+        return;
+    }
+
     let help = if app.inline {
-        "Enter/Tab: paste | Esc: cancel | Ctrl+R: mode | Ctrl+S: filter | Up/Down: navigate"
+        "Enter: paste | Tab: mark | Ctrl+D: delete marked | Esc: cancel | Ctrl+R: mode | Ctrl+S: filter | Ctrl+O: order | Alt+I: case | Alt+W: word | Alt+E: explain | Up/Down: navigate"
     } else {
-        "Enter: select | Esc: cancel | Ctrl+R: mode | Ctrl+S: filter | Up/Down: navigate"
+        "Enter: select | Tab: mark | Ctrl+D: delete marked | Esc: cancel | Ctrl+R: mode | Ctrl+S: filter | Ctrl+O: order | Alt+I: case | Alt+W: word | Alt+E: explain | Up/Down: navigate"
     };
 
-    let filter_info = match app.filter_mode {
-        crate::app::FilterMode::Global => String::new(),
-        _ => format!(" | filter: {}", app.filter_value()),
-    };
+    let mut filter_info = footer_filter_info(app);
+    if app.order == shell_sync_core::db::HistoryOrder::OldestFirst {
+        filter_info.push_str(" | oldest first");
+    }
 
     let footer_line = Line::from(vec![
-        Span::styled(help, Style::default().fg(Color::DarkGray)),
-        Span::styled(filter_info, Style::default().fg(Color::Yellow)),
+        Span::styled(help, app.theme.muted),
+        Span::styled(filter_info, app.theme.warning),
+        Span::styled(footer_match_info(app), app.theme.toggles),
     ]);
 
     let footer = Paragraph::new(footer_line).block(Block::default().borders(Borders::ALL));
     frame.render_widget(footer, area);
 }
 
+fn delete_prompt(app: &App) -> Line<'static> {
+    let prompt = format!(
+        "Delete {} marked entries from local history? y/n",
+        app.marked.len()
+    );
+    Line::from(Span::styled(prompt, app.theme.error))
+}
+
+fn footer_filter_info(app: &App) -> String {
+    match app.filter_mode {
+        crate::app::FilterMode::Global => String::new(),
+        crate::app::FilterMode::Session if app.current_session_id.is_empty() => {
+            " | filter: no session".to_string()
+        }
+        crate::app::FilterMode::Session => format!(
+            " | filter: {} ({} commands)",
+            app.filter_value(),
+            app.session_command_count
+        ),
+        _ => format!(" | filter: {}", app.filter_value()),
+    }
+}
+
+/// Score and matched characters shown after a result in explain mode, e.g.
+/// `  score 412 (match 140) "gst"`.
+fn explain_label(why: &MatchExplain, command: &str) -> String {
+    format!(
+        "  score {:.0} (match {}) {:?}",
+        why.score,
+        why.match_score,
+        why.matched(command)
+    )
+}
+
+/// Active match toggles, e.g. " | Aa word". Case and word only apply in
+/// fulltext and regex modes, explain only in fuzzy mode.
+fn footer_match_info(app: &App) -> String {
+    use crate::app::SearchMode;
+    if app.search_mode == SearchMode::Fuzzy && app.explain {
+        return " | explain".to_string();
+    }
+    if !matches!(app.search_mode, SearchMode::Fulltext | SearchMode::Regex) {
+        return String::new();
+    }
+    let opts = app.match_options;
+    let mut flags = Vec::new();
+    if opts.case_sensitive {
+        flags.push("Aa");
+    }
+    if opts.whole_word {
+        flags.push("word");
+    }
+    if flags.is_empty() {
+        String::new()
+    } else {
+        format!(" | {}", flags.join(" "))
+    }
+}
+
 fn format_duration(ms: i64) -> String {
     if ms < 1000 {
         format!("{}ms", ms)
@@ -149,21 +293,224 @@ fn format_duration(ms: i64) -> String {
     }
 }
 
-fn format_timestamp(ts: i64) -> String {
-    // Timestamp is in milliseconds
-    let secs = ts / 1000;
-    match Utc.timestamp_opt(secs, 0) {
-        chrono::LocalResult::Single(dt) => dt.format("%m-%d %H:%M").to_string(),
-        _ => "?".to_string(),
-    }
+fn format_timestamp(ts: i64, tz: &DisplayTz) -> String {
+    // Timestamp is in milliseconds (UTC); render in the display zone
+    tz.localize(ts)
+        .map(|dt| dt.format("%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "?".to_string())
 }
 
-fn truncate_cwd(cwd: &str, max_len: usize) -> String {
-    if cwd.len() <= max_len {
-        cwd.to_string()
+/// Title of the results list: the count, or "Showing N of M" when the search
+/// matched more than it displays.
+fn results_title(app: &App) -> String {
+    let shown = app.results.len();
+    let mut title = if app.total_count > shown as i64 {
+        format!(
+            " Showing {} of {}{}",
+            format_count(shown as i64),
+            format_count(app.total_count),
+            if app.total_is_partial { "+" } else { "" }
+        )
     } else {
-        // Show the last part of the path
-        let truncated = &cwd[cwd.len() - max_len + 3..];
-        format!("...{}", truncated)
+        format!(" Results ({shown}")
+    };
+    if !app.marked.is_empty() {
+        title.push_str(&format!(", {} marked", app.marked.len()));
+    }
+    if title.starts_with(" Results") {
+        title.push(')');
+    }
+    title.push(' ');
+    title
+}
+
+/// `1342` → `1,342`.
+fn format_count(n: i64) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+/// Shorten a path to at most `max_width` display cells, eliding the middle
+/// (`/home/.../deep/dir`) so the root and the leaf stay visible. Cuts only on
+/// char boundaries, so non-ASCII paths never panic.
+fn truncate_cwd(cwd: &str, max_width: usize) -> String {
+    if cwd.width() <= max_width {
+        return cwd.to_string();
+    }
+
+    // Keep the first component and as many trailing components as fit
+    let parts: Vec<&str> = cwd.split('/').collect();
+    let head_len = if cwd.starts_with('/') { 2 } else { 1 };
+    if parts.len() > head_len + 1 {
+        let head = parts[..head_len].join("/");
+        let mut tail = String::new();
+        for part in parts[head_len..].iter().rev() {
+            let candidate = if tail.is_empty() {
+                part.to_string()
+            } else {
+                format!("{}/{}", part, tail)
+            };
+            if head.width() + "/.../".len() + candidate.width() > max_width {
+                break;
+            }
+            tail = candidate;
+        }
+        if !tail.is_empty() {
+            return format!("{}/.../{}", head, tail);
+        }
+    }
+
+    // Leaf alone is too long: show as much of the end as fits
+    let budget = max_width.saturating_sub(3);
+    let mut width = 0;
+    let mut start = cwd.len();
+    for (i, c) in cwd.char_indices().rev() {
+        let w = c.width().unwrap_or(0);
+        if width + w > budget {
+            break;
+        }
+        width += w;
+        start = i;
+    }
+    format!("...{}", &cwd[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_column_uses_display_width() {
+        let mut app = App::new("git 日本", false);
+
+        let prefix = 2 + 1 + 2 + 3;
+        // "git " is 4 cells and each CJK character is 2 cells wide
+        assert_eq!(cursor_column("[]", "[]", &app), prefix + 8);
+        assert_ne!(
+            cursor_column("[]", "[]", &app) as usize,
+            prefix as usize + app.input.len()
+        );
+
+        app.input = "🚀 deploy".to_string();
+        app.cursor = "🚀".len();
+        assert_eq!(cursor_column("[]", "[]", &app), prefix + 2);
+    }
+
+    #[test]
+    fn truncate_cwd_keeps_short_paths() {
+        assert_eq!(truncate_cwd("/home/josé", 30), "/home/josé");
+    }
+
+    #[test]
+    fn truncate_cwd_elides_middle_of_multibyte_path() {
+        let out = truncate_cwd("/home/josé/проект/src/глубоко/каталог", 25);
+        assert_eq!(out, "/home/.../глубоко/каталог");
+        assert!(out.width() <= 25);
+    }
+
+    #[test]
+    fn truncate_cwd_long_leaf_cuts_on_char_boundary() {
+        for max in 6..20 {
+            let out = truncate_cwd("/tmp/проектпроектпроект", max);
+            assert!(out.width() <= max, "{} exceeds {}", out, max);
+            assert!(out.starts_with("..."));
+            assert!(out.ends_with("ект"));
+        }
+
+        // Wide characters count as two cells
+        let out = truncate_cwd("/srv/日本語日本語日本語", 10);
+        assert_eq!(out, "...日本語");
+    }
+
+    #[test]
+    fn tiny_terminal_falls_back_to_compact_layout() {
+        use crate::app::BulkDelete;
+        use ratatui::{backend::TestBackend, Terminal};
+        use shell_sync_core::models::HistoryEntry;
+
+        let entry = |id: &str, command: &str| HistoryEntry {
+            timestamp: 1_705_321_800_000,
+            ..crate::test_entry(id, command)
+        };
+        let mut app = App::new("git", false);
+        let results = ["git status", "git push", "git log", "git diff", "git pull"];
+        app.set_results(Ok(results
+            .iter()
+            .enumerate()
+            .map(|(i, c)| entry(&i.to_string(), c))
+            .collect()));
+        let rows = |terminal: &Terminal<TestBackend>| -> Vec<String> {
+            let buffer = terminal.backend().buffer();
+            (0..buffer.area.height)
+                .map(|y| {
+                    (0..buffer.area.width)
+                        .map(|x| buffer[(x, y)].symbol())
+                        .collect()
+                })
+                .collect()
+        };
+
+        for height in 0..=FULL_LAYOUT_ROWS {
+            let mut terminal = Terminal::new(TestBackend::new(40, height)).unwrap();
+            terminal.draw(|frame| draw(frame, &app)).unwrap();
+        }
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 4)).unwrap();
+        terminal.draw(|frame| draw(frame, &app)).unwrap();
+        let lines = rows(&terminal);
+        assert!(lines[0].contains("> git"), "{:?}", lines);
+        assert!(lines[1].starts_with("git status"), "{:?}", lines);
+        assert!(lines[3].starts_with("git log"), "{:?}", lines);
+
+        app.toggle_mark();
+        app.bulk_delete = BulkDelete::Confirming;
+        terminal.draw(|frame| draw(frame, &app)).unwrap();
+        let lines = rows(&terminal);
+        assert!(lines[0].contains("> git"), "{:?}", lines);
+        assert!(lines[3].starts_with("Delete 1 marked"), "{:?}", lines);
+    }
+
+    #[test]
+    fn results_title_shows_capped_total() {
+        use crate::search::SearchResults;
+
+        let entry = |id: &str| crate::test_entry(id, "make");
+        let mut app = App::new("", false);
+        app.set_results(Ok(vec![entry("a"), entry("b")]));
+        assert_eq!(results_title(&app), " Results (2) ");
+
+        app.set_search_results(Ok(SearchResults {
+            entries: vec![entry("a"), entry("b")],
+            total: 1342,
+            partial: false,
+            explain: Vec::new(),
+        }));
+        assert_eq!(app.total_count, 1342);
+        assert_eq!(results_title(&app), " Showing 2 of 1,342 ");
+
+        app.total_is_partial = true;
+        app.toggle_mark();
+        assert_eq!(results_title(&app), " Showing 2 of 1,342+, 1 marked ");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1_000_000), "1,000,000");
+    }
+
+    #[test]
+    fn session_footer_shows_command_count() {
+        let mut app = App::new("", false);
+        app.filter_mode = crate::app::FilterMode::Session;
+        app.current_session_id = "abc".into();
+        app.session_command_count = 42;
+        assert_eq!(footer_filter_info(&app), " | filter: abc (42 commands)");
+
+        app.current_session_id.clear();
+        assert_eq!(footer_filter_info(&app), " | filter: no session");
     }
 }
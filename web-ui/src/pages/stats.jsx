@@ -159,6 +159,7 @@ export function Stats() {
 
           <BarChart items={stats.top_commands} label="Top Commands" />
           <BarChart items={stats.top_prefixes} label="Top Prefixes" />
+          <BarChart items={stats.top_subcommands} label="Top Subcommands" />
           <HourlyChart data={stats.hourly_distribution} />
           <DailyChart data={stats.daily_distribution} />
           <BarChart items={stats.per_directory} label="Top Directories" />